@@ -4,6 +4,7 @@ use iset::set::IntervalSet;
 use pgr_db::aln;
 use pgr_db::ext::{get_fastx_reader, GZFastaReader, SeqIndexDB};
 use pgr_db::fasta_io::{reverse_complement, SeqRec};
+use pgr_db::tandem_repeat;
 use rayon::prelude::*;
 use rustc_hash::{FxHashMap, FxHashSet};
 use serde::Serialize;
@@ -78,6 +79,24 @@ struct CmdOptions {
     /// if specified, generate fasta files for the sequence covering the SV candidates
     #[clap(long, short, default_value_t = false)]
     skip_uncalled_sv_seq_file: bool,
+
+    /// refine the breakpoints of the SV candidates that fail the direct alignment to the base resolution,
+    /// the unaligned cores are reported as the V records of type `R` (inversion) or `C` (complex replacement)
+    /// and marked with the `SVTYPE` INFO field in the VCF file
+    #[clap(long, default_value_t = false)]
+    refine_breakpoints: bool,
+
+    /// annotate the tandem repeat context (RU, RC, TR) of the variants in the VCF file
+    #[clap(long, default_value_t = false)]
+    tr_annotation: bool,
+
+    /// represent the repeat length changes as single alleles spanning the whole tandem repeats in the VCF file
+    #[clap(long, default_value_t = false)]
+    tr_collapse: bool,
+
+    /// the maximum period of the tandem repeats for the annotation
+    #[clap(long, default_value_t = 100)]
+    tr_max_period: usize,
 }
 
 struct Parameters {
@@ -486,6 +505,26 @@ fn main() -> Result<(), std::io::Error> {
                                         } else {
                                             AlnDiff::FailAln
                                         };
+                                        let wf_aln_diff = match wf_aln_diff {
+                                            AlnDiff::FailAln | AlnDiff::FailLengthDiff
+                                                if args.refine_breakpoints =>
+                                            {
+                                                if let Some(aln_res) = aln::refine_sv_breakpoints(
+                                                    &s0str,
+                                                    &s1str,
+                                                    aln::SV_REFINE_ANCHOR_K,
+                                                    aln::SV_REFINE_MAX_ANCHOR_GAP,
+                                                    aln::SV_REFINE_MISMATCH_PENALTY,
+                                                    aln::SV_REFINE_OPEN_PENALTY,
+                                                    aln::SV_REFINE_EXTENSION_PENALTY,
+                                                ) {
+                                                    AlnDiff::Aligned(aln_res)
+                                                } else {
+                                                    wf_aln_diff
+                                                }
+                                            }
+                                            _ => wf_aln_diff,
+                                        };
                                         ((ts, te), (qs, qe), orientation, wf_aln_diff)
                                     })
                                     .collect::<Vec<_>>()
@@ -948,7 +987,7 @@ fn main() -> Result<(), std::io::Error> {
             .expect("fail to write the 'in-alignment' sv candidate bed file");
     });
 
-    let mut vcf_records = Vec::<(u32, u32, String, String, ShimmerMatchBlock)>::new();
+    let mut vcf_records = Vec::<(u32, u32, String, String, ShimmerMatchBlock, char)>::new();
 
     // the second round loop through all_records to output and tagged variant from duplicate / overlapped blocks
     all_records
@@ -1129,7 +1168,14 @@ fn main() -> Result<(), std::io::Error> {
                     }
                     Record::Variant(match_block, td, qd, tc, vt, tvs, qvs) => {
                         let (t_idx, ts, te, q_idx, qs, qe, orientation) = match_block;
-                        vcf_records.push((t_idx, tc + 1, tvs.clone(), qvs.clone(), match_block));
+                        vcf_records.push((
+                            t_idx,
+                            tc + 1,
+                            tvs.clone(),
+                            qvs.clone(),
+                            match_block,
+                            vt,
+                        ));
                         let tn = target_name.get(&t_idx).unwrap();
                         let qn = query_name.get(&q_idx).unwrap();
 
@@ -1202,15 +1248,109 @@ fn main() -> Result<(), std::io::Error> {
         r#"##FILTER=<ID=to,Description="variant from overlapped contig alignment on query">"#
     )
     .expect("fail to write the vcf file");
+    if args.tr_annotation || args.tr_collapse {
+        writeln!(
+            out_vcf,
+            r#"##INFO=<ID=RU,Number=1,Type=String,Description="Tandem repeat unit">"#
+        )
+        .expect("fail to write the vcf file");
+        writeln!(
+            out_vcf,
+            r#"##INFO=<ID=RC,Number=R,Type=Float,Description="Tandem repeat copy number of each allele">"#
+        )
+        .expect("fail to write the vcf file");
+        writeln!(
+            out_vcf,
+            r#"##INFO=<ID=TR,Number=2,Type=Integer,Description="Start and end (1-based, inclusive) of the tandem repeat on the reference">"#
+        )
+        .expect("fail to write the vcf file");
+    }
+    if args.refine_breakpoints {
+        writeln!(
+            out_vcf,
+            r#"##INFO=<ID=SVTYPE,Number=1,Type=String,Description="Type of the structural variant not aligned to the base resolution, INV: inversion, CPX: complex replacement">"#
+        )
+        .expect("fail to write the vcf file");
+    }
     writeln!(out_vcf, "#CHROM\tPOS\tID\tREF\tALT\tQUAL\tFILTER\tINFO")
         .expect("fail to write the vcf file");
 
+    // sort by the targets first so each target sequence is only fetched once for the repeat annotation
+    vcf_records.sort();
+    let mut current_target_seq: Option<(u32, Vec<u8>)> = None;
+    let mut vcf_records = vcf_records
+        .into_iter()
+        .map(|(t_idx, tc, tvs, qvs, match_block, vt)| {
+            let tvs = tvs.trim_end_matches('-').to_string();
+            let qvs = qvs.trim_end_matches('-').to_string();
+
+            let (tc, tvs, qvs, info) = if args.tr_annotation || args.tr_collapse {
+                if current_target_seq.as_ref().map(|v| v.0) != Some(t_idx) {
+                    current_target_seq =
+                        Some((t_idx, ref_seq_index_db.get_seq_by_id(t_idx).unwrap()));
+                }
+                let t_seq = &current_target_seq.as_ref().unwrap().1[..];
+                let pos = (tc - 1) as usize;
+                if let Some(annotation) = tandem_repeat::annotate_variant(
+                    t_seq,
+                    pos,
+                    tvs.as_bytes(),
+                    qvs.as_bytes(),
+                    args.tr_max_period,
+                    2.0,
+                    6,
+                ) {
+                    let (tc, tvs, qvs) = match tandem_repeat::collapse_repeat_variant(
+                        t_seq,
+                        &annotation,
+                        pos,
+                        tvs.as_bytes(),
+                        qvs.as_bytes(),
+                    ) {
+                        Some((pos, tvs, qvs)) if args.tr_collapse => (
+                            pos as u32 + 1,
+                            String::from_utf8_lossy(&tvs[..]).to_string(),
+                            String::from_utf8_lossy(&qvs[..]).to_string(),
+                        ),
+                        _ => (tc, tvs, qvs),
+                    };
+                    let info = format!(
+                        "RU={};RC={:.1},{:.1};TR={},{}",
+                        String::from_utf8_lossy(&annotation.repeat.unit[..]),
+                        annotation.ref_copy_number,
+                        annotation.alt_copy_number,
+                        annotation.repeat.bgn + 1,
+                        annotation.repeat.end
+                    );
+                    (tc, tvs, qvs, info)
+                } else {
+                    (tc, tvs, qvs, ".".to_string())
+                }
+            } else {
+                (tc, tvs, qvs, ".".to_string())
+            };
+            // the unaligned cores from the breakpoint refinement
+            let info = match (vt, info.as_str()) {
+                ('R', ".") => "SVTYPE=INV".to_string(),
+                ('C', ".") => "SVTYPE=CPX".to_string(),
+                ('R', _) => format!("SVTYPE=INV;{}", info),
+                ('C', _) => format!("SVTYPE=CPX;{}", info),
+                _ => info,
+            };
+            (t_idx, tc, tvs, qvs, info, match_block)
+        })
+        .collect::<Vec<_>>();
+
+    // the collapsing moves the repeat variants, so the records are sorted again,
+    // and the collapsed repeat variants from the same alignment block can be identical
     vcf_records.sort();
+    if args.tr_collapse {
+        vcf_records.dedup_by(|a, b| (a.0, a.1, &a.2, &a.3) == (b.0, b.1, &b.2, &b.3));
+    }
     vcf_records
         .into_iter()
-        .for_each(|(t_idx, tc, tvs, qvs, match_block)| {
+        .for_each(|(t_idx, tc, tvs, qvs, info, match_block)| {
             let tn = target_name.get(&t_idx).unwrap();
-
             let dup =
                 if let Some(target_duplicate_intervals) = target_duplicate_intervals.get(&t_idx) {
                     if match_block.2 > match_block.1 {
@@ -1242,13 +1382,8 @@ fn main() -> Result<(), std::io::Error> {
             let qv: u32 = if filter != "PASS" { 10 } else { 60 };
             writeln!(
                 out_vcf,
-                "{}\t{}\t.\t{}\t{}\t{}\t{}\t.",
-                tn,
-                tc,
-                tvs.trim_end_matches('-'),
-                qvs.trim_end_matches('-'),
-                qv,
-                filter
+                "{}\t{}\t.\t{}\t{}\t{}\t{}\t{}",
+                tn, tc, tvs, qvs, qv, filter, info
             )
             .expect("fail to write the vcf file");
         });
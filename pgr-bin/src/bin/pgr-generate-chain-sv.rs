@@ -0,0 +1,614 @@
+const VERSION_STRING: &str = env!("VERSION_STRING");
+use clap::{self, CommandFactory, Parser};
+use pgr_db::ext::{get_fastx_reader, GZFastaReader};
+use pgr_db::fasta_io::SeqRec;
+use rustc_hash::FxHashMap;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+
+/// Identify inversions, translocations and large duplications from the order and the orientation of the alignment chains in an alnmap file
+#[derive(Parser, Debug)]
+#[clap(name = "pgr-generate-chain-sv")]
+#[clap(author, version)]
+#[clap(about, long_about = None)]
+struct CmdOptions {
+    /// path to the alnmap file
+    alnmap_path: String,
+    /// the prefix of the output files
+    output_prefix: String,
+    /// path to the target_len.json file from pgr-alnmap for the contig lines of the VCF header
+    #[clap(long)]
+    target_len_json_path: Option<String>,
+    /// path to the reference fasta file for the REF bases of the VCF records, the bases are written as `N` if it is not specified
+    #[clap(long)]
+    reference_fasta_path: Option<String>,
+    /// the sample name in the VCF file
+    #[clap(long, default_value = "Sample")]
+    sample_name: String,
+    /// ignore the chains that are shorter than this length on the query contig
+    #[clap(long, default_value_t = 10000)]
+    min_chain_length: u32,
+    /// the minimum target overlap between two adjacent chains to call a duplication
+    #[clap(long, default_value_t = 5000)]
+    min_dup_length: u32,
+    /// the minimum target jump between two adjacent chains in the same orientation to report a breakend
+    #[clap(long, default_value_t = 1000000)]
+    min_jump_length: u32,
+    /// a junction is labeled as high confidence if both supporting chains are longer than this length
+    #[clap(long, default_value_t = 100000)]
+    high_conf_chain_length: u32,
+}
+
+type TargetSeqLength = Vec<(u32, String, u32)>;
+
+#[derive(Clone, Debug)]
+struct Chain {
+    aln_idx: String,
+    t_name: String,
+    ts: u32,
+    te: u32,
+    q_name: String,
+    qs: u32,
+    qe: u32,
+    orientation: u32,
+    q_dup: bool,
+}
+
+impl Chain {
+    fn q_span(&self) -> u32 {
+        self.qe - self.qs
+    }
+
+    // the breakend position (1-based) on the target at the query end of the chain
+    fn exit_pos(&self) -> u32 {
+        if self.orientation == 0 {
+            self.te
+        } else {
+            self.ts + 1
+        }
+    }
+
+    // the breakend position (1-based) on the target at the query start of the chain
+    fn entry_pos(&self) -> u32 {
+        if self.orientation == 0 {
+            self.ts + 1
+        } else {
+            self.te
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+enum ChainSvType {
+    Inversion,
+    Translocation,
+    Duplication,
+    Breakend,
+}
+
+impl ChainSvType {
+    fn to_str(self) -> &'static str {
+        match self {
+            ChainSvType::Inversion => "INV",
+            ChainSvType::Translocation => "TRA",
+            ChainSvType::Duplication => "DUP",
+            ChainSvType::Breakend => "BND",
+        }
+    }
+}
+
+// the junction between two adjacent chains (on the query contig)
+struct Junction {
+    sv_type: ChainSvType,
+    high_conf: bool,
+    chain0: Chain,
+    chain1: Chain,
+    q_pos: u32,
+}
+
+fn classify_junction(
+    chain0: &Chain,
+    chain1: &Chain,
+    next_chain: Option<&Chain>,
+    prev_chain: Option<&Chain>,
+    args: &CmdOptions,
+) -> Option<(ChainSvType, bool)> {
+    let long_enough = std::cmp::min(chain0.q_span(), chain1.q_span())
+        >= args.high_conf_chain_length
+        && !chain0.q_dup
+        && !chain1.q_dup;
+    if chain0.t_name != chain1.t_name {
+        return Some((ChainSvType::Translocation, long_enough));
+    }
+    if chain0.orientation != chain1.orientation {
+        // a reciprocal inversion is supported by a chain on each side with the same orientation
+        let is_reciprocal = |c: Option<&Chain>, flanking: &Chain| {
+            c.map(|c| c.t_name == flanking.t_name && c.orientation == flanking.orientation)
+                .unwrap_or(false)
+        };
+        let reciprocal = is_reciprocal(next_chain, chain0) || is_reciprocal(prev_chain, chain1);
+        return Some((ChainSvType::Inversion, long_enough || reciprocal));
+    }
+    let overlap =
+        std::cmp::min(chain0.te, chain1.te) as i64 - std::cmp::max(chain0.ts, chain1.ts) as i64;
+    if overlap >= args.min_dup_length as i64 {
+        return Some((ChainSvType::Duplication, long_enough));
+    }
+    let jump = if chain0.orientation == 0 {
+        chain1.ts as i64 - chain0.te as i64
+    } else {
+        chain0.ts as i64 - chain1.te as i64
+    };
+    if jump < -(args.min_dup_length as i64) || jump >= args.min_jump_length as i64 {
+        return Some((ChainSvType::Breakend, long_enough));
+    }
+    None
+}
+
+// the ALT field of a VCF BND record for the junction from the breakend at the record to the mate
+// `base` is the reference base at the record
+// `joined_after` is true if the mate sequence is joined after the base of the record
+// `mate_extends_right` is true if the mate piece extends to the right of the mate position
+fn bnd_alt(
+    base: char,
+    joined_after: bool,
+    mate_extends_right: bool,
+    mate_name: &str,
+    mate_pos: u32,
+) -> String {
+    match (joined_after, mate_extends_right) {
+        (true, true) => format!("{}[{}:{}[", base, mate_name, mate_pos),
+        (true, false) => format!("{}]{}:{}]", base, mate_name, mate_pos),
+        (false, true) => format!("[{}:{}[{}", mate_name, mate_pos, base),
+        (false, false) => format!("]{}:{}]{}", mate_name, mate_pos, base),
+    }
+}
+
+// the chains of each query contig from the B and E records of an alnmap file, the chains shorter than
+// `min_chain_length` on the query are ignored
+fn load_query_chains<R: BufRead>(
+    alnmap_file: R,
+    min_chain_length: u32,
+) -> FxHashMap<String, Vec<Chain>> {
+    // collect the B and E records of each chain
+    let mut chain_bgn = FxHashMap::<String, Vec<String>>::default();
+    let mut chain_end = FxHashMap::<String, Vec<String>>::default();
+    alnmap_file.lines().for_each(|line| {
+        if let Ok(line) = line {
+            if line.trim().starts_with('#') {
+                return;
+            };
+            let fields = line.split('\t').map(|s| s.to_string()).collect::<Vec<_>>();
+            assert!(fields.len() > 3);
+            match fields[1].as_str() {
+                "B" => {
+                    chain_bgn.insert(fields[0].clone(), fields);
+                }
+                "E" => {
+                    chain_end.insert(fields[0].clone(), fields);
+                }
+                _ => (),
+            }
+        }
+    });
+
+    let mut query_chains = FxHashMap::<String, Vec<Chain>>::default();
+    chain_bgn.iter().for_each(|(aln_idx, b_fields)| {
+        let err_msg = format!("fail to parse on {}", b_fields.join("\t"));
+        let e_fields = chain_end.get(aln_idx).expect(&err_msg);
+        let t_name = b_fields[2].clone();
+        let ts = b_fields[3].parse::<u32>().expect(&err_msg);
+        let q_name = b_fields[5].clone();
+        let orientation = b_fields[8].parse::<u32>().expect(&err_msg);
+        let q_dup = b_fields[13] == "1";
+        let te = e_fields[4].parse::<u32>().expect(&err_msg);
+        let (qs, qe) = if orientation == 0 {
+            (
+                b_fields[6].parse::<u32>().expect(&err_msg),
+                e_fields[7].parse::<u32>().expect(&err_msg),
+            )
+        } else {
+            (
+                e_fields[6].parse::<u32>().expect(&err_msg),
+                b_fields[7].parse::<u32>().expect(&err_msg),
+            )
+        };
+        let chain = Chain {
+            aln_idx: aln_idx.clone(),
+            t_name,
+            ts,
+            te,
+            q_name: q_name.clone(),
+            qs,
+            qe,
+            orientation,
+            q_dup,
+        };
+        if chain.q_span() >= min_chain_length {
+            query_chains.entry(q_name).or_default().push(chain);
+        }
+    });
+    query_chains
+}
+
+// the junctions between the adjacent chains ordered by the query contig names and the query positions
+fn get_junctions(
+    mut query_chains: FxHashMap<String, Vec<Chain>>,
+    args: &CmdOptions,
+) -> Vec<Junction> {
+    let mut q_names = query_chains.keys().cloned().collect::<Vec<_>>();
+    q_names.sort();
+
+    let mut junctions = Vec::<Junction>::new();
+    q_names.iter().for_each(|q_name| {
+        let chains = query_chains.get_mut(q_name).unwrap();
+        chains.sort_by_key(|c| (c.qs, c.qe));
+        (1..chains.len()).for_each(|idx| {
+            let chain0 = &chains[idx - 1];
+            let chain1 = &chains[idx];
+            let prev_chain = if idx >= 2 { chains.get(idx - 2) } else { None };
+            let next_chain = chains.get(idx + 1);
+            if let Some((sv_type, high_conf)) =
+                classify_junction(chain0, chain1, next_chain, prev_chain, args)
+            {
+                junctions.push(Junction {
+                    sv_type,
+                    high_conf,
+                    chain0: chain0.clone(),
+                    chain1: chain1.clone(),
+                    q_pos: (chain0.qe + chain1.qs) >> 1,
+                });
+            }
+        });
+    });
+    junctions
+}
+
+fn main() -> Result<(), std::io::Error> {
+    CmdOptions::command().version(VERSION_STRING).get_matches();
+    let args = CmdOptions::parse();
+
+    let alnmap_file = BufReader::new(File::open(Path::new(&args.alnmap_path)).unwrap());
+    let query_chains = load_query_chains(alnmap_file, args.min_chain_length);
+    let junctions = get_junctions(query_chains, &args);
+
+    let mut ref_seqs = FxHashMap::<String, Vec<u8>>::default();
+    if let Some(reference_fasta_path) = args.reference_fasta_path.as_ref() {
+        let mut add_seqs = |seq_iter: &mut dyn Iterator<Item = io::Result<SeqRec>>| {
+            seq_iter.into_iter().for_each(|r| {
+                if let Ok(r) = r {
+                    ref_seqs.insert(String::from_utf8_lossy(&r.id[..]).to_string(), r.seq);
+                };
+            });
+        };
+        match get_fastx_reader(reference_fasta_path.clone(), true)? {
+            #[allow(clippy::useless_conversion)] // the into_iter() is necessary for dyn patching
+            GZFastaReader::GZFile(reader) => add_seqs(&mut reader.into_iter()),
+
+            #[allow(clippy::useless_conversion)] // the into_iter() is necessary for dyn patching
+            GZFastaReader::RegularFile(reader) => add_seqs(&mut reader.into_iter()),
+        };
+    }
+    // the reference base at a 1-based position
+    let get_base = |t_name: &str, pos: u32| {
+        ref_seqs
+            .get(t_name)
+            .and_then(|seq| seq.get(pos as usize - 1))
+            .map_or('N', |&b| b.to_ascii_uppercase() as char)
+    };
+
+    let mut out_bedpe = BufWriter::new(
+        File::create(Path::new(&args.output_prefix).with_extension("chainsv.bedpe"))
+            .expect("can't create the output file"),
+    );
+    writeln!(
+        out_bedpe,
+        "#chrom1\tstart1\tend1\tchrom2\tstart2\tend2\tname\tscore\tstrand1\tstrand2\tsv_type\tconfidence\tquery_name\tquery_pos\tsupporting_blocks"
+    )
+    .expect("fail to write the bedpe file");
+
+    let mut vcf_records = Vec::<(String, u32, String, char, String, String)>::new(); // t_name, pos, id, ref, alt, info
+    junctions.iter().enumerate().for_each(|(event_id, j)| {
+        let c0 = &j.chain0;
+        let c1 = &j.chain1;
+        let event_name = format!("CHAIN_{}_{:06}", j.sv_type.to_str(), event_id);
+        let conf = if j.high_conf { "HIGH" } else { "LOW" };
+        let pos0 = c0.exit_pos();
+        let pos1 = c1.entry_pos();
+        let strand = |orientation: u32| if orientation == 0 { '+' } else { '-' };
+        writeln!(
+            out_bedpe,
+            "{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{},{}",
+            c0.t_name,
+            pos0 - 1,
+            pos0,
+            c1.t_name,
+            pos1 - 1,
+            pos1,
+            event_name,
+            if j.high_conf { 60 } else { 10 },
+            strand(c0.orientation),
+            strand(c1.orientation),
+            j.sv_type.to_str(),
+            conf,
+            c0.q_name,
+            j.q_pos,
+            c0.aln_idx,
+            c1.aln_idx
+        )
+        .expect("fail to write the bedpe file");
+
+        let info = format!(
+            "SVTYPE=BND;EVENT={};EVENTTYPE={};CONF={};QNAME={};QPOS={};BLOCKS={},{}",
+            event_name,
+            j.sv_type.to_str(),
+            conf,
+            c0.q_name,
+            j.q_pos,
+            c0.aln_idx,
+            c1.aln_idx
+        );
+        // the query sequence leaves chain0 and enters chain1
+        let base0 = get_base(&c0.t_name, pos0);
+        let base1 = get_base(&c1.t_name, pos1);
+        let alt0 = bnd_alt(
+            base0,
+            c0.orientation == 0,
+            c1.orientation == 0,
+            &c1.t_name,
+            pos1,
+        );
+        let alt1 = bnd_alt(
+            base1,
+            c1.orientation != 0,
+            c0.orientation != 0,
+            &c0.t_name,
+            pos0,
+        );
+        vcf_records.push((
+            c0.t_name.clone(),
+            pos0,
+            format!("{}_1", event_name),
+            base0,
+            alt0,
+            format!("{};MATEID={}_2", info, event_name),
+        ));
+        vcf_records.push((
+            c1.t_name.clone(),
+            pos1,
+            format!("{}_2", event_name),
+            base1,
+            alt1,
+            format!("{};MATEID={}_1", info, event_name),
+        ));
+    });
+
+    let mut out_vcf = BufWriter::new(
+        File::create(Path::new(&args.output_prefix).with_extension("chainsv.vcf"))
+            .expect("can't create the output file"),
+    );
+    writeln!(out_vcf, "##fileformat=VCFv4.2").expect("fail to write the vcf file");
+    if let Some(target_len_json_path) = args.target_len_json_path.as_ref() {
+        let mut target_length_json_file = BufReader::new(
+            File::open(Path::new(target_len_json_path)).expect("can't open the input file"),
+        );
+        let mut buffer = Vec::new();
+        target_length_json_file.read_to_end(&mut buffer)?;
+        let mut target_length: TargetSeqLength =
+            serde_json::from_str(&String::from_utf8_lossy(&buffer[..]))
+                .expect("can't parse the target_len.json file");
+        target_length.sort();
+        target_length.iter().for_each(|(_, t_name, t_len)| {
+            writeln!(out_vcf, r#"##contig=<ID={},length={}>"#, t_name, t_len)
+                .expect("fail to write the vcf file");
+        });
+    }
+    [
+        r#"##ALT=<ID=BND,Description="Breakend">"#,
+        r#"##INFO=<ID=SVTYPE,Number=1,Type=String,Description="Type of structural variant">"#,
+        r#"##INFO=<ID=MATEID,Number=.,Type=String,Description="ID of mate breakends">"#,
+        r#"##INFO=<ID=EVENT,Number=1,Type=String,Description="ID of event associated to breakend">"#,
+        r#"##INFO=<ID=EVENTTYPE,Number=1,Type=String,Description="Type of the event from the chain structure: INV, TRA, DUP or BND">"#,
+        r#"##INFO=<ID=CONF,Number=1,Type=String,Description="Confidence of the event: HIGH or LOW">"#,
+        r#"##INFO=<ID=QNAME,Number=1,Type=String,Description="Query contig name">"#,
+        r#"##INFO=<ID=QPOS,Number=1,Type=Integer,Description="Approximate junction position on the query contig">"#,
+        r#"##INFO=<ID=BLOCKS,Number=.,Type=String,Description="Supporting alignment block ids in the alnmap file">"#,
+        r#"##FILTER=<ID=LowConf,Description="low confidence junction">"#,
+        r#"##FORMAT=<ID=GT,Number=1,Type=String,Description="Genotype">"#,
+    ]
+    .iter()
+    .for_each(|line| {
+        writeln!(out_vcf, "{}", line).expect("fail to write the vcf file");
+    });
+    writeln!(
+        out_vcf,
+        "#CHROM\tPOS\tID\tREF\tALT\tQUAL\tFILTER\tINFO\tFORMAT\t{}",
+        args.sample_name
+    )
+    .expect("fail to write the vcf file");
+
+    vcf_records.sort();
+    vcf_records
+        .into_iter()
+        .for_each(|(t_name, pos, id, ref_base, alt, info)| {
+            let filter = if info.contains("CONF=HIGH") {
+                "PASS"
+            } else {
+                "LowConf"
+            };
+            writeln!(
+                out_vcf,
+                "{}\t{}\t{}\t{}\t{}\t60\t{}\t{}\tGT\t1",
+                t_name, pos, id, ref_base, alt, filter, info
+            )
+            .expect("fail to write the vcf file");
+        });
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn test_args() -> CmdOptions {
+        CmdOptions::parse_from(["pgr-generate-chain-sv", "test.alnmap", "test"])
+    }
+
+    fn chain(
+        aln_idx: &str,
+        t_name: &str,
+        (ts, te): (u32, u32),
+        (qs, qe): (u32, u32),
+        orientation: u32,
+    ) -> Chain {
+        Chain {
+            aln_idx: aln_idx.to_string(),
+            t_name: t_name.to_string(),
+            ts,
+            te,
+            q_name: "ctg1".to_string(),
+            qs,
+            qe,
+            orientation,
+            q_dup: false,
+        }
+    }
+
+    /// the B and E records of a chain, the first and the last blocks cover 1kb each
+    fn alnmap_records(
+        aln_idx: u32,
+        t_name: &str,
+        (ts, te): (u32, u32),
+        (qs, qe): (u32, u32),
+        orientation: u32,
+    ) -> String {
+        let (b_q, e_q) = if orientation == 0 {
+            ((qs, qs + 1000), (qe - 1000, qe))
+        } else {
+            ((qe - 1000, qe), (qs, qs + 1000))
+        };
+        format!(
+            "{:06}\tB\t{}\t{}\t{}\tctg1\t{}\t{}\t{}\t300000\t0\t0\t0\t0\t0\n\
+             {:06}\tM\t{}\t{}\t{}\tctg1\t{}\t{}\t{}\t0\t0\t0\t0\n\
+             {:06}\tE\t{}\t{}\t{}\tctg1\t{}\t{}\t{}\t300000\t0\n",
+            aln_idx,
+            t_name,
+            ts,
+            ts + 1000,
+            b_q.0,
+            b_q.1,
+            orientation,
+            aln_idx,
+            t_name,
+            ts + 1000,
+            ts + 2000,
+            b_q.1,
+            b_q.1 + 1000,
+            orientation,
+            aln_idx,
+            t_name,
+            te - 1000,
+            te,
+            e_q.0,
+            e_q.1,
+            orientation
+        )
+    }
+
+    #[test]
+    fn test_bnd_alt() {
+        assert_eq!(bnd_alt('A', true, true, "chr2", 100), "A[chr2:100[");
+        assert_eq!(bnd_alt('A', true, false, "chr2", 100), "A]chr2:100]");
+        assert_eq!(bnd_alt('C', false, true, "chr2", 100), "[chr2:100[C");
+        assert_eq!(bnd_alt('C', false, false, "chr2", 100), "]chr2:100]C");
+    }
+
+    #[test]
+    fn test_classify_junction() {
+        let args = test_args();
+        let c0 = chain("0", "chr1", (0, 200_000), (0, 200_000), 0);
+
+        let c1 = chain("1", "chr2", (0, 200_000), (200_000, 400_000), 0);
+        assert_eq!(
+            classify_junction(&c0, &c1, None, None, &args),
+            Some((ChainSvType::Translocation, true))
+        );
+        let mut c1_dup = c1.clone();
+        c1_dup.q_dup = true;
+        assert_eq!(
+            classify_junction(&c0, &c1_dup, None, None, &args),
+            Some((ChainSvType::Translocation, false))
+        );
+
+        // a short inversion is high confidence only with the chain on the other side
+        let c1 = chain("1", "chr1", (200_000, 220_000), (200_000, 220_000), 1);
+        let c2 = chain("2", "chr1", (220_000, 400_000), (220_000, 400_000), 0);
+        assert_eq!(
+            classify_junction(&c0, &c1, None, None, &args),
+            Some((ChainSvType::Inversion, false))
+        );
+        assert_eq!(
+            classify_junction(&c0, &c1, Some(&c2), None, &args),
+            Some((ChainSvType::Inversion, true))
+        );
+
+        let c1 = chain("1", "chr1", (190_000, 400_000), (200_000, 410_000), 0);
+        assert_eq!(
+            classify_junction(&c0, &c1, None, None, &args),
+            Some((ChainSvType::Duplication, true))
+        );
+        let c1 = chain("1", "chr1", (2_200_000, 2_400_000), (200_000, 400_000), 0);
+        assert_eq!(
+            classify_junction(&c0, &c1, None, None, &args),
+            Some((ChainSvType::Breakend, true))
+        );
+        let c1 = chain("1", "chr1", (201_000, 400_000), (200_000, 399_000), 0);
+        assert_eq!(classify_junction(&c0, &c1, None, None, &args), None);
+    }
+
+    #[test]
+    fn test_chain_junctions() {
+        let args = test_args();
+        let alnmap = [
+            "#V\t0.1\n".to_string(),
+            alnmap_records(1, "chr1", (0, 100_000), (0, 100_000), 0),
+            alnmap_records(2, "chr1", (100_000, 150_000), (100_000, 150_000), 1),
+            alnmap_records(3, "chr1", (150_000, 300_000), (150_000, 300_000), 0),
+            // shorter than the min_chain_length
+            alnmap_records(4, "chr5", (0, 5_000), (300_000, 305_000), 0),
+        ]
+        .concat();
+        let query_chains = load_query_chains(alnmap.as_bytes(), args.min_chain_length);
+        assert_eq!(query_chains["ctg1"].len(), 3);
+        let junctions = get_junctions(query_chains, &args);
+        assert_eq!(junctions.len(), 2);
+        junctions.iter().for_each(|j| {
+            assert_eq!(j.sv_type, ChainSvType::Inversion);
+            assert!(j.high_conf);
+        });
+        // the reversed chain enters at its target end and exits at its target start
+        assert_eq!(
+            (
+                junctions[0].chain0.exit_pos(),
+                junctions[0].chain1.entry_pos()
+            ),
+            (100_000, 150_000)
+        );
+        assert_eq!(
+            (
+                junctions[1].chain0.exit_pos(),
+                junctions[1].chain1.entry_pos()
+            ),
+            (100_001, 150_001)
+        );
+        assert_eq!(
+            (
+                junctions[1].chain0.aln_idx.as_str(),
+                junctions[1].chain1.aln_idx.as_str()
+            ),
+            ("000002", "000003")
+        );
+    }
+}
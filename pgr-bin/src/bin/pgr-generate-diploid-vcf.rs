@@ -1,6 +1,7 @@
 const VERSION_STRING: &str = env!("VERSION_STRING");
 use clap::{self, CommandFactory, Parser};
 use iset::set::IntervalSet;
+use iset::IntervalMap;
 // use rayon::prelude::*;
 use rustc_hash::{FxHashMap, FxHashSet};
 use std::fs::File;
@@ -24,6 +25,12 @@ struct CmdOptions {
     /// the prefix of the output files
     #[clap(long, default_value = "Sample")]
     sample_name: String,
+    /// the minimum overlap on the reference between two contig alignments of the same haplotype to check the phase consistency
+    #[clap(long, default_value_t = 1000)]
+    min_conflict_overlap: u32,
+    /// the minimum number of discordant variant sites in an overlap to report a phase conflict
+    #[clap(long, default_value_t = 2)]
+    min_discordant_sites: usize,
     /// number of threads used in parallel (more memory usage), default to "0" using all CPUs available or the number set by RAYON_NUM_THREADS
     #[clap(long, default_value_t = 0)]
     number_of_thread: usize,
@@ -33,6 +40,8 @@ type TargetSeqLength = Vec<(u32, String, u32)>;
 
 type ShimmerMatchBlock = (String, u32, u32, String, u32, u32, u32);
 type VariantRecord = (String, u32, u32, u64, u8, String, String, String); //t_name, tc, tl, aln_block_id, hap_type, tvs, qvs, rec_type
+type ChainRecord = (String, u32, u32, String, u64, bool); //t_name, ts, te, q_name, aln_block_id, t_dup
+type PhaseBlock = (u32, u32, u32, u64, u64); // bgn, end, phase set, hap0 aln_block_id, hap1 aln_block_id
 
 fn main() -> Result<(), std::io::Error> {
     CmdOptions::command().version(VERSION_STRING).get_matches();
@@ -65,8 +74,11 @@ fn main() -> Result<(), std::io::Error> {
         Vec<VariantRecord>,
         FxHashMap<u64, Vec<ShimmerMatchBlock>>,
         FxHashMap<u64, Vec<ShimmerMatchBlock>>,
+        Vec<ChainRecord>,
     ) {
         let mut variant_records = Vec::<VariantRecord>::new();
+        let mut chain_bgn = FxHashMap::<u64, (String, u32, String, bool)>::default();
+        let mut chain_end = FxHashMap::<u64, u32>::default();
         let mut aln_blocks = FxHashMap::<u64, Vec<ShimmerMatchBlock>>::default();
         let mut unique_aln_blocks = FxHashMap::<u64, Vec<ShimmerMatchBlock>>::default();
 
@@ -78,8 +90,26 @@ fn main() -> Result<(), std::io::Error> {
                 let fields = line.split('\t').collect::<Vec<&str>>();
                 assert!(fields.len() > 3);
                 let rec_type = fields[1];
+                if rec_type == "B" {
+                    let err_msg = format!("fail to parse on {}", line);
+                    let aln_block_id = fields[0].parse::<u64>().expect(&err_msg);
+                    let t_name = fields[2];
+                    let ts = fields[3].parse::<u32>().expect(&err_msg);
+                    let q_name = fields[5];
+                    let t_dup = fields[11] == "1";
+                    chain_bgn.insert(
+                        aln_block_id,
+                        (t_name.to_string(), ts, q_name.to_string(), t_dup),
+                    );
+                } else if rec_type == "E" {
+                    let err_msg = format!("fail to parse on {}", line);
+                    let aln_block_id = fields[0].parse::<u64>().expect(&err_msg);
+                    let te = fields[4].parse::<u32>().expect(&err_msg);
+                    chain_end.insert(aln_block_id, te);
+                }
                 if rec_type.starts_with('V') {
-                    assert!(fields.len() == 15 || fields.len() == 17);
+                    // 18 fields with the tandem repeat annotation from `pgr-generate-sv-analysis`
+                    assert!(fields.len() == 15 || fields.len() == 17 || fields.len() == 18);
                     let err_msg = format!("fail to parse on {}", line);
                     let aln_block_id = fields[0].parse::<u64>().expect(&err_msg);
                     let t_name = fields[2];
@@ -142,13 +172,226 @@ fn main() -> Result<(), std::io::Error> {
                 }
             }
         });
-        (variant_records, aln_blocks, unique_aln_blocks)
+        let mut chains = chain_bgn
+            .into_iter()
+            .flat_map(|(aln_block_id, (t_name, ts, q_name, t_dup))| {
+                chain_end
+                    .get(&aln_block_id)
+                    .map(|&te| (t_name, ts, te, q_name, aln_block_id, t_dup))
+            })
+            .collect::<Vec<_>>();
+        chains.sort();
+        (variant_records, aln_blocks, unique_aln_blocks, chains)
     };
-    let (hap0_recs, hap0_aln_blocks, hap0_unique_aln_blocks) =
+    let (hap0_recs, hap0_aln_blocks, hap0_unique_aln_blocks, hap0_chains) =
         get_variant_recs(hap0_alnmap_file, 0);
-    let (hap1_recs, hap1_aln_blocks, hap1_unique_aln_blocks) =
+    let (hap1_recs, hap1_aln_blocks, hap1_unique_aln_blocks, hap1_chains) =
         get_variant_recs(hap1_alnmap_file, 1);
 
+    // the alleles carried by each contig alignment chain, keyed by (hap_type, aln_block_id)
+    let mut chain_alleles = FxHashMap::<(u8, u64), FxHashMap<u32, String>>::default();
+    hap0_recs.iter().chain(hap1_recs.iter()).for_each(
+        |(_t_name, tc, _tl, aln_block_id, ht, _tvs, qvs, _rt)| {
+            chain_alleles
+                .entry((*ht, *aln_block_id))
+                .or_default()
+                .insert(*tc, qvs.clone());
+        },
+    );
+
+    // phase blocks: the reference intervals covered by one contig alignment from each haplotype,
+    // the relative phase of the two haplotypes can only be trusted within such a block; the phase set
+    // is the (1-based) reference start of the hap0 contig alignment, as the hap0 alleles along one
+    // contig alignment stay on the same haplotype when the hap1 contig alignment changes
+    let mut phase_blocks = FxHashMap::<String, Vec<PhaseBlock>>::default();
+    hap0_chains
+        .iter()
+        .filter(|c| !c.5)
+        .for_each(|(t_name, ts0, te0, _, block_id0, _)| {
+            hap1_chains
+                .iter()
+                .filter(|c| !c.5 && c.0 == *t_name)
+                .for_each(|(_, ts1, te1, _, block_id1, _)| {
+                    let bgn = std::cmp::max(*ts0, *ts1);
+                    let end = std::cmp::min(*te0, *te1);
+                    if bgn < end {
+                        phase_blocks.entry(t_name.clone()).or_default().push((
+                            bgn,
+                            end,
+                            ts0 + 1,
+                            *block_id0,
+                            *block_id1,
+                        ));
+                    }
+                });
+        });
+    phase_blocks.values_mut().for_each(|blocks| blocks.sort());
+
+    // phase conflicts: two contig alignments from the same haplotype overlapping on the reference
+    // with different alleles, if one of them carries the alleles of the other haplotype, it is
+    // likely a haplotype switch
+    let mut phase_conflicts = Vec::<(String, u32, u32, String)>::new();
+    let mut conflict_intervals = FxHashMap::<String, IntervalSet<u32>>::default();
+    [
+        (0_u8, &hap0_chains, &hap1_chains),
+        (1, &hap1_chains, &hap0_chains),
+    ]
+    .iter()
+    .for_each(|(ht, chains, other_chains)| {
+        // "*" for the reference allele
+        let get_allele = |ht: u8, block_id: u64, pos: u32| -> String {
+            chain_alleles
+                .get(&(ht, block_id))
+                .and_then(|alleles| alleles.get(&pos))
+                .cloned()
+                .unwrap_or_else(|| "*".to_string())
+        };
+        chains.iter().enumerate().for_each(|(idx, c0)| {
+            chains[idx + 1..]
+                .iter()
+                .take_while(|c1| c1.0 == c0.0 && c1.1 < c0.2)
+                .for_each(|c1| {
+                    let bgn = c1.1;
+                    let end = std::cmp::min(c0.2, c1.2);
+                    if end < bgn + args.min_conflict_overlap {
+                        return;
+                    }
+                    // the contig alignment from the other haplotype covering most of the overlap
+                    let other = other_chains
+                        .iter()
+                        .filter(|c| c.0 == c0.0 && c.1 < end && c.2 > bgn)
+                        .max_by_key(|c| std::cmp::min(c.2, end) - std::cmp::max(c.1, bgn));
+                    let mut sites = FxHashSet::<u32>::default();
+                    [(*ht, c0.4), (*ht, c1.4)]
+                        .into_iter()
+                        .chain(other.map(|c| (1 - *ht, c.4)))
+                        .for_each(|key| {
+                            if let Some(alleles) = chain_alleles.get(&key) {
+                                sites
+                                    .extend(alleles.keys().filter(|&&pos| pos >= bgn && pos < end));
+                            }
+                        });
+                    let mut n_discordant = 0_usize;
+                    let mut n_c0_switch = 0_usize;
+                    let mut n_c1_switch = 0_usize;
+                    sites.into_iter().for_each(|pos| {
+                        let a0 = get_allele(*ht, c0.4, pos);
+                        let a1 = get_allele(*ht, c1.4, pos);
+                        if a0 == a1 {
+                            return;
+                        }
+                        n_discordant += 1;
+                        if let Some(other) = other {
+                            let a_other = get_allele(1 - *ht, other.4, pos);
+                            if a0 == a_other {
+                                n_c0_switch += 1;
+                            }
+                            if a1 == a_other {
+                                n_c1_switch += 1;
+                            }
+                        }
+                    });
+                    let conflict_type = if n_discordant < args.min_discordant_sites {
+                        "CONSISTENT"
+                    } else if 2 * std::cmp::max(n_c0_switch, n_c1_switch) >= n_discordant {
+                        "SWITCH"
+                    } else {
+                        "DISCORDANT"
+                    };
+                    if conflict_type != "CONSISTENT" {
+                        conflict_intervals
+                            .entry(c0.0.clone())
+                            .or_default()
+                            .insert(bgn..end);
+                    }
+                    phase_conflicts.push((
+                        c0.0.clone(),
+                        bgn,
+                        end,
+                        format!(
+                            "{}:H{}:{}:{}:{}:{}:{}",
+                            conflict_type, ht, c0.3, c1.3, n_discordant, n_c0_switch, n_c1_switch
+                        ),
+                    ));
+                });
+        });
+    });
+    phase_conflicts.sort();
+
+    let mut out_phase_block_bed = BufWriter::new(
+        File::create(Path::new(&args.output_prefix).with_extension("phase_blocks.bed")).unwrap(),
+    );
+    let mut t_names = phase_blocks.keys().cloned().collect::<Vec<_>>();
+    t_names.sort();
+    let hap0_chain_names = hap0_chains
+        .iter()
+        .map(|c| (c.4, c.3.clone()))
+        .collect::<FxHashMap<_, _>>();
+    let hap1_chain_names = hap1_chains
+        .iter()
+        .map(|c| (c.4, c.3.clone()))
+        .collect::<FxHashMap<_, _>>();
+    t_names.iter().for_each(|t_name| {
+        phase_blocks.get(t_name).unwrap().iter().for_each(
+            |(bgn, end, phase_set, block_id0, block_id1)| {
+                writeln!(
+                    out_phase_block_bed,
+                    "{}\t{}\t{}\tPS:{}:{}:{}",
+                    t_name,
+                    bgn,
+                    end,
+                    phase_set,
+                    hap0_chain_names.get(block_id0).unwrap(),
+                    hap1_chain_names.get(block_id1).unwrap()
+                )
+                .expect("fail to write the phase block bed file");
+            },
+        );
+    });
+
+    let mut out_phase_conflict_bed = BufWriter::new(
+        File::create(Path::new(&args.output_prefix).with_extension("phase_conflicts.bed")).unwrap(),
+    );
+    phase_conflicts
+        .iter()
+        .for_each(|(t_name, bgn, end, annotation)| {
+            writeln!(
+                out_phase_conflict_bed,
+                "{}\t{}\t{}\t{}",
+                t_name, bgn, end, annotation
+            )
+            .expect("fail to write the phase conflict bed file");
+        });
+
+    // assign the phase set to a genotype, a genotype not in a single phase block or in a region with
+    // a phase conflict is reported as unphased
+    let phase_set_intervals = phase_blocks
+        .iter()
+        .map(|(t_name, blocks)| {
+            let mut intervals = IntervalMap::<u32, u32>::new();
+            blocks.iter().for_each(|(bgn, end, phase_set, _, _)| {
+                intervals.insert(*bgn..*end, *phase_set);
+            });
+            (t_name.clone(), intervals)
+        })
+        .collect::<FxHashMap<_, _>>();
+    let phase_genotype = |ref_name: &String, ts0: u32, tl0: u32, gt: String| -> (String, String) {
+        let in_conflict = conflict_intervals
+            .get(ref_name)
+            .map(|i_set| i_set.has_overlap(ts0..ts0 + tl0))
+            .unwrap_or(false);
+        let phase_set = phase_set_intervals.get(ref_name).and_then(|intervals| {
+            intervals
+                .iter(ts0..ts0 + tl0)
+                .find(|(range, _)| range.start <= ts0 && ts0 + tl0 <= range.end)
+                .map(|(_, &phase_set)| phase_set)
+        });
+        match phase_set {
+            Some(ps) if !in_conflict && !gt.contains('.') => (gt, format!("{}", ps)),
+            _ => (gt.replace('|', "/"), ".".to_string()),
+        }
+    };
+
     let blocks_to_intervals =
         |blocks: FxHashMap<u64, Vec<ShimmerMatchBlock>>| -> FxHashMap<String, IntervalSet<u32>> {
             let mut aln_intervals = FxHashMap::<String, IntervalSet<u32>>::default();
@@ -195,6 +438,11 @@ fn main() -> Result<(), std::io::Error> {
         r#"##FORMAT=<ID=GT,Number=1,Type=String,Description="Genotype">"#
     )
     .expect("fail to write the vcf file");
+    writeln!(
+        out_vcf,
+        r#"##FORMAT=<ID=PS,Number=1,Type=Integer,Description="Phase set">"#
+    )
+    .expect("fail to write the vcf file");
 
     writeln!(
         out_vcf,
@@ -352,7 +600,8 @@ fn main() -> Result<(), std::io::Error> {
             ".".to_string()
         };
         let gt = [h0_al_idx, h1_al_idx].join("|");
-        (ref_name, ts0, ref_str, query_alleles, gt, rec_type)
+        let (gt, ps) = phase_genotype(&ref_name, ts0, tl0, gt);
+        (ref_name, ts0, ref_str, query_alleles, gt, ps, rec_type)
     };
 
     let mut variant_records = Vec::<VariantRecord>::new();
@@ -382,7 +631,7 @@ fn main() -> Result<(), std::io::Error> {
                     ));
                 } else if !variant_group.is_empty() {
                     //println!("X {} {} {} {} {:?}", ref_name, ts, tl, variant_group.len(), variant_group);
-                    let (vcf_rec_ref_name, ts0, ref_str, query_alleles, gt, g_rec_type) =
+                    let (vcf_rec_ref_name, ts0, ref_str, query_alleles, gt, ps, g_rec_type) =
                         convert_to_vcf_record(&mut variant_group);
                     let rt = if let Some(g_rec_type) = g_rec_type {
                         if g_rec_type == "V_D" {
@@ -406,7 +655,7 @@ fn main() -> Result<(), std::io::Error> {
                     //);
                     writeln!(
                         out_vcf,
-                        "{}\t{}\t.\t{}\t{}\t{}\t{}\t.\tGT\t{}",
+                        "{}\t{}\t.\t{}\t{}\t{}\t{}\t.\tGT:PS\t{}:{}",
                         vcf_rec_ref_name,
                         ts0 + 1,
                         ref_str,
@@ -414,6 +663,7 @@ fn main() -> Result<(), std::io::Error> {
                         qv,
                         rt,
                         gt,
+                        ps,
                     )
                     .expect("fail to write the vcf file");
                     variant_group.clear();
@@ -447,7 +697,7 @@ fn main() -> Result<(), std::io::Error> {
     );
     if !variant_group.is_empty() {
         // println!("X {} {} {} {}", ref_name, ts, tl, variant_group.len());
-        let (vcf_rec_ref_name, ts0, ref_str, query_alleles, gt, g_rec_type) =
+        let (vcf_rec_ref_name, ts0, ref_str, query_alleles, gt, ps, g_rec_type) =
             convert_to_vcf_record(&mut variant_group);
         let rt = if let Some(g_rec_type) = g_rec_type {
             if g_rec_type == "V_D" {
@@ -463,7 +713,7 @@ fn main() -> Result<(), std::io::Error> {
         let qv: u32 = if rt != "PASS" { 30 } else { 40 };
         writeln!(
             out_vcf,
-            "{}\t{}\t.\t{}\t{}\t{}\t{}\t.\tGT\t{}",
+            "{}\t{}\t.\t{}\t{}\t{}\t{}\t.\tGT:PS\t{}:{}",
             vcf_rec_ref_name,
             ts0 + 1,
             ref_str,
@@ -471,6 +721,7 @@ fn main() -> Result<(), std::io::Error> {
             qv,
             rt,
             gt,
+            ps,
         )
         .expect("fail to write the vcf file");
     };
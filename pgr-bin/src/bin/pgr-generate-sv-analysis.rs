@@ -2,7 +2,10 @@ const VERSION_STRING: &str = env!("VERSION_STRING");
 use clap::{self, CommandFactory, Parser};
 // use rayon::prelude::*;
 use pgr_db::aln;
-use pgr_db::ext::{get_principal_bundle_decomposition, SeqIndexDB};
+use pgr_db::ext::{
+    get_principal_bundle_decomposition, group_smps_by_principal_bundle_id, SeqIndexDB,
+};
+use pgr_db::tandem_repeat;
 use rustc_hash::FxHashMap;
 use serde::*;
 use std::fs::File;
@@ -25,9 +28,21 @@ struct CmdOptions {
     /// set the flag to generate long indel calls, but it might break some VCF file parsing
     #[clap(long, default_value_t = false)]
     large_indel_call: bool,
+    /// refine the breakpoints of the SV candidates that can not be aligned directly to the base resolution
+    #[clap(long, default_value_t = false)]
+    refine_breakpoints: bool,
     /// number of threads used in parallel (more memory usage), default to "0" using all CPUs available or the number set by RAYON_NUM_THREADS
     #[clap(long, default_value_t = 0)]
     number_of_thread: usize,
+    /// annotate the variants inside tandem repeats, the "RU=...;RC=...;TR=..." annotation is appended to the V records as an extra column
+    #[clap(long, default_value_t = false)]
+    tr_annotation: bool,
+    /// represent the repeat length changes as single alleles spanning the whole tandem repeats in the V records
+    #[clap(long, default_value_t = false)]
+    tr_collapse: bool,
+    /// the maximum period of the tandem repeats for the annotation
+    #[clap(long, default_value_t = 100)]
+    tr_max_period: usize,
 }
 
 #[derive(Debug)]
@@ -225,85 +240,6 @@ fn align_bundles(
     )
 }
 
-#[allow(clippy::type_complexity)]
-fn group_smps_by_principle_bundle_id(
-    smps: &[((u64, u64, u32, u32, u8), Option<(usize, u8, usize)>)],
-    bundle_length_cutoff: usize,
-    bundle_merge_distance: usize,
-) -> Vec<Vec<((u64, u64, u32, u32, u8), usize, u32, usize)>> {
-    let mut pre_bundle_id: Option<usize> = None;
-    let mut pre_direction: Option<u32> = None;
-    let mut all_partitions = vec![];
-    let mut new_partition = vec![];
-    smps.iter().for_each(|&(smp, bundle_info)| {
-        if bundle_info.is_none() {
-            return;
-        };
-        let bundle_info = bundle_info.unwrap();
-        let d = if smp.4 == bundle_info.1 { 0_u32 } else { 1_u32 };
-        let bid = bundle_info.0;
-        let bpos = bundle_info.2;
-        if pre_bundle_id.is_none() {
-            new_partition.clear();
-            new_partition.push((smp, bid, d, bpos));
-            pre_bundle_id = Some(bid);
-            pre_direction = Some(d);
-            return;
-        };
-        if bid != pre_bundle_id.unwrap() || d != pre_direction.unwrap() {
-            let l = new_partition.len();
-            if new_partition[l - 1].0 .3 as usize - new_partition[0].0 .2 as usize
-                > bundle_length_cutoff
-            {
-                all_partitions.push(new_partition.clone());
-                new_partition.clear();
-            } else {
-                new_partition.clear();
-            };
-            pre_bundle_id = Some(bid);
-            pre_direction = Some(d);
-        };
-        new_partition.push((smp, bid, d, bpos));
-    });
-    let l = new_partition.len();
-    if l > 0
-        && new_partition[l - 1].0 .3 as usize - new_partition[0].0 .2 as usize
-            > bundle_length_cutoff
-    {
-        all_partitions.push(new_partition);
-    };
-
-    let mut rtn_partitions = vec![];
-
-    if all_partitions.is_empty() {
-        return rtn_partitions;
-    }
-    let mut partition = all_partitions[0].clone();
-    (1..all_partitions.len()).for_each(|idx| {
-        let p = all_partitions[idx].clone();
-        let p_len = partition.len();
-        let p_end = partition[p_len - 1].0 .3;
-        let p_bid = partition[p_len - 1].1;
-        let p_d = partition[p_len - 1].2;
-        let np_bgn = p[0].0 .2;
-        let np_bid = p[0].1;
-        let np_d = p[0].2;
-        if p_bid == np_bid
-            && p_d == np_d
-            && (np_bgn as i64 - p_end as i64).abs() < bundle_merge_distance as i64
-        {
-            partition.extend(p);
-        } else {
-            rtn_partitions.push(partition.clone());
-            partition = p;
-        }
-    });
-    if !partition.is_empty() {
-        rtn_partitions.push(partition);
-    }
-    rtn_partitions
-}
-
 fn get_wf_aln_diff(s0str: &[u8], s1str: &[u8]) -> AlnDiff {
     let wf_aln_diff: AlnDiff = if s0str.is_empty() || s1str.is_empty() {
         AlnDiff::FailShortSeq
@@ -331,6 +267,22 @@ fn get_sw_aln_diff(s0str: &[u8], s1str: &[u8]) -> AlnDiff {
     wfa_aln_diff
 }
 
+fn get_refined_aln_diff(s0str: &[u8], s1str: &[u8]) -> AlnDiff {
+    if let Some(aln_res) = aln::refine_sv_breakpoints(
+        s0str,
+        s1str,
+        aln::SV_REFINE_ANCHOR_K,
+        aln::SV_REFINE_MAX_ANCHOR_GAP,
+        aln::SV_REFINE_MISMATCH_PENALTY,
+        aln::SV_REFINE_OPEN_PENALTY,
+        aln::SV_REFINE_EXTENSION_PENALTY,
+    ) {
+        AlnDiff::Aligned(aln_res)
+    } else {
+        AlnDiff::FailAln
+    }
+}
+
 fn aln_diff_to_records(
     rec: &CandidateRecord,
     diff: AlnDiff,
@@ -440,6 +392,112 @@ fn aln_diff_to_records(
     aln_block_records
 }
 
+/// annotate the variant records inside the tandem repeats of the target sequence of an SV candidate,
+/// and optionally collapse the repeat length changes, the identical collapsed variants are removed
+fn annotate_repeat_variants(
+    rec: &CandidateRecord,
+    records: &[Record],
+    args: &CmdOptions,
+) -> Vec<(Record, Option<String>)> {
+    let mut annotated_records = Vec::<(Record, Option<String>)>::new();
+    records.iter().for_each(|record| {
+        let annotated_record = match record.clone() {
+            Record::Variant(match_block, td, qd, tc, vt, tvs, qvs, target_path, query_path)
+                if args.tr_annotation || args.tr_collapse =>
+            {
+                let t_seq = &rec.target_sequence[..];
+                let pos = (tc - rec.ts) as usize;
+                let tvs = tvs.trim_end_matches('-');
+                let qvs = qvs.trim_end_matches('-');
+                if let Some(annotation) = tandem_repeat::annotate_variant(
+                    t_seq,
+                    pos,
+                    tvs.as_bytes(),
+                    qvs.as_bytes(),
+                    args.tr_max_period,
+                    2.0,
+                    6,
+                ) {
+                    let info = format!(
+                        "RU={};RC={:.1},{:.1};TR={},{}",
+                        String::from_utf8_lossy(&annotation.repeat.unit[..]),
+                        annotation.ref_copy_number,
+                        annotation.alt_copy_number,
+                        annotation.repeat.bgn as u32 + rec.ts + 1,
+                        annotation.repeat.end as u32 + rec.ts
+                    );
+                    let collapsed = tandem_repeat::collapse_repeat_variant(
+                        t_seq,
+                        &annotation,
+                        pos,
+                        tvs.as_bytes(),
+                        qvs.as_bytes(),
+                    )
+                    .filter(|_| args.tr_collapse);
+                    // the collapsed variant is moved to the left, it has to stay inside the alignment block on both sequences
+                    let (td, qd, tc, tvs, qvs) = match collapsed {
+                        Some((c_pos, c_tvs, c_qvs))
+                            if c_pos as u32 + rec.ts >= tc.saturating_sub(td.min(qd)) =>
+                        {
+                            let shift = tc - (c_pos as u32 + rec.ts);
+                            (
+                                td - shift,
+                                qd - shift,
+                                tc - shift,
+                                String::from_utf8_lossy(&c_tvs[..]).to_string(),
+                                String::from_utf8_lossy(&c_qvs[..]).to_string(),
+                            )
+                        }
+                        _ => (td, qd, tc, tvs.to_string(), qvs.to_string()),
+                    };
+                    (
+                        Record::Variant(
+                            match_block,
+                            td,
+                            qd,
+                            tc,
+                            vt,
+                            tvs,
+                            qvs,
+                            target_path,
+                            query_path,
+                        ),
+                        Some(info),
+                    )
+                } else {
+                    (
+                        Record::Variant(
+                            match_block,
+                            td,
+                            qd,
+                            tc,
+                            vt,
+                            tvs.to_string(),
+                            qvs.to_string(),
+                            target_path,
+                            query_path,
+                        ),
+                        Some(".".to_string()),
+                    )
+                }
+            }
+            record => (record, None),
+        };
+        if let (Record::Variant(_, _, _, tc, _, tvs, qvs, _, _), _) = &annotated_record {
+            if args.tr_collapse
+                && annotated_records.iter().any(|(r, _)| {
+                    matches!(r, Record::Variant(_, _, _, tc0, _, tvs0, qvs0, _, _)
+                        if tc0 == tc && tvs0 == tvs && qvs0 == qvs)
+                })
+            {
+                return;
+            }
+        }
+        annotated_records.push(annotated_record);
+    });
+    annotated_records
+}
+
 fn aln_segments(
     ts: usize,
     te: usize,
@@ -448,7 +506,7 @@ fn aln_segments(
     rec: &CandidateRecord,
     target_bundle_path: &str,
     query_bundle_path: &str,
-    _args: &CmdOptions,
+    args: &CmdOptions,
 ) -> Vec<Record> {
     let target_name = &rec.target_name;
     let query_name = &rec.query_name;
@@ -474,6 +532,12 @@ fn aln_segments(
             AlnDiff::FailAln
         };
 
+    let diff = if args.refine_breakpoints && diff == AlnDiff::FailAln {
+        get_refined_aln_diff(target_seg_sequence, query_seg_sequence)
+    } else {
+        diff
+    };
+
     // println!("XX: {:?}", diff);
     aln_diff_to_records(
         rec,
@@ -563,7 +627,7 @@ fn get_aln_block_records(rec: &CandidateRecord, args: &CmdOptions) -> Vec<Vec<Re
         let (_ctg, _src, _len) = sdata;
         let smps = sid_smps.get(sid).unwrap();
         let smp_partitions =
-            group_smps_by_principle_bundle_id(smps, bundle_length_cutoff, bundle_merge_distance);
+            group_smps_by_principal_bundle_id(smps, bundle_length_cutoff, bundle_merge_distance);
         let mut ctg_bundle_count = FxHashMap::<usize, usize>::default();
         smp_partitions.iter().for_each(|p| {
             let bid = p[0].1;
@@ -791,7 +855,10 @@ fn main() -> Result<(), std::io::Error> {
     );
 
     paired_seq_records.into_iter().enumerate().for_each(|(pair_id, rec)| {
-        let aln_block_records = get_aln_block_records(&rec, &args);
+        let aln_block_records = get_aln_block_records(&rec, &args)
+            .iter()
+            .map(|records| annotate_repeat_variants(&rec, records, &args))
+            .collect::<Vec<_>>();
 
         writeln!(
             outpu_alnmap_file,
@@ -816,7 +883,7 @@ fn main() -> Result<(), std::io::Error> {
             .enumerate()
             .for_each(|(sub_block_id, records)| {
                 let block_id = (((pair_id + 1) as u64) << 32) | sub_block_id as u64; 
-                records.iter().for_each(|record| {
+                records.iter().for_each(|(record, tr_info)| {
                     let rec_out = match record.clone() {
                         Record::Match(
                             (tn, ts, te, qn, qs, qe, orientation),
@@ -928,6 +995,12 @@ fn main() -> Result<(), std::io::Error> {
                         }
                         _ => None,
                     };
+                    let rec_out = match (rec_out, tr_info) {
+                        (Some(rec_out), Some(tr_info)) => {
+                            Some(format!("{}\t{}", rec_out, tr_info))
+                        }
+                        (rec_out, _) => rec_out,
+                    };
                     if let Some(rec_out) = rec_out {
                         writeln!(outpu_alnmap_file, "{}", rec_out)
                             .expect("can't write the alnmap output file");
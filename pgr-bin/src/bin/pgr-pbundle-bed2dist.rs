@@ -1,6 +1,9 @@
 const VERSION_STRING: &str = env!("VERSION_STRING");
 use clap::{self, CommandFactory, Parser};
-use kodama::{linkage, Method};
+use pgr_db::pbundle::{
+    get_bundle_dendrogram, get_bundle_distances, neighbor_joining, BundleDistanceMethod,
+    BundleSegment,
+};
 use rustc_hash::FxHashMap;
 use std::io::{BufRead, BufReader, BufWriter, Write};
 use std::path::Path;
@@ -18,144 +21,26 @@ struct CmdOptions {
     output_prefix: String,
     /// using local alignment
     #[clap(long, short, default_value_t = false)]
-    local_aln: bool
-}
-
-#[derive(PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Debug)]
-struct BundleSegment {
-    bgn: u32,
-    end: u32,
-    bundle_id: u32,
-    bundle_v_count: u32,
-    bundle_dir: u32,
-    bundle_v_bgn: u32,
-    bundle_v_end: u32,
-}
-
-#[derive(Clone, Copy, Debug)]
-enum AlnType {
-    Match,
-    Insertion,
-    Deletion,
-}
-
-fn align_bundles(
-    q_bundles: &[BundleSegment],
-    t_bundles: &[BundleSegment],
     local_aln: bool,
-) -> (f32, usize, usize, i64, isize) {
-    let q_count = q_bundles.len();
-    let t_count = t_bundles.len();
-    let mut s_map = FxHashMap::<(usize, usize), i64>::default();
-    let mut t_map = FxHashMap::<(usize, usize), AlnType>::default();
-
-    let mut get_aln_direction_with_best_score =
-        |q_idx: usize, t_idx: usize, s_map: &FxHashMap<(usize, usize), i64>| -> (AlnType, i64) {
-            let mut best = (AlnType::Match, i64::MIN);
-            let q_len = (q_bundles[q_idx].end as i64 - q_bundles[q_idx].bgn as i64).abs();
-            let t_len = (t_bundles[t_idx].end as i64 - t_bundles[t_idx].bgn as i64).abs();
-            let min_len = if q_len > t_len { t_len } else { q_len };
-            let q_b_seg = q_bundles[q_idx];
-            let t_b_seg = t_bundles[t_idx];
-            if q_idx == 0
-                && t_idx == 0
-                && (q_b_seg.bundle_id == t_b_seg.bundle_id)
-                && (q_b_seg.bundle_dir == t_b_seg.bundle_dir)
-            {
-                best = (AlnType::Match, 2 * min_len)
-            };
-            if q_idx > 0
-                && t_idx > 0
-                && q_b_seg.bundle_id == t_b_seg.bundle_id
-                && (q_b_seg.bundle_dir == t_b_seg.bundle_dir)
-            {
-                best = (
-                    AlnType::Match,
-                    2 * min_len + s_map.get(&(q_idx - 1, t_idx - 1)).unwrap(),
-                )
-            };
-            if t_idx > 0 {
-                let score = -2 * q_len + s_map.get(&(q_idx, t_idx - 1)).unwrap();
-                if score > best.1 {
-                    best = (AlnType::Deletion, score)
-                };
-            };
-            if q_idx > 0 {
-                let score = -2 * t_len + s_map.get(&(q_idx - 1, t_idx)).unwrap();
-                if score > best.1 {
-                    best = (AlnType::Insertion, score)
-                }
-            }
-            t_map.insert((q_idx, t_idx), best.0);
-            best
-        };
-
-    let mut best_score = 0;
-    let mut best_q_idx = 0;
-    let mut best_t_idx = 0;
-
-    (0..t_count)
-        .flat_map(|t_idx| (0..q_count).map(move |q_idx| (q_idx, t_idx)))
-        .for_each(|(q_idx, t_idx)| {
-            //println!("{} {}", q_idx, t_idx);
-            let (_, score) = get_aln_direction_with_best_score(q_idx, t_idx, &s_map);
-
-            let score = if local_aln && score < 0 { 0 } else { score };
-
-            s_map.insert((q_idx, t_idx), score);
-
-            if score > best_score {
-                best_score = score;
-                best_q_idx = q_idx;
-                best_t_idx = t_idx;
-            }
-        });
-    let mut q_idx = if local_aln {best_q_idx} else {q_count - 1};
-    let mut t_idx = if local_aln {best_t_idx} else {t_count - 1};
-    let offset = q_bundles[q_idx].bgn as isize - t_bundles[t_idx].bgn as isize; 
-    let mut diff_len = 0_usize;
-    let mut max_len = 1_usize;
-    while let Some(aln_type) = t_map.get(&(q_idx, t_idx)) {
-        // let qq_idx = q_idx;
-        // let tt_idx = t_idx;
-        if local_aln && *s_map.get(&(q_idx, t_idx)).unwrap_or(&0) == 0 {
-            break
-        }
-        let (diff_len_delta, max_len_delta) = match aln_type {
-            AlnType::Match => {
-                let q_len = (q_bundles[q_idx].end as i64 - q_bundles[q_idx].bgn as i64).abs();
-                let t_len = (t_bundles[t_idx].end as i64 - t_bundles[t_idx].bgn as i64).abs();
-                let diff_len_delta = (q_len - t_len).unsigned_abs() as usize;
-                let max_len_delta = if q_len > t_len {
-                    q_len as usize
-                } else {
-                    t_len as usize
-                };
-                q_idx -= 1;
-                t_idx -= 1;
-                (diff_len_delta, max_len_delta)
-            }
-            AlnType::Insertion => {
-                let q_len = (q_bundles[q_idx].end as i64 - q_bundles[q_idx].bgn as i64).abs();
-                q_idx -= 1;
-                (q_len as usize, q_len as usize)
-            }
-            AlnType::Deletion => {
-                let t_len = (t_bundles[t_idx].end as i64 - t_bundles[t_idx].bgn as i64).abs();
-                t_idx -= 1;
-                (t_len as usize, t_len as usize)
-            }
-        };
-        diff_len += diff_len_delta;
-        max_len += max_len_delta;
-        /*
-        println!(
-            "{} {} {:?} {:?} {:?} {} {}",
-            qq_idx, tt_idx, aln_type, q_bundles[qq_idx].bundle_id, t_bundles[tt_idx].bundle_id, diff_len_delta, max_len_delta
-        );
-            */
-    }
-    (diff_len as f32 / max_len as f32, diff_len, max_len, best_score, offset)
+    /// estimate the distances as one minus the weighted Jaccard index of the bundle segment tokens with MinHash sketches, instead of aligning the bundles,
+    /// the columns of the dist file are then the distance, the number of the unshared and all hashes in the union sketch, the number of the shared hashes and a zero offset
+    #[clap(long, default_value_t = false, conflicts_with = "local_aln")]
+    sketch: bool,
+    /// the number of the consecutive bundle segments in a token for the sketch mode
+    #[clap(long, default_value_t = 2)]
+    sketch_token_size: usize,
+    /// the number of the minimum hash values kept in a sketch
+    #[clap(long, default_value_t = 1024)]
+    sketch_size: usize,
+    /// the length of a weight unit of the tokens, a token is weighted by the total length of its segments in the units
+    #[clap(long, default_value_t = 100)]
+    sketch_weight_unit: u32,
+    /// output the distance matrix in the (relaxed) PHYLIP format
+    #[clap(long, default_value_t = false)]
+    phylip: bool,
+    /// output a neighbor-joining tree in addition to the hierarchical clustering tree
+    #[clap(long, default_value_t = false)]
+    nj_tree: bool,
 }
 
 fn main() -> Result<(), std::io::Error> {
@@ -206,197 +91,75 @@ fn main() -> Result<(), std::io::Error> {
         .collect::<Vec<_>>();
 
     ctg_data.sort();
-    let n_ctg = ctg_data.len();
+    let (ctg_names, ctg_bundles): (Vec<_>, Vec<_>) = ctg_data.into_iter().unzip();
+
+    let method = if args.sketch {
+        BundleDistanceMethod::Sketch {
+            token_size: args.sketch_token_size,
+            sketch_size: args.sketch_size,
+            weight_unit: args.sketch_weight_unit,
+        }
+    } else if args.local_aln {
+        BundleDistanceMethod::LocalAlignment
+    } else {
+        BundleDistanceMethod::GlobalAlignment
+    };
+    let distances = get_bundle_distances(&ctg_bundles, method);
 
     let out_path = Path::new(&args.output_prefix).with_extension("dist");
     let mut out_file = BufWriter::new(File::create(out_path).expect("can't create the dist file"));
+    distances
+        .write_dist(&mut out_file, &ctg_names)
+        .expect("writing error");
+
+    if args.phylip {
+        let mut phylip_file = BufWriter::new(
+            File::create(Path::new(&args.output_prefix).with_extension("phy"))
+                .expect("can't create the phylip file"),
+        );
+        distances
+            .write_phylip(&mut phylip_file, &ctg_names)
+            .expect("can't write the phylip file");
+    }
 
-    let mut dist_map = FxHashMap::<(usize, usize), f32>::default();
-    let mut offset_map = FxHashMap::<(usize, usize), isize>::default();
-    let mut min_dist = 0.0_f32;
-    let mut max_dist = 1.0_f32;
-    (0..n_ctg)
-        .flat_map(|ctg_idx0| (0..n_ctg).map(move |ctg_idx1| (ctg_idx0, ctg_idx1)))
-        .for_each(|(ctg_idx0, ctg_idx1)| {
-            if ctg_idx0 > ctg_idx1 {
-                return;
-            };
-            let (ctg0, bundles0) = &ctg_data[ctg_idx0];
-            let (ctg1, bundles1) = &ctg_data[ctg_idx1];
-            let (dist0, diff_len0, max_len0, best_score0, best_offset0) = align_bundles(bundles0, bundles1, args.local_aln);
-            let (dist1, diff_len1, max_len1, best_score1, _best_offset1) = align_bundles(bundles1, bundles0, args.local_aln);
-            let (dist, diff_len, max_len, best_score ) = if dist0 > dist1 {
-                (dist0, diff_len0, max_len0, best_score0 )
-            } else {
-                (dist1, diff_len1, max_len1, best_score1 )
-            };
-            writeln!(
-                out_file,
-                "{} {} {} {} {} {} {}",
-                ctg0, ctg1, dist, diff_len, max_len, best_score, best_offset0
-            )
-            .expect("writing error");
-            if ctg_idx1 != ctg_idx0 {
-                writeln!(
-                    out_file,
-                    "{} {} {} {} {} {} {}",
-                    ctg1, ctg0, dist, diff_len, max_len, best_score, -best_offset0
-                )
-                .expect("writing error");
-                if args.local_aln {
-                    let d = 1.0/(best_score as f32 + 10.0).log10();
-                    min_dist = if d < min_dist { d } else { min_dist };
-                    max_dist = if d > max_dist { d } else { max_dist };
-                    dist_map.insert((ctg_idx0, ctg_idx1), 1.0/(best_score as f32 + 10.0).log10());
-                    offset_map.insert( (ctg_idx0, ctg_idx1), best_offset0);
-                    offset_map.insert( (ctg_idx1, ctg_idx0), -best_offset0);
-                } else {
-                    dist_map.insert((ctg_idx0, ctg_idx1), dist);
-                    offset_map.insert( (ctg_idx0, ctg_idx1), 0);
-                    offset_map.insert( (ctg_idx1, ctg_idx0), 0);
-                }
-            }
-        });
-
-    let w = max_dist - min_dist + 0.01; 
-    dist_map.iter_mut().for_each(|(_k, v)| {
-        *v = (*v - min_dist + 0.01 ) / w;
-    });
-    let mut dist_mat = vec![];
-    (0..n_ctg - 1).for_each(|i| {
-        (i + 1..n_ctg).for_each(|j| {
-            dist_mat.push( *dist_map.get(&(i, j)).unwrap() );
-        })
-    });
-    let dend = linkage(&mut dist_mat, n_ctg, Method::Average);
-
-    let steps = dend.steps().to_vec();
-    let mut node_data = FxHashMap::<usize, (String, Vec<usize>, f32)>::default();
-    (0..n_ctg).for_each(|ctg_idx| {
-        node_data.insert(ctg_idx, (format!("{}", ctg_idx), vec![ctg_idx], 0.0_f32));
-    });
+    if args.nj_tree {
+        let mut nj_tree_file = BufWriter::new(
+            File::create(Path::new(&args.output_prefix).with_extension("nj.nwk"))
+                .expect("can't create the nj tree file"),
+        );
+        writeln!(
+            nj_tree_file,
+            "{};",
+            neighbor_joining(&distances.dist_matrix())
+        )
+        .expect("can't write the nj tree file");
+    }
 
-    let mut last_node_id = 0_usize;
-    steps.iter().enumerate().for_each(|(c, s)| {
-        let (node_string1, nodes1, height1) = node_data.remove(&s.cluster1).unwrap();
-        let (node_string2, nodes2, height2) = node_data.remove(&s.cluster2).unwrap();
-        let new_node_id = c + n_ctg;
-        let mut nodes = Vec::<usize>::new();
-        let new_node_string = if nodes1.len() > nodes2.len() {
-            nodes.extend(nodes1);
-            nodes.extend(nodes2);
-            format!(
-                "({}:{}, {}:{})",
-                node_string1,
-                s.dissimilarity - height1,
-                node_string2,
-                s.dissimilarity - height2
-            )
-        } else {
-            nodes.extend(nodes2);
-            nodes.extend(nodes1);
-            format!(
-                "({}:{}, {}:{})",
-                node_string2,
-                s.dissimilarity - height2,
-                node_string1,
-                s.dissimilarity - height1
-            )
-        };
-        node_data.insert(new_node_id, (new_node_string, nodes, s.dissimilarity));
-        last_node_id = new_node_id;
-    });
+    let leaf_labels = (0..ctg_names.len())
+        .map(|ctg_idx| format!("{}", ctg_idx))
+        .collect::<Vec<_>>();
+    let dendrogram = get_bundle_dendrogram(&distances, &leaf_labels);
 
     let mut tree_file = BufWriter::new(
         File::create(Path::new(&args.output_prefix).with_extension("nwk"))
             .expect("can't create the nwk file"),
     );
-
-    let emptyp_string = ("".to_string(), vec![], 0.0);
-    let (tree_string, nodes, _) = node_data.get(&last_node_id).unwrap_or(&emptyp_string);
-    writeln!(tree_file, "{};", tree_string).expect("can't write the nwk file");
+    writeln!(tree_file, "{};", dendrogram.newick).expect("can't write the nwk file");
 
     let mut dendrogram_file = BufWriter::new(
         File::create(Path::new(&args.output_prefix).with_extension("ddg"))
             .expect("can't create the dendrogram file"),
     );
+    dendrogram
+        .write_ddg(&mut dendrogram_file, &ctg_names)
+        .expect("can't write the dendrogram file");
 
     let mut offset_file = BufWriter::new(
         File::create(Path::new(&args.output_prefix).with_extension("offset"))
             .expect("can't create the alignment offset file"),
     );
-
-    let mut node_position_size = FxHashMap::<usize, ((f32, f32), usize)>::default();
-    let mut position = 0.0_f32;
-    let mut offset = 0_isize;
-    let mut p_idx: Option<usize> = None; 
-    let mut offset_group = Vec::<_>::new();
-    let mut group_min_offset = 100000_isize;
-    nodes.iter().for_each(|&ctg_idx| {
-        node_position_size.insert(ctg_idx, ((position, 0.0), 1));
-        writeln!(dendrogram_file, "L\t{}\t{}", ctg_idx, ctg_data[ctg_idx].0)
-            .expect("can't write the dendrogram file");
-        position += 1.0;
-        if let Some(p_idx) = p_idx {
-            let (idx0, idx1) = if p_idx < ctg_idx {
-                (p_idx, ctg_idx)
-            } else {
-                (ctg_idx, p_idx)
-            };
-            if *dist_map.get( &(idx0, idx1) ).unwrap_or(&1.0) < 0.25 {
-
-                offset += *offset_map.get( &(p_idx, ctg_idx) ).unwrap_or(&0);
-                offset_group.push( (ctg_idx, offset) );
-                if offset < group_min_offset {
-                    group_min_offset = offset;
-                };
-            } else {
-                offset_group.iter().for_each(| &(ctg_idx, offset ) | {
-                    writeln!(offset_file, "{}\t{}", ctg_data[ctg_idx].0, offset-group_min_offset)
-                    .expect("can't write the offset file");
-                 });
-                group_min_offset = 100000_isize;
-                offset_group.clear(); 
-                offset = 0; 
-            }
-        } else {
-            offset_group.push( (ctg_idx, offset) );
-        };
-        p_idx = Some(ctg_idx)
-    });
-
-    offset_group.iter().for_each(| &(ctg_idx, offset ) | {
-        writeln!(offset_file, "{}\t{}", ctg_data[ctg_idx].0, offset-group_min_offset)
+    dendrogram
+        .write_offsets(&mut offset_file, &ctg_names)
         .expect("can't write the offset file");
-     });
-
-    steps.into_iter().enumerate().for_each(|(c, s)| {
-        let ((pos0, _), size0) = *node_position_size.get(&s.cluster1).unwrap();
-        let ((pos1, _), size1) = *node_position_size.get(&s.cluster2).unwrap();
-
-        let pos = ((size0 as f32) * pos0 + (size1 as f32) * pos1) / ((size0 + size1) as f32);
-        writeln!(
-            dendrogram_file,
-            "I\t{}\t{}\t{}\t{}\t{}",
-            c + n_ctg,
-            s.cluster1,
-            s.cluster2,
-            s.size,
-            s.dissimilarity,
-        )
-        .expect("can't write the dendrogram file");
-        node_position_size.insert(c + n_ctg, ((pos, s.dissimilarity), s.size));
-    });
-    let mut node_positions = node_position_size
-        .into_iter()
-        .collect::<Vec<(usize, ((f32, f32), usize))>>();
-    node_positions.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap());
-
-    node_positions
-        .into_iter()
-        .for_each(|(vid, ((pos, h), size))| {
-            writeln!(dendrogram_file, "P\t{}\t{}\t{}\t{}", vid, pos, h, size)
-                .expect("can't write the dendrogram file");
-        });
     Ok(())
 }
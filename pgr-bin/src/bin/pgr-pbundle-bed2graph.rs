@@ -0,0 +1,118 @@
+const VERSION_STRING: &str = env!("VERSION_STRING");
+use clap::{self, CommandFactory, Parser};
+use pgr_db::bundle_graph::{read_bundle_walks_from_bed, BundleGraph, RearrangementType};
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+/// Generate the bundle graph, its bubbles, rearrangements and haplotype specific bundle orders from a principal bundle bed file
+#[derive(Parser, Debug)]
+#[clap(name = "pgr-pbundle-bed2graph")]
+#[clap(author, version)]
+#[clap(about, long_about = None)]
+struct CmdOptions {
+    /// the path to the principal bundle bed file
+    bed_file_path: String,
+    /// the prefix of the output files
+    output_prefix: String,
+    /// the minimum fraction of the sequences with a single copy of a bundle for the bundle to be used as a core bundle
+    #[clap(long, default_value_t = 0.5)]
+    min_core_fraction: f32,
+    /// write the sequences as P lines instead of W lines with the PanSN (sample#haplotype#contig) names in the GFA file
+    #[clap(long, default_value_t = false)]
+    use_p_lines: bool,
+}
+
+fn main() -> Result<(), std::io::Error> {
+    CmdOptions::command().version(VERSION_STRING).get_matches();
+    let args = CmdOptions::parse();
+    let output_prefix_path = Path::new(&args.output_prefix);
+
+    let walks = read_bundle_walks_from_bed(&args.bed_file_path)?;
+    let graph = BundleGraph::from_walks(walks);
+
+    let mut output_gfa_file = BufWriter::new(File::create(
+        output_prefix_path.with_extension("bgraph.gfa"),
+    )?);
+    graph.write_gfa(&mut output_gfa_file, !args.use_p_lines)?;
+
+    let step_string = |steps: &[(usize, u32)]| {
+        if steps.is_empty() {
+            "*".to_string()
+        } else {
+            steps
+                .iter()
+                .map(|(bid, d)| format!("{}{}", bid, if *d == 0 { '+' } else { '-' }))
+                .collect::<Vec<_>>()
+                .join(",")
+        }
+    };
+
+    let mut output_bubble_file = BufWriter::new(File::create(
+        output_prefix_path.with_extension("bubbles.tsv"),
+    )?);
+    writeln!(
+        output_bubble_file,
+        "#bubble_id\tsource\tsink\tallele_id\tallele\tn_seqs\tseqs"
+    )?;
+    graph
+        .get_bubbles(args.min_core_fraction)
+        .iter()
+        .enumerate()
+        .try_for_each(|(bubble_id, bubble)| {
+            bubble
+                .alleles
+                .iter()
+                .enumerate()
+                .try_for_each(|(allele_id, allele)| {
+                    writeln!(
+                        output_bubble_file,
+                        "{}\t{}\t{}\t{}\t{}\t{}\t{}",
+                        bubble_id,
+                        step_string(&[bubble.source]),
+                        step_string(&[bubble.sink]),
+                        allele_id,
+                        step_string(&allele.steps),
+                        allele.walks.len(),
+                        allele.walks.join(",")
+                    )
+                })
+        })?;
+
+    let mut output_rearrangement_file = BufWriter::new(File::create(
+        output_prefix_path.with_extension("rearrangements.bed"),
+    )?);
+    graph
+        .get_rearrangements(args.min_core_fraction)
+        .iter()
+        .try_for_each(|r| {
+            let rearrangement_type = match r.rearrangement_type {
+                RearrangementType::Inversion => "INV",
+                RearrangementType::Transposition => "TRANSPOS",
+                RearrangementType::Duplication => "DUP",
+            };
+            writeln!(
+                output_rearrangement_file,
+                "{}\t{}\t{}\t{}:{}",
+                r.walk, r.bgn, r.end, rearrangement_type, r.bundle_id
+            )
+        })?;
+
+    let mut output_order_file = BufWriter::new(File::create(
+        output_prefix_path.with_extension("hap_orders.tsv"),
+    )?);
+    writeln!(output_order_file, "#ctg\torientation\tcore_bundle_order")?;
+    graph
+        .get_haplotype_bundle_orders(args.min_core_fraction)
+        .iter()
+        .try_for_each(|order| {
+            writeln!(
+                output_order_file,
+                "{}\t{}\t{}",
+                order.walk,
+                if order.reversed { '-' } else { '+' },
+                step_string(&order.steps)
+            )
+        })?;
+    Ok(())
+}
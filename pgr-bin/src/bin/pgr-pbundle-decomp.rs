@@ -1,14 +1,16 @@
 const VERSION_STRING: &str = env!("VERSION_STRING");
-use bincode::config;
 use clap::{self, CommandFactory, Parser};
 use pgr_db::ext::{
-    get_principal_bundle_decomposition, PrincipalBundlesWithId, SeqIndexDB, VertexToBundleIdMap,
+    get_principal_bundle_decomposition, group_smps_by_principal_bundle_id, BundleDecompLevelSpec,
+    BundleMapping, GFAPathOptions, PrincipalBundleDB, SeqIndexDB, ShmmrSpec,
 };
+use pgr_db::gfa_io::GFANodeCoordinate;
+use pgr_db::pbundle::{bundle_bed_line, BundleSegment};
 use rustc_hash::{FxHashMap, FxHashSet};
 //use std::fs::File;
 use std::{
     fs::File,
-    io::{BufRead, BufReader, BufWriter, Read, Write},
+    io::{BufRead, BufReader, BufWriter, Write},
     path::Path,
 };
 
@@ -18,7 +20,7 @@ use std::{
 #[clap(author, version)]
 #[clap(about, long_about = None)]
 struct CmdOptions {
-    /// the path to the input fasta file for building the principal bundles
+    /// the path to the input fasta file for building the principal bundles, the paths of a GFA file are used if the file name ends with ".gfa" or ".gfa.gz"
     fastx_path: String,
     /// the prefix of the output files
     output_prefix: String,
@@ -28,7 +30,7 @@ struct CmdOptions {
     /// the path to the file that contains a list of contig name in the <FASTX_PATH> to be analyzed
     #[clap(long, short, default_value = None)]
     include: Option<String>,
-    /// the path to the fasta file for principal bundle decomposition. if not specified, using the same one from from <FASTX_PATH>
+    /// the path to the fasta (or GFA) file for principal bundle decomposition. if not specified, using the same one from from <FASTX_PATH>
     #[clap(long, short, default_value = None)]
     decomp_fastx_path: Option<String>,
     /// the SHIMMER parameter w
@@ -55,90 +57,144 @@ struct CmdOptions {
     /// merge two bundles with the same id with the specified length
     #[clap(long, default_value_t = 10000)]
     bundle_merge_distance: usize,
+    /// if specified, map the bundles to those in the <REFERENCE_BUNDLES> pdb file and use the reference bundle ids in the bed file
+    #[clap(long, default_value = None)]
+    reference_bundles: Option<String>,
+    /// the minimum fraction of the bundle vertices shared with a reference bundle to map a bundle
+    #[clap(long, default_value_t = 0.5)]
+    min_shared_fraction: f32,
+    /// if specified, decompose the sequences of each bundle recursively with finer parameters,
+    /// one level per comma separated "w:k:r:min_span:bundle_length_cutoff:bundle_merge_distance[:min_cov:min_branch_size]"
+    /// item, a level without min_cov and min_branch_size uses --min-cov and --min-branch-size, the results are written to <OUTPUT_PREFIX>.hier.bed and <OUTPUT_PREFIX>.hier.json
+    #[clap(long, default_value = None)]
+    hierarchy: Option<String>,
+    /// write a W line (or a P line with --gfa-p-lines) for every sequence in the MAP-graph GFA files
+    #[clap(long, default_value_t = false)]
+    gfa_paths: bool,
+    /// write the sequences as P lines instead of W lines in the MAP-graph GFA files
+    #[clap(long, default_value_t = false)]
+    gfa_p_lines: bool,
+    /// add the rGFA SN/SO/SR tags relative to the sequences of the sample to the MAP-graph GFA files
+    #[clap(long, default_value = None)]
+    gfa_reference_sample: Option<String>,
+    /// write the segment sequences in the MAP-graph GFA files, a segment uses the first sequence having its shimmer pair
+    #[clap(long, default_value_t = false)]
+    gfa_sequences: bool,
 }
 
-#[allow(clippy::type_complexity)]
-fn group_smps_by_principle_bundle_id(
-    smps: &[((u64, u64, u32, u32, u8), Option<(usize, u8, usize)>)],
-    bundle_length_cutoff: usize,
-    bundle_merge_distance: usize,
-) -> Vec<Vec<((u64, u64, u32, u32, u8), usize, u32, usize)>> {
-    let mut pre_bundle_id: Option<usize> = None;
-    let mut pre_direction: Option<u32> = None;
-    let mut all_partitions = vec![];
-    let mut new_partition = vec![];
-    smps.iter().for_each(|&(smp, bundle_info)| {
-        if bundle_info.is_none() {
-            return;
-        };
-        let bundle_info = bundle_info.unwrap();
-        let d = if smp.4 == bundle_info.1 { 0_u32 } else { 1_u32 };
-        let bid = bundle_info.0;
-        let bpos = bundle_info.2;
-        if pre_bundle_id.is_none() {
-            new_partition.clear();
-            new_partition.push((smp, bid, d, bpos));
-            pre_bundle_id = Some(bid);
-            pre_direction = Some(d);
-            return;
-        };
-        if bid != pre_bundle_id.unwrap() || d != pre_direction.unwrap() {
-            let l = new_partition.len();
-            if new_partition[l - 1].0 .3 as usize - new_partition[0].0 .2 as usize
-                > bundle_length_cutoff
-            {
-                all_partitions.push(new_partition.clone());
-                new_partition.clear();
-            } else {
-                new_partition.clear();
-            };
-            pre_bundle_id = Some(bid);
-            pre_direction = Some(d);
-        };
-        new_partition.push((smp, bid, d, bpos));
-    });
-    let l = new_partition.len();
-    if l > 0
-        && new_partition[l - 1].0 .3 as usize - new_partition[0].0 .2 as usize
-            > bundle_length_cutoff
-    {
-        all_partitions.push(new_partition);
-    };
-
-    let mut rtn_partitions = vec![];
-
-    if all_partitions.is_empty() {
-        return rtn_partitions;
+// load the sequences from a fasta/fastq file or the paths of a GFA file (with a ".gfa" or ".gfa.gz" extension),
+// the coordinates of the graph segments in the paths are returned for a GFA file
+fn load_seq_index_db(
+    seq_index_db: &mut SeqIndexDB,
+    path: String,
+    args: &CmdOptions,
+) -> Result<Option<FxHashMap<String, Vec<GFANodeCoordinate>>>, std::io::Error> {
+    if path.ends_with(".gfa") || path.ends_with(".gfa.gz") {
+        seq_index_db.load_from_gfa(&path, args.w, args.k, args.r, args.min_span, true)
+    } else {
+        seq_index_db
+            .load_from_fastx(path, args.w, args.k, args.r, args.min_span, true)
+            .map(|_| None)
     }
-    let mut partition = all_partitions[0].clone();
-    (1..all_partitions.len()).for_each(|idx| {
-        let p = all_partitions[idx].clone();
-        let p_len = partition.len();
-        let p_end = partition[p_len - 1].0 .3;
-        let p_bid = partition[p_len - 1].1;
-        let p_d = partition[p_len - 1].2;
-        let np_bgn = p[0].0 .2;
-        let np_bid = p[0].1;
-        let np_d = p[0].2;
-        if p_bid == np_bid
-            && p_d == np_d
-            && (np_bgn as i64 - p_end as i64).abs() < bundle_merge_distance as i64
-        {
-            partition.extend(p);
-        } else {
-            rtn_partitions.push(partition.clone());
-            partition = p;
-        }
-    });
-    if !partition.is_empty() {
-        rtn_partitions.push(partition);
+}
+
+// bid -> (bundle id in the bed file, orientation relative to the reference bundle), only the bundle
+// sharing the most vertices with a reference bundle gets its id, the other bundles mapped to the same
+// reference bundle and the unmapped bundles get new ids after the largest reference bundle id
+fn get_bed_bundle_ids(
+    bundle_mapping: &[BundleMapping],
+    reference_pdb: Option<&PrincipalBundleDB>,
+) -> FxHashMap<usize, (usize, u32)> {
+    if let Some(reference_pdb) = reference_pdb {
+        let mut next_bid = reference_pdb
+            .principal_bundles_with_id
+            .iter()
+            .map(|b| b.0 + 1)
+            .max()
+            .unwrap_or(0);
+        // ref_bid -> the bid with the most shared vertices, the smaller bid for a tie
+        let mut best_match = FxHashMap::<usize, (usize, usize)>::default();
+        bundle_mapping.iter().for_each(|(bid, _, _, ref_match)| {
+            if let Some((ref_bid, _, n_shared, _)) = ref_match {
+                let e = best_match.entry(*ref_bid).or_insert((*bid, *n_shared));
+                if *n_shared > e.1 || (*n_shared == e.1 && *bid < e.0) {
+                    *e = (*bid, *n_shared);
+                }
+            }
+        });
+        bundle_mapping
+            .iter()
+            .map(|(bid, _, _, ref_match)| match ref_match {
+                Some((ref_bid, _, _, orientation)) if best_match[ref_bid].0 == *bid => {
+                    (*bid, (*ref_bid, *orientation as u32))
+                }
+                _ => {
+                    next_bid += 1;
+                    (*bid, (next_bid - 1, 0))
+                }
+            })
+            .collect()
+    } else {
+        bundle_mapping
+            .iter()
+            .map(|(bid, _, _, _)| (*bid, (*bid, 0)))
+            .collect()
     }
-    rtn_partitions
+}
+
+fn parse_hierarchy_levels(
+    hierarchy: &str,
+    min_cov: usize,
+    min_branch_size: usize,
+) -> Result<Vec<BundleDecompLevelSpec>, std::io::Error> {
+    hierarchy
+        .split(',')
+        .map(|level| {
+            let parse_err = || {
+                std::io::Error::new(
+                    std::io::ErrorKind::InvalidInput,
+                    format!(
+                        "the hierarchy level should be w:k:r:min_span:bundle_length_cutoff:bundle_merge_distance[:min_cov:min_branch_size], got {}",
+                        level
+                    ),
+                )
+            };
+            let fields = level
+                .split(':')
+                .map(|v| v.trim().parse::<u32>().map_err(|_| parse_err()))
+                .collect::<Result<Vec<_>, _>>()?;
+            if fields.len() != 6 && fields.len() != 8 {
+                return Err(parse_err());
+            }
+            let (min_cov, min_branch_size) = if fields.len() == 8 {
+                (fields[6] as usize, fields[7] as usize)
+            } else {
+                (min_cov, min_branch_size)
+            };
+            Ok(BundleDecompLevelSpec {
+                shmmr_spec: ShmmrSpec {
+                    w: fields[0],
+                    k: fields[1],
+                    r: fields[2],
+                    min_span: fields[3],
+                    sketch: false,
+                },
+                min_cov,
+                min_branch_size,
+                bundle_length_cutoff: fields[4] as usize,
+                bundle_merge_distance: fields[5] as usize,
+            })
+        })
+        .collect()
 }
 
 fn main() -> Result<(), std::io::Error> {
     CmdOptions::command().version(VERSION_STRING).get_matches();
     let mut args = CmdOptions::parse();
+    // report the invalid hierarchy levels before building the bundles
+    if let Some(hierarchy) = args.hierarchy.as_ref() {
+        parse_hierarchy_levels(hierarchy, args.min_cov, args.min_branch_size)?;
+    }
     let cmd_string = std::env::args().collect::<Vec<String>>().join(" ");
     let fastx_path = args.fastx_path.clone();
     let mut seq_index_db = SeqIndexDB::new();
@@ -154,74 +210,23 @@ fn main() -> Result<(), std::io::Error> {
         vertex_to_bundle_id_direction_pos,
     ) = match args.precomputed_bundles.clone() {
         Some(precomputed_bundles) => {
-            let mut pdb_input_file = BufReader::new(
-                File::open(Path::new(&precomputed_bundles)).expect("pdb input file open error"),
-            );
-            let mut buf = [0_u8; 7];
-            pdb_input_file
-                .read_exact(&mut buf)
-                .expect("pdb input file reading error");
-            let config = config::standard();
-            let mut s: Vec<u8> = vec![];
-            pdb_input_file
-                .read_to_end(&mut s)
+            let pdb = PrincipalBundleDB::load_from_file(&precomputed_bundles)
                 .expect("pdb input file reading error");
-
-            #[allow(clippy::type_complexity)]
-            let (
-                (
-                    w,
-                    k,
-                    r,
-                    min_span,
-                    min_branch_size,
-                    min_cov,
-                    principal_bundles_with_id,
-                    vertex_to_bundle_id_direction_pos,
-                ),
-                _size,
-            ): (
-                (
-                    u32,
-                    u32,
-                    u32,
-                    u32,
-                    usize,
-                    usize,
-                    PrincipalBundlesWithId,
-                    VertexToBundleIdMap,
-                ),
-                usize,
-            ) = bincode::decode_from_slice::<
-                (
-                    u32,
-                    u32,
-                    u32,
-                    u32,
-                    usize,
-                    usize,
-                    PrincipalBundlesWithId,
-                    VertexToBundleIdMap,
-                ),
-                config::Configuration,
-            >(&s[..], config)
-            .unwrap();
             (
-                w,
-                k,
-                r,
-                min_span,
-                min_branch_size,
-                min_cov,
-                principal_bundles_with_id,
-                vertex_to_bundle_id_direction_pos,
+                pdb.shmmr_spec.w,
+                pdb.shmmr_spec.k,
+                pdb.shmmr_spec.r,
+                pdb.shmmr_spec.min_span,
+                pdb.min_branch_size,
+                pdb.min_cov,
+                pdb.principal_bundles_with_id,
+                pdb.vertex_to_bundle_id_direction_pos,
             )
         }
         None => {
             // get principle bundle from fastx_path
 
-            seq_index_db
-                .load_from_fastx(fastx_path.clone(), args.w, args.k, args.r, args.min_span, true)
+            load_seq_index_db(&mut seq_index_db, fastx_path.clone(), &args)
                 .unwrap_or_else(|_| panic!("can't read file {}", fastx_path));
 
             let (principal_bundles_with_id, vertex_to_bundle_id_direction_pos) = seq_index_db
@@ -248,19 +253,20 @@ fn main() -> Result<(), std::io::Error> {
 
     // get sequence data for decomposition from `target_fastx_path`. If it is not specified, using `fastx_path`
     let decomp_fastx_path;
-    let mut decomp_seq_index_db = if let Some(target_fastx_path) = args.decomp_fastx_path {
+    let gfa_node_coordinates;
+    let mut decomp_seq_index_db = if let Some(target_fastx_path) = args.decomp_fastx_path.clone() {
         decomp_fastx_path = target_fastx_path.clone();
         let mut decomp_seq_index_db = SeqIndexDB::new();
-        decomp_seq_index_db
-            .load_from_fastx(target_fastx_path, args.w, args.k, args.r, args.min_span, true)
-            .unwrap_or_else(|_| panic!("can't read file {}", fastx_path));
+        gfa_node_coordinates =
+            load_seq_index_db(&mut decomp_seq_index_db, target_fastx_path, &args)
+                .unwrap_or_else(|_| panic!("can't read file {}", fastx_path));
         decomp_seq_index_db
     } else {
         //The file is read using a Mmap which is not clonable, need to rebuild the database. TODO: fix this.
         let mut decomp_seq_index_db = SeqIndexDB::new();
-        decomp_seq_index_db
-            .load_from_fastx(fastx_path.clone(), args.w, args.k, args.r, args.min_span, true)
-            .unwrap_or_else(|_| panic!("can't read file {}", fastx_path));
+        gfa_node_coordinates =
+            load_seq_index_db(&mut decomp_seq_index_db, fastx_path.clone(), &args)
+                .unwrap_or_else(|_| panic!("can't read file {}", fastx_path));
         decomp_fastx_path = fastx_path;
         decomp_seq_index_db
     };
@@ -293,8 +299,46 @@ fn main() -> Result<(), std::io::Error> {
 
     let output_prefix_path = Path::new(&args.output_prefix);
 
+    // the coordinates of the graph segments in the paths for mapping the bundles back to a GFA input
+    if let Some(gfa_node_coordinates) = gfa_node_coordinates {
+        let mut output_gfa_node_file = BufWriter::new(File::create(
+            output_prefix_path.with_extension("gfa_nodes.bed"),
+        )?);
+        let mut path_names = gfa_node_coordinates.keys().collect::<Vec<_>>();
+        path_names.sort();
+        path_names.into_iter().try_for_each(|path_name| {
+            gfa_node_coordinates[path_name].iter().try_for_each(
+                |(segment, orientation, bgn, end)| {
+                    writeln!(
+                        output_gfa_node_file,
+                        "{}\t{}\t{}\t{}{}",
+                        path_name,
+                        bgn,
+                        end,
+                        segment,
+                        if *orientation == 0 { '+' } else { '-' }
+                    )
+                },
+            )
+        })?;
+    }
+
     if args.precomputed_bundles.is_none() {
-        seq_index_db.generate_mapg_gfa(
+        let path_options = GFAPathOptions {
+            use_p_lines: args.gfa_p_lines,
+            reference_sample: args.gfa_reference_sample.clone(),
+            with_sequences: args.gfa_sequences,
+        };
+        let path_options = if args.gfa_paths
+            || args.gfa_p_lines
+            || args.gfa_reference_sample.is_some()
+            || args.gfa_sequences
+        {
+            Some(&path_options)
+        } else {
+            None
+        };
+        seq_index_db.generate_mapg_gfa_with_paths(
             0,
             output_prefix_path
                 .with_extension("mapg.gfa")
@@ -302,6 +346,7 @@ fn main() -> Result<(), std::io::Error> {
                 .unwrap(),
             "from_fragmap",
             None,
+            path_options,
         )?;
 
         seq_index_db.write_mapg_idx(
@@ -311,7 +356,7 @@ fn main() -> Result<(), std::io::Error> {
                 .unwrap(),
         )?;
 
-        seq_index_db.generate_principal_mapg_gfa(
+        seq_index_db.generate_principal_mapg_gfa_with_paths(
             args.min_cov,
             args.min_branch_size,
             output_prefix_path
@@ -319,6 +364,7 @@ fn main() -> Result<(), std::io::Error> {
                 .to_str()
                 .unwrap(),
             None,
+            path_options,
         )?;
     };
 
@@ -341,6 +387,7 @@ fn main() -> Result<(), std::io::Error> {
 
     let mut seq_info = decomp_seq_index_db
         .seq_info
+        .clone()
         .unwrap()
         .into_iter()
         .map(|(k, v)| (k, v))
@@ -354,38 +401,75 @@ fn main() -> Result<(), std::io::Error> {
         .collect::<FxHashMap<usize, usize>>();
     let sid_smps: FxHashMap<u32, Vec<_>> = sid_smps.into_iter().collect();
 
+    let pdb = PrincipalBundleDB {
+        shmmr_spec: ShmmrSpec {
+            w: args.w,
+            k: args.k,
+            r: args.r,
+            min_span: args.min_span,
+            sketch: false,
+        },
+        min_cov: args.min_cov,
+        min_branch_size: args.min_branch_size,
+        principal_bundles_with_id,
+        vertex_to_bundle_id_direction_pos,
+    };
+
     if args.precomputed_bundles.is_none() {
-        let mut pdb_output_file = BufWriter::new(
-            File::create(Path::new(&args.output_prefix).with_extension("pdb"))
-                .expect("pdb file creating error"),
-        );
-        pdb_output_file
-            .write_all("PDB:0.5".as_bytes())
-            .expect("pdb file writing error");
-        let config = config::standard();
-        let bincode_vec = bincode::encode_to_vec(
-            (
-                args.w,
-                args.k,
-                args.r,
-                args.min_span,
-                args.min_branch_size,
-                args.min_cov,
-                principal_bundles_with_id,
-                vertex_to_bundle_id_direction_pos,
-            ),
-            config,
+        pdb.write_to_file(
+            Path::new(&args.output_prefix)
+                .with_extension("pdb")
+                .to_str()
+                .unwrap(),
         )
-        .unwrap();
-        pdb_output_file
-            .write_all(&bincode_vec[..])
-            .expect("pdb file writing error");
+        .expect("pdb file writing error");
     }
 
+    // map the bundle ids to the content derived ids and optionally to the bundle ids of a reference decomposition
+    let reference_pdb = args.reference_bundles.as_ref().map(|reference_bundles| {
+        PrincipalBundleDB::load_from_file(reference_bundles)
+            .expect("reference pdb file reading error")
+    });
+    let mut bundle_mapping = if let Some(reference_pdb) = reference_pdb.as_ref() {
+        pdb.map_bundles_to_reference(reference_pdb, args.min_shared_fraction)
+    } else {
+        pdb.get_bundle_content_ids()
+            .into_iter()
+            .map(|(bid, content_id)| (bid, content_id, bid_to_size[&bid], None))
+            .collect::<Vec<_>>()
+    };
+    let mut output_bundle_map_file = BufWriter::new(File::create(
+        output_prefix_path.with_extension("bundle_map.tsv"),
+    )?);
+    writeln!(
+        output_bundle_map_file,
+        "#bundle_id\tcontent_id\tsize\tref_bundle_id\tref_content_id\tshared_size\torientation"
+    )?;
+    bundle_mapping.sort();
+    bundle_mapping
+        .iter()
+        .try_for_each(|(bid, content_id, size, ref_match)| {
+            if let Some((ref_bid, ref_content_id, shared_size, orientation)) = ref_match {
+                writeln!(
+                    output_bundle_map_file,
+                    "{}\t{:016x}\t{}\t{}\t{:016x}\t{}\t{}",
+                    bid, content_id, size, ref_bid, ref_content_id, shared_size, orientation
+                )
+            } else {
+                writeln!(
+                    output_bundle_map_file,
+                    "{}\t{:016x}\t{}\tNA\tNA\tNA\tNA",
+                    bid, content_id, size
+                )
+            }
+        })?;
+
+    let bed_bundle_id = get_bed_bundle_ids(&bundle_mapping, reference_pdb.as_ref());
+
     seq_info.iter().for_each(|(sid, sdata)| {
         let (ctg, _src, _len) = sdata;
         let smps = sid_smps.get(sid).unwrap();
-        let smp_partitions = group_smps_by_principle_bundle_id(
+        let smp_partitions = group_smps_by_principal_bundle_id(
             smps,
             args.bundle_length_cutoff,
             args.bundle_merge_distance,
@@ -405,29 +489,87 @@ fn main() -> Result<(), std::io::Error> {
                     .entry(*sid)
                     .or_insert_with(Vec::new)
                     .push(e - b - args.k);
-                "R"
+                true
             } else {
                 non_repeat_count
                     .entry(*sid)
                     .or_insert_with(Vec::new)
                     .push(e - b - args.k);
-                "U"
+                false
             };
-            let _ = writeln!(
-                outpu_bed_file,
-                "{}\t{}\t{}\t{}:{}:{}:{}:{}:{}",
-                ctg,
-                b,
-                e,
-                bid,
-                bid_to_size[&bid],
-                direction,
-                p[0].3,
-                p[p.len() - 1].3,
-                is_repeat
-            );
+            let (bed_bid, orientation) = bed_bundle_id[&bid];
+            let seg = BundleSegment {
+                bgn: b,
+                end: e,
+                bundle_id: bed_bid as u32,
+                bundle_v_count: bid_to_size[&bid] as u32,
+                bundle_dir: direction ^ orientation,
+                bundle_v_bgn: p[0].3 as u32,
+                bundle_v_end: p[p.len() - 1].3 as u32,
+            };
+            let _ = writeln!(outpu_bed_file, "{}", bundle_bed_line(ctg, &seg, is_repeat));
         });
     });
+
+    if let Some(hierarchy) = args.hierarchy.as_ref() {
+        let sub_levels = parse_hierarchy_levels(hierarchy, args.min_cov, args.min_branch_size)?;
+        let mut segments = pdb.get_hierarchical_decomposition(
+            &decomp_seq_index_db,
+            args.bundle_length_cutoff,
+            args.bundle_merge_distance,
+            &sub_levels,
+        )?;
+
+        // use the bundle ids of the bed file for the top level, the ids of the sub-level bundles
+        // are shifted to follow the largest bundle id in the bed file
+        let first_sub_level_bid = bid_to_size.keys().max().map_or(0, |bid| bid + 1);
+        let next_bed_bid = bed_bundle_id.values().map(|v| v.0 + 1).max().unwrap_or(0);
+        let to_bed_bid = |bid: usize| {
+            if let Some((bed_bid, _)) = bed_bundle_id.get(&bid) {
+                *bed_bid
+            } else {
+                bid - first_sub_level_bid + next_bed_bid
+            }
+        };
+        segments.iter_mut().for_each(|seg| {
+            if seg.level == 0 {
+                seg.direction ^= bed_bundle_id[&seg.bundle_id].1;
+            }
+            seg.bundle_id = to_bed_bid(seg.bundle_id);
+            seg.parent_id = seg.parent_id.map(to_bed_bid);
+        });
+
+        let mut output_hier_bed_file =
+            BufWriter::new(File::create(output_prefix_path.with_extension("hier.bed"))?);
+        writeln!(output_hier_bed_file, "# cmd: {}", cmd_string)?;
+        segments.iter().try_for_each(|seg| {
+            let parent_id = match seg.parent_id {
+                Some(parent_id) => format!("{}", parent_id),
+                None => ".".to_string(),
+            };
+            writeln!(
+                output_hier_bed_file,
+                "{}\t{}\t{}\t{}:{}:{}:{}:{}:{}\t{}\t{}",
+                seg.ctg,
+                seg.bgn,
+                seg.end,
+                seg.bundle_id,
+                seg.bundle_size,
+                seg.direction,
+                seg.bundle_v_bgn,
+                seg.bundle_v_end,
+                if seg.is_repeat { "R" } else { "U" },
+                seg.level,
+                parent_id
+            )
+        })?;
+
+        let output_hier_json_file = BufWriter::new(File::create(
+            output_prefix_path.with_extension("hier.json"),
+        )?);
+        serde_json::to_writer(output_hier_json_file, &segments)?;
+    }
+
     #[allow(clippy::write_literal)]
     let _ = writeln!(
         output_ctg_summary_file,
@@ -529,3 +671,83 @@ fn main() -> Result<(), std::io::Error> {
     });
     Ok(())
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn test_pdb(bundle_ids: &[usize]) -> PrincipalBundleDB {
+        PrincipalBundleDB {
+            shmmr_spec: ShmmrSpec {
+                w: 48,
+                k: 56,
+                r: 4,
+                min_span: 12,
+                sketch: false,
+            },
+            min_cov: 0,
+            min_branch_size: 8,
+            principal_bundles_with_id: bundle_ids.iter().map(|&bid| (bid, 0, vec![])).collect(),
+            vertex_to_bundle_id_direction_pos: FxHashMap::default(),
+        }
+    }
+
+    #[test]
+    fn test_bed_bundle_ids_after_unmatched_reference_bundles() {
+        // the reference bundle 9 is not matched, the new ids must not reuse it
+        let reference_pdb = test_pdb(&[0, 1, 9]);
+        let bundle_mapping: Vec<BundleMapping> = vec![
+            (0, 0x10, 5, Some((1, 0x11, 5, 1))),
+            (1, 0x20, 5, None),
+            (2, 0x30, 5, Some((0, 0x31, 4, 0))),
+            (3, 0x40, 5, None),
+        ];
+        let bed_bundle_ids = get_bed_bundle_ids(&bundle_mapping, Some(&reference_pdb));
+        assert_eq!(bed_bundle_ids[&0], (1, 1));
+        assert_eq!(bed_bundle_ids[&1], (10, 0));
+        assert_eq!(bed_bundle_ids[&2], (0, 0));
+        assert_eq!(bed_bundle_ids[&3], (11, 0));
+
+        let bed_bundle_ids = get_bed_bundle_ids(&bundle_mapping, None);
+        assert_eq!(bed_bundle_ids[&3], (3, 0));
+    }
+
+    #[test]
+    fn test_bed_bundle_ids_many_to_one() {
+        // the bundles 1, 2 and 3 are mapped to the reference bundle 5, the bundles 2 and 3 share the most
+        // vertices, the tie goes to the smaller bundle id
+        let reference_pdb = test_pdb(&[0, 5]);
+        let bundle_mapping: Vec<BundleMapping> = vec![
+            (0, 0x10, 5, Some((0, 0x11, 5, 0))),
+            (1, 0x20, 8, Some((5, 0x21, 4, 0))),
+            (2, 0x30, 8, Some((5, 0x21, 6, 1))),
+            (3, 0x40, 8, Some((5, 0x21, 6, 0))),
+        ];
+        let bed_bundle_ids = get_bed_bundle_ids(&bundle_mapping, Some(&reference_pdb));
+        assert_eq!(bed_bundle_ids[&0], (0, 0));
+        assert_eq!(bed_bundle_ids[&1], (6, 0));
+        assert_eq!(bed_bundle_ids[&2], (5, 1));
+        assert_eq!(bed_bundle_ids[&3], (7, 0));
+    }
+
+    #[test]
+    fn test_parse_hierarchy_levels() {
+        let levels =
+            parse_hierarchy_levels("24:32:2:8:500:2000, 12:24:1:4:100:500:2:4", 0, 8).unwrap();
+        assert_eq!(levels.len(), 2);
+        assert_eq!(levels[0].shmmr_spec.w, 24);
+        assert_eq!(levels[0].shmmr_spec.k, 32);
+        assert_eq!(levels[0].bundle_length_cutoff, 500);
+        assert_eq!(levels[0].bundle_merge_distance, 2000);
+        assert_eq!((levels[0].min_cov, levels[0].min_branch_size), (0, 8));
+        assert_eq!(levels[1].shmmr_spec.min_span, 4);
+        assert_eq!((levels[1].min_cov, levels[1].min_branch_size), (2, 4));
+    }
+
+    #[test]
+    fn test_parse_hierarchy_levels_errors() {
+        assert!(parse_hierarchy_levels("24:32:2:8:500:2000:2", 0, 8).is_err());
+        assert!(parse_hierarchy_levels("24:32:2:8:500:x", 0, 8).is_err());
+        assert!(parse_hierarchy_levels("24:32:2:8:500:2000,", 0, 8).is_err());
+    }
+}
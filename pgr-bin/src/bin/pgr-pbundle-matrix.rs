@@ -0,0 +1,381 @@
+const VERSION_STRING: &str = env!("VERSION_STRING");
+use clap::{self, CommandFactory, Parser};
+use rustc_hash::{FxHashMap, FxHashSet};
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+
+/// Generate the bundle x sample copy number and coverage fraction matrices from a principal bundle bed file
+#[derive(Parser, Debug)]
+#[clap(name = "pgr-pbundle-matrix")]
+#[clap(author, version)]
+#[clap(about, long_about = None)]
+struct CmdOptions {
+    /// the path to the principal bundle bed file
+    bed_file_path: String,
+    /// the prefix of the output files
+    output_prefix: String,
+    /// how to group the contigs into the columns: "contig", "haplotype" (sample#haplotype of a PanSN contig name) or "sample"
+    #[clap(long, value_enum, default_value_t = GroupBy::Haplotype)]
+    group_by: GroupBy,
+    /// a two column (contig, group) tsv file for assigning the contigs to the groups, this overrides --group-by
+    #[clap(long, default_value = None)]
+    metadata: Option<String>,
+    /// the minimum length of a bundle segment to be counted
+    #[clap(long, default_value_t = 0)]
+    min_segment_length: u32,
+}
+
+#[derive(Clone, Copy, clap::ValueEnum, Debug, PartialEq, Eq)]
+enum GroupBy {
+    Contig,
+    Haplotype,
+    Sample,
+}
+
+struct BundleSegment {
+    bgn: u32,
+    end: u32,
+    bundle_id: u32,
+    bundle_v_count: u32,
+    bundle_v_bgn: u32,
+    bundle_v_end: u32,
+}
+
+/// the copy numbers and the covered bundle vertex positions of the bundles in each group
+struct BundleMatrix {
+    groups: Vec<String>,
+    bundle_ids: Vec<u32>,
+    bundle_size: FxHashMap<u32, u32>,
+    // (bundle_id, group index) -> (copy number, covered bundle vertex positions)
+    cells: FxHashMap<(u32, usize), (usize, FxHashSet<u32>)>,
+}
+
+impl BundleMatrix {
+    fn coverage_fraction(&self, bid: u32, count: &(usize, FxHashSet<u32>)) -> f32 {
+        count.1.len() as f32 / self.bundle_size[&bid].max(1) as f32
+    }
+}
+
+fn get_group(ctg: &str, group_by: GroupBy) -> String {
+    let fields = ctg.splitn(3, '#').collect::<Vec<_>>();
+    match (group_by, fields.len()) {
+        (GroupBy::Haplotype, 3) => format!("{}#{}", fields[0], fields[1]),
+        (GroupBy::Sample, 3) => fields[0].to_string(),
+        _ => ctg.to_string(),
+    }
+}
+
+fn invalid_data(msg: String) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::InvalidData, msg)
+}
+
+fn parse_metadata<R: BufRead>(reader: R) -> Result<FxHashMap<String, String>, std::io::Error> {
+    let mut ctg_to_group = FxHashMap::<String, String>::default();
+    for line in reader.lines() {
+        let line = line?;
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let fields = line.split('\t').collect::<Vec<&str>>();
+        if fields.len() < 2 {
+            return Err(invalid_data(format!(
+                "metadata file parsing error: {}",
+                line
+            )));
+        }
+        ctg_to_group.insert(fields[0].to_string(), fields[1].to_string());
+    }
+    Ok(ctg_to_group)
+}
+
+fn parse_bed<R: BufRead>(
+    reader: R,
+) -> Result<FxHashMap<String, Vec<BundleSegment>>, std::io::Error> {
+    let mut ctg_data = FxHashMap::<String, Vec<BundleSegment>>::default();
+    for line in reader.lines() {
+        let line = line?;
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let parse_err = || invalid_data(format!("bed file parsing error: {}", line));
+        let bed_fields = line.split('\t').collect::<Vec<&str>>();
+        if bed_fields.len() < 4 {
+            return Err(parse_err());
+        }
+        // only the top level bundles of a hierarchical bed file are used
+        if bed_fields.len() > 5 && bed_fields[4] != "0" {
+            continue;
+        }
+        let pbundle_fields = bed_fields[3].split(':').collect::<Vec<&str>>();
+        if pbundle_fields.len() < 5 {
+            return Err(parse_err());
+        }
+        let parse = |field: &str| field.parse::<u32>().map_err(|_| parse_err());
+        ctg_data
+            .entry(bed_fields[0].to_string())
+            .or_default()
+            .push(BundleSegment {
+                bgn: parse(bed_fields[1])?,
+                end: parse(bed_fields[2])?,
+                bundle_id: parse(pbundle_fields[0])?,
+                bundle_v_count: parse(pbundle_fields[1])?,
+                bundle_v_bgn: parse(pbundle_fields[3])?,
+                bundle_v_end: parse(pbundle_fields[4])?,
+            });
+    }
+    Ok(ctg_data)
+}
+
+fn get_bundle_matrix(
+    ctg_data: &FxHashMap<String, Vec<BundleSegment>>,
+    ctg_to_group: &FxHashMap<String, String>,
+    group_by: GroupBy,
+    min_segment_length: u32,
+) -> BundleMatrix {
+    let mut ctgs = ctg_data.keys().cloned().collect::<Vec<_>>();
+    ctgs.sort();
+    let ctg_group = |ctg: &String| {
+        ctg_to_group
+            .get(ctg)
+            .cloned()
+            .unwrap_or_else(|| get_group(ctg, group_by))
+    };
+    let mut groups = ctgs
+        .iter()
+        .map(ctg_group)
+        .collect::<FxHashSet<_>>()
+        .into_iter()
+        .collect::<Vec<_>>();
+    groups.sort();
+    let group_to_idx = groups
+        .iter()
+        .enumerate()
+        .map(|(idx, g)| (g.clone(), idx))
+        .collect::<FxHashMap<_, _>>();
+
+    let mut bundle_size = FxHashMap::<u32, u32>::default();
+    let mut cells = FxHashMap::<(u32, usize), (usize, FxHashSet<u32>)>::default();
+    ctgs.iter().for_each(|ctg| {
+        let group_idx = group_to_idx[&ctg_group(ctg)];
+        ctg_data[ctg]
+            .iter()
+            .filter(|s| s.end.saturating_sub(s.bgn) >= min_segment_length)
+            .for_each(|s| {
+                bundle_size.insert(s.bundle_id, s.bundle_v_count);
+                let cell = cells.entry((s.bundle_id, group_idx)).or_default();
+                cell.0 += 1;
+                let (v_bgn, v_end) = if s.bundle_v_bgn <= s.bundle_v_end {
+                    (s.bundle_v_bgn, s.bundle_v_end)
+                } else {
+                    (s.bundle_v_end, s.bundle_v_bgn)
+                };
+                cell.1.extend(v_bgn..=v_end);
+            });
+    });
+    let mut bundle_ids = bundle_size.keys().copied().collect::<Vec<_>>();
+    bundle_ids.sort();
+    BundleMatrix {
+        groups,
+        bundle_ids,
+        bundle_size,
+        cells,
+    }
+}
+
+fn main() -> Result<(), std::io::Error> {
+    CmdOptions::command().version(VERSION_STRING).get_matches();
+    let args = CmdOptions::parse();
+
+    let ctg_to_group = if let Some(metadata) = args.metadata.as_ref() {
+        parse_metadata(BufReader::new(File::open(metadata)?))?
+    } else {
+        FxHashMap::default()
+    };
+    let ctg_data = parse_bed(BufReader::new(File::open(Path::new(&args.bed_file_path))?))?;
+    let matrix = get_bundle_matrix(
+        &ctg_data,
+        &ctg_to_group,
+        args.group_by,
+        args.min_segment_length,
+    );
+    let BundleMatrix {
+        groups,
+        bundle_ids,
+        bundle_size,
+        cells,
+    } = &matrix;
+    let coverage_fraction =
+        |bid: u32, count: &(usize, FxHashSet<u32>)| matrix.coverage_fraction(bid, count);
+
+    let output_prefix_path = Path::new(&args.output_prefix);
+    let mut output_cn_file = BufWriter::new(File::create(
+        output_prefix_path.with_extension("copy_number.tsv"),
+    )?);
+    let mut output_cov_file = BufWriter::new(File::create(
+        output_prefix_path.with_extension("coverage.tsv"),
+    )?);
+    writeln!(output_cn_file, "#bundle_id\t{}", groups.join("\t"))?;
+    writeln!(output_cov_file, "#bundle_id\t{}", groups.join("\t"))?;
+    bundle_ids
+        .iter()
+        .try_for_each(|bid| -> Result<(), std::io::Error> {
+            let (cn, cov): (Vec<_>, Vec<_>) = (0..groups.len())
+                .map(|idx| match cells.get(&(*bid, idx)) {
+                    Some(count) => (
+                        format!("{}", count.0),
+                        format!("{:.4}", coverage_fraction(*bid, count)),
+                    ),
+                    None => ("0".to_string(), "0".to_string()),
+                })
+                .unzip();
+            writeln!(output_cn_file, "{}\t{}", bid, cn.join("\t"))?;
+            writeln!(output_cov_file, "{}\t{}", bid, cov.join("\t"))?;
+            Ok(())
+        })?;
+
+    // the sparse matrices in the Matrix Market coordinate format with the row and column names in separated files
+    let mut output_rows_file = BufWriter::new(File::create(
+        output_prefix_path.with_extension("mtx_rows.tsv"),
+    )?);
+    bundle_ids
+        .iter()
+        .try_for_each(|bid| writeln!(output_rows_file, "{}\t{}", bid, bundle_size[bid]))?;
+    let mut output_cols_file = BufWriter::new(File::create(
+        output_prefix_path.with_extension("mtx_cols.tsv"),
+    )?);
+    groups
+        .iter()
+        .try_for_each(|g| writeln!(output_cols_file, "{}", g))?;
+
+    let bundle_to_row = bundle_ids
+        .iter()
+        .enumerate()
+        .map(|(idx, bid)| (*bid, idx + 1))
+        .collect::<FxHashMap<_, _>>();
+    let mut entries = cells
+        .iter()
+        .map(|((bid, group_idx), count)| (bundle_to_row[bid], group_idx + 1, *bid, count))
+        .collect::<Vec<_>>();
+    entries.sort_by_key(|e| (e.0, e.1));
+
+    let mut output_cn_mtx_file = BufWriter::new(File::create(
+        output_prefix_path.with_extension("copy_number.mtx"),
+    )?);
+    writeln!(
+        output_cn_mtx_file,
+        "%%MatrixMarket matrix coordinate integer general"
+    )?;
+    writeln!(
+        output_cn_mtx_file,
+        "{} {} {}",
+        bundle_ids.len(),
+        groups.len(),
+        entries.len()
+    )?;
+    entries.iter().try_for_each(|(row, col, _, count)| {
+        writeln!(output_cn_mtx_file, "{} {} {}", row, col, count.0)
+    })?;
+
+    let mut output_cov_mtx_file = BufWriter::new(File::create(
+        output_prefix_path.with_extension("coverage.mtx"),
+    )?);
+    writeln!(
+        output_cov_mtx_file,
+        "%%MatrixMarket matrix coordinate real general"
+    )?;
+    writeln!(
+        output_cov_mtx_file,
+        "{} {} {}",
+        bundle_ids.len(),
+        groups.len(),
+        entries.len()
+    )?;
+    entries.iter().try_for_each(|(row, col, bid, count)| {
+        writeln!(
+            output_cov_mtx_file,
+            "{} {} {:.4}",
+            row,
+            col,
+            coverage_fraction(*bid, count)
+        )
+    })?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    // bundle 0 has 10 vertices and bundle 1 has 4 vertices
+    const BED: &str = "\
+#ctg\tbgn\tend\tbundle
+HG002#1#ctg1\t0\t1000\t0:10:0:0:9
+HG002#1#ctg1\t1000\t1500\t1:4:0:0:3
+HG002#1#ctg1\t1500\t1600\t0:10:1:2:4
+HG002#2#ctg2\t0\t800\t0:10:1:5:0
+HG003#1#ctg3\t0\t100\t1:4:0:1:2
+";
+
+    fn get_cell(matrix: &BundleMatrix, bid: u32, group: &str) -> Option<(usize, f32)> {
+        let idx = matrix.groups.iter().position(|g| g == group).unwrap();
+        matrix
+            .cells
+            .get(&(bid, idx))
+            .map(|count| (count.0, matrix.coverage_fraction(bid, count)))
+    }
+
+    #[test]
+    fn test_get_group() {
+        assert_eq!(get_group("HG002#1#ctg1", GroupBy::Haplotype), "HG002#1");
+        assert_eq!(get_group("HG002#1#ctg1", GroupBy::Sample), "HG002");
+        assert_eq!(get_group("HG002#1#ctg1", GroupBy::Contig), "HG002#1#ctg1");
+        // a contig name not in the PanSN format is its own group
+        assert_eq!(get_group("chr1", GroupBy::Sample), "chr1");
+    }
+
+    #[test]
+    fn test_bundle_matrix() {
+        let ctg_data = parse_bed(BED.as_bytes()).unwrap();
+        let no_metadata = FxHashMap::default();
+
+        let matrix = get_bundle_matrix(&ctg_data, &no_metadata, GroupBy::Haplotype, 0);
+        assert_eq!(matrix.groups, vec!["HG002#1", "HG002#2", "HG003#1"]);
+        assert_eq!(matrix.bundle_ids, vec![0, 1]);
+        // two copies covering the vertices 0..=9
+        assert_eq!(get_cell(&matrix, 0, "HG002#1"), Some((2, 1.0)));
+        assert_eq!(get_cell(&matrix, 0, "HG002#2"), Some((1, 0.6)));
+        assert_eq!(get_cell(&matrix, 0, "HG003#1"), None);
+        assert_eq!(get_cell(&matrix, 1, "HG003#1"), Some((1, 0.5)));
+
+        let matrix = get_bundle_matrix(&ctg_data, &no_metadata, GroupBy::Sample, 0);
+        assert_eq!(matrix.groups, vec!["HG002", "HG003"]);
+        assert_eq!(get_cell(&matrix, 0, "HG002"), Some((3, 1.0)));
+
+        // the short segments are not counted
+        let matrix = get_bundle_matrix(&ctg_data, &no_metadata, GroupBy::Sample, 500);
+        assert_eq!(get_cell(&matrix, 0, "HG002"), Some((2, 1.0)));
+        assert_eq!(matrix.bundle_ids, vec![0, 1]);
+        assert_eq!(get_cell(&matrix, 1, "HG003"), None);
+
+        let metadata =
+            parse_metadata("# ctg\tgroup\nHG002#1#ctg1\tA\n\nHG003#1#ctg3\tA\n".as_bytes())
+                .unwrap();
+        let matrix = get_bundle_matrix(&ctg_data, &metadata, GroupBy::Contig, 0);
+        assert_eq!(matrix.groups, vec!["A", "HG002#2#ctg2"]);
+        assert_eq!(get_cell(&matrix, 1, "A"), Some((2, 1.0)));
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert!(parse_metadata("HG002#1#ctg1\n".as_bytes()).is_err());
+        assert!(parse_bed("ctg1\t0\t1000\n".as_bytes()).is_err());
+        assert!(parse_bed("ctg1\t0\t1000\t0:10:0\n".as_bytes()).is_err());
+        assert!(parse_bed("ctg1\t0\tx\t0:10:0:0:9\n".as_bytes()).is_err());
+        // the sub-level bundles of a hierarchical bed file are skipped
+        let ctg_data = parse_bed("ctg1\t0\t1000\t0:10:0:0:9\t1\t0\n".as_bytes()).unwrap();
+        assert!(ctg_data.is_empty());
+    }
+}
@@ -29,6 +29,10 @@ struct CmdOptions {
     #[clap(long, default_value_t = false)]
     fastx_file: bool,
 
+    /// read the input file as a GFA file, the path sequences from the P and W lines are indexed
+    #[clap(long, default_value_t = false)]
+    gfa_file: bool,
+
     #[clap(long, short, default_value_t = 80)]
     w: u32,
     /// minimizer k-mer size
@@ -124,6 +128,20 @@ fn main() -> Result<(), std::io::Error> {
             args.min_span,
             true,
         );
+    } else if args.gfa_file {
+        let stderr = io::stderr();
+        let mut handle = stderr.lock();
+        let _ = handle.write_all(
+            b"the option `--gfa-file` is specified, read the input file as a GFA file.\n",
+        );
+        seq_index_db.load_from_gfa(
+            &args.pgr_db_prefix,
+            args.w,
+            args.k,
+            args.r,
+            args.min_span,
+            false,
+        )?;
     } else {
         #[cfg(feature = "with_agc")]
         {
@@ -0,0 +1,307 @@
+const VERSION_STRING: &str = env!("VERSION_STRING");
+use clap::{self, CommandFactory, Parser};
+use pgr_db::ext::{BundleDecompLevelSpec, RegionDecompSpec, SeqIndexDB, ShmmrSpec};
+use pgr_db::pbundle::{
+    bundle_bed_line, bundle_tracks_to_svg, get_bundle_dendrogram, get_bundle_distances,
+    get_track_range, BundleDistanceMethod, BundleSegment, BundleSvgOptions, BundleSvgTrack,
+};
+use rustc_hash::FxHashMap;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+/// Decompose the sequences homologous to a region of a reference sequence in a PGR-TK database into
+/// principal bundles, output the bed, distance, newick and SVG files in one pass
+#[derive(Parser, Debug)]
+#[clap(name = "pgr-region-decomp")]
+#[clap(author, version)]
+#[clap(about, long_about = None)]
+struct CmdOptions {
+    /// the prefix to a PGR-TK sequence database
+    pgr_db_prefix: String,
+    /// the sample (source) name of the reference sequence
+    sample_name: String,
+    /// the contig name of the reference sequence
+    ctg_name: String,
+    /// the start of the region
+    bgn: usize,
+    /// the end of the region
+    end: usize,
+    /// the prefix of the output files
+    output_prefix: String,
+
+    /// using the frg format for the sequence database (default to the AGC backend database if not specified)
+    #[clap(long, default_value_t = false)]
+    frg_file: bool,
+    /// read the database as a fastx file
+    #[clap(long, default_value_t = false)]
+    fastx_file: bool,
+    /// read the database as a GFA file, the path sequences from the P and W lines are indexed
+    #[clap(long, default_value_t = false)]
+    gfa_file: bool,
+    /// the SHIMMER parameter w for indexing a fastx or GFA database
+    #[clap(long, short, default_value_t = 80)]
+    w: u32,
+    /// the SHIMMER parameter k for indexing a fastx or GFA database
+    #[clap(long, short, default_value_t = 56)]
+    k: u32,
+    /// the SHIMMER parameter r for indexing a fastx or GFA database
+    #[clap(long, short, default_value_t = 4)]
+    r: u32,
+    /// the SHIMMER parameter minimum span length for indexing a fastx or GFA database
+    #[clap(long, short, default_value_t = 64)]
+    min_span: u32,
+
+    /// the length added to both sides of the region for finding the homologous sequences
+    #[clap(long, default_value_t = 10000)]
+    flank_length: usize,
+    /// the gap penalty factor for sparse alignments in the SHIMMER space
+    #[clap(long, short, default_value_t = 0.025)]
+    gap_penalty_factor: f32,
+    /// merge hits with the specified distance
+    #[clap(long, default_value_t = 100000)]
+    merge_range_tol: usize,
+    /// the max count of SHIMMER used for the sparse alignment
+    #[clap(long, default_value_t = 128)]
+    max_count: u32,
+    /// the max count of SHIMMER in the query sequences used for the sparse alignment
+    #[clap(long, default_value_t = 128)]
+    max_query_count: u32,
+    /// the max count of SHIMMER in the targets sequences used for the sparse alignment
+    #[clap(long, default_value_t = 128)]
+    max_target_count: u32,
+    /// the span of the chain for building the sparse alignment directed acyclic graph
+    #[clap(long, default_value_t = 8)]
+    max_aln_chain_span: u32,
+    /// the minimum fraction of the query (with the flanks) covered by the alignment of a hit
+    #[clap(long, default_value_t = 0.5)]
+    min_query_coverage: f32,
+
+    /// the SHIMMER parameter w for the decomposition
+    #[clap(long, default_value_t = 48)]
+    decomp_w: u32,
+    /// the SHIMMER parameter k for the decomposition
+    #[clap(long, default_value_t = 56)]
+    decomp_k: u32,
+    /// the SHIMMER parameter r for the decomposition
+    #[clap(long, default_value_t = 4)]
+    decomp_r: u32,
+    /// the SHIMMER parameter minimum span length for the decomposition
+    #[clap(long, default_value_t = 12)]
+    decomp_min_span: u32,
+    /// vertex minimum coverage in MAP-graph to be included in principal bundles
+    #[clap(long, default_value_t = 0)]
+    min_cov: usize,
+    /// the minimum branch length in MAP-graph to be included in the principal bundles
+    #[clap(long, default_value_t = 8)]
+    min_branch_size: usize,
+    /// the minimum local project bundle size to includes
+    #[clap(long, default_value_t = 2500)]
+    bundle_length_cutoff: usize,
+    /// merge two bundles with the same id with the specified length
+    #[clap(long, default_value_t = 10000)]
+    bundle_merge_distance: usize,
+
+    /// the track panel size in pixel
+    #[clap(long, default_value_t = 1600)]
+    track_panel_width: usize,
+    /// number of threads used in parallel (more memory usage), default to "0" using all CPUs available or the number set by RAYON_NUM_THREADS
+    #[clap(long, default_value_t = 0)]
+    number_of_thread: usize,
+}
+
+fn main() -> Result<(), std::io::Error> {
+    CmdOptions::command().version(VERSION_STRING).get_matches();
+    let args = CmdOptions::parse();
+
+    rayon::ThreadPoolBuilder::new()
+        .num_threads(args.number_of_thread)
+        .build_global()
+        .unwrap();
+
+    let mut seq_index_db = SeqIndexDB::new();
+    if args.frg_file {
+        seq_index_db.load_from_frg_index(args.pgr_db_prefix.clone())?;
+    } else if args.fastx_file {
+        seq_index_db.load_from_fastx(
+            args.pgr_db_prefix.clone(),
+            args.w,
+            args.k,
+            args.r,
+            args.min_span,
+            true,
+        )?;
+    } else if args.gfa_file {
+        seq_index_db.load_from_gfa(
+            &args.pgr_db_prefix,
+            args.w,
+            args.k,
+            args.r,
+            args.min_span,
+            false,
+        )?;
+    } else {
+        #[cfg(feature = "with_agc")]
+        seq_index_db.load_from_agc_index(args.pgr_db_prefix.clone())?;
+
+        #[cfg(not(feature = "with_agc"))]
+        panic!("This command is compiled with only frg file support, please specify `--frg-file");
+    }
+
+    let spec = RegionDecompSpec {
+        gap_penalty_factor: args.gap_penalty_factor,
+        max_count: args.max_count,
+        max_query_count: args.max_query_count,
+        max_target_count: args.max_target_count,
+        max_aln_chain_span: args.max_aln_chain_span,
+        merge_range_tol: args.merge_range_tol,
+        min_query_coverage: args.min_query_coverage,
+        flank_length: args.flank_length,
+        decomp: BundleDecompLevelSpec {
+            shmmr_spec: ShmmrSpec {
+                w: args.decomp_w,
+                k: args.decomp_k,
+                r: args.decomp_r,
+                min_span: args.decomp_min_span,
+                sketch: false,
+            },
+            min_cov: args.min_cov,
+            min_branch_size: args.min_branch_size,
+            bundle_length_cutoff: args.bundle_length_cutoff,
+            bundle_merge_distance: args.bundle_merge_distance,
+        },
+    };
+    let region_decomp = seq_index_db.get_region_decomposition(
+        args.sample_name.clone(),
+        args.ctg_name.clone(),
+        args.bgn,
+        args.end,
+        &spec,
+    )?;
+    let (q_bgn, q_end) = region_decomp.query_range;
+    {
+        let stderr = io::stderr();
+        let mut handle = stderr.lock();
+        writeln!(
+            handle,
+            "{} homologous sequences found for {}:{}-{} (with the flanks)",
+            region_decomp.hits.len(),
+            args.ctg_name,
+            q_bgn,
+            q_end
+        )?;
+    }
+
+    let output_prefix_path = Path::new(&args.output_prefix);
+    let cmd_string = std::env::args().collect::<Vec<String>>().join(" ");
+
+    // the homologous sequences and their locations in the database
+    let mut output_fasta_file =
+        BufWriter::new(File::create(output_prefix_path.with_extension("fa"))?);
+    let mut output_hit_file =
+        BufWriter::new(File::create(output_prefix_path.with_extension("hit.bed"))?);
+    writeln!(
+        output_hit_file,
+        "#ctg\tbgn\tend\tname\torientation\taln_anchor_count"
+    )?;
+    let seq_info = seq_index_db.seq_info.as_ref().unwrap();
+    region_decomp.hits.iter().try_for_each(|hit| {
+        writeln!(output_fasta_file, ">{}", hit.name)?;
+        writeln!(output_fasta_file, "{}", String::from_utf8_lossy(&hit.seq))?;
+        writeln!(
+            output_hit_file,
+            "{}\t{}\t{}\t{}\t{}\t{}",
+            seq_info[&hit.sid].0, hit.bgn, hit.end, hit.name, hit.orientation, hit.anchor_count
+        )
+    })?;
+
+    // the bundle decomposition in the same format as `pgr-pbundle-decomp`
+    let mut output_bed_file =
+        BufWriter::new(File::create(output_prefix_path.with_extension("bed"))?);
+    writeln!(output_bed_file, "# cmd: {}", cmd_string)?;
+    let mut ctg_data = FxHashMap::<String, Vec<BundleSegment>>::default();
+    region_decomp.segments.iter().try_for_each(|seg| {
+        let bundle_segment = BundleSegment {
+            bgn: seg.bgn,
+            end: seg.end,
+            bundle_id: seg.bundle_id as u32,
+            bundle_v_count: seg.bundle_size as u32,
+            bundle_dir: seg.direction,
+            bundle_v_bgn: seg.bundle_v_bgn as u32,
+            bundle_v_end: seg.bundle_v_end as u32,
+        };
+        ctg_data
+            .entry(seg.ctg.clone())
+            .or_default()
+            .push(bundle_segment);
+        writeln!(
+            output_bed_file,
+            "{}",
+            bundle_bed_line(&seg.ctg, &bundle_segment, seg.is_repeat)
+        )
+    })?;
+
+    // the pairwise distances from the bundle alignments, the sequences without any bundle are included
+    let ctgs = region_decomp
+        .hits
+        .iter()
+        .map(|hit| hit.name.clone())
+        .collect::<Vec<_>>();
+    let ctg_bundles = ctgs
+        .iter()
+        .map(|ctg| ctg_data.get(ctg).cloned().unwrap_or_default())
+        .collect::<Vec<_>>();
+    let distances = get_bundle_distances(&ctg_bundles, BundleDistanceMethod::GlobalAlignment);
+    let mut output_dist_file =
+        BufWriter::new(File::create(output_prefix_path.with_extension("dist"))?);
+    distances.write_dist(&mut output_dist_file, &ctgs)?;
+
+    // the average linkage tree, the leaves are ordered as the tree for the SVG tracks
+    let leaf_labels = ctgs
+        .iter()
+        .map(|ctg| format!("'{}'", ctg.replace('\'', "''")))
+        .collect::<Vec<_>>();
+    let dendrogram = get_bundle_dendrogram(&distances, &leaf_labels);
+    let mut output_tree_file =
+        BufWriter::new(File::create(output_prefix_path.with_extension("nwk"))?);
+    writeln!(output_tree_file, "{};", dendrogram.newick)?;
+
+    // the bundle tracks with the tree on the left
+    let max_range = ctg_bundles
+        .iter()
+        .flat_map(|segments| segments.iter().map(|s| s.end as usize))
+        .chain(region_decomp.hits.iter().map(|hit| hit.seq.len()))
+        .max()
+        .unwrap_or(0);
+    let tracks = dendrogram
+        .leaf_order
+        .iter()
+        .map(|&ctg_idx| BundleSvgTrack {
+            ctg: ctgs[ctg_idx].clone(),
+            annotation: ctgs[ctg_idx].clone(),
+            segments: ctg_bundles[ctg_idx]
+                .iter()
+                .map(|s| (s.bgn, s.end, s.bundle_id, s.bundle_dir))
+                .collect(),
+            regions: vec![],
+            offset: 0,
+        })
+        .collect::<Vec<_>>();
+    let options = BundleSvgOptions {
+        track_range: get_track_range(max_range),
+        track_tick_interval: None,
+        track_panel_width: args.track_panel_width,
+        track_scaling: 1.0,
+        left_padding: 30,
+        stroke_width: 0.5,
+        annotation_region_track: false,
+        annotation_region_stroke_width: 2.5,
+        annotation_panel_width: 500.0,
+        highlight_repeats: 1.0,
+        no_tooltips: false,
+        h_factor: 1.5,
+    };
+    let document = bundle_tracks_to_svg(tracks, Some(&dendrogram), &FxHashMap::default(), &options);
+    svg::save(output_prefix_path.with_extension("svg"), &document)?;
+    Ok(())
+}
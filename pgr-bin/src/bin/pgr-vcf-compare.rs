@@ -0,0 +1,915 @@
+const VERSION_STRING: &str = env!("VERSION_STRING");
+use clap::{self, CommandFactory, Parser};
+use flate2::bufread::MultiGzDecoder;
+use iset::IntervalMap;
+use pgr_db::aln;
+use pgr_db::ext::{get_fastx_reader, GZFastaReader};
+use pgr_db::fasta_io::SeqRec;
+use rayon::prelude::*;
+use rustc_hash::{FxHashMap, FxHashSet};
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+
+/// Compare a VCF file (e.g. from pgr-alnmap) to a truth VCF file by haplotype-aware sequence equivalence
+#[derive(Parser, Debug)]
+#[clap(name = "pgr-vcf-compare")]
+#[clap(author, version)]
+#[clap(about, long_about = None)]
+struct CmdOptions {
+    /// path to the reference fasta file
+    reference_fasta_path: String,
+    /// path to the truth vcf file (plain text or gzipped)
+    truth_vcf_path: String,
+    /// path to the query vcf file to evaluate (plain text or gzipped)
+    query_vcf_path: String,
+    /// the prefix of the output files
+    output_prefix: String,
+    /// path to a bed file for stratification, the 4th column is used as the region label if present
+    #[clap(long)]
+    bed_path: Option<String>,
+    /// variants within this distance are evaluated together in the same local window
+    #[clap(long, default_value_t = 100)]
+    window_size: u32,
+    /// the maximum span of a local window, a window is split if it gets larger than this
+    #[clap(long, default_value_t = 10000)]
+    max_window_span: u32,
+    /// only use the records with FILTER as "PASS" or "."
+    #[clap(long, default_value_t = false)]
+    pass_only: bool,
+    /// the sample of the genotypes in both vcf files, default to the first sample, the records of a vcf file without
+    /// any sample column are treated as the haploid calls of the first alt alleles
+    #[clap(long)]
+    sample: Option<String>,
+    /// number of threads used in parallel (more memory usage), default to "0" using all CPUs available or the number set by RAYON_NUM_THREADS
+    #[clap(long, default_value_t = 0)]
+    number_of_thread: usize,
+}
+
+type Allele = (u32, Vec<u8>, Vec<u8>); // normalized (0-based position, ref bases, alt bases), no padding base
+
+#[derive(Clone, Debug)]
+struct VcfRecord {
+    line: String,
+    chr: String,
+    pos: u32, // 0-based
+    ref_seq: Vec<u8>,
+    alts: Vec<Vec<u8>>,
+    gt: Vec<usize>,               // the allele index for each haplotype
+    alleles: Vec<Option<Allele>>, // normalized alt alleles, None for the alleles that can not be evaluated
+}
+
+impl VcfRecord {
+    fn end(&self) -> u32 {
+        self.pos + self.ref_seq.len() as u32
+    }
+
+    fn used_alleles(&self) -> Vec<usize> {
+        let mut used = self
+            .gt
+            .iter()
+            .filter(|&&a| a > 0)
+            .copied()
+            .collect::<Vec<_>>();
+        used.sort();
+        used.dedup();
+        used
+    }
+
+    fn is_evaluable(&self) -> bool {
+        let used = self.used_alleles();
+        !used.is_empty() && used.iter().all(|&a| self.alleles[a - 1].is_some())
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum MatchType {
+    Haplotype,
+    Allele,
+    Alignment,
+    GenotypeMismatch,
+    Miss,
+    NotAssessed,
+}
+
+impl MatchType {
+    fn to_bk(self) -> &'static str {
+        match self {
+            MatchType::Haplotype => "hap",
+            MatchType::Allele => "allele",
+            MatchType::Alignment => "aln",
+            MatchType::GenotypeMismatch => "gm",
+            MatchType::Miss => "miss",
+            MatchType::NotAssessed => ".",
+        }
+    }
+
+    fn is_match(self) -> bool {
+        matches!(
+            self,
+            MatchType::Haplotype | MatchType::Allele | MatchType::Alignment
+        )
+    }
+}
+
+fn normalize_allele(ref_seq: &[u8], pos: u32, r: &[u8], a: &[u8]) -> Allele {
+    let mut r = r.to_vec();
+    let mut a = a.to_vec();
+    let mut pos = pos;
+    while !r.is_empty() && !a.is_empty() && r.last() == a.last() {
+        r.pop();
+        a.pop();
+    }
+    let prefix_len = r
+        .iter()
+        .zip(a.iter())
+        .take_while(|(rb, ab)| rb == ab)
+        .count();
+    r.drain(..prefix_len);
+    a.drain(..prefix_len);
+    pos += prefix_len as u32;
+
+    // left-align the insertions and the deletions
+    if r.is_empty() != a.is_empty() {
+        let seq = if r.is_empty() { &mut a } else { &mut r };
+        while pos > 0
+            && (pos as usize) <= ref_seq.len()
+            && ref_seq[pos as usize - 1] == *seq.last().unwrap()
+        {
+            seq.pop();
+            seq.insert(0, ref_seq[pos as usize - 1]);
+            pos -= 1;
+        }
+    }
+    (pos, r, a)
+}
+
+fn variant_type(allele: &Allele) -> &'static str {
+    let (_, r, a) = allele;
+    match (r.len(), a.len()) {
+        (1, 1) => "SNV",
+        (0, _) => "INS",
+        (_, 0) => "DEL",
+        _ => "COMPLEX",
+    }
+}
+
+fn variant_size(allele: &Allele) -> usize {
+    std::cmp::max(allele.1.len(), allele.2.len())
+}
+
+fn size_bin(size: usize) -> &'static str {
+    match size {
+        0..=1 => "1",
+        2..=49 => "2-49",
+        50..=999 => "50-999",
+        _ => "1000+",
+    }
+}
+
+/// get the genotype of the sample in the column `sample_column`, `None` if the sample column is missing
+fn parse_gt(fields: &[&str], sample_column: usize) -> Option<Vec<usize>> {
+    if fields.len() < 10 {
+        // no sample column, e.g. the VCF from pgr-alnmap, treat it as a haploid call of the first alt allele
+        return Some(vec![1]);
+    }
+    let sample = fields.get(sample_column)?;
+    let gt_idx = fields[8].split(':').position(|f| f == "GT");
+    if let Some(gt_idx) = gt_idx {
+        if let Some(gt) = sample.split(':').nth(gt_idx) {
+            return Some(
+                gt.split(['|', '/'])
+                    .map(|a| a.parse::<usize>().unwrap_or(0))
+                    .collect::<Vec<_>>(),
+            );
+        }
+    }
+    Some(vec![1])
+}
+
+fn get_vcf_reader(path: &str) -> Box<dyn BufRead> {
+    let reader = BufReader::new(File::open(Path::new(path)).expect("can't open the vcf file"));
+    if path.ends_with(".gz") {
+        Box::new(BufReader::new(MultiGzDecoder::new(reader)))
+    } else {
+        Box::new(reader)
+    }
+}
+
+fn load_vcf(
+    path: &str,
+    ref_seqs: &FxHashMap<String, Vec<u8>>,
+    pass_only: bool,
+    sample: Option<&str>,
+) -> Result<(Vec<String>, Vec<VcfRecord>), io::Error> {
+    parse_vcf(get_vcf_reader(path), ref_seqs, pass_only, sample)
+        .map_err(|err| io::Error::new(err.kind(), format!("{}: {}", path, err)))
+}
+
+fn parse_vcf<R: BufRead>(
+    reader: R,
+    ref_seqs: &FxHashMap<String, Vec<u8>>,
+    pass_only: bool,
+    sample: Option<&str>,
+) -> Result<(Vec<String>, Vec<VcfRecord>), io::Error> {
+    let mut header = Vec::<String>::new();
+    let mut records = Vec::<VcfRecord>::new();
+    let invalid_data = |msg: String| io::Error::new(io::ErrorKind::InvalidData, msg);
+    // the column of the genotypes, the first sample is used if the sample is not specified
+    let mut sample_column = 9;
+    for line in reader.lines() {
+        let line = line?;
+        if line.starts_with('#') {
+            if let (true, Some(sample)) = (line.starts_with("#CHROM"), sample) {
+                let columns = line.split('\t').collect::<Vec<&str>>();
+                if columns.len() > 9 {
+                    sample_column = columns
+                        .iter()
+                        .skip(9)
+                        .position(|&c| c == sample)
+                        .map(|p| p + 9)
+                        .ok_or_else(|| invalid_data(format!("sample {} is not found", sample)))?;
+                }
+            }
+            header.push(line);
+            continue;
+        }
+        let parse_err = || invalid_data(format!("fail to parse on {}", line));
+        let fields = line.split('\t').collect::<Vec<&str>>();
+        if fields.len() < 8 {
+            return Err(parse_err());
+        }
+        let chr = fields[0].to_string();
+        // POS is 1-based
+        let pos = fields[1]
+            .parse::<u32>()
+            .ok()
+            .and_then(|pos| pos.checked_sub(1))
+            .ok_or_else(parse_err)?;
+        let ref_seq = fields[3].to_uppercase().into_bytes();
+        let alts = fields[4]
+            .split(',')
+            .map(|a| a.to_uppercase().into_bytes())
+            .collect::<Vec<_>>();
+        let gt = if pass_only && fields[6] != "PASS" && fields[6] != "." {
+            vec![]
+        } else {
+            parse_gt(&fields[..], sample_column).ok_or_else(parse_err)?
+        };
+        if gt.iter().any(|&a| a > alts.len()) {
+            return Err(invalid_data(format!(
+                "the genotype has an allele not in ALT on {}",
+                line
+            )));
+        }
+        let alleles = alts
+            .iter()
+            .map(|alt| {
+                let is_symbolic = alt.iter().any(|&b| !b"ACGTN".contains(&b));
+                if is_symbolic || alt.is_empty() {
+                    return None;
+                };
+                ref_seqs
+                    .get(&chr)
+                    .map(|chr_seq| normalize_allele(chr_seq, pos, &ref_seq[..], &alt[..]))
+            })
+            .collect::<Vec<_>>();
+        records.push(VcfRecord {
+            line,
+            chr,
+            pos,
+            ref_seq,
+            alts,
+            gt,
+            alleles,
+        });
+    }
+    Ok((header, records))
+}
+
+/// the labeled regions of a bed file, the 4th column is the label, default to "BED"
+fn parse_bed_regions<R: BufRead>(
+    reader: R,
+) -> Result<FxHashMap<String, IntervalMap<u32, String>>, io::Error> {
+    let mut regions = FxHashMap::<String, IntervalMap<u32, String>>::default();
+    for line in reader.lines() {
+        let line = line?;
+        if line.starts_with('#') || line.trim().is_empty() {
+            continue;
+        };
+        let parse_err = || {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("fail to parse on {}", line),
+            )
+        };
+        let fields = line.split('\t').collect::<Vec<&str>>();
+        if fields.len() < 3 {
+            return Err(parse_err());
+        }
+        let chr = fields[0].to_string();
+        let bgn = fields[1].parse::<u32>().map_err(|_| parse_err())?;
+        let end = fields[2].parse::<u32>().map_err(|_| parse_err())?;
+        if bgn >= end {
+            return Err(parse_err());
+        }
+        let label = fields.get(3).unwrap_or(&"BED").to_string();
+        regions.entry(chr).or_default().insert(bgn..end, label);
+    }
+    Ok(regions)
+}
+
+// apply the variants of one haplotype to the reference segment, overlapping variants are ignored
+fn replay_haplotype(
+    chr_seq: &[u8],
+    window: (u32, u32),
+    records: &[&VcfRecord],
+    hap: usize,
+) -> Vec<u8> {
+    let mut out = Vec::<u8>::new();
+    let mut cur = window.0;
+    records.iter().for_each(|rec| {
+        let allele = rec.gt.get(hap).copied().unwrap_or(0);
+        if allele == 0 || rec.pos < cur || rec.end() > window.1 {
+            return;
+        }
+        out.extend_from_slice(&chr_seq[cur as usize..rec.pos as usize]);
+        out.extend_from_slice(&rec.alts[allele - 1][..]);
+        cur = rec.end();
+    });
+    out.extend_from_slice(&chr_seq[cur as usize..window.1 as usize]);
+    out
+}
+
+// get the canonical representation of the differences between the reference and a haplotype through alignment
+fn get_canonical_alleles(
+    chr_seq: &[u8],
+    window: (u32, u32),
+    hap_seq: &[u8],
+) -> Option<FxHashSet<Allele>> {
+    let ref_seg = &chr_seq[window.0 as usize..window.1 as usize];
+    if ref_seg == hap_seq {
+        return Some(FxHashSet::<Allele>::default());
+    }
+    let variants = aln::get_wfa_variant_segments(ref_seg, hap_seq, 1, None, 4, 4, 1)?;
+    let mut alleles = FxHashSet::<Allele>::default();
+    variants
+        .into_iter()
+        .for_each(|(t_pos, _q_pos, vt, tvs, qvs)| {
+            let pos = window.0 + t_pos;
+            if vt == 'X' {
+                // split multiple mismatches into SNVs
+                tvs.bytes()
+                    .zip(qvs.bytes())
+                    .enumerate()
+                    .for_each(|(i, (tb, qb))| {
+                        if tb != qb {
+                            alleles.insert((pos + i as u32, vec![tb], vec![qb]));
+                        }
+                    });
+            } else {
+                alleles.insert(normalize_allele(
+                    chr_seq,
+                    pos,
+                    tvs.as_bytes(),
+                    qvs.as_bytes(),
+                ));
+            }
+        });
+    Some(alleles)
+}
+
+// (side, record index) for side 0 = truth, 1 = query
+type RecordRef = (usize, usize);
+
+fn compare_window(
+    chr_seq: &[u8],
+    window: (u32, u32),
+    sides: [Vec<&VcfRecord>; 2],
+) -> [Vec<MatchType>; 2] {
+    let ploidy = sides
+        .iter()
+        .flat_map(|recs| recs.iter().map(|r| r.gt.len()))
+        .max()
+        .unwrap_or(1);
+
+    let haplotypes = sides
+        .iter()
+        .map(|recs| {
+            let mut haps = (0..ploidy)
+                .map(|hap| replay_haplotype(chr_seq, window, &recs[..], hap))
+                .collect::<Vec<_>>();
+            haps.sort();
+            haps
+        })
+        .collect::<Vec<_>>();
+
+    // tier 1: the haplotype sequences are identical, phasing is ignored as the haplotypes are sorted
+    if haplotypes[0] == haplotypes[1] {
+        return [
+            vec![MatchType::Haplotype; sides[0].len()],
+            vec![MatchType::Haplotype; sides[1].len()],
+        ];
+    }
+
+    // tier 2: the normalized alleles are identical and carried by the same numbers of the haplotypes,
+    // phasing is ignored
+    let allele_counts = sides
+        .iter()
+        .map(|recs| {
+            let mut counts = FxHashMap::<&Allele, usize>::default();
+            recs.iter().for_each(|r| {
+                r.gt.iter()
+                    .filter(|&&a| a > 0)
+                    .flat_map(|&a| r.alleles[a - 1].as_ref())
+                    .for_each(|allele| *counts.entry(allele).or_default() += 1)
+            });
+            counts
+        })
+        .collect::<Vec<_>>();
+
+    // tier 3: compare the alleles from aligning each haplotype to the reference and the numbers of the haplotypes
+    // carrying them, computed lazily, the alleles found with different haplotype counts are genotype mismatches
+    let mut canonical_counts: [Option<FxHashMap<Allele, usize>>; 2] = [None, None];
+    let has_left_anchor = sides
+        .iter()
+        .flat_map(|recs| recs.iter().map(|r| r.pos))
+        .min()
+        .map(|p| p > window.0)
+        .unwrap_or(false);
+
+    let mut results: [Vec<MatchType>; 2] = [vec![], vec![]];
+    (0..2).for_each(|side| {
+        let other = 1 - side;
+        results[side] = sides[side]
+            .iter()
+            .map(|rec| {
+                let used = rec.used_alleles();
+                if used.iter().all(|&a| {
+                    let allele = rec.alleles[a - 1].as_ref().unwrap();
+                    allele_counts[other].get(allele) == allele_counts[side].get(allele)
+                }) {
+                    return MatchType::Allele;
+                }
+                if !has_left_anchor {
+                    return MatchType::Miss;
+                }
+                if canonical_counts[other].is_none() {
+                    let mut counts = FxHashMap::<Allele, usize>::default();
+                    haplotypes[other].iter().for_each(|hap_seq| {
+                        if let Some(alleles) = get_canonical_alleles(chr_seq, window, hap_seq) {
+                            alleles
+                                .into_iter()
+                                .for_each(|allele| *counts.entry(allele).or_default() += 1);
+                        }
+                    });
+                    canonical_counts[other] = Some(counts);
+                }
+                let other_counts = canonical_counts[other].as_ref().unwrap();
+                let mut match_type = MatchType::Alignment;
+                for &a in used.iter() {
+                    let mut single = (*rec).clone();
+                    single.gt = vec![a];
+                    let hap_seq = replay_haplotype(chr_seq, window, &[&single], 0);
+                    let alleles = match get_canonical_alleles(chr_seq, window, &hap_seq) {
+                        Some(alleles) if !alleles.is_empty() => alleles,
+                        _ => return MatchType::Miss,
+                    };
+                    let hap_count = rec.gt.iter().filter(|&&g| g == a).count();
+                    for allele in alleles.iter() {
+                        match other_counts.get(allele) {
+                            None => return MatchType::Miss,
+                            Some(&count) if count != hap_count => {
+                                match_type = MatchType::GenotypeMismatch
+                            }
+                            _ => {}
+                        }
+                    }
+                }
+                match_type
+            })
+            .collect::<Vec<_>>();
+    });
+    results
+}
+
+fn main() -> Result<(), std::io::Error> {
+    CmdOptions::command().version(VERSION_STRING).get_matches();
+    let args = CmdOptions::parse();
+
+    rayon::ThreadPoolBuilder::new()
+        .num_threads(args.number_of_thread)
+        .build_global()
+        .unwrap();
+
+    let mut ref_seqs = FxHashMap::<String, Vec<u8>>::default();
+    let mut add_seqs = |seq_iter: &mut dyn Iterator<Item = io::Result<SeqRec>>| {
+        seq_iter.into_iter().for_each(|r| {
+            if let Ok(r) = r {
+                ref_seqs.insert(String::from_utf8_lossy(&r.id[..]).to_string(), r.seq);
+            };
+        });
+    };
+
+    match get_fastx_reader(args.reference_fasta_path.clone(), true)? {
+        #[allow(clippy::useless_conversion)] // the into_iter() is necessary for dyn patching
+        GZFastaReader::GZFile(reader) => add_seqs(&mut reader.into_iter()),
+
+        #[allow(clippy::useless_conversion)] // the into_iter() is necessary for dyn patching
+        GZFastaReader::RegularFile(reader) => add_seqs(&mut reader.into_iter()),
+    };
+
+    let (truth_header, truth_records) = load_vcf(
+        &args.truth_vcf_path,
+        &ref_seqs,
+        args.pass_only,
+        args.sample.as_deref(),
+    )?;
+    let (query_header, query_records) = load_vcf(
+        &args.query_vcf_path,
+        &ref_seqs,
+        args.pass_only,
+        args.sample.as_deref(),
+    )?;
+
+    let regions = match args.bed_path.as_ref() {
+        Some(bed_path) => parse_bed_regions(BufReader::new(File::open(Path::new(bed_path))?))
+            .map_err(|err| io::Error::new(err.kind(), format!("{}: {}", bed_path, err)))?,
+        None => FxHashMap::default(),
+    };
+
+    // group the evaluable records by the chromosomes
+    let mut chr_records = FxHashMap::<String, Vec<RecordRef>>::default();
+    [&truth_records, &query_records]
+        .iter()
+        .enumerate()
+        .for_each(|(side, records)| {
+            records.iter().enumerate().for_each(|(idx, rec)| {
+                if rec.is_evaluable() && ref_seqs.contains_key(&rec.chr) {
+                    chr_records
+                        .entry(rec.chr.clone())
+                        .or_default()
+                        .push((side, idx));
+                }
+            })
+        });
+
+    let get_record = |(side, idx): RecordRef| -> &VcfRecord {
+        if side == 0 {
+            &truth_records[idx]
+        } else {
+            &query_records[idx]
+        }
+    };
+
+    let match_results = chr_records
+        .par_iter()
+        .flat_map(|(chr, rec_refs)| {
+            let chr_seq = ref_seqs.get(chr).unwrap();
+            let mut rec_refs = rec_refs.clone();
+            rec_refs.sort_by_key(|&r| (get_record(r).pos, r));
+
+            // cluster the nearby records into local windows
+            let mut clusters = Vec::<(u32, u32, Vec<RecordRef>)>::new();
+            rec_refs.into_iter().for_each(|r| {
+                let rec = get_record(r);
+                if let Some(last) = clusters.last_mut() {
+                    if rec.pos <= last.1 + args.window_size
+                        && rec.end() <= last.0 + args.max_window_span
+                    {
+                        last.1 = std::cmp::max(last.1, rec.end());
+                        last.2.push(r);
+                        return;
+                    }
+                }
+                clusters.push((rec.pos, rec.end(), vec![r]));
+            });
+
+            clusters
+                .into_iter()
+                .flat_map(|(bgn, end, members)| {
+                    let window = (
+                        bgn.saturating_sub(args.window_size),
+                        std::cmp::min(end + args.window_size, chr_seq.len() as u32),
+                    );
+                    let mut sides: [Vec<RecordRef>; 2] = [vec![], vec![]];
+                    members.iter().for_each(|&r| sides[r.0].push(r));
+                    let results = compare_window(
+                        chr_seq,
+                        window,
+                        [
+                            sides[0].iter().map(|&r| get_record(r)).collect(),
+                            sides[1].iter().map(|&r| get_record(r)).collect(),
+                        ],
+                    );
+                    sides
+                        .into_iter()
+                        .zip(results)
+                        .flat_map(|(refs, res)| refs.into_iter().zip(res).collect::<Vec<_>>())
+                        .collect::<Vec<_>>()
+                })
+                .collect::<Vec<_>>()
+        })
+        .collect::<FxHashMap<RecordRef, MatchType>>();
+
+    // (region, variant type, size bin) -> [TP (truth), FN, TP (query), FP, FN (genotype mismatch), FP (genotype mismatch)]
+    let mut summary = FxHashMap::<(String, String, String), [u64; 6]>::default();
+    let mut annotations: [Vec<(MatchType, &'static str)>; 2] = [vec![], vec![]];
+    [&truth_records, &query_records]
+        .iter()
+        .enumerate()
+        .for_each(|(side, records)| {
+            annotations[side] = records
+                .iter()
+                .enumerate()
+                .map(|(idx, rec)| {
+                    let match_type = *match_results
+                        .get(&(side, idx))
+                        .unwrap_or(&MatchType::NotAssessed);
+                    if match_type == MatchType::NotAssessed {
+                        return (match_type, "NA");
+                    }
+                    let (decision, count_idx) = match (side, match_type.is_match()) {
+                        (0, true) => ("TP", 0),
+                        (0, false) => ("FN", 1),
+                        (_, true) => ("TP", 2),
+                        (_, false) => ("FP", 3),
+                    };
+
+                    // use the largest allele of the record for the stratification
+                    let allele = rec
+                        .used_alleles()
+                        .into_iter()
+                        .map(|a| rec.alleles[a - 1].clone().unwrap())
+                        .max_by_key(variant_size)
+                        .unwrap();
+                    let vtype = variant_type(&allele);
+                    let sbin = size_bin(variant_size(&allele));
+                    let mut region_labels = vec!["*".to_string()];
+                    if let Some(i_map) = regions.get(&rec.chr) {
+                        let mut labels = i_map
+                            .values(rec.pos..std::cmp::max(rec.end(), rec.pos + 1))
+                            .cloned()
+                            .collect::<Vec<_>>();
+                        labels.sort();
+                        labels.dedup();
+                        region_labels.extend(labels);
+                    }
+                    region_labels.iter().for_each(|region| {
+                        [vtype, "*"].iter().for_each(|vt| {
+                            [sbin, "*"].iter().for_each(|sb| {
+                                let e = summary
+                                    .entry((region.clone(), vt.to_string(), sb.to_string()))
+                                    .or_default();
+                                e[count_idx] += 1;
+                                if match_type == MatchType::GenotypeMismatch {
+                                    e[count_idx + 3] += 1;
+                                }
+                            })
+                        })
+                    });
+                    (match_type, decision)
+                })
+                .collect::<Vec<_>>();
+        });
+
+    let mut out_summary = BufWriter::new(
+        File::create(Path::new(&args.output_prefix).with_extension("summary.tsv")).unwrap(),
+    );
+    writeln!(
+        out_summary,
+        "#region\ttype\tsize\ttruth_total\tTP_truth\tFN\tquery_total\tTP_query\tFP\tprecision\trecall\tF1\tFN_genotype\tFP_genotype"
+    )
+    .expect("fail to write the summary file");
+    let mut summary = summary.into_iter().collect::<Vec<_>>();
+    summary.sort();
+    summary.into_iter().for_each(
+        |((region, vtype, sbin), [tp_t, fn_, tp_q, fp, fn_gt, fp_gt])| {
+            let precision = if tp_q + fp > 0 {
+                tp_q as f64 / (tp_q + fp) as f64
+            } else {
+                0.0
+            };
+            let recall = if tp_t + fn_ > 0 {
+                tp_t as f64 / (tp_t + fn_) as f64
+            } else {
+                0.0
+            };
+            let f1 = if precision + recall > 0.0 {
+                2.0 * precision * recall / (precision + recall)
+            } else {
+                0.0
+            };
+            writeln!(
+                out_summary,
+                "{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{:.6}\t{:.6}\t{:.6}\t{}\t{}",
+                region,
+                vtype,
+                sbin,
+                tp_t + fn_,
+                tp_t,
+                fn_,
+                tp_q + fp,
+                tp_q,
+                fp,
+                precision,
+                recall,
+                f1,
+                fn_gt,
+                fp_gt
+            )
+            .expect("fail to write the summary file");
+        },
+    );
+
+    let write_annotated_vcf =
+        |ext: &str,
+         header: &[String],
+         records: &[VcfRecord],
+         annotations: &[(MatchType, &'static str)]| {
+            let mut out_vcf = BufWriter::new(
+                File::create(Path::new(&args.output_prefix).with_extension(ext)).unwrap(),
+            );
+            header.iter().for_each(|line| {
+            if line.starts_with("#CHROM") {
+                writeln!(
+                    out_vcf,
+                    r#"##INFO=<ID=BD,Number=1,Type=String,Description="Benchmarking decision: TP, FP, FN or NA (not assessed)">"#
+                )
+                .expect("fail to write the vcf file");
+                writeln!(
+                    out_vcf,
+                    r#"##INFO=<ID=BK,Number=1,Type=String,Description="Match type: hap (haplotype sequence), allele (normalized allele and genotype), aln (alignment), gm (genotype mismatch, the alleles are found by the alignment on different numbers of haplotypes) or miss">"#
+                )
+                .expect("fail to write the vcf file");
+            }
+            writeln!(out_vcf, "{}", line).expect("fail to write the vcf file");
+        });
+            records
+                .iter()
+                .zip(annotations.iter())
+                .for_each(|(rec, (match_type, decision))| {
+                    let mut fields = rec.line.split('\t').collect::<Vec<&str>>();
+                    let info = if fields[7] == "." {
+                        format!("BD={};BK={}", decision, match_type.to_bk())
+                    } else {
+                        format!("{};BD={};BK={}", fields[7], decision, match_type.to_bk())
+                    };
+                    fields[7] = &info;
+                    writeln!(out_vcf, "{}", fields.join("\t")).expect("fail to write the vcf file");
+                });
+        };
+
+    write_annotated_vcf("truth.vcf", &truth_header, &truth_records, &annotations[0]);
+    write_annotated_vcf("query.vcf", &query_header, &query_records, &annotations[1]);
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const WINDOW: (u32, u32) = (50, 250);
+
+    /// a random reference with a homopolymer `CAAAAG` at 149..155
+    fn get_ref_seqs() -> FxHashMap<String, Vec<u8>> {
+        let mut state = 7_u32;
+        let mut seq = (0..300)
+            .map(|_| {
+                state = state.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+                b"ACGT"[(state >> 16) as usize % 4]
+            })
+            .collect::<Vec<_>>();
+        seq[149..155].copy_from_slice(b"CAAAAG");
+        [("chr1".to_string(), seq)].into_iter().collect()
+    }
+
+    /// a SNV at the 0-based position `pos` as a VCF record
+    fn snv(ref_seqs: &FxHashMap<String, Vec<u8>>, pos: usize, gt: &str) -> String {
+        let r = ref_seqs["chr1"][pos];
+        let a = if r == b'A' { b'C' } else { b'A' };
+        format!(
+            "chr1\t{}\t.\t{}\t{}\t.\tPASS\t.\tGT\t{}",
+            pos + 1,
+            r as char,
+            a as char,
+            gt
+        )
+    }
+
+    fn compare(
+        ref_seqs: &FxHashMap<String, Vec<u8>>,
+        truth: &[String],
+        query: &[String],
+    ) -> [Vec<MatchType>; 2] {
+        let parse = |lines: &[String]| {
+            parse_vcf(lines.join("\n").as_bytes(), ref_seqs, false, None)
+                .unwrap()
+                .1
+        };
+        let (truth, query) = (parse(truth), parse(query));
+        compare_window(
+            &ref_seqs["chr1"],
+            WINDOW,
+            [truth.iter().collect(), query.iter().collect()],
+        )
+    }
+
+    #[test]
+    fn test_haplotype_match() {
+        let ref_seqs = get_ref_seqs();
+        // the same deletion in the homopolymer at different positions and on different haplotypes
+        let truth = vec!["chr1\t150\t.\tCA\tC\t.\tPASS\t.\tGT\t0|1".to_string()];
+        let query = vec!["chr1\t153\t.\tAA\tA\t.\tPASS\t.\tGT\t1|0".to_string()];
+        let [truth_res, query_res] = compare(&ref_seqs, &truth, &query);
+        assert_eq!(truth_res, vec![MatchType::Haplotype]);
+        assert_eq!(query_res, vec![MatchType::Haplotype]);
+    }
+
+    #[test]
+    fn test_allele_match_and_miss() {
+        let ref_seqs = get_ref_seqs();
+        let truth = vec![snv(&ref_seqs, 100, "0|1"), snv(&ref_seqs, 200, "1|0")];
+        let query = vec![snv(&ref_seqs, 100, "1|0")];
+        let [truth_res, query_res] = compare(&ref_seqs, &truth, &query);
+        assert_eq!(truth_res, vec![MatchType::Allele, MatchType::Miss]);
+        assert_eq!(query_res, vec![MatchType::Allele]);
+    }
+
+    #[test]
+    fn test_genotype_mismatch() {
+        let ref_seqs = get_ref_seqs();
+        // a homozygous call is not a match of a heterozygous one, the allele is found by the alignment
+        let truth = vec![snv(&ref_seqs, 100, "1|1"), snv(&ref_seqs, 200, "1|0")];
+        let query = vec![snv(&ref_seqs, 100, "0|1")];
+        let [truth_res, query_res] = compare(&ref_seqs, &truth, &query);
+        assert_eq!(
+            truth_res,
+            vec![MatchType::GenotypeMismatch, MatchType::Miss]
+        );
+        assert_eq!(query_res, vec![MatchType::GenotypeMismatch]);
+        assert!(!MatchType::GenotypeMismatch.is_match());
+
+        // two SNVs as a MNP are an alignment match with the same genotype, a genotype mismatch otherwise
+        let mnp = |gt: &str| {
+            let (r0, r1) = (ref_seqs["chr1"][100], ref_seqs["chr1"][101]);
+            let alt = |b: u8| if b == b'A' { 'C' } else { 'A' };
+            format!(
+                "chr1\t101\t.\t{}{}\t{}{}\t.\tPASS\t.\tGT\t{}",
+                r0 as char,
+                r1 as char,
+                alt(r0),
+                alt(r1),
+                gt
+            )
+        };
+        let truth = vec![mnp("1|1"), snv(&ref_seqs, 200, "1|0")];
+        let query = vec![snv(&ref_seqs, 100, "1|1"), snv(&ref_seqs, 101, "1|1")];
+        let [truth_res, query_res] = compare(&ref_seqs, &truth, &query);
+        assert_eq!(truth_res, vec![MatchType::Alignment, MatchType::Miss]);
+        assert_eq!(query_res, vec![MatchType::Alignment, MatchType::Alignment]);
+        let query = vec![snv(&ref_seqs, 100, "0|1"), snv(&ref_seqs, 101, "0|1")];
+        let [truth_res, query_res] = compare(&ref_seqs, &truth, &query);
+        assert_eq!(
+            truth_res,
+            vec![MatchType::GenotypeMismatch, MatchType::Miss]
+        );
+        assert_eq!(
+            query_res,
+            vec![MatchType::GenotypeMismatch, MatchType::GenotypeMismatch]
+        );
+    }
+
+    #[test]
+    fn test_parse_vcf() {
+        let ref_seqs = get_ref_seqs();
+        let parse = |lines: &[&str], sample: Option<&str>| {
+            parse_vcf(lines.join("\n").as_bytes(), &ref_seqs, false, sample)
+        };
+        let header = "#CHROM\tPOS\tID\tREF\tALT\tQUAL\tFILTER\tINFO\tFORMAT\tS1\tS2";
+        let rec = "chr1\t101\t.\tA\tC,G\t.\tPASS\t.\tGT:DP\t0|1:10\t2/1:5";
+        let (_, records) = parse(&[header, rec], None).unwrap();
+        assert_eq!(records[0].pos, 100);
+        assert_eq!(records[0].gt, vec![0, 1]);
+        let (_, records) = parse(&[header, rec], Some("S2")).unwrap();
+        assert_eq!(records[0].gt, vec![2, 1]);
+        assert!(parse(&[header, rec], Some("S3")).is_err());
+
+        // POS is 1-based and the allele indices of GT must be in ALT
+        assert!(parse(&["chr1\t0\t.\tA\tC\t.\tPASS\t.\tGT\t0|1"], None).is_err());
+        assert!(parse(&["chr1\t101\t.\tA\tC\t.\tPASS\t.\tGT\t0|2"], None).is_err());
+        assert!(parse(&["chr1\t101\t.\tA\tC\t.\tPASS"], None).is_err());
+    }
+
+    #[test]
+    fn test_parse_bed_regions() {
+        let regions =
+            parse_bed_regions("#header\nchr1\t10\t20\tTR\nchr1\t30\t40\n".as_bytes()).unwrap();
+        let labels = regions["chr1"].values(0..100).cloned().collect::<Vec<_>>();
+        assert_eq!(labels, vec!["TR".to_string(), "BED".to_string()]);
+        assert!(parse_bed_regions("chr1\t10\n".as_bytes()).is_err());
+        assert!(parse_bed_regions("chr1\tx\t20\n".as_bytes()).is_err());
+        assert!(parse_bed_regions("chr1\t20\t10\n".as_bytes()).is_err());
+    }
+}
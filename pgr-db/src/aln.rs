@@ -1,4 +1,5 @@
 // use rayon::prelude::*;
+use crate::fasta_io::reverse_complement;
 use crate::seq_db::{self, FragmentHit};
 use crate::shmmrutils::{self, ShmmrSpec};
 use log::debug;
@@ -638,6 +639,307 @@ pub fn get_sw_variant_segments(
     }
 }
 
+// (target_pos, query_pos) of the k-mers that are unique in both sequences, sorted by target_pos
+fn get_unique_kmer_anchors(target_str: &[u8], query_str: &[u8], k: usize) -> Vec<(u32, u32)> {
+    if k == 0 || target_str.len() < k || query_str.len() < k {
+        return vec![];
+    }
+    let count_kmers = |seq: &[u8]| {
+        let mut kmers = FxHashMap::<Vec<u8>, (u32, u32)>::default(); // k-mer -> (position, count)
+        seq.windows(k).enumerate().for_each(|(pos, kmer)| {
+            let e = kmers.entry(kmer.to_vec()).or_insert((pos as u32, 0));
+            e.1 += 1;
+        });
+        kmers
+    };
+    let t_kmers = count_kmers(target_str);
+    let q_kmers = count_kmers(query_str);
+    let mut anchors = q_kmers
+        .into_iter()
+        .filter_map(|(kmer, (q_pos, q_count))| match t_kmers.get(&kmer) {
+            Some(&(t_pos, 1)) if q_count == 1 => Some((t_pos, q_pos)),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    anchors.sort();
+    anchors
+}
+
+// greedily chain the anchors from the start anchor, the gap between two anchors is limited by max_gap
+fn chain_anchors(anchors: &[(u32, u32)], start: (u32, u32), max_gap: u32) -> Vec<(u32, u32)> {
+    let mut chain = vec![start];
+    let mut current = start;
+    for &(t_pos, q_pos) in anchors {
+        if t_pos <= current.0 || q_pos <= current.1 {
+            continue;
+        }
+        if t_pos - current.0 > max_gap {
+            break;
+        }
+        if q_pos - current.1 > max_gap {
+            continue;
+        }
+        chain.push((t_pos, q_pos));
+        current = (t_pos, q_pos);
+    }
+    chain
+}
+
+fn align_small_segment(
+    target_str: &[u8],
+    query_str: &[u8],
+    mismatch_penalty: i32,
+    open_penalty: i32,
+    extension_penalty: i32,
+) -> Option<AlignmentResult> {
+    if target_str == query_str {
+        return Some(vec![]);
+    }
+    let len_diff = (target_str.len() as isize - query_str.len() as isize).unsigned_abs();
+    if len_diff < 128 {
+        if let Some(aln_res) = get_wfa_variant_segments(
+            target_str,
+            query_str,
+            1,
+            None,
+            mismatch_penalty,
+            open_penalty,
+            extension_penalty,
+        ) {
+            return Some(aln_res);
+        }
+    }
+    // keep the memory usage of the dynamic programming matrix bounded
+    if target_str.len() * query_str.len() <= (1 << 24) {
+        get_sw_variant_segments(
+            target_str,
+            query_str,
+            1,
+            mismatch_penalty,
+            open_penalty,
+            extension_penalty,
+        )
+    } else {
+        None
+    }
+}
+
+/// the k-mer size of the anchors used by `refine_sv_breakpoints()` in `pgr-alnmap` and `pgr-generate-sv-analysis`
+pub const SV_REFINE_ANCHOR_K: usize = 21;
+/// the maximum gap between two chained anchors used by `refine_sv_breakpoints()`
+pub const SV_REFINE_MAX_ANCHOR_GAP: u32 = 256;
+/// the mismatch penalty used by `refine_sv_breakpoints()`
+pub const SV_REFINE_MISMATCH_PENALTY: i32 = 4;
+/// the gap opening penalty used by `refine_sv_breakpoints()`
+pub const SV_REFINE_OPEN_PENALTY: i32 = 4;
+/// the gap extension penalty used by `refine_sv_breakpoints()`
+pub const SV_REFINE_EXTENSION_PENALTY: i32 = 1;
+
+/// Refine the breakpoints of a structural variant candidate to the base resolution
+///
+/// The target and query sequences should share at least `anchor_k` identical bases on both ends,
+/// e.g., the sequences of an `S` record from `pgr-alnmap`. Unique k-mer anchors are chained from both
+/// ends to find the colinear flanks, the flanks are aligned piecewise between the anchors and only the
+/// region between the two flanks is treated as the structural variant. The variant in the core region
+/// is reported as a deletion (`D`), an insertion (`I`), an inversion (`R`) or a complex replacement (`C`)
+/// if it is too large for a base-level alignment. The output uses the same convention as
+/// `get_wfa_variant_segments()`.
+pub fn refine_sv_breakpoints(
+    target_str: &[u8],
+    query_str: &[u8],
+    anchor_k: usize,
+    max_anchor_gap: u32,
+    mismatch_penalty: i32,
+    open_penalty: i32,
+    extension_penalty: i32,
+) -> Option<AlignmentResult> {
+    let k = anchor_k;
+    let t_len = target_str.len();
+    let q_len = query_str.len();
+    if k == 0
+        || t_len <= k
+        || q_len <= k
+        || target_str[..k] != query_str[..k]
+        || target_str[t_len - k..] != query_str[q_len - k..]
+    {
+        return None;
+    }
+
+    let anchors = get_unique_kmer_anchors(target_str, query_str, k);
+    let left_chain = chain_anchors(&anchors[..], (0, 0), max_anchor_gap);
+    let left_end = *left_chain.last().unwrap();
+    let (mut lt, mut lq) = (left_end.0 as usize + k, left_end.1 as usize + k);
+
+    // chain from the right end with the reversed coordinates, only use the anchors on the right of the left flank
+    let end_anchor = ((t_len - k) as u32, (q_len - k) as u32);
+    let right_chain = if left_end != end_anchor && lt <= t_len - k && lq <= q_len - k {
+        let mut r_anchors = anchors
+            .iter()
+            .filter(|&&(t_pos, q_pos)| t_pos as usize >= lt && q_pos as usize >= lq)
+            .map(|&(t_pos, q_pos)| (end_anchor.0 - t_pos, end_anchor.1 - q_pos))
+            .collect::<Vec<_>>();
+        r_anchors.sort();
+        chain_anchors(&r_anchors[..], (0, 0), max_anchor_gap)
+            .into_iter()
+            .map(|(t_pos, q_pos)| (end_anchor.0 - t_pos, end_anchor.1 - q_pos))
+            .rev()
+            .collect::<Vec<_>>()
+    } else {
+        vec![end_anchor]
+    };
+    let (mut rt, mut rq) = (right_chain[0].0 as usize, right_chain[0].1 as usize);
+
+    let align_flank = |chain: &[(u32, u32)]| -> Option<AlignmentResult> {
+        let mut flank_variants = AlignmentResult::new();
+        for w in chain.windows(2) {
+            let (t0, q0) = (w[0].0 as usize, w[0].1 as usize);
+            let (t1, q1) = (w[1].0 as usize + k, w[1].1 as usize + k);
+            let aln_res = align_small_segment(
+                &target_str[t0..t1],
+                &query_str[q0..q1],
+                mismatch_penalty,
+                open_penalty,
+                extension_penalty,
+            )?;
+            flank_variants.extend(aln_res.into_iter().map(|(td, qd, vt, t_str, q_str)| {
+                (td + t0 as u32, qd + q0 as u32, vt, t_str, q_str)
+            }));
+        }
+        Some(flank_variants)
+    };
+    let mut variants = align_flank(&left_chain[..])?;
+    // the ends of the last variant of the left flank, the left alignment of the core variant stops there
+    let (flank_t_end, flank_q_end) = variants.iter().fold((0, 0), |(t_end, q_end), v| {
+        (
+            std::cmp::max(t_end, v.0 as usize + v.3.len()),
+            std::cmp::max(q_end, v.1 as usize + v.4.len()),
+        )
+    });
+    let right_variants = align_flank(&right_chain[..])?;
+    // the starts of the first variant of the right flank
+    let (flank_t_bgn, flank_q_bgn) =
+        right_variants
+            .iter()
+            .fold((t_len, q_len), |(t_bgn, q_bgn), v| {
+                (
+                    std::cmp::min(t_bgn, v.0 as usize),
+                    std::cmp::min(q_bgn, v.1 as usize),
+                )
+            });
+    variants.extend(right_variants);
+
+    if left_end == end_anchor {
+        // the left flank covers the whole sequences
+        (lt, lq) = (rt, rq);
+    } else if rt < lt || rq < lq {
+        // the left flank overlaps with the right end, e.g., for a small indel near the end,
+        // realign the bases between the last left anchor and the right end
+        lt = left_end.0 as usize + 1;
+        lq = left_end.1 as usize + 1;
+        rt = std::cmp::max(rt, lt);
+        rq = std::cmp::max(rq, lq);
+    }
+    // clamp the core region along the flank diagonals so that it can't overlap the flank variants,
+    // the base before the core region is used as the padding base of the core variant
+    let shift = std::cmp::max(
+        (flank_t_end + 1).saturating_sub(lt),
+        (flank_q_end + 1).saturating_sub(lq),
+    );
+    let shift = shift.min(t_len - lt).min(q_len - lq);
+    lt += shift;
+    lq += shift;
+    rt = std::cmp::max(rt, lt);
+    rq = std::cmp::max(rq, lq);
+    let shift = std::cmp::max(
+        rt.saturating_sub(flank_t_bgn),
+        rq.saturating_sub(flank_q_bgn),
+    );
+    let shift = shift.min(rt - lt).min(rq - lq);
+    rt -= shift;
+    rq -= shift;
+
+    // extend the flanks to the base resolution
+    while lt < rt && lq < rq && target_str[lt] == query_str[lq] {
+        lt += 1;
+        lq += 1;
+    }
+    while rt > lt && rq > lq && target_str[rt - 1] == query_str[rq - 1] {
+        rt -= 1;
+        rq -= 1;
+    }
+
+    let t_core_len = rt - lt;
+    let q_core_len = rq - lq;
+    if q_core_len == 0 && t_core_len > 0 {
+        // left-align the deletion
+        while lt > flank_t_end + 1
+            && lq > flank_q_end + 1
+            && target_str[lt - 1] == target_str[rt - 1]
+        {
+            lt -= 1;
+            rt -= 1;
+            lq -= 1;
+        }
+        variants.push((
+            (lt - 1) as u32,
+            (lq - 1) as u32,
+            'D',
+            String::from_utf8_lossy(&target_str[lt - 1..rt]).to_string(),
+            String::from_utf8_lossy(&query_str[lq - 1..lq]).to_string(),
+        ));
+    } else if t_core_len == 0 && q_core_len > 0 {
+        // left-align the insertion
+        while lt > flank_t_end + 1 && lq > flank_q_end + 1 && query_str[lq - 1] == query_str[rq - 1]
+        {
+            lq -= 1;
+            rq -= 1;
+            lt -= 1;
+        }
+        variants.push((
+            (lt - 1) as u32,
+            (lq - 1) as u32,
+            'I',
+            String::from_utf8_lossy(&target_str[lt - 1..lt]).to_string(),
+            String::from_utf8_lossy(&query_str[lq - 1..rq]).to_string(),
+        ));
+    } else if t_core_len > 0 && q_core_len > 0 {
+        let t_core = &target_str[lt..rt];
+        let q_core = &query_str[lq..rq];
+        let min_core_len = std::cmp::min(t_core_len, q_core_len);
+        let is_inversion = min_core_len >= 2 * k && {
+            let rc_anchors = get_unique_kmer_anchors(t_core, &reverse_complement(q_core), k);
+            rc_anchors.len() * 2 > min_core_len - k
+        };
+        let core_aln = if is_inversion {
+            None
+        } else {
+            align_small_segment(
+                &target_str[lt - 1..rt],
+                &query_str[lq - 1..rq],
+                mismatch_penalty,
+                open_penalty,
+                extension_penalty,
+            )
+        };
+        if let Some(aln_res) = core_aln {
+            variants.extend(aln_res.into_iter().map(|(td, qd, vt, t_str, q_str)| {
+                (td + lt as u32 - 1, qd + lq as u32 - 1, vt, t_str, q_str)
+            }));
+        } else {
+            variants.push((
+                (lt - 1) as u32,
+                (lq - 1) as u32,
+                if is_inversion { 'R' } else { 'C' },
+                String::from_utf8_lossy(&target_str[lt - 1..rt]).to_string(),
+                String::from_utf8_lossy(&query_str[lq - 1..rq]).to_string(),
+            ));
+        }
+    }
+    variants.sort_by_key(|a| (a.0, a.1));
+    variants.dedup();
+    Some(variants)
+}
+
 #[cfg(test)]
 mod test {
 
@@ -755,4 +1057,120 @@ mod test {
         };
         // TODO: Test the output properly
     }
+
+    #[test]
+    fn test_refine_sv_breakpoints() {
+        use crate::aln::refine_sv_breakpoints;
+        use crate::fasta_io::reverse_complement;
+
+        let mut state = 42_u64;
+        let mut random_seq = |len: usize| {
+            (0..len)
+                .map(|_| {
+                    state = state.wrapping_mul(6364136223846793005).wrapping_add(1);
+                    b"ACGT"[(state >> 33) as usize % 4]
+                })
+                .collect::<Vec<u8>>()
+        };
+        let apply_variants = |t_str: &[u8], variants: &[(u32, u32, char, String, String)]| {
+            let mut out = Vec::<u8>::new();
+            let mut cur = 0_usize;
+            variants.iter().for_each(|(td, _qd, _vt, tvs, qvs)| {
+                out.extend_from_slice(&t_str[cur..*td as usize]);
+                out.extend_from_slice(qvs.as_bytes());
+                cur = *td as usize + tvs.len();
+            });
+            out.extend_from_slice(&t_str[cur..]);
+            out
+        };
+
+        let left = random_seq(600);
+        let core = random_seq(2000);
+        let right = random_seq(600);
+
+        // a large deletion with a SNV in the left flank
+        let t_str = [&left[..], &core[..], &right[..]].concat();
+        let mut q_left = left.clone();
+        q_left[300] = if q_left[300] == b'A' { b'C' } else { b'A' };
+        let q_str = [&q_left[..], &right[..]].concat();
+        let variants = refine_sv_breakpoints(&t_str, &q_str, 21, 256, 4, 4, 1).unwrap();
+        assert_eq!(apply_variants(&t_str, &variants), q_str);
+        assert_eq!(variants.len(), 2);
+        assert_eq!(variants[0].2, 'X');
+        assert_eq!(variants[0].0, 300);
+        assert_eq!(variants[1].2, 'D');
+        assert_eq!(variants[1].3.len(), core.len() + 1);
+
+        // a large insertion
+        let t_str = [&left[..], &right[..]].concat();
+        let q_str = [&left[..], &core[..], &right[..]].concat();
+        let variants = refine_sv_breakpoints(&t_str, &q_str, 21, 256, 4, 4, 1).unwrap();
+        assert_eq!(apply_variants(&t_str, &variants), q_str);
+        assert_eq!(variants.len(), 1);
+        assert_eq!(variants[0].2, 'I');
+        assert_eq!(variants[0].4.len(), core.len() + 1);
+
+        // an inversion
+        let t_str = [&left[..], &core[..], &right[..]].concat();
+        let q_str = [&left[..], &reverse_complement(&core)[..], &right[..]].concat();
+        let variants = refine_sv_breakpoints(&t_str, &q_str, 21, 256, 4, 4, 1).unwrap();
+        assert_eq!(apply_variants(&t_str, &variants), q_str);
+        assert_eq!(variants.len(), 1);
+        assert_eq!(variants[0].2, 'R');
+
+        // the ends need to be identical
+        assert!(refine_sv_breakpoints(&core, &left, 21, 256, 4, 4, 1).is_none());
+
+        // a repeat contraction longer than the anchor gap at the start of the sequences,
+        // the deletion is left-aligned to the first base
+        let repeat = b"AC".repeat(400);
+        let t_str = [&repeat[..], &core[..600], &right[..]].concat();
+        let q_str = [&repeat[..200], &core[..600], &right[..]].concat();
+        let variants = refine_sv_breakpoints(&t_str, &q_str, 21, 256, 4, 4, 1).unwrap();
+        assert_eq!(apply_variants(&t_str, &variants), q_str);
+        assert_eq!(variants.len(), 1);
+        assert_eq!(variants[0].2, 'D');
+        assert_eq!((variants[0].0, variants[0].1), (0, 0));
+
+        // a repeat expansion at the start of the sequences
+        let variants = refine_sv_breakpoints(&q_str, &t_str, 21, 256, 4, 4, 1).unwrap();
+        assert_eq!(apply_variants(&q_str, &variants), t_str);
+        assert_eq!(variants.len(), 1);
+        assert_eq!(variants[0].2, 'I');
+        assert_eq!((variants[0].0, variants[0].1), (0, 0));
+
+        // a repeat contraction after a deletion in the left flank, the query offsets are shifted
+        // and the deletion is not left-aligned beyond the flank
+        let t_str = [&left[..], &repeat[..], &core[..600], &right[..]].concat();
+        let q_str = [
+            &left[..200],
+            &left[210..],
+            &repeat[..200],
+            &core[..600],
+            &right[..],
+        ]
+        .concat();
+        let variants = refine_sv_breakpoints(&t_str, &q_str, 21, 256, 4, 4, 1).unwrap();
+        assert_eq!(apply_variants(&t_str, &variants), q_str);
+        let (flank_variants, core_variant) = variants.split_at(variants.len() - 1);
+        assert!(flank_variants.iter().all(|v| v.2 == 'D' && v.0 < 600));
+        assert_eq!(core_variant[0].2, 'D');
+        assert_eq!(core_variant[0].3.len(), 601);
+        assert_eq!(core_variant[0].0 - core_variant[0].1, 10);
+
+        // a repeat contraction right after a SNV near the end of the left flank, the left flank
+        // overlaps with the right end and the core variant can't overlap the flank variants
+        let t_str = [&left[..100], &repeat[..30], &repeat[..30]].concat();
+        let mut q_str = [&left[..100], &repeat[..30]].concat();
+        q_str[90] = if q_str[90] == b'A' { b'C' } else { b'A' };
+        let variants = refine_sv_breakpoints(&t_str, &q_str, 21, 256, 4, 4, 1).unwrap();
+        assert_eq!(apply_variants(&t_str, &variants), q_str);
+        assert!(variants
+            .windows(2)
+            .all(|w| w[0].0 as usize + w[0].3.len() <= w[1].0 as usize));
+        assert_eq!(variants.len(), 2);
+        assert_eq!((variants[0].0, variants[0].2), (90, 'X'));
+        assert_eq!(variants[1].2, 'D');
+        assert_eq!(variants[1].3.len(), 31);
+    }
 }
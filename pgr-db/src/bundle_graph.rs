@@ -0,0 +1,676 @@
+// A bidirected graph of the principal bundles built from the bundle decomposition of the sequences
+
+use rustc_hash::{FxHashMap, FxHashSet};
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Write};
+
+/// (bundle_id, direction), direction = 0 for the forward and 1 for the reverse strand of a bundle
+pub type BundleStep = (usize, u32);
+
+/// an edge between two oriented bundles, stored in the canonical orientation
+pub type BundleEdge = (BundleStep, BundleStep);
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct BundleWalkSegment {
+    pub bundle_id: usize,
+    pub direction: u32,
+    pub bgn: u32,
+    pub end: u32,
+}
+
+/// the bundle segments of a sequence, ordered by the coordinates in the sequence
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct BundleWalk {
+    pub name: String,
+    pub segments: Vec<BundleWalkSegment>,
+}
+
+impl BundleWalk {
+    pub fn steps(&self) -> Vec<BundleStep> {
+        self.segments
+            .iter()
+            .map(|s| (s.bundle_id, s.direction))
+            .collect()
+    }
+
+    /// the (sample, haplotype, contig) from a PanSN style name `sample#haplotype#contig`,
+    /// a name without the PanSN delimiters is used as both the sample and the contig name with haplotype 0
+    pub fn pansn_name(&self) -> (String, String, String) {
+        let fields = self.name.splitn(3, '#').collect::<Vec<_>>();
+        if fields.len() == 3 {
+            (
+                fields[0].to_string(),
+                fields[1].to_string(),
+                fields[2].to_string(),
+            )
+        } else {
+            (self.name.clone(), "0".to_string(), self.name.clone())
+        }
+    }
+
+    pub fn haplotype(&self) -> String {
+        let (sample, hap, _) = self.pansn_name();
+        format!("{}#{}", sample, hap)
+    }
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct BundleNodeData {
+    pub segment_count: usize,
+    pub total_length: usize,
+    pub haplotype_count: usize,
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct BundleEdgeData {
+    pub traversal_count: usize,
+    pub haplotype_count: usize,
+}
+
+/// an allele of a bubble: the steps between the source and the sink and the walks supporting it
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct BubbleAllele {
+    pub steps: Vec<BundleStep>,
+    pub walks: Vec<String>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct BundleBubble {
+    pub source: BundleStep,
+    pub sink: BundleStep,
+    pub alleles: Vec<BubbleAllele>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum RearrangementType {
+    Inversion,
+    Transposition,
+    Duplication,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct BundleRearrangement {
+    pub walk: String,
+    pub rearrangement_type: RearrangementType,
+    pub bundle_id: usize,
+    pub bgn: u32,
+    pub end: u32,
+}
+
+/// the core bundle order of a walk, oriented to the reference walk
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct HaplotypeBundleOrder {
+    pub walk: String,
+    pub reversed: bool,
+    pub steps: Vec<BundleStep>,
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct BundleGraph {
+    pub walks: Vec<BundleWalk>,
+    pub nodes: FxHashMap<usize, BundleNodeData>,
+    pub edges: FxHashMap<BundleEdge, BundleEdgeData>,
+}
+
+fn reverse_step(step: BundleStep) -> BundleStep {
+    (step.0, 1 - step.1)
+}
+
+fn canonical_edge(s0: BundleStep, s1: BundleStep) -> BundleEdge {
+    let rev = (reverse_step(s1), reverse_step(s0));
+    if (s0, s1) <= rev {
+        (s0, s1)
+    } else {
+        rev
+    }
+}
+
+fn reverse_walk(walk: &BundleWalk) -> Vec<BundleWalkSegment> {
+    walk.segments
+        .iter()
+        .rev()
+        .map(|s| BundleWalkSegment {
+            direction: 1 - s.direction,
+            ..s.clone()
+        })
+        .collect()
+}
+
+// the indices of a longest strictly increasing subsequence of `v`
+fn longest_increasing_subsequence(v: &[usize]) -> FxHashSet<usize> {
+    let mut tails = Vec::<usize>::new(); // the index of the smallest tail of each length
+    let mut prev = vec![None; v.len()];
+    v.iter().enumerate().for_each(|(i, &x)| {
+        let pos = tails.partition_point(|&t| v[t] < x);
+        if pos > 0 {
+            prev[i] = Some(tails[pos - 1]);
+        }
+        if pos == tails.len() {
+            tails.push(i);
+        } else {
+            tails[pos] = i;
+        }
+    });
+    let mut lis = FxHashSet::<usize>::default();
+    let mut cur = tails.last().copied();
+    while let Some(i) = cur {
+        lis.insert(i);
+        cur = prev[i];
+    }
+    lis
+}
+
+/// read the bundle walks from a bed file generated by `pgr-pbundle-decomp`, only the top level bundles
+/// are used for a hierarchical bed file
+pub fn read_bundle_walks_from_bed(path: &str) -> Result<Vec<BundleWalk>, io::Error> {
+    let bed_file = BufReader::new(File::open(path)?);
+    let parse_err = |line: &str| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("bed file parsing error: {}", line),
+        )
+    };
+    let mut ctg_segments = FxHashMap::<String, Vec<BundleWalkSegment>>::default();
+    let mut ctg_names = Vec::<String>::new();
+    for line in bed_file.lines() {
+        let line = line?;
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let bed_fields = line.split('\t').collect::<Vec<&str>>();
+        if bed_fields.len() < 4 {
+            return Err(parse_err(line));
+        }
+        if bed_fields.len() > 5 && bed_fields[4] != "0" {
+            continue;
+        }
+        let pbundle_fields = bed_fields[3].split(':').collect::<Vec<&str>>();
+        if pbundle_fields.len() < 3 {
+            return Err(parse_err(line));
+        }
+        let segment = BundleWalkSegment {
+            bundle_id: pbundle_fields[0].parse().map_err(|_| parse_err(line))?,
+            direction: pbundle_fields[2].parse().map_err(|_| parse_err(line))?,
+            bgn: bed_fields[1].parse().map_err(|_| parse_err(line))?,
+            end: bed_fields[2].parse().map_err(|_| parse_err(line))?,
+        };
+        let ctg = bed_fields[0].to_string();
+        if !ctg_segments.contains_key(&ctg) {
+            ctg_names.push(ctg.clone());
+        }
+        ctg_segments.entry(ctg).or_default().push(segment);
+    }
+    Ok(ctg_names
+        .into_iter()
+        .map(|name| {
+            let mut segments = ctg_segments.remove(&name).unwrap();
+            segments.sort_by_key(|s| (s.bgn, s.end));
+            BundleWalk { name, segments }
+        })
+        .collect())
+}
+
+impl BundleGraph {
+    pub fn from_walks(walks: Vec<BundleWalk>) -> Self {
+        let mut node_haplotypes = FxHashMap::<usize, FxHashSet<String>>::default();
+        let mut edge_haplotypes = FxHashMap::<BundleEdge, FxHashSet<String>>::default();
+        let mut nodes = FxHashMap::<usize, BundleNodeData>::default();
+        let mut edges = FxHashMap::<BundleEdge, BundleEdgeData>::default();
+        walks.iter().for_each(|walk| {
+            let haplotype = walk.haplotype();
+            walk.segments.iter().for_each(|s| {
+                let node = nodes.entry(s.bundle_id).or_default();
+                node.segment_count += 1;
+                node.total_length += (s.end - s.bgn) as usize;
+                node_haplotypes
+                    .entry(s.bundle_id)
+                    .or_default()
+                    .insert(haplotype.clone());
+            });
+            let steps = walk.steps();
+            steps.windows(2).for_each(|w| {
+                let edge = canonical_edge(w[0], w[1]);
+                edges.entry(edge).or_default().traversal_count += 1;
+                edge_haplotypes
+                    .entry(edge)
+                    .or_default()
+                    .insert(haplotype.clone());
+            });
+        });
+        node_haplotypes.into_iter().for_each(|(bid, haplotypes)| {
+            nodes.get_mut(&bid).unwrap().haplotype_count = haplotypes.len();
+        });
+        edge_haplotypes.into_iter().for_each(|(edge, haplotypes)| {
+            edges.get_mut(&edge).unwrap().haplotype_count = haplotypes.len();
+        });
+        BundleGraph {
+            walks,
+            nodes,
+            edges,
+        }
+    }
+
+    /// write the graph as GFA 1.1, each bundle is a segment without sequence, each walk is written as
+    /// a `W` line with the PanSN name fields if `use_w_lines` is true, or as a `P` line otherwise
+    pub fn write_gfa<W: Write>(&self, out: &mut W, use_w_lines: bool) -> Result<(), io::Error> {
+        let strand = |d: u32| if d == 0 { '+' } else { '-' };
+        writeln!(out, "H\tVN:Z:1.1")?;
+        let mut bundle_ids = self.nodes.keys().copied().collect::<Vec<_>>();
+        bundle_ids.sort();
+        bundle_ids.iter().try_for_each(|bid| {
+            let node = &self.nodes[bid];
+            writeln!(
+                out,
+                "S\t{}\t*\tLN:i:{}\tSC:i:{}\tHC:i:{}",
+                bid,
+                node.total_length / node.segment_count,
+                node.segment_count,
+                node.haplotype_count
+            )
+        })?;
+        let mut edges = self.edges.iter().collect::<Vec<_>>();
+        edges.sort_by_key(|(edge, _)| **edge);
+        edges
+            .into_iter()
+            .try_for_each(|(((b0, d0), (b1, d1)), data)| {
+                writeln!(
+                    out,
+                    "L\t{}\t{}\t{}\t{}\t0M\tRC:i:{}\tHC:i:{}",
+                    b0,
+                    strand(*d0),
+                    b1,
+                    strand(*d1),
+                    data.traversal_count,
+                    data.haplotype_count
+                )
+            })?;
+        self.walks.iter().try_for_each(|walk| {
+            if walk.segments.is_empty() {
+                return Ok(());
+            }
+            if use_w_lines {
+                let (sample, hap, ctg) = walk.pansn_name();
+                let path = walk
+                    .segments
+                    .iter()
+                    .map(|s| {
+                        format!(
+                            "{}{}",
+                            if s.direction == 0 { '>' } else { '<' },
+                            s.bundle_id
+                        )
+                    })
+                    .collect::<Vec<_>>()
+                    .join("");
+                writeln!(
+                    out,
+                    "W\t{}\t{}\t{}\t{}\t{}\t{}",
+                    sample,
+                    hap,
+                    ctg,
+                    walk.segments[0].bgn,
+                    walk.segments[walk.segments.len() - 1].end,
+                    path
+                )
+            } else {
+                let path = walk
+                    .segments
+                    .iter()
+                    .map(|s| format!("{}{}", s.bundle_id, strand(s.direction)))
+                    .collect::<Vec<_>>()
+                    .join(",");
+                writeln!(out, "P\t{}\t{}\t*", walk.name, path)
+            }
+        })
+    }
+
+    /// the bundles appearing exactly once in at least `min_fraction` of the walks
+    pub fn get_core_bundles(&self, min_fraction: f32) -> FxHashSet<usize> {
+        let mut single_copy_count = FxHashMap::<usize, usize>::default();
+        self.walks.iter().for_each(|walk| {
+            let mut count = FxHashMap::<usize, usize>::default();
+            walk.segments.iter().for_each(|s| {
+                *count.entry(s.bundle_id).or_default() += 1;
+            });
+            count.into_iter().for_each(|(bid, c)| {
+                if c == 1 {
+                    *single_copy_count.entry(bid).or_default() += 1;
+                }
+            });
+        });
+        let min_count = (min_fraction * self.walks.len() as f32).ceil().max(1.0) as usize;
+        single_copy_count
+            .into_iter()
+            .filter(|(_, c)| *c >= min_count)
+            .map(|(bid, _)| bid)
+            .collect()
+    }
+
+    // the index of the reference walk, the walk with the most core bundles, ties are broken by the name
+    fn get_reference_walk(&self, core_bundles: &FxHashSet<usize>) -> Option<usize> {
+        (0..self.walks.len()).max_by(|&i, &j| {
+            let ci = self.walks[i]
+                .segments
+                .iter()
+                .filter(|s| core_bundles.contains(&s.bundle_id))
+                .count();
+            let cj = self.walks[j]
+                .segments
+                .iter()
+                .filter(|s| core_bundles.contains(&s.bundle_id))
+                .count();
+            ci.cmp(&cj)
+                .then_with(|| self.walks[j].name.cmp(&self.walks[i].name))
+        })
+    }
+
+    // the walks oriented to the reference walk with the reversed flags, and the rank and
+    // direction of the core bundles in the reference walk
+    #[allow(clippy::type_complexity)]
+    fn get_oriented_walks(
+        &self,
+        core_bundles: &FxHashSet<usize>,
+    ) -> (
+        Vec<(bool, Vec<BundleWalkSegment>)>,
+        FxHashMap<usize, (usize, u32)>,
+    ) {
+        let ref_idx = match self.get_reference_walk(core_bundles) {
+            Some(idx) => idx,
+            None => return (vec![], FxHashMap::default()),
+        };
+        let single_copy = |segments: &[BundleWalkSegment]| {
+            let mut count = FxHashMap::<usize, usize>::default();
+            segments.iter().for_each(|s| {
+                *count.entry(s.bundle_id).or_default() += 1;
+            });
+            count
+                .into_iter()
+                .filter(|(bid, c)| *c == 1 && core_bundles.contains(bid))
+                .map(|(bid, _)| bid)
+                .collect::<FxHashSet<usize>>()
+        };
+        let ref_segments = &self.walks[ref_idx].segments;
+        let ref_single_copy = single_copy(ref_segments);
+        let ref_rank = ref_segments
+            .iter()
+            .filter(|s| ref_single_copy.contains(&s.bundle_id))
+            .enumerate()
+            .map(|(rank, s)| (s.bundle_id, (rank, s.direction)))
+            .collect::<FxHashMap<usize, (usize, u32)>>();
+
+        let oriented_walks = self
+            .walks
+            .iter()
+            .map(|walk| {
+                let walk_single_copy = single_copy(&walk.segments);
+                let (same, opposite) = walk
+                    .segments
+                    .iter()
+                    .filter(|s| walk_single_copy.contains(&s.bundle_id))
+                    .filter_map(|s| ref_rank.get(&s.bundle_id).map(|v| v.1 == s.direction))
+                    .fold((0, 0), |(same, opposite), is_same| {
+                        if is_same {
+                            (same + 1, opposite)
+                        } else {
+                            (same, opposite + 1)
+                        }
+                    });
+                if opposite > same {
+                    (true, reverse_walk(walk))
+                } else {
+                    (false, walk.segments.clone())
+                }
+            })
+            .collect::<Vec<_>>();
+        (oriented_walks, ref_rank)
+    }
+
+    /// the order of the core bundles in each walk, the walks are oriented to the reference walk,
+    /// the walk with the most core bundles
+    pub fn get_haplotype_bundle_orders(&self, min_fraction: f32) -> Vec<HaplotypeBundleOrder> {
+        let core_bundles = self.get_core_bundles(min_fraction);
+        let (oriented_walks, _) = self.get_oriented_walks(&core_bundles);
+        self.walks
+            .iter()
+            .zip(oriented_walks)
+            .map(|(walk, (reversed, segments))| HaplotypeBundleOrder {
+                walk: walk.name.clone(),
+                reversed,
+                steps: segments
+                    .iter()
+                    .filter(|s| core_bundles.contains(&s.bundle_id))
+                    .map(|s| (s.bundle_id, s.direction))
+                    .collect(),
+            })
+            .collect()
+    }
+
+    /// the inversions, transpositions and duplications of the core bundles in each walk relative to the
+    /// reference walk, the coordinates are those of the bundle segments in the walk
+    pub fn get_rearrangements(&self, min_fraction: f32) -> Vec<BundleRearrangement> {
+        let core_bundles = self.get_core_bundles(min_fraction);
+        let (oriented_walks, ref_rank) = self.get_oriented_walks(&core_bundles);
+        let mut rearrangements = vec![];
+        self.walks
+            .iter()
+            .zip(oriented_walks)
+            .for_each(|(walk, (_, segments))| {
+                let mut count = FxHashMap::<usize, usize>::default();
+                segments.iter().for_each(|s| {
+                    *count.entry(s.bundle_id).or_default() += 1;
+                });
+                let add = |rearrangements: &mut Vec<_>, t, s: &BundleWalkSegment| {
+                    rearrangements.push(BundleRearrangement {
+                        walk: walk.name.clone(),
+                        rearrangement_type: t,
+                        bundle_id: s.bundle_id,
+                        bgn: s.bgn,
+                        end: s.end,
+                    })
+                };
+                segments
+                    .iter()
+                    .filter(|s| core_bundles.contains(&s.bundle_id) && count[&s.bundle_id] > 1)
+                    .for_each(|s| add(&mut rearrangements, RearrangementType::Duplication, s));
+
+                let ranked = segments
+                    .iter()
+                    .filter(|s| count[&s.bundle_id] == 1)
+                    .filter_map(|s| ref_rank.get(&s.bundle_id).map(|&(rank, d)| (s, rank, d)))
+                    .collect::<Vec<_>>();
+                ranked
+                    .iter()
+                    .filter(|(s, _, d)| s.direction != *d)
+                    .for_each(|(s, _, _)| {
+                        add(&mut rearrangements, RearrangementType::Inversion, s)
+                    });
+                let lis = longest_increasing_subsequence(
+                    &ranked.iter().map(|(_, rank, _)| *rank).collect::<Vec<_>>(),
+                );
+                ranked
+                    .iter()
+                    .enumerate()
+                    .filter(|(i, _)| !lis.contains(i))
+                    .for_each(|(_, (s, _, _))| {
+                        add(&mut rearrangements, RearrangementType::Transposition, s)
+                    });
+            });
+        rearrangements
+    }
+
+    /// the bubbles between the core bundles, the anchors of a walk are its single copy core bundles in the same order
+    /// and direction as the reference walk, each pair of consecutive anchors in any walk is a candidate source and sink,
+    /// and a bubble is reported when the walks with both anchors do not have identical steps between them
+    pub fn get_bubbles(&self, min_fraction: f32) -> Vec<BundleBubble> {
+        let core_bundles = self.get_core_bundles(min_fraction);
+        let (oriented_walks, ref_rank) = self.get_oriented_walks(&core_bundles);
+        let walk_anchors = oriented_walks
+            .iter()
+            .map(|(_, segments)| {
+                let mut count = FxHashMap::<usize, usize>::default();
+                segments.iter().for_each(|s| {
+                    *count.entry(s.bundle_id).or_default() += 1;
+                });
+                let candidates = segments
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, s)| count[&s.bundle_id] == 1)
+                    .filter_map(|(i, s)| {
+                        ref_rank
+                            .get(&s.bundle_id)
+                            .filter(|(_, d)| *d == s.direction)
+                            .map(|(rank, _)| (i, *rank))
+                    })
+                    .collect::<Vec<_>>();
+                let lis = longest_increasing_subsequence(
+                    &candidates.iter().map(|(_, rank)| *rank).collect::<Vec<_>>(),
+                );
+                candidates
+                    .iter()
+                    .enumerate()
+                    .filter(|(i, _)| lis.contains(i))
+                    .map(|(_, (pos, _))| *pos)
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>();
+
+        let mut source_sink_pairs = FxHashSet::<(usize, usize)>::default();
+        oriented_walks
+            .iter()
+            .zip(walk_anchors.iter())
+            .for_each(|((_, segments), anchors)| {
+                anchors.windows(2).for_each(|w| {
+                    source_sink_pairs.insert((segments[w[0]].bundle_id, segments[w[1]].bundle_id));
+                });
+            });
+
+        let mut source_sink_to_alleles =
+            FxHashMap::<(usize, usize), FxHashMap<Vec<BundleStep>, Vec<String>>>::default();
+        self.walks
+            .iter()
+            .zip(oriented_walks.iter())
+            .zip(walk_anchors.iter())
+            .for_each(|((walk, (_, segments)), anchors)| {
+                let anchor_pos = anchors
+                    .iter()
+                    .map(|&pos| (segments[pos].bundle_id, pos))
+                    .collect::<FxHashMap<usize, usize>>();
+                source_sink_pairs.iter().for_each(|(source, sink)| {
+                    if let (Some(&p0), Some(&p1)) = (anchor_pos.get(source), anchor_pos.get(sink)) {
+                        let allele = segments[p0 + 1..p1]
+                            .iter()
+                            .map(|s| (s.bundle_id, s.direction))
+                            .collect::<Vec<_>>();
+                        source_sink_to_alleles
+                            .entry((*source, *sink))
+                            .or_default()
+                            .entry(allele)
+                            .or_default()
+                            .push(walk.name.clone());
+                    }
+                });
+            });
+        let mut bubbles = source_sink_to_alleles
+            .into_iter()
+            .filter(|(_, alleles)| alleles.len() > 1)
+            .map(|((source, sink), alleles)| {
+                let source = (source, ref_rank[&source].1);
+                let sink = (sink, ref_rank[&sink].1);
+                let mut alleles = alleles
+                    .into_iter()
+                    .map(|(steps, walks)| BubbleAllele { steps, walks })
+                    .collect::<Vec<_>>();
+                alleles.sort_by(|a, b| {
+                    b.walks
+                        .len()
+                        .cmp(&a.walks.len())
+                        .then(a.steps.cmp(&b.steps))
+                });
+                BundleBubble {
+                    source,
+                    sink,
+                    alleles,
+                }
+            })
+            .collect::<Vec<_>>();
+        bubbles.sort_by_key(|b| (ref_rank[&b.source.0].0, ref_rank[&b.sink.0].0));
+        bubbles
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::bundle_graph::{BundleGraph, BundleWalk, BundleWalkSegment, RearrangementType};
+
+    fn walk(name: &str, steps: &[(usize, u32)]) -> BundleWalk {
+        BundleWalk {
+            name: name.to_string(),
+            segments: steps
+                .iter()
+                .enumerate()
+                .map(|(i, &(bundle_id, direction))| BundleWalkSegment {
+                    bundle_id,
+                    direction,
+                    bgn: i as u32 * 100,
+                    end: i as u32 * 100 + 90,
+                })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn test_bundle_graph() {
+        let walks = vec![
+            walk("s0#1#c0", &[(0, 0), (1, 0), (2, 0), (3, 0), (4, 0)]),
+            walk("s0#2#c0", &[(0, 0), (1, 0), (5, 0), (3, 0), (4, 0)]),
+            // the reverse complement of s0#1#c0 with an inversion of the bundle 3
+            walk("s1#1#c0", &[(4, 1), (3, 0), (2, 1), (1, 1), (0, 1)]),
+            // a transposition of the bundle 1
+            walk("s1#2#c0", &[(0, 0), (2, 0), (3, 0), (1, 0), (4, 0)]),
+        ];
+        let graph = BundleGraph::from_walks(walks);
+        assert_eq!(graph.nodes.len(), 6);
+        assert_eq!(graph.nodes[&0].haplotype_count, 4);
+        // s0#1, s0#2 and s1#1 (in the reverse direction) traverse 0+ -> 1+
+        assert_eq!(graph.edges[&((0, 0), (1, 0))].haplotype_count, 3);
+
+        let mut gfa = Vec::<u8>::new();
+        graph.write_gfa(&mut gfa, true).unwrap();
+        let gfa = String::from_utf8(gfa).unwrap();
+        assert!(gfa.contains("W\ts0\t2\tc0\t0\t490\t>0>1>5>3>4"));
+
+        let orders = graph.get_haplotype_bundle_orders(0.75);
+        assert!(orders[2].reversed);
+        assert_eq!(
+            orders[2].steps,
+            vec![(0, 0), (1, 0), (2, 0), (3, 1), (4, 0)]
+        );
+
+        let rearrangements = graph.get_rearrangements(0.75);
+        assert_eq!(rearrangements.len(), 2);
+        assert_eq!(rearrangements[0].walk, "s1#1#c0");
+        assert_eq!(
+            rearrangements[0].rearrangement_type,
+            RearrangementType::Inversion
+        );
+        assert_eq!(rearrangements[0].bundle_id, 3);
+        assert_eq!(rearrangements[1].walk, "s1#2#c0");
+        assert_eq!(
+            rearrangements[1].rearrangement_type,
+            RearrangementType::Transposition
+        );
+        assert_eq!(rearrangements[1].bundle_id, 1);
+
+        let bubbles = graph.get_bubbles(0.75);
+        // the bubble of the bundle 2 and 5 between 1 and 3, and those from the inversion and the transposition
+        assert_eq!(bubbles.len(), 4);
+        assert_eq!(bubbles[1].source, (1, 0));
+        assert_eq!(bubbles[1].sink, (3, 0));
+        assert_eq!(bubbles[1].alleles.len(), 2);
+        assert_eq!(bubbles[1].alleles[0].steps, vec![(2, 0)]);
+        assert_eq!(bubbles[1].alleles[0].walks, vec!["s0#1#c0".to_string()]);
+        assert_eq!(bubbles[1].alleles[1].steps, vec![(5, 0)]);
+    }
+}
@@ -3,8 +3,9 @@ use flate2::bufread::MultiGzDecoder;
 #[cfg(feature = "with_agc")]
 use memmap2::Mmap;
 
-use crate::fasta_io::FastaReader;
+use crate::fasta_io::{reverse_complement, FastaReader};
 use crate::frag_file_io;
+use crate::gfa_io::{GFAGraph, GFANodeCoordinate};
 use crate::graph_utils::{AdjList, ShmmrGraphNode};
 pub use crate::seq_db::pair_shmmrs;
 use crate::seq_db::{self, raw_query_fragment, raw_query_fragment_from_mmap_midx, GetSeq};
@@ -16,6 +17,7 @@ use crate::agc_io::{self, AGCSeqDB};
 
 use rayon::prelude::*;
 use rustc_hash::{FxHashMap, FxHashSet};
+use serde::{Deserialize, Serialize};
 use std::fs::File;
 
 #[cfg(feature = "with_agc")]
@@ -24,9 +26,33 @@ use std::io::BufRead;
 use std::io::{BufReader, BufWriter, Read, Write};
 
 pub type PrincipalBundles = Vec<Vec<(u64, u64, u8)>>; //shimmer pair vector
-pub type PrincipalBundlesWithId = Vec<(usize, usize, Vec<(u64, u64, u8)>)>; //vector of "bundle_id, mean_order, shimmer pair vector"
+pub type PrincipalBundleWithId = (usize, usize, Vec<(u64, u64, u8)>); // bundle_id, mean_order, shimmer pair vector
+pub type PrincipalBundlesWithId = Vec<PrincipalBundleWithId>; //vector of "bundle_id, mean_order, shimmer pair vector"
 type ShmmrPair = (u64, u64);
-type ShmmrPairAndBundleVertices = Vec<((u64, u64, u32, u32, u8), Option<(usize, u8, usize)>)>; // Vector of ( sequence_id, vector of (shimmer pair, optional bundle vertex)
+pub type ShmmrPairAndBundleVertices = Vec<((u64, u64, u32, u32, u8), Option<(usize, u8, usize)>)>; // Vector of ( sequence_id, vector of (shimmer pair, optional bundle vertex)
+pub type SeqShmmrPairAndBundleVertices = Vec<(u32, ShmmrPairAndBundleVertices)>; // vector of (sequence_id, shimmer pairs with the optional bundle vertices)
+
+/// the options for the sequence paths in a MAP-graph GFA file
+#[derive(Clone, Debug, Default)]
+pub struct GFAPathOptions {
+    /// write the sequences as `P` lines instead of GFA 1.1 `W` lines
+    pub use_p_lines: bool,
+    /// the sample of the reference sequences, if specified, the segments carry the rGFA `SN`/`SO`/`SR` tags
+    /// and the shimmer pair of a segment is written in the `SP` tag instead of the `SN` tag
+    pub reference_sample: Option<String>,
+    /// write the segment sequences instead of `*`, the sequence of a segment is the fragment of its shimmer pair
+    /// with the smallest sequence id and position, so the path sequences spelled from the segments may differ from
+    /// the other sequences going through the same shimmer pairs
+    pub with_sequences: bool,
+}
+
+// a walk of a sequence through the MAP-graph, steps are (segment id, orientation, start of the segment in the sequence)
+struct MapGWalk {
+    sid: u32,
+    bgn: u32,
+    end: u32,
+    steps: Vec<(usize, u8, u32)>,
+}
 pub type VertexToBundleIdMap = FxHashMap<ShmmrPair, (usize, u8, usize)>;
 
 #[allow(clippy::large_enum_variant)]
@@ -215,6 +241,48 @@ impl SeqIndexDB {
         };
     }
 
+    /// load the path sequences of a GFA file, from the P and W lines, into a memory backed database,
+    /// the path names are used as the contig names, and the W lines are named as `sample#haplotype#sequence`,
+    /// the sequences are converted to upper case
+    ///
+    /// If `with_node_coordinates` is true, return the coordinates of the graph segments in each path.
+    pub fn load_from_gfa(
+        &mut self,
+        filepath: &str,
+        w: u32,
+        k: u32,
+        r: u32,
+        min_span: u32,
+        with_node_coordinates: bool,
+    ) -> Result<Option<FxHashMap<String, Vec<GFANodeCoordinate>>>, std::io::Error> {
+        let graph = GFAGraph::from_file(filepath)?;
+        let path_seqs = graph
+            .paths
+            .par_iter()
+            .map(|path| {
+                let (mut seq, coordinates) = graph.get_path_seq(path)?;
+                seq.make_ascii_uppercase();
+                Ok((path.name.clone(), seq, coordinates))
+            })
+            .collect::<Result<Vec<_>, std::io::Error>>()?;
+        let mut node_coordinates = FxHashMap::<String, Vec<GFANodeCoordinate>>::default();
+        let seq_list = path_seqs
+            .into_iter()
+            .map(|(name, seq, coordinates)| {
+                if with_node_coordinates {
+                    node_coordinates.insert(name.clone(), coordinates);
+                }
+                (name, seq)
+            })
+            .collect::<Vec<_>>();
+        self.load_from_seq_list(seq_list, Some(filepath), w, k, r, min_span)?;
+        if with_node_coordinates {
+            Ok(Some(node_coordinates))
+        } else {
+            Ok(None)
+        }
+    }
+
     pub fn load_from_seq_list(
         &mut self,
         seq_list: Vec<(String, Vec<u8>)>,
@@ -541,21 +609,7 @@ impl SeqIndexDB {
     }
 
     fn get_smps(&self, seq: Vec<u8>, shmmr_spec: &ShmmrSpec) -> Vec<(u64, u64, u32, u32, u8)> {
-        let shmmrs = sequence_to_shmmrs(0, &seq, shmmr_spec, false);
-        seq_db::pair_shmmrs(&shmmrs)
-            .par_iter()
-            .map(|(s0, s1)| {
-                let p0 = s0.pos() + 1;
-                let p1 = s1.pos() + 1;
-                let s0 = s0.x >> 8;
-                let s1 = s1.x >> 8;
-                if s0 < s1 {
-                    (s0, s1, p0, p1, 0_u8)
-                } else {
-                    (s1, s0, p0, p1, 1_u8)
-                }
-            })
-            .collect::<Vec<(u64, u64, u32, u32, u8)>>()
+        get_smps(seq, shmmr_spec)
     }
 
     #[allow(clippy::type_complexity)] // TODO: Define the type for readability
@@ -571,7 +625,7 @@ impl SeqIndexDB {
         let mut vertex_to_bundle_id_direction_pos =
             self.get_vertex_map_from_principal_bundles(pb.clone()); //not efficient but it is PyO3 limit now
 
-        let seqid_smps: Vec<(u32, Vec<(u64, u64, u32, u32, u8)>)> = self
+        let mut seqid_smps: Vec<(u32, Vec<(u64, u64, u32, u32, u8)>)> = self
             .seq_info
             .clone()
             .unwrap_or_default()
@@ -583,6 +637,8 @@ impl SeqIndexDB {
                 (*sid, self.get_smps(seq, &self.shmmr_spec.clone().unwrap()))
             })
             .collect();
+        // visit the sequences in the order of the sequence id so the voting below does not depend on the hash map order
+        seqid_smps.sort_by_key(|(sid, _)| *sid);
         // data for reordering the bundles and for re-ordering them along the sequences
         let mut bundle_id_to_directions = FxHashMap::<usize, Vec<u32>>::default();
         let mut bundle_id_to_orders = FxHashMap::<usize, Vec<f32>>::default();
@@ -665,6 +721,18 @@ impl SeqIndexDB {
         filepath: &str,
         method: &str,
         keeps: Option<Vec<u32>>,
+    ) -> Result<(), std::io::Error> {
+        self.generate_mapg_gfa_with_paths(min_count, filepath, method, keeps, None)
+    }
+
+    /// generate the MAP-graph GFA file with a path line for every sequence if `path_options` is specified
+    pub fn generate_mapg_gfa_with_paths(
+        &self,
+        min_count: usize,
+        filepath: &str,
+        method: &str,
+        keeps: Option<Vec<u32>>,
+        path_options: Option<&GFAPathOptions>,
     ) -> Result<(), std::io::Error> {
         let get_seq_by_id = |sid| -> Vec<u8> {
             match self.backend {
@@ -756,27 +824,42 @@ impl SeqIndexDB {
         let mut out_file = BufWriter::new(File::create(filepath).unwrap());
 
         let kmer_size = self.shmmr_spec.as_ref().unwrap().k;
-        out_file
-            .write_all("H\tVN:Z:1.0\tCM:Z:Sparse Genome Graph Generated By pgr-tk\n".as_bytes())?;
+        let walks = if path_options.is_some() {
+            self.get_mapg_walks(&frag_id, &overlaps)?
+        } else {
+            vec![]
+        };
+        let (shmmr_pair_tag, rgfa_tags) = self.get_rgfa_tags(&walks, path_options);
+        if path_options.is_some() {
+            out_file.write_all(
+                "H\tVN:Z:1.1\tCM:Z:Sparse Genome Graph Generated By pgr-tk\n".as_bytes(),
+            )?;
+        } else {
+            out_file.write_all(
+                "H\tVN:Z:1.0\tCM:Z:Sparse Genome Graph Generated By pgr-tk\n".as_bytes(),
+            )?;
+        }
         frag_id
             .iter()
             .try_for_each(|(smp, id)| -> Result<(), std::io::Error> {
                 let hits = frag_map.get(smp).unwrap();
-                let ave_len =
-                    hits.iter().fold(0_u32, |len_sum, &s| len_sum + s.3 - s.2) / hits.len() as u32;
+                let (seg_seq, seg_len) = self.get_mapg_segment_seq(hits, path_options)?;
                 let seg_line = format!(
-                    "S\t{}\t*\tLN:i:{}\tSN:Z:{:016x}_{:016x}\n",
+                    "S\t{}\t{}\tLN:i:{}\t{}:Z:{:016x}_{:016x}{}\n",
                     id,
-                    ave_len + kmer_size,
+                    seg_seq,
+                    seg_len,
+                    shmmr_pair_tag,
                     smp.0,
-                    smp.1
+                    smp.1,
+                    rgfa_tags.get(id).map_or("", |t| t.as_str())
                 );
                 out_file.write_all(seg_line.as_bytes())?;
                 Ok(())
             })?;
 
         overlaps
-            .into_iter()
+            .iter()
             .try_for_each(|(op, vs)| -> Result<(), std::io::Error> {
                 let o1 = if op.0 .2 == 0 { "+" } else { "-" };
                 let o2 = if op.1 .2 == 0 { "+" } else { "-" };
@@ -795,6 +878,10 @@ impl SeqIndexDB {
                 Ok(())
             })?;
 
+        if let Some(path_options) = path_options {
+            self.write_gfa_path_lines(&mut out_file, &walks, path_options.use_p_lines)?;
+        }
+
         Ok(())
     }
 
@@ -862,6 +949,24 @@ impl SeqIndexDB {
         path_len_cutoff: usize,
         filepath: &str,
         keeps: Option<Vec<u32>>,
+    ) -> Result<(), std::io::Error> {
+        self.generate_principal_mapg_gfa_with_paths(
+            min_count,
+            path_len_cutoff,
+            filepath,
+            keeps,
+            None,
+        )
+    }
+
+    /// generate the principal MAP-graph GFA file with a path line for every sequence if `path_options` is specified
+    pub fn generate_principal_mapg_gfa_with_paths(
+        &self,
+        min_count: usize,
+        path_len_cutoff: usize,
+        filepath: &str,
+        keeps: Option<Vec<u32>>,
+        path_options: Option<&GFAPathOptions>,
     ) -> Result<(), std::io::Error> {
         let frag_map = self.get_shmmr_map_internal();
         if frag_map.is_none() {
@@ -913,32 +1018,50 @@ impl SeqIndexDB {
         let mut out_file = BufWriter::new(File::create(filepath).unwrap());
 
         let kmer_size = self.shmmr_spec.as_ref().unwrap().k;
-        out_file
-            .write_all("H\tVN:Z:1.0\tCM:Z:Sparse Genome Graph Generated By pgr-tk\n".as_bytes())?;
+        let walks = if path_options.is_some() {
+            self.get_mapg_walks(&frag_id, &overlaps)?
+        } else {
+            vec![]
+        };
+        let (shmmr_pair_tag, rgfa_tags) = self.get_rgfa_tags(&walks, path_options);
+        if path_options.is_some() {
+            out_file.write_all(
+                "H\tVN:Z:1.1\tCM:Z:Sparse Genome Graph Generated By pgr-tk\n".as_bytes(),
+            )?;
+        } else {
+            out_file.write_all(
+                "H\tVN:Z:1.0\tCM:Z:Sparse Genome Graph Generated By pgr-tk\n".as_bytes(),
+            )?;
+        }
         frag_id
             .iter()
             .try_for_each(|(smp, id)| -> Result<(), std::io::Error> {
                 let hits = frag_map.get(smp).unwrap();
-                let ave_len =
-                    hits.iter().fold(0_u32, |len_sum, &s| len_sum + s.3 - s.2) / hits.len() as u32;
+                let (seg_seq, seg_len) = self.get_mapg_segment_seq(hits, path_options)?;
                 let seg_line;
                 if let Some(bundle_id) = vertex_to_bundle_id_direction_pos.get(smp) {
                     seg_line = format!(
-                        "S\t{}\t*\tLN:i:{}\tSN:Z:{:016x}_{:016x}\tBN:i:{}\tBP:i:{}\n",
+                        "S\t{}\t{}\tLN:i:{}\t{}:Z:{:016x}_{:016x}\tBN:i:{}\tBP:i:{}{}\n",
                         id,
-                        ave_len + kmer_size,
+                        seg_seq,
+                        seg_len,
+                        shmmr_pair_tag,
                         smp.0,
                         smp.1,
                         bundle_id.0,
-                        bundle_id.2
+                        bundle_id.2,
+                        rgfa_tags.get(id).map_or("", |t| t.as_str())
                     );
                 } else {
                     seg_line = format!(
-                        "S\t{}\t*\tLN:i:{}\tSN:Z:{:016x}_{:016x}\n",
+                        "S\t{}\t{}\tLN:i:{}\t{}:Z:{:016x}_{:016x}{}\n",
                         id,
-                        ave_len + kmer_size,
+                        seg_seq,
+                        seg_len,
+                        shmmr_pair_tag,
                         smp.0,
-                        smp.1
+                        smp.1,
+                        rgfa_tags.get(id).map_or("", |t| t.as_str())
                     );
                 }
                 out_file.write_all(seg_line.as_bytes())?;
@@ -946,7 +1069,7 @@ impl SeqIndexDB {
             })?;
 
         overlaps
-            .into_iter()
+            .iter()
             .try_for_each(|(op, vs)| -> Result<(), std::io::Error> {
                 let o1 = if op.0 .2 == 0 { "+" } else { "-" };
                 let o2 = if op.1 .2 == 0 { "+" } else { "-" };
@@ -965,8 +1088,409 @@ impl SeqIndexDB {
                 Ok(())
             })?;
 
+        if let Some(path_options) = path_options {
+            self.write_gfa_path_lines(&mut out_file, &walks, path_options.use_p_lines)?;
+        }
+
         Ok(())
     }
+
+    /// the sequence field and the length of a MAP-graph segment, the sequence is `*` and the length is the
+    /// average length of the fragments unless `with_sequences` is set in the path options
+    fn get_mapg_segment_seq(
+        &self,
+        hits: &[seq_db::FragmentSignature],
+        path_options: Option<&GFAPathOptions>,
+    ) -> Result<(String, u32), std::io::Error> {
+        let kmer_size = self.shmmr_spec.as_ref().unwrap().k;
+        if !path_options.is_some_and(|options| options.with_sequences) {
+            let ave_len =
+                hits.iter().fold(0_u32, |len_sum, &s| len_sum + s.3 - s.2) / hits.len() as u32;
+            return Ok(("*".to_string(), ave_len + kmer_size));
+        }
+        let (_, sid, bgn, end, orientation) = *hits.iter().min_by_key(|s| (s.1, s.2)).unwrap();
+        let seq = self.get_sub_seq_by_id(sid, (bgn - kmer_size) as usize, end as usize)?;
+        // the segment is in the orientation of the shimmer pair
+        let seq = if orientation == 0 {
+            seq
+        } else {
+            reverse_complement(&seq)
+        };
+        let seq_len = seq.len() as u32;
+        Ok((String::from_utf8_lossy(&seq).to_string(), seq_len))
+    }
+
+    /// the walks of the sequences through the MAP-graph segments, a sequence is split into multiple walks
+    /// when two consecutive shimmer pairs of the sequence are not connected in the graph
+    fn get_mapg_walks<T>(
+        &self,
+        frag_id: &FxHashMap<(u64, u64), usize>,
+        overlaps: &FxHashMap<(ShmmrGraphNode, ShmmrGraphNode), T>,
+    ) -> Result<Vec<MapGWalk>, std::io::Error> {
+        let shmmr_spec = self.shmmr_spec.as_ref().unwrap();
+        let mut edges = FxHashSet::<(ShmmrGraphNode, ShmmrGraphNode)>::default();
+        overlaps.keys().for_each(|(v, w)| {
+            edges.insert((*v, *w));
+            edges.insert((
+                ShmmrGraphNode(w.0, w.1, 1 - w.2),
+                ShmmrGraphNode(v.0, v.1, 1 - v.2),
+            ));
+        });
+        let mut sids = self
+            .seq_info
+            .as_ref()
+            .unwrap()
+            .keys()
+            .copied()
+            .collect::<Vec<u32>>();
+        sids.sort();
+        let walks = sids
+            .into_par_iter()
+            .map(|sid| {
+                let seq = self.get_seq_by_id(sid)?;
+                let mut walks = Vec::<MapGWalk>::new();
+                let mut walk: Option<MapGWalk> = None;
+                let mut pre_node: Option<ShmmrGraphNode> = None;
+                get_smps(seq, shmmr_spec).into_iter().for_each(|smp| {
+                    let node = ShmmrGraphNode(smp.0, smp.1, smp.4);
+                    let step_bgn = smp.2.saturating_sub(shmmr_spec.k);
+                    let id = frag_id.get(&(smp.0, smp.1));
+                    let connected = matches!(pre_node, Some(v) if edges.contains(&(v, node)));
+                    if !connected || id.is_none() {
+                        if let Some(walk) = walk.take() {
+                            walks.push(walk);
+                        }
+                    }
+                    if let Some(id) = id {
+                        let walk = walk.get_or_insert_with(|| MapGWalk {
+                            sid,
+                            bgn: step_bgn,
+                            end: smp.3,
+                            steps: vec![],
+                        });
+                        walk.end = smp.3;
+                        walk.steps.push((*id, smp.4, step_bgn));
+                        pre_node = Some(node);
+                    } else {
+                        pre_node = None;
+                    }
+                });
+                if let Some(walk) = walk {
+                    walks.push(walk);
+                }
+                Ok(walks)
+            })
+            .collect::<Result<Vec<_>, std::io::Error>>()?;
+        Ok(walks.into_iter().flatten().collect())
+    }
+
+    /// the (sample, haplotype, contig) names of a sequence, using the PanSN `sample#haplotype#contig`
+    /// convention, the sample name of an AGC backend is used if the sequence name is not a PanSN name
+    pub fn get_pansn_name(&self, sid: u32) -> Option<(String, String, String)> {
+        let (ctg, source, _) = self.seq_info.as_ref()?.get(&sid)?;
+        let fields = ctg.splitn(3, '#').collect::<Vec<_>>();
+        if fields.len() == 3 {
+            return Some((
+                fields[0].to_string(),
+                fields[1].to_string(),
+                fields[2].to_string(),
+            ));
+        }
+        let sample = match (&self.backend, source) {
+            #[cfg(feature = "with_agc")]
+            (Backend::AGC, Some(sample)) => sample.clone(),
+            _ => ctg.clone(),
+        };
+        Some((sample, "0".to_string(), ctg.clone()))
+    }
+
+    /// find the sequences homologous to `query_seq` in the database
+    ///
+    /// The sparse alignments to the same sequence in the same orientation are merged if they are within
+    /// `merge_range_tol`. The target range of a merged hit is extended with the un-aligned ends of the query,
+    /// so all hits have the same flanks as the query. The hits of which the aligned part covers less than
+    /// `min_query_coverage` of the query are skipped.
+    pub fn query_region_homologous_seqs(
+        &self,
+        query_seq: &Vec<u8>,
+        spec: &RegionDecompSpec,
+    ) -> Result<Vec<RegionHit>, std::io::Error> {
+        let k = self
+            .shmmr_spec
+            .as_ref()
+            .ok_or_else(|| std::io::Error::other("the sequence database is not indexed"))?
+            .k as i64;
+        let q_len = query_seq.len() as i64;
+        let query_results = if self.get_shmmr_map_internal().is_some() {
+            self.query_fragment_to_hps(
+                query_seq,
+                spec.gap_penalty_factor,
+                Some(spec.max_count),
+                Some(spec.max_query_count),
+                Some(spec.max_target_count),
+                Some(spec.max_aln_chain_span),
+                None,
+                false,
+            )
+        } else {
+            self.query_fragment_to_hps_from_mmap_file(
+                query_seq,
+                spec.gap_penalty_factor,
+                Some(spec.max_count),
+                Some(spec.max_query_count),
+                Some(spec.max_target_count),
+                Some(spec.max_aln_chain_span),
+                None,
+                false,
+            )
+        }
+        .unwrap_or_default();
+
+        let seq_info = self.seq_info.as_ref().unwrap();
+        let mut hit_ranges = Vec::<(u32, u32, u32, u32, usize)>::new();
+        query_results.into_iter().for_each(|(sid, alns)| {
+            let ctg_len = seq_info[&sid].2 as i64;
+            let mut oriented_alns = [vec![], vec![]];
+            alns.into_iter()
+                .filter(|(_, aln)| aln.len() > 2)
+                .for_each(|(_, mut aln)| {
+                    let f_count = aln.iter().filter(|hp| hp.0 .2 == hp.1 .2).count();
+                    let orientation = if f_count * 2 > aln.len() { 0 } else { 1 };
+                    aln.sort_by_key(|hp| hp.1);
+                    oriented_alns[orientation].push(aln);
+                });
+            oriented_alns
+                .into_iter()
+                .enumerate()
+                .for_each(|(orientation, mut alns)| {
+                    alns.sort_by_key(|aln| aln[0].1 .0);
+                    let mut merged_alns = Vec::<(u32, Vec<aln::HitPair>)>::new();
+                    alns.into_iter().for_each(|aln| {
+                        let aln_end = aln.iter().map(|hp| hp.1 .1).max().unwrap();
+                        match merged_alns.last_mut() {
+                            Some((end, merged))
+                                if (aln[0].1 .0 as i64) - (*end as i64)
+                                    < spec.merge_range_tol as i64 =>
+                            {
+                                *end = (*end).max(aln_end);
+                                merged.extend(aln);
+                            }
+                            _ => merged_alns.push((aln_end, aln)),
+                        }
+                    });
+                    merged_alns.into_iter().for_each(|(_, aln)| {
+                        let q_bgn = aln.iter().map(|hp| hp.0 .0).min().unwrap() as i64;
+                        let q_end = aln.iter().map(|hp| hp.0 .1).max().unwrap() as i64;
+                        if ((q_end - q_bgn + k) as f32) < spec.min_query_coverage * q_len as f32 {
+                            return;
+                        }
+                        // the anchors with the left-most and the right-most target positions
+                        let a = aln.iter().min_by_key(|hp| hp.1 .0).unwrap();
+                        let b = aln.iter().max_by_key(|hp| hp.1 .1).unwrap();
+                        let (bgn, end) = if orientation == 0 {
+                            (
+                                a.1 .0 as i64 - a.0 .0 as i64,
+                                b.1 .0 as i64 - b.0 .0 as i64 + q_len,
+                            )
+                        } else {
+                            // the query base x is aligned to the target base c - x with c = t1 - 1 + q0 - k
+                            (
+                                a.1 .1 as i64 + a.0 .0 as i64 - k - q_len,
+                                b.1 .1 as i64 + b.0 .0 as i64 - k,
+                            )
+                        };
+                        let bgn = bgn.clamp(0, ctg_len) as u32;
+                        let end = end.clamp(0, ctg_len) as u32;
+                        if end > bgn {
+                            hit_ranges.push((sid, bgn, end, orientation as u32, aln.len()));
+                        }
+                    });
+                });
+        });
+
+        let mut hits = hit_ranges
+            .into_par_iter()
+            .map(|(sid, bgn, end, orientation, anchor_count)| {
+                let (ctg, src, _) = &seq_info[&sid];
+                let src = src.clone().unwrap_or_else(|| "N/A".to_string());
+                let base = std::path::Path::new(&src)
+                    .file_stem()
+                    .map(|s| s.to_string_lossy().to_string())
+                    .unwrap_or(src);
+                let seq = self.get_sub_seq_by_id(sid, bgn as usize, end as usize)?;
+                let seq = if orientation == 1 {
+                    reverse_complement(&seq)
+                } else {
+                    seq
+                };
+                Ok(RegionHit {
+                    name: format!("{}::{}_{}_{}_{}", base, ctg, bgn, end, orientation),
+                    sid,
+                    bgn,
+                    end,
+                    orientation,
+                    anchor_count,
+                    seq,
+                })
+            })
+            .collect::<Result<Vec<_>, std::io::Error>>()?;
+        hits.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(hits)
+    }
+
+    /// decompose the sequences homologous to the region `bgn..end` of the contig `ctg_name` of the sample
+    /// `sample_name` with the principal bundles built from these sequences
+    ///
+    /// The region is extended by `flank_length` on both sides for finding the homologous sequences, see
+    /// `query_region_homologous_seqs()`.
+    pub fn get_region_decomposition(
+        &self,
+        sample_name: String,
+        ctg_name: String,
+        bgn: usize,
+        end: usize,
+        spec: &RegionDecompSpec,
+    ) -> Result<RegionDecomposition, std::io::Error> {
+        let ctg_len = self
+            .seq_index
+            .as_ref()
+            .and_then(|seq_index| seq_index.get(&(ctg_name.clone(), Some(sample_name.clone()))))
+            .map(|(_, ctg_len)| *ctg_len as usize)
+            .ok_or_else(|| {
+                std::io::Error::other(format!(
+                    "sequence {} of {} not found",
+                    ctg_name, sample_name
+                ))
+            })?;
+        let q_bgn = bgn.saturating_sub(spec.flank_length);
+        let q_end = (end + spec.flank_length).min(ctg_len);
+        if q_bgn >= q_end {
+            return Err(std::io::Error::other(format!(
+                "invalid region {}:{}-{}",
+                ctg_name, bgn, end
+            )));
+        }
+        let query_seq = self.get_sub_seq(sample_name, ctg_name.clone(), q_bgn, q_end)?;
+        let hits = self.query_region_homologous_seqs(&query_seq, spec)?;
+        if hits.is_empty() {
+            return Err(std::io::Error::other(format!(
+                "no homologous sequence found for {}:{}-{}",
+                ctg_name, bgn, end
+            )));
+        }
+
+        let seq_list = hits
+            .iter()
+            .map(|hit| (hit.name.clone(), hit.seq.clone()))
+            .collect::<Vec<_>>();
+        let shmmr_spec = &spec.decomp.shmmr_spec;
+        let mut region_seq_db = SeqIndexDB::new();
+        region_seq_db.load_from_seq_list(
+            seq_list,
+            None,
+            shmmr_spec.w,
+            shmmr_spec.k,
+            shmmr_spec.r,
+            shmmr_spec.min_span,
+        )?;
+        let principal_bundle_db = PrincipalBundleDB::from_seq_index_db(
+            &region_seq_db,
+            spec.decomp.min_cov,
+            spec.decomp.min_branch_size,
+            None,
+        )?;
+        let segments = principal_bundle_db.get_hierarchical_decomposition(
+            &region_seq_db,
+            spec.decomp.bundle_length_cutoff,
+            spec.decomp.bundle_merge_distance,
+            &[],
+        )?;
+        Ok(RegionDecomposition {
+            query_range: (q_bgn, q_end),
+            hits,
+            principal_bundle_db,
+            segments,
+        })
+    }
+
+    // the tag name for the shimmer pair of the segments and the rGFA tags of the segments, a segment is
+    // assigned to the first walk, in the order of the sequence ids, from the reference sample or from the
+    // other samples if the segment is not in any reference sequence
+    fn get_rgfa_tags(
+        &self,
+        walks: &[MapGWalk],
+        path_options: Option<&GFAPathOptions>,
+    ) -> (&'static str, FxHashMap<usize, String>) {
+        let mut rgfa_tags = FxHashMap::<usize, String>::default();
+        let reference_sample = match path_options.and_then(|o| o.reference_sample.as_ref()) {
+            Some(reference_sample) => reference_sample,
+            None => return ("SN", rgfa_tags),
+        };
+        let seq_info = self.seq_info.as_ref().unwrap();
+        let is_reference = |sid: u32| matches!(self.get_pansn_name(sid), Some((sample, _, _)) if sample == *reference_sample);
+        [0_u32, 1].into_iter().for_each(|rank| {
+            walks
+                .iter()
+                .filter(|walk| is_reference(walk.sid) == (rank == 0))
+                .for_each(|walk| {
+                    walk.steps.iter().for_each(|(id, _, bgn)| {
+                        rgfa_tags.entry(*id).or_insert_with(|| {
+                            format!(
+                                "\tSN:Z:{}\tSO:i:{}\tSR:i:{}",
+                                seq_info[&walk.sid].0, bgn, rank
+                            )
+                        });
+                    });
+                });
+        });
+        ("SP", rgfa_tags)
+    }
+
+    fn write_gfa_path_lines<W: Write>(
+        &self,
+        out_file: &mut W,
+        walks: &[MapGWalk],
+        use_p_lines: bool,
+    ) -> Result<(), std::io::Error> {
+        let mut sid_walk_count = FxHashMap::<u32, usize>::default();
+        walks.iter().for_each(|walk| {
+            *sid_walk_count.entry(walk.sid).or_default() += 1;
+        });
+        let seq_info = self.seq_info.as_ref().unwrap();
+        walks
+            .iter()
+            .try_for_each(|walk| -> Result<(), std::io::Error> {
+                let line = if use_p_lines {
+                    let ctg = &seq_info[&walk.sid].0;
+                    // a sequence split into multiple walks gets the range of each walk in the path name
+                    let path_name = if sid_walk_count[&walk.sid] > 1 {
+                        format!("{}:{}-{}", ctg, walk.bgn, walk.end)
+                    } else {
+                        ctg.clone()
+                    };
+                    let path = walk
+                        .steps
+                        .iter()
+                        .map(|(id, o, _)| format!("{}{}", id, if *o == 0 { '+' } else { '-' }))
+                        .collect::<Vec<_>>()
+                        .join(",");
+                    format!("P\t{}\t{}\t*\n", path_name, path)
+                } else {
+                    let (sample, hap, ctg) = self.get_pansn_name(walk.sid).unwrap();
+                    let path = walk
+                        .steps
+                        .iter()
+                        .map(|(id, o, _)| format!("{}{}", if *o == 0 { '>' } else { '<' }, id))
+                        .collect::<Vec<_>>()
+                        .join("");
+                    format!(
+                        "W\t{}\t{}\t{}\t{}\t{}\t{}\n",
+                        sample, hap, ctg, walk.bgn, walk.end, path
+                    )
+                };
+                out_file.write_all(line.as_bytes())
+            })
+    }
 }
 
 impl SeqIndexDB {
@@ -981,13 +1505,57 @@ impl SeqIndexDB {
             Backend::UNKNOWN => None,
         }
     }
+
+    /// get the number of the indexed fragments of a shimmer pair, it works for all backends
+    pub fn get_shmmr_pair_count(&self, shmmr_pair: ShmmrPair) -> usize {
+        match self.backend {
+            #[cfg(feature = "with_agc")]
+            Backend::AGC => self
+                .agc_db
+                .as_ref()
+                .unwrap()
+                .frag_location_map
+                .get(&shmmr_pair)
+                .map_or(0, |&(_start, count)| count),
+            Backend::FRG => self
+                .frg_db
+                .as_ref()
+                .unwrap()
+                .frag_location_map
+                .get(&shmmr_pair)
+                .map_or(0, |&(_start, count)| count),
+            _ => self
+                .get_shmmr_map_internal()
+                .and_then(|frag_map| frag_map.get(&shmmr_pair))
+                .map_or(0, |frags| frags.len()),
+        }
+    }
+
+    /// get the shimmer pairs of a sequence with the numbers of the indexed fragments,
+    /// as a vector of (hash0, hash1, pos0, pos1, orientation, count)
+    #[allow(clippy::type_complexity)]
+    pub fn get_shmmr_pair_counts_of_seq(
+        &self,
+        seq: Vec<u8>,
+    ) -> Option<Vec<(u64, u64, u32, u32, u8, usize)>> {
+        let shmmr_spec = self.shmmr_spec.as_ref()?;
+        Some(
+            get_smps(seq, shmmr_spec)
+                .into_iter()
+                .map(|(s0, s1, p0, p1, orientation)| {
+                    let count = self.get_shmmr_pair_count((s0, s1));
+                    (s0, s1, p0, p1, orientation, count)
+                })
+                .collect(),
+        )
+    }
 }
 #[allow(clippy::type_complexity)] // TODO: Define the type for readability
 pub fn get_principal_bundle_decomposition(
     vertex_to_bundle_id_direction_pos: &VertexToBundleIdMap,
     seq_db: &SeqIndexDB,
 ) -> Vec<(u32, ShmmrPairAndBundleVertices)> {
-    let seqid_smps: Vec<(u32, Vec<(u64, u64, u32, u32, u8)>)> = seq_db
+    let mut seqid_smps: Vec<(u32, Vec<(u64, u64, u32, u32, u8)>)> = seq_db
         .seq_info
         .clone()
         .unwrap_or_default()
@@ -1002,6 +1570,7 @@ pub fn get_principal_bundle_decomposition(
             )
         })
         .collect();
+    seqid_smps.sort_by_key(|(sid, _)| *sid);
 
     // loop through each sequence and generate the decomposition for the sequence
     let seqid_smps_with_bundle_id_seg_direction = seqid_smps
@@ -1024,6 +1593,598 @@ pub fn get_principal_bundle_decomposition(
     seqid_smps_with_bundle_id_seg_direction
 }
 
+#[allow(clippy::type_complexity)]
+pub fn group_smps_by_principal_bundle_id(
+    smps: &[((u64, u64, u32, u32, u8), Option<(usize, u8, usize)>)],
+    bundle_length_cutoff: usize,
+    bundle_merge_distance: usize,
+) -> Vec<Vec<((u64, u64, u32, u32, u8), usize, u32, usize)>> {
+    let mut pre_bundle_id: Option<usize> = None;
+    let mut pre_direction: Option<u32> = None;
+    let mut all_partitions = vec![];
+    let mut new_partition = vec![];
+    smps.iter().for_each(|&(smp, bundle_info)| {
+        if bundle_info.is_none() {
+            return;
+        };
+        let bundle_info = bundle_info.unwrap();
+        let d = if smp.4 == bundle_info.1 { 0_u32 } else { 1_u32 };
+        let bid = bundle_info.0;
+        let bpos = bundle_info.2;
+        if pre_bundle_id.is_none() {
+            new_partition.clear();
+            new_partition.push((smp, bid, d, bpos));
+            pre_bundle_id = Some(bid);
+            pre_direction = Some(d);
+            return;
+        };
+        if bid != pre_bundle_id.unwrap() || d != pre_direction.unwrap() {
+            let l = new_partition.len();
+            if new_partition[l - 1].0 .3 as usize - new_partition[0].0 .2 as usize
+                > bundle_length_cutoff
+            {
+                all_partitions.push(new_partition.clone());
+                new_partition.clear();
+            } else {
+                new_partition.clear();
+            };
+            pre_bundle_id = Some(bid);
+            pre_direction = Some(d);
+        };
+        new_partition.push((smp, bid, d, bpos));
+    });
+    let l = new_partition.len();
+    if l > 0
+        && new_partition[l - 1].0 .3 as usize - new_partition[0].0 .2 as usize
+            > bundle_length_cutoff
+    {
+        all_partitions.push(new_partition);
+    };
+
+    let mut rtn_partitions = vec![];
+
+    if all_partitions.is_empty() {
+        return rtn_partitions;
+    }
+    let mut partition = all_partitions[0].clone();
+    (1..all_partitions.len()).for_each(|idx| {
+        let p = all_partitions[idx].clone();
+        let p_len = partition.len();
+        let p_end = partition[p_len - 1].0 .3;
+        let p_bid = partition[p_len - 1].1;
+        let p_d = partition[p_len - 1].2;
+        let np_bgn = p[0].0 .2;
+        let np_bid = p[0].1;
+        let np_d = p[0].2;
+        if p_bid == np_bid
+            && p_d == np_d
+            && (np_bgn as i64 - p_end as i64).abs() < bundle_merge_distance as i64
+        {
+            partition.extend(p);
+        } else {
+            rtn_partitions.push(partition.clone());
+            partition = p;
+        }
+    });
+    if !partition.is_empty() {
+        rtn_partitions.push(partition);
+    }
+    rtn_partitions
+}
+
+fn get_smps(seq: Vec<u8>, shmmr_spec: &ShmmrSpec) -> Vec<(u64, u64, u32, u32, u8)> {
+    let shmmrs = sequence_to_shmmrs(0, &seq, shmmr_spec, false);
+    seq_db::pair_shmmrs(&shmmrs)
+        .par_iter()
+        .map(|(s0, s1)| {
+            let p0 = s0.pos() + 1;
+            let p1 = s1.pos() + 1;
+            let s0 = s0.x >> 8;
+            let s1 = s1.x >> 8;
+            if s0 < s1 {
+                (s0, s1, p0, p1, 0_u8)
+            } else {
+                (s1, s0, p0, p1, 1_u8)
+            }
+        })
+        .collect::<Vec<(u64, u64, u32, u32, u8)>>()
+}
+
+/// The parameters to decompose the sequences at one level of a hierarchical decomposition
+#[derive(Clone, Debug)]
+pub struct BundleDecompLevelSpec {
+    pub shmmr_spec: ShmmrSpec,
+    pub min_cov: usize,
+    pub min_branch_size: usize,
+    pub bundle_length_cutoff: usize,
+    pub bundle_merge_distance: usize,
+}
+
+/// A bundle segment in a hierarchical decomposition, a segment at level `n + 1` is contained in
+/// a segment of the bundle `parent_id` at level `n`
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct HierarchicalBundleSegment {
+    pub level: usize,
+    pub ctg: String,
+    pub bgn: u32,
+    pub end: u32,
+    pub bundle_id: usize,
+    pub bundle_size: usize,
+    pub direction: u32,
+    pub bundle_v_bgn: usize,
+    pub bundle_v_end: usize,
+    pub is_repeat: bool,
+    pub parent_id: Option<usize>,
+}
+
+/// The parameters to pull the homologous sequences of a region from a `SeqIndexDB` and to decompose them
+#[derive(Clone, Debug)]
+pub struct RegionDecompSpec {
+    pub gap_penalty_factor: f32,
+    pub max_count: u32,
+    pub max_query_count: u32,
+    pub max_target_count: u32,
+    pub max_aln_chain_span: u32,
+    pub merge_range_tol: usize,
+    pub min_query_coverage: f32,
+    pub flank_length: usize,
+    pub decomp: BundleDecompLevelSpec,
+}
+
+/// A sequence homologous to a query region, `bgn` and `end` are the coordinates in the sequence `sid` of
+/// the database, `seq` is reverse complemented if `orientation` is 1
+#[derive(Clone, Debug)]
+pub struct RegionHit {
+    pub name: String,
+    pub sid: u32,
+    pub bgn: u32,
+    pub end: u32,
+    pub orientation: u32,
+    pub anchor_count: usize,
+    pub seq: Vec<u8>,
+}
+
+/// The principal bundle decomposition of the homologous sequences of a region, `query_range` is the
+/// region with the flanks used for the query and the segments are named by the `name` of the hits
+pub struct RegionDecomposition {
+    pub query_range: (usize, usize),
+    pub hits: Vec<RegionHit>,
+    pub principal_bundle_db: PrincipalBundleDB,
+    pub segments: Vec<HierarchicalBundleSegment>,
+}
+
+#[allow(clippy::too_many_arguments)]
+fn smps_to_hierarchical_segments(
+    smps: &ShmmrPairAndBundleVertices,
+    level: usize,
+    ctg: &str,
+    offset: u32,
+    k: u32,
+    bid_to_size: &FxHashMap<usize, usize>,
+    bid_offset: usize,
+    parent_id: Option<usize>,
+    bundle_length_cutoff: usize,
+    bundle_merge_distance: usize,
+) -> Vec<HierarchicalBundleSegment> {
+    let smp_partitions =
+        group_smps_by_principal_bundle_id(smps, bundle_length_cutoff, bundle_merge_distance);
+    let mut ctg_bundle_count = FxHashMap::<usize, usize>::default();
+    smp_partitions.iter().for_each(|p| {
+        *ctg_bundle_count.entry(p[0].1).or_insert(0) += 1;
+    });
+    smp_partitions
+        .into_iter()
+        .map(|p| {
+            let bid = p[0].1;
+            HierarchicalBundleSegment {
+                level,
+                ctg: ctg.to_string(),
+                bgn: offset + p[0].0 .2.saturating_sub(k),
+                end: offset + p[p.len() - 1].0 .3,
+                bundle_id: bid + bid_offset,
+                bundle_size: bid_to_size[&bid],
+                direction: p[0].2,
+                bundle_v_bgn: p[0].3,
+                bundle_v_end: p[p.len() - 1].3,
+                is_repeat: *ctg_bundle_count.get(&bid).unwrap_or(&0) > 1,
+                parent_id,
+            }
+        })
+        .collect()
+}
+
+const PDB_FILE_TAG: &str = "PDB:0.5";
+
+/// A frozen set of principal bundles, it can be saved to / loaded from a `.pdb` file and used to
+/// decompose new sequences without rebuilding the MAP graph
+pub struct PrincipalBundleDB {
+    pub shmmr_spec: ShmmrSpec,
+    pub min_cov: usize,
+    pub min_branch_size: usize,
+    pub principal_bundles_with_id: PrincipalBundlesWithId,
+    pub vertex_to_bundle_id_direction_pos: VertexToBundleIdMap,
+}
+
+impl PrincipalBundleDB {
+    /// build the principal bundles from the sequences in a `SeqIndexDB`
+    pub fn from_seq_index_db(
+        seq_db: &SeqIndexDB,
+        min_cov: usize,
+        min_branch_size: usize,
+        keeps: Option<Vec<u32>>,
+    ) -> Result<Self, std::io::Error> {
+        let shmmr_spec = seq_db
+            .shmmr_spec
+            .clone()
+            .ok_or_else(|| std::io::Error::other("the sequence database is not indexed"))?;
+        let (principal_bundles_with_id, vertex_to_bundle_id_direction_pos) =
+            seq_db.get_principal_bundles_with_id(min_cov, min_branch_size, keeps);
+        Ok(PrincipalBundleDB {
+            shmmr_spec,
+            min_cov,
+            min_branch_size,
+            principal_bundles_with_id,
+            vertex_to_bundle_id_direction_pos,
+        })
+    }
+
+    pub fn load_from_file(filepath: &str) -> Result<Self, std::io::Error> {
+        let mut pdb_file = BufReader::new(File::open(filepath)?);
+        let mut buf = [0_u8; 7];
+        pdb_file.read_exact(&mut buf)?;
+        if buf != PDB_FILE_TAG.as_bytes() {
+            return Err(std::io::Error::other(format!(
+                "{} is not a principal bundle (.pdb) file",
+                filepath
+            )));
+        }
+        let mut s: Vec<u8> = vec![];
+        pdb_file.read_to_end(&mut s)?;
+        let config = bincode::config::standard();
+        #[allow(clippy::type_complexity)]
+        let (
+            (
+                w,
+                k,
+                r,
+                min_span,
+                min_branch_size,
+                min_cov,
+                principal_bundles_with_id,
+                vertex_to_bundle_id_direction_pos,
+            ),
+            _size,
+        ): (
+            (
+                u32,
+                u32,
+                u32,
+                u32,
+                usize,
+                usize,
+                PrincipalBundlesWithId,
+                VertexToBundleIdMap,
+            ),
+            usize,
+        ) = bincode::decode_from_slice(&s[..], config)
+            .map_err(|e| std::io::Error::other(format!("fail to decode {}: {}", filepath, e)))?;
+        Ok(PrincipalBundleDB {
+            shmmr_spec: ShmmrSpec {
+                w,
+                k,
+                r,
+                min_span,
+                sketch: false,
+            },
+            min_cov,
+            min_branch_size,
+            principal_bundles_with_id,
+            vertex_to_bundle_id_direction_pos,
+        })
+    }
+
+    pub fn write_to_file(&self, filepath: &str) -> Result<(), std::io::Error> {
+        let mut pdb_file = BufWriter::new(File::create(filepath)?);
+        pdb_file.write_all(PDB_FILE_TAG.as_bytes())?;
+        let config = bincode::config::standard();
+        let bincode_vec = bincode::encode_to_vec(
+            (
+                self.shmmr_spec.w,
+                self.shmmr_spec.k,
+                self.shmmr_spec.r,
+                self.shmmr_spec.min_span,
+                self.min_branch_size,
+                self.min_cov,
+                &self.principal_bundles_with_id,
+                &self.vertex_to_bundle_id_direction_pos,
+            ),
+            config,
+        )
+        .map_err(|e| std::io::Error::other(e.to_string()))?;
+        pdb_file.write_all(&bincode_vec[..])?;
+        Ok(())
+    }
+
+    /// return (bundle_id, direction, position in the bundle) of a shimmer pair
+    pub fn get_bundle_by_shmmr_pair(&self, shmmr_pair: (u64, u64)) -> Option<(usize, u8, usize)> {
+        let shmmr_pair = if shmmr_pair.0 < shmmr_pair.1 {
+            shmmr_pair
+        } else {
+            (shmmr_pair.1, shmmr_pair.0)
+        };
+        self.vertex_to_bundle_id_direction_pos
+            .get(&shmmr_pair)
+            .copied()
+    }
+
+    /// return the (bundle_id, mean_order, vertex list) of a bundle
+    pub fn get_bundle(&self, bundle_id: usize) -> Option<&PrincipalBundleWithId> {
+        self.principal_bundles_with_id
+            .iter()
+            .find(|(bid, _, _)| *bid == bundle_id)
+    }
+
+    /// return a vector of (bundle_id, mean_order, bundle size), the bundle size is the number of vertices
+    pub fn get_bundle_stats(&self) -> Vec<(usize, usize, usize)> {
+        self.principal_bundles_with_id
+            .iter()
+            .map(|(bid, ord, vertices)| (*bid, *ord, vertices.len()))
+            .collect()
+    }
+
+    /// decompose a sequence into the shimmer pairs annotated with the bundle vertices
+    pub fn decompose_seq(&self, seq: Vec<u8>) -> ShmmrPairAndBundleVertices {
+        get_smps(seq, &self.shmmr_spec)
+            .into_iter()
+            .map(|v| {
+                let seg_match = self
+                    .vertex_to_bundle_id_direction_pos
+                    .get(&(v.0, v.1))
+                    .copied();
+                (v, seg_match)
+            })
+            .collect()
+    }
+
+    /// decompose all sequences in a `SeqIndexDB`, the database has to be indexed with the same shimmer parameters
+    pub fn decompose_seq_index_db(
+        &self,
+        seq_db: &SeqIndexDB,
+    ) -> Result<SeqShmmrPairAndBundleVertices, std::io::Error> {
+        match &seq_db.shmmr_spec {
+            Some(spec)
+                if spec.w == self.shmmr_spec.w
+                    && spec.k == self.shmmr_spec.k
+                    && spec.r == self.shmmr_spec.r
+                    && spec.min_span == self.shmmr_spec.min_span =>
+            {
+                Ok(get_principal_bundle_decomposition(
+                    &self.vertex_to_bundle_id_direction_pos,
+                    seq_db,
+                ))
+            }
+            _ => Err(std::io::Error::other(
+                "the shimmer specification of the sequence database does not match the principal bundles",
+            )),
+        }
+    }
+
+    /// decompose the sequences in `seq_db` with the principal bundles, then recursively decompose the sequences
+    /// of each bundle with the finer specifications in `sub_levels`
+    ///
+    /// The bundles of each sub-level are built from the segments of the same parent bundle with the `min_cov` and
+    /// `min_branch_size` of the level, the bundle ids of a sub-level are offset so they are unique across all levels.
+    pub fn get_hierarchical_decomposition(
+        &self,
+        seq_db: &SeqIndexDB,
+        bundle_length_cutoff: usize,
+        bundle_merge_distance: usize,
+        sub_levels: &[BundleDecompLevelSpec],
+    ) -> Result<Vec<HierarchicalBundleSegment>, std::io::Error> {
+        let seq_info = seq_db.seq_info.clone().unwrap_or_default();
+        let bid_to_size = self
+            .principal_bundles_with_id
+            .iter()
+            .map(|v| (v.0, v.2.len()))
+            .collect::<FxHashMap<usize, usize>>();
+        let mut sid_smps = self.decompose_seq_index_db(seq_db)?;
+        sid_smps.sort_by_key(|(sid, _)| seq_info[sid].0.clone());
+
+        let mut all_segments = Vec::<HierarchicalBundleSegment>::new();
+        // the segments of the current level and their sequences to be decomposed at the next level
+        let mut segments = Vec::<(HierarchicalBundleSegment, Vec<u8>)>::new();
+        for (sid, smps) in sid_smps.iter() {
+            let ctg = &seq_info[sid].0;
+            let level_segments = smps_to_hierarchical_segments(
+                smps,
+                0,
+                ctg,
+                0,
+                self.shmmr_spec.k,
+                &bid_to_size,
+                0,
+                None,
+                bundle_length_cutoff,
+                bundle_merge_distance,
+            );
+            if !sub_levels.is_empty() {
+                let seq = seq_db.get_seq_by_id(*sid)?;
+                level_segments.iter().for_each(|seg| {
+                    let sub_seq = seq[seg.bgn as usize..seg.end as usize].to_vec();
+                    segments.push((seg.clone(), sub_seq));
+                });
+            }
+            all_segments.extend(level_segments);
+        }
+
+        let mut next_bid = bid_to_size.keys().max().map(|bid| bid + 1).unwrap_or(0);
+        for (level, spec) in sub_levels.iter().enumerate() {
+            let level = level + 1;
+            let mut parent_to_segments =
+                FxHashMap::<usize, Vec<(HierarchicalBundleSegment, Vec<u8>)>>::default();
+            segments.into_iter().for_each(|(seg, seq)| {
+                parent_to_segments
+                    .entry(seg.bundle_id)
+                    .or_default()
+                    .push((seg, seq));
+            });
+            let mut parent_to_segments = parent_to_segments.into_iter().collect::<Vec<_>>();
+            parent_to_segments.sort_by_key(|(parent_id, _)| *parent_id);
+
+            // decompose the segments of each parent bundle, the bundle ids are local to each parent at this step
+            let child_segments = parent_to_segments
+                .par_iter()
+                .map(|(parent_id, parent_segments)| {
+                    let seq_list = parent_segments
+                        .iter()
+                        .enumerate()
+                        .map(|(idx, (_, seq))| (format!("{}", idx), seq.clone()))
+                        .collect::<Vec<_>>();
+                    let mut sub_seq_db = SeqIndexDB::new();
+                    sub_seq_db.load_from_seq_list(
+                        seq_list,
+                        None,
+                        spec.shmmr_spec.w,
+                        spec.shmmr_spec.k,
+                        spec.shmmr_spec.r,
+                        spec.shmmr_spec.min_span,
+                    )?;
+                    let sub_pdb = PrincipalBundleDB::from_seq_index_db(
+                        &sub_seq_db,
+                        spec.min_cov,
+                        spec.min_branch_size,
+                        None,
+                    )?;
+                    let sub_bid_to_size = sub_pdb
+                        .principal_bundles_with_id
+                        .iter()
+                        .map(|v| (v.0, v.2.len()))
+                        .collect::<FxHashMap<usize, usize>>();
+                    let mut children = Vec::<(HierarchicalBundleSegment, Vec<u8>)>::new();
+                    for (sid, smps) in sub_pdb.decompose_seq_index_db(&sub_seq_db)? {
+                        let (parent_segment, parent_seq) = &parent_segments[sid as usize];
+                        smps_to_hierarchical_segments(
+                            &smps,
+                            level,
+                            &parent_segment.ctg,
+                            parent_segment.bgn,
+                            spec.shmmr_spec.k,
+                            &sub_bid_to_size,
+                            0,
+                            Some(*parent_id),
+                            spec.bundle_length_cutoff,
+                            spec.bundle_merge_distance,
+                        )
+                        .into_iter()
+                        .for_each(|seg| {
+                            let sub_seq = parent_seq[(seg.bgn - parent_segment.bgn) as usize
+                                ..(seg.end - parent_segment.bgn) as usize]
+                                .to_vec();
+                            children.push((seg, sub_seq));
+                        });
+                    }
+                    Ok((sub_bid_to_size.len(), children))
+                })
+                .collect::<Result<Vec<_>, std::io::Error>>()?;
+
+            // make the bundle ids unique across the parents and the levels
+            segments = vec![];
+            child_segments
+                .into_iter()
+                .for_each(|(n_bundles, children)| {
+                    children.into_iter().for_each(|(mut seg, seq)| {
+                        seg.bundle_id += next_bid;
+                        all_segments.push(seg.clone());
+                        segments.push((seg, seq));
+                    });
+                    next_bid += n_bundles;
+                });
+        }
+        all_segments.sort_by(|a, b| (&a.ctg, a.level, a.bgn).cmp(&(&b.ctg, b.level, b.bgn)));
+        Ok(all_segments)
+    }
+
+    /// return a map from the bundle id to the content derived bundle id, see `get_bundle_content_id()`
+    pub fn get_bundle_content_ids(&self) -> FxHashMap<usize, u64> {
+        self.principal_bundles_with_id
+            .iter()
+            .map(|(bid, _, vertices)| (*bid, get_bundle_content_id(vertices)))
+            .collect()
+    }
+
+    /// map the bundles onto the bundles of a reference decomposition by the shared vertices
+    ///
+    /// A bundle is mapped to the reference bundle sharing the most vertices if at least `min_shared_fraction` of
+    /// its vertices are shared. The orientation is 1 if most shared vertices have opposite directions.
+    pub fn map_bundles_to_reference(
+        &self,
+        reference: &PrincipalBundleDB,
+        min_shared_fraction: f32,
+    ) -> Vec<BundleMapping> {
+        let ref_content_ids = reference.get_bundle_content_ids();
+        self.principal_bundles_with_id
+            .iter()
+            .map(|(bid, _, vertices)| {
+                // ref_bid -> (number of shared vertices, number of vertices with opposite directions)
+                let mut shared_count = FxHashMap::<usize, (usize, usize)>::default();
+                vertices.iter().for_each(|v| {
+                    if let Some((ref_bid, ref_direction, _)) =
+                        reference.vertex_to_bundle_id_direction_pos.get(&(v.0, v.1))
+                    {
+                        let e = shared_count.entry(*ref_bid).or_default();
+                        e.0 += 1;
+                        if *ref_direction != v.2 {
+                            e.1 += 1;
+                        }
+                    }
+                });
+                let best_match = shared_count
+                    .into_iter()
+                    .max_by_key(|(ref_bid, (n_shared, _))| (*n_shared, std::cmp::Reverse(*ref_bid)))
+                    .filter(|(_, (n_shared, _))| {
+                        *n_shared as f32 >= min_shared_fraction * vertices.len() as f32
+                    })
+                    .map(|(ref_bid, (n_shared, n_reversed))| {
+                        let orientation = if 2 * n_reversed > n_shared {
+                            1_u8
+                        } else {
+                            0_u8
+                        };
+                        (ref_bid, ref_content_ids[&ref_bid], n_shared, orientation)
+                    });
+                (
+                    *bid,
+                    get_bundle_content_id(vertices),
+                    vertices.len(),
+                    best_match,
+                )
+            })
+            .collect()
+    }
+}
+
+/// (bundle_id, content_id, number of vertices, optional (ref_bundle_id, ref_content_id, number of shared vertices, orientation))
+pub type BundleMapping = (usize, u64, usize, Option<(usize, u64, usize, u8)>);
+
+/// A bundle id derived from the bundle content that does not depend on the order of the bundles
+///
+/// It is the minimum of the hashed shimmer pairs of the bundle vertices, so it is independent of the
+/// bundle direction and stays the same between runs as long as the bundle keeps its minimum vertex.
+pub fn get_bundle_content_id(vertices: &[(u64, u64, u8)]) -> u64 {
+    let hash_shmmr_pair = |s0: u64, s1: u64| -> u64 {
+        // splitmix64 finalizer on the combined shimmer pair hashes
+        let mut x = s0.wrapping_mul(0x9E3779B97F4A7C15) ^ s1.rotate_left(32);
+        x = (x ^ (x >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+        x = (x ^ (x >> 27)).wrapping_mul(0x94D049BB133111EB);
+        x ^ (x >> 31)
+    };
+    vertices
+        .iter()
+        .map(|v| hash_shmmr_pair(v.0, v.1))
+        .min()
+        .unwrap_or(0)
+}
+
 pub fn get_fastx_reader(
     filepath: String,
     to_upper_case: bool,
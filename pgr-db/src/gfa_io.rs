@@ -0,0 +1,311 @@
+// Read the segments and the path (P) and walk (W) lines of a GFA file to reconstruct the path sequences
+
+use crate::fasta_io::reverse_complement;
+use flate2::bufread::MultiGzDecoder;
+use rustc_hash::FxHashMap;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Read};
+
+/// a path of a GFA graph, the steps are (segment name, orientation), orientation = 0 for `+` and 1 for `-`
+#[derive(Clone, Debug)]
+pub struct GFAPath {
+    pub name: String,
+    pub steps: Vec<(String, u8)>,
+    pub overlaps: Vec<u32>, // the overlap length between the consecutive steps, from the L lines if not in the path line
+}
+
+/// (segment name, orientation, start of the segment in the path, end of the segment in the path)
+pub type GFANodeCoordinate = (String, u8, u32, u32);
+
+#[derive(Clone, Debug, Default)]
+pub struct GFAGraph {
+    pub segments: FxHashMap<String, Vec<u8>>,
+    pub paths: Vec<GFAPath>,
+}
+
+fn parse_err(msg: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+// the overlap length from a CIGAR string of a P line, only the matched bases are counted
+fn parse_overlap(cigar: &str) -> u32 {
+    if cigar == "*" {
+        return 0;
+    }
+    let mut len = 0_u32;
+    let mut num = 0_u32;
+    for c in cigar.chars() {
+        if let Some(d) = c.to_digit(10) {
+            num = num * 10 + d;
+        } else {
+            if c == 'M' || c == '=' || c == 'X' {
+                len += num;
+            }
+            num = 0;
+        }
+    }
+    len
+}
+
+fn parse_p_line(fields: &[&str], line_number: usize) -> Result<GFAPath, io::Error> {
+    if fields.len() < 3 {
+        return Err(parse_err(format!(
+            "malformed P line at line {}",
+            line_number
+        )));
+    }
+    let steps = fields[2]
+        .split(',')
+        .map(|s| {
+            let (name, o) = s.split_at(s.len().saturating_sub(1));
+            match o {
+                "+" => Ok((name.to_string(), 0_u8)),
+                "-" => Ok((name.to_string(), 1_u8)),
+                _ => Err(parse_err(format!(
+                    "malformed P line step {} at line {}",
+                    s, line_number
+                ))),
+            }
+        })
+        .collect::<Result<Vec<_>, io::Error>>()?;
+    // the overlaps are filled from the L lines later if they are not specified
+    let overlaps = if fields.len() > 3 && fields[3] != "*" {
+        fields[3].split(',').map(parse_overlap).collect::<Vec<_>>()
+    } else {
+        vec![]
+    };
+    Ok(GFAPath {
+        name: fields[1].to_string(),
+        steps,
+        overlaps,
+    })
+}
+
+// a W line is converted to a path with the PanSN name `sample#haplotype#sequence`
+fn parse_w_line(fields: &[&str], line_number: usize) -> Result<(GFAPath, String), io::Error> {
+    if fields.len() < 7 {
+        return Err(parse_err(format!(
+            "malformed W line at line {}",
+            line_number
+        )));
+    }
+    let mut steps = vec![];
+    let mut orientation = None;
+    let mut name = String::new();
+    for c in fields[6].chars() {
+        match c {
+            '>' | '<' => {
+                if let Some(o) = orientation {
+                    steps.push((name.clone(), o));
+                }
+                orientation = Some(if c == '>' { 0_u8 } else { 1_u8 });
+                name.clear();
+            }
+            _ => name.push(c),
+        }
+    }
+    match orientation {
+        Some(o) if !name.is_empty() => steps.push((name, o)),
+        _ => {
+            return Err(parse_err(format!(
+                "malformed W line walk at line {}",
+                line_number
+            )))
+        }
+    }
+    let path_name = format!("{}#{}#{}", fields[1], fields[2], fields[3]);
+    let range = format!("{}-{}", fields[4], fields[5]);
+    Ok((
+        GFAPath {
+            name: path_name,
+            steps,
+            overlaps: vec![],
+        },
+        range,
+    ))
+}
+
+impl GFAGraph {
+    /// read a GFA (or a gzipped GFA) file, the paths are from the P lines and the W lines, a W line is named as
+    /// `sample#haplotype#sequence` and the range `:start-end` is added to the name if a sequence has multiple walks
+    ///
+    /// The overlaps between the steps of the W lines and the P lines without the overlap field are taken from the
+    /// L lines, they are 0 if the link is not in the file.
+    pub fn from_file(filepath: &str) -> Result<Self, io::Error> {
+        let mut reader = BufReader::new(File::open(filepath)?);
+        let mut magic = Vec::<u8>::new();
+        reader.by_ref().take(2).read_to_end(&mut magic)?;
+        drop(reader);
+        let reader = BufReader::new(File::open(filepath)?);
+        let reader: Box<dyn BufRead> = if magic == [0x1F_u8, 0x8B_u8] {
+            Box::new(BufReader::new(MultiGzDecoder::new(reader)))
+        } else {
+            Box::new(reader)
+        };
+
+        let mut graph = GFAGraph::default();
+        let mut walks = Vec::<(GFAPath, String)>::new();
+        // (segment, orientation, segment, orientation) -> the overlap length, for both strands of a link
+        let mut links = FxHashMap::<(String, u8, String, u8), u32>::default();
+        for (line_number, line) in reader.lines().enumerate() {
+            let line = line?;
+            let fields = line.trim_end().split('\t').collect::<Vec<&str>>();
+            match fields[0] {
+                "S" => {
+                    if fields.len() < 3 {
+                        return Err(parse_err(format!(
+                            "malformed S line at line {}",
+                            line_number + 1
+                        )));
+                    }
+                    graph
+                        .segments
+                        .insert(fields[1].to_string(), fields[2].as_bytes().to_vec());
+                }
+                "L" => {
+                    if fields.len() < 6 {
+                        return Err(parse_err(format!(
+                            "malformed L line at line {}",
+                            line_number + 1
+                        )));
+                    }
+                    let o0 = (fields[2] == "-") as u8;
+                    let o1 = (fields[4] == "-") as u8;
+                    let overlap = parse_overlap(fields[5]);
+                    links.insert(
+                        (fields[1].to_string(), o0, fields[3].to_string(), o1),
+                        overlap,
+                    );
+                    links.insert(
+                        (fields[3].to_string(), 1 - o1, fields[1].to_string(), 1 - o0),
+                        overlap,
+                    );
+                }
+                "P" => graph.paths.push(parse_p_line(&fields, line_number + 1)?),
+                "W" => walks.push(parse_w_line(&fields, line_number + 1)?),
+                _ => (),
+            }
+        }
+
+        let mut walk_count = FxHashMap::<String, usize>::default();
+        walks.iter().for_each(|(path, _)| {
+            *walk_count.entry(path.name.clone()).or_default() += 1;
+        });
+        walks.into_iter().for_each(|(mut path, range)| {
+            if walk_count[&path.name] > 1 {
+                path.name = format!("{}:{}", path.name, range);
+            }
+            graph.paths.push(path);
+        });
+        graph
+            .paths
+            .iter_mut()
+            .filter(|path| path.overlaps.is_empty())
+            .for_each(|path| {
+                path.overlaps = path
+                    .steps
+                    .windows(2)
+                    .map(|w| {
+                        let key = (w[0].0.clone(), w[0].1, w[1].0.clone(), w[1].1);
+                        *links.get(&key).unwrap_or(&0)
+                    })
+                    .collect();
+            });
+        Ok(graph)
+    }
+
+    /// the sequence of a path and the coordinates of the segments in the path sequence
+    pub fn get_path_seq(
+        &self,
+        path: &GFAPath,
+    ) -> Result<(Vec<u8>, Vec<GFANodeCoordinate>), io::Error> {
+        let mut seq = Vec::<u8>::new();
+        let mut coordinates = Vec::<GFANodeCoordinate>::with_capacity(path.steps.len());
+        for (i, (name, orientation)) in path.steps.iter().enumerate() {
+            let segment_seq = self.segments.get(name).ok_or_else(|| {
+                parse_err(format!("segment {} in path {} not found", name, path.name))
+            })?;
+            if segment_seq == b"*" {
+                return Err(parse_err(format!(
+                    "segment {} in path {} has no sequence",
+                    name, path.name
+                )));
+            }
+            let segment_seq = if *orientation == 0 {
+                segment_seq.clone()
+            } else {
+                reverse_complement(segment_seq)
+            };
+            let overlap = if i > 0 {
+                (*path.overlaps.get(i - 1).unwrap_or(&0) as usize).min(segment_seq.len())
+            } else {
+                0
+            };
+            let bgn = seq.len() - overlap;
+            seq.extend_from_slice(&segment_seq[overlap..]);
+            coordinates.push((name.clone(), *orientation, bgn as u32, seq.len() as u32));
+        }
+        Ok((seq, coordinates))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::gfa_io::GFAGraph;
+    use std::io::Write;
+
+    #[test]
+    fn test_read_gfa_paths() {
+        let gfa = "H\tVN:Z:1.1\n\
+                   S\t1\tACGT\n\
+                   S\t2\tGGA\n\
+                   S\t3\tTTC\n\
+                   L\t1\t+\t2\t+\t0M\n\
+                   L\t1\t+\t3\t-\t0M\n\
+                   P\tref\t1+,2+\t*\n\
+                   W\tHG002\t1\tchr1\t0\t7\t>1<3\n\
+                   W\tHG002\t2\tchr1\t0\t7\t>1>2\n";
+        let filepath = std::env::temp_dir().join("test_read_gfa_paths.gfa");
+        std::fs::File::create(&filepath)
+            .unwrap()
+            .write_all(gfa.as_bytes())
+            .unwrap();
+        let graph = GFAGraph::from_file(filepath.to_str().unwrap()).unwrap();
+        assert_eq!(graph.segments.len(), 3);
+        assert_eq!(graph.paths.len(), 3);
+        assert_eq!(graph.paths[1].name, "HG002#1#chr1");
+
+        let (seq, coordinates) = graph.get_path_seq(&graph.paths[0]).unwrap();
+        assert_eq!(seq, b"ACGTGGA".to_vec());
+        assert_eq!(coordinates[1], ("2".to_string(), 0, 4, 7));
+
+        let (seq, coordinates) = graph.get_path_seq(&graph.paths[1]).unwrap();
+        assert_eq!(seq, b"ACGTGAA".to_vec());
+        assert_eq!(coordinates[1], ("3".to_string(), 1, 4, 7));
+    }
+
+    #[test]
+    fn test_read_gfa_link_overlaps() {
+        let gfa = "H\tVN:Z:1.1\n\
+                   S\t1\tACGT\n\
+                   S\t2\tGTAA\n\
+                   L\t2\t-\t1\t-\t2M\n\
+                   P\tref\t1+,2+\t*\n\
+                   P\tref2\t1+,2+\t0M\n\
+                   W\tHG002\t1\tchr1\t0\t6\t<2<1\n";
+        let filepath = std::env::temp_dir().join("test_read_gfa_link_overlaps.gfa");
+        std::fs::File::create(&filepath)
+            .unwrap()
+            .write_all(gfa.as_bytes())
+            .unwrap();
+        let graph = GFAGraph::from_file(filepath.to_str().unwrap()).unwrap();
+        // the overlap of `1+ -> 2+` is from the link `2- -> 1-` on the other strand
+        let (seq, _) = graph.get_path_seq(&graph.paths[0]).unwrap();
+        assert_eq!(seq, b"ACGTAA".to_vec());
+        let (seq, _) = graph.get_path_seq(&graph.paths[1]).unwrap();
+        assert_eq!(seq, b"ACGTGTAA".to_vec());
+        let (seq, coordinates) = graph.get_path_seq(&graph.paths[2]).unwrap();
+        assert_eq!(seq, b"TTACGT".to_vec());
+        assert_eq!(coordinates[1], ("1".to_string(), 1, 2, 6));
+    }
+}
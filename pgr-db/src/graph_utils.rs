@@ -7,28 +7,30 @@ use std::fmt::Debug;
 use std::hash::Hash;
 
 // A struct to support node weight prioritized path traversal.
+// The nodes with the same weight are ordered by the nodes themselves, so the traversal
+// does not depend on the order of the neighbors in the graph.
 #[derive(Copy, Clone)]
 pub struct WeightedNode<N>(pub u32, pub N);
 
-impl<N> Ord for WeightedNode<N> {
+impl<N: Ord> Ord for WeightedNode<N> {
     fn cmp(&self, other: &Self) -> std::cmp::Ordering {
-        self.0.cmp(&other.0)
+        self.0.cmp(&other.0).then_with(|| self.1.cmp(&other.1))
     }
 }
 
-impl<N> PartialOrd for WeightedNode<N> {
+impl<N: Ord> PartialOrd for WeightedNode<N> {
     fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
         Some(self.cmp(other))
     }
 }
 
-impl<N> PartialEq for WeightedNode<N> {
+impl<N: Ord> PartialEq for WeightedNode<N> {
     fn eq(&self, other: &Self) -> bool {
-        self.0 == other.0
+        self.0 == other.0 && self.1 == other.1
     }
 }
 
-impl<N> Eq for WeightedNode<N> {}
+impl<N: Ord> Eq for WeightedNode<N> {}
 
 impl<N> Debug for WeightedNode<N>
 where
@@ -252,22 +254,17 @@ where
 
                 //println!("DBG: next node: {:?}", self.next_node);
 
+                // the visited predecessor with the lowest rank, the smallest node is used for a tie
                 let mut node_rank = u32::MAX;
                 let mut p_node: Option<N> = None;
-                graph.neighbors_directed(node.1, Incoming).for_each(|n| {
-                    if let Some(r) = global_rank.get(&n) {
-                        if *r < node_rank {
-                            node_rank = *r;
-                            p_node = Some(n)
-                        }
-                    }
-                });
-
                 graph
-                    .neighbors_directed(node.1.reverse(), Incoming)
+                    .neighbors_directed(node.1, Incoming)
+                    .chain(graph.neighbors_directed(node.1.reverse(), Incoming))
                     .for_each(|n| {
                         if let Some(r) = global_rank.get(&n) {
-                            if *r < node_rank {
+                            if *r < node_rank
+                                || (*r == node_rank && matches!(p_node, Some(p) if n < p))
+                            {
                                 node_rank = *r;
                                 p_node = Some(n);
                             }
@@ -4,16 +4,21 @@ pub const VERSION_STRING: &str = env!("VERSION_STRING");
 pub mod agc_io;
 pub mod aln;
 pub mod bindings;
+pub mod bundle_graph;
 pub mod ec;
 pub mod fasta_io;
 pub mod frag_file_io;
+pub mod gfa_io;
 //pub mod gff_db;
 pub mod graph_utils;
 pub mod kmer_filter;
+#[cfg(feature = "pbundle")]
+pub mod pbundle;
 pub mod seq_db;
 //pub mod seqs2variants;
 pub mod ext;
 pub mod shmmrutils;
+pub mod tandem_repeat;
 
 #[cfg(test)]
 mod tests {
@@ -419,6 +424,449 @@ mod tests {
         assert_eq!(seq[250..1423], sub_seq[..]);
     }
 
+    #[test]
+    fn test_principal_bundle_db_read_write() {
+        use crate::ext::{PrincipalBundleDB, SeqIndexDB};
+        let mut seq_index_db = SeqIndexDB::new();
+        let _ = seq_index_db.load_from_fastx(
+            "test/test_data/test_seqs.fa".to_string(),
+            48,
+            56,
+            4,
+            12,
+            true,
+        );
+        let pdb = PrincipalBundleDB::from_seq_index_db(&seq_index_db, 0, 8, None).unwrap();
+        assert!(!pdb.principal_bundles_with_id.is_empty());
+        let filepath = std::env::temp_dir().join("test_principal_bundle_db.pdb");
+        let filepath = filepath.to_str().unwrap();
+        pdb.write_to_file(filepath).unwrap();
+        let pdb2 = PrincipalBundleDB::load_from_file(filepath).unwrap();
+        assert_eq!(
+            pdb.principal_bundles_with_id,
+            pdb2.principal_bundles_with_id
+        );
+        assert_eq!(
+            pdb.vertex_to_bundle_id_direction_pos,
+            pdb2.vertex_to_bundle_id_direction_pos
+        );
+
+        let (bid, _, vertices) = &pdb2.principal_bundles_with_id[0];
+        let v = vertices[0];
+        assert_eq!(pdb2.get_bundle_by_shmmr_pair((v.1, v.0)).unwrap().0, *bid);
+        assert_eq!(pdb2.get_bundle(*bid).unwrap().2.len(), vertices.len());
+
+        let decomp = pdb2.decompose_seq_index_db(&seq_index_db).unwrap();
+        let seq = seq_index_db.get_seq_by_id(decomp[0].0).unwrap();
+        let smps = pdb2.decompose_seq(seq);
+        let smps0 = decomp[0].1.clone();
+        assert_eq!(smps, smps0);
+    }
+
+    #[test]
+    fn test_map_bundles_to_reference() {
+        use crate::ext::{PrincipalBundleDB, SeqIndexDB};
+        let mut seq_index_db = SeqIndexDB::new();
+        let _ = seq_index_db.load_from_fastx(
+            "test/test_data/test_seqs.fa".to_string(),
+            48,
+            56,
+            4,
+            12,
+            true,
+        );
+        let pdb = PrincipalBundleDB::from_seq_index_db(&seq_index_db, 0, 8, None).unwrap();
+        let content_ids = pdb.get_bundle_content_ids();
+        let mapping = pdb.map_bundles_to_reference(&pdb, 0.5);
+        assert_eq!(mapping.len(), pdb.principal_bundles_with_id.len());
+        mapping
+            .into_iter()
+            .for_each(|(bid, content_id, size, ref_match)| {
+                let (ref_bid, ref_content_id, shared_size, orientation) = ref_match.unwrap();
+                assert_eq!(bid, ref_bid);
+                assert_eq!(content_id, content_ids[&bid]);
+                assert_eq!(content_id, ref_content_id);
+                assert_eq!(size, shared_size);
+                assert_eq!(orientation, 0);
+            });
+    }
+
+    #[test]
+    fn test_mapg_gfa_with_paths() {
+        use crate::ext::{GFAPathOptions, SeqIndexDB};
+        let mut seq_index_db = SeqIndexDB::new();
+        let _ = seq_index_db.load_from_fastx(
+            "test/test_data/test_seqs.fa".to_string(),
+            48,
+            56,
+            4,
+            12,
+            true,
+        );
+        let n_seqs = seq_index_db.seq_info.as_ref().unwrap().len();
+        let path_options = GFAPathOptions {
+            use_p_lines: false,
+            reference_sample: Some("GRCH38_chr6:32000466-32046826".to_string()),
+            with_sequences: false,
+        };
+        let filepath = std::env::temp_dir().join("test_mapg_with_paths.gfa");
+        let filepath = filepath.to_str().unwrap();
+        seq_index_db
+            .generate_mapg_gfa_with_paths(0, filepath, "from_fragmap", None, Some(&path_options))
+            .unwrap();
+        let gfa = std::fs::read_to_string(filepath).unwrap();
+        let mut links = std::collections::HashSet::<(String, String)>::new();
+        let mut walk_ctgs = std::collections::HashSet::<String>::new();
+        let mut n_segments = 0;
+        gfa.lines().for_each(|line| {
+            let fields = line.split('\t').collect::<Vec<_>>();
+            match fields[0] {
+                "S" => {
+                    n_segments += 1;
+                    assert!(fields.iter().any(|f| f.starts_with("SR:i:")));
+                }
+                "L" => {
+                    let flip = |o: &str| if o == "+" { "<" } else { ">" };
+                    let keep = |o: &str| if o == "+" { ">" } else { "<" };
+                    links.insert((
+                        format!("{}{}", keep(fields[2]), fields[1]),
+                        format!("{}{}", keep(fields[4]), fields[3]),
+                    ));
+                    links.insert((
+                        format!("{}{}", flip(fields[4]), fields[3]),
+                        format!("{}{}", flip(fields[2]), fields[1]),
+                    ));
+                }
+                "W" => {
+                    walk_ctgs.insert(format!("{}#{}#{}", fields[1], fields[2], fields[3]));
+                    let steps = fields[6]
+                        .replace('>', " >")
+                        .replace('<', " <")
+                        .split_whitespace()
+                        .map(|s| s.to_string())
+                        .collect::<Vec<_>>();
+                    steps.windows(2).for_each(|w| {
+                        assert!(links.contains(&(w[0].clone(), w[1].clone())));
+                    });
+                }
+                _ => (),
+            }
+        });
+        assert!(n_segments > 0);
+        assert_eq!(walk_ctgs.len(), n_seqs);
+    }
+
+    #[test]
+    fn test_mapg_gfa_with_sequences() {
+        use crate::ext::{GFAPathOptions, SeqIndexDB};
+        use crate::gfa_io::GFAGraph;
+        let mut seq_index_db = SeqIndexDB::new();
+        let _ = seq_index_db.load_from_fastx(
+            "test/test_data/test_seqs.fa".to_string(),
+            48,
+            56,
+            4,
+            12,
+            true,
+        );
+        let path_options = GFAPathOptions {
+            use_p_lines: false,
+            reference_sample: None,
+            with_sequences: true,
+        };
+        let filepath = std::env::temp_dir().join("test_mapg_with_sequences.gfa");
+        let filepath = filepath.to_str().unwrap();
+        seq_index_db
+            .generate_mapg_gfa_with_paths(0, filepath, "from_fragmap", None, Some(&path_options))
+            .unwrap();
+        let graph = GFAGraph::from_file(filepath).unwrap();
+        assert!(graph.segments.values().all(|seq| seq != b"*"));
+
+        // the segments are taken from the sequence with the smallest id, so its walks spell the sequence
+        let (sample, hap, ctg) = seq_index_db.get_pansn_name(0).unwrap();
+        let seq = seq_index_db.get_seq_by_id(0).unwrap();
+        let gfa = std::fs::read_to_string(filepath).unwrap();
+        let walks = gfa
+            .lines()
+            .map(|line| line.split('\t').collect::<Vec<_>>())
+            .filter(|fields| {
+                fields[0] == "W" && fields[1] == sample && fields[2] == hap && fields[3] == ctg
+            })
+            .map(|fields| {
+                (
+                    fields[4].parse::<usize>().unwrap(),
+                    fields[5].parse::<usize>().unwrap(),
+                )
+            })
+            .collect::<Vec<_>>();
+        assert!(!walks.is_empty());
+        walks.iter().for_each(|&(bgn, end)| {
+            let path_name = if walks.len() > 1 {
+                format!("{}#{}#{}:{}-{}", sample, hap, ctg, bgn, end)
+            } else {
+                format!("{}#{}#{}", sample, hap, ctg)
+            };
+            let path = graph.paths.iter().find(|p| p.name == path_name).unwrap();
+            let (path_seq, _) = graph.get_path_seq(path).unwrap();
+            assert_eq!(path_seq, seq[bgn..end].to_vec());
+        });
+    }
+
+    #[test]
+    fn test_hierarchical_decomposition() {
+        use crate::ext::{BundleDecompLevelSpec, PrincipalBundleDB, SeqIndexDB, ShmmrSpec};
+        let mut seq_index_db = SeqIndexDB::new();
+        let _ = seq_index_db.load_from_fastx(
+            "test/test_data/test_seqs.fa".to_string(),
+            128,
+            56,
+            4,
+            12,
+            true,
+        );
+        let pdb = PrincipalBundleDB::from_seq_index_db(&seq_index_db, 0, 8, None).unwrap();
+        let sub_levels = vec![BundleDecompLevelSpec {
+            shmmr_spec: ShmmrSpec {
+                w: 24,
+                k: 32,
+                r: 2,
+                min_span: 8,
+                sketch: false,
+            },
+            min_cov: 0,
+            min_branch_size: 8,
+            bundle_length_cutoff: 500,
+            bundle_merge_distance: 2000,
+        }];
+        let segments = pdb
+            .get_hierarchical_decomposition(&seq_index_db, 500, 10000, &sub_levels)
+            .unwrap();
+        let top_segments = segments
+            .iter()
+            .filter(|seg| seg.level == 0)
+            .collect::<Vec<_>>();
+        let sub_segments = segments
+            .iter()
+            .filter(|seg| seg.level == 1)
+            .collect::<Vec<_>>();
+        assert!(!top_segments.is_empty());
+        assert!(!sub_segments.is_empty());
+        top_segments.iter().for_each(|seg| {
+            assert!(seg.parent_id.is_none());
+            assert!(seg.bundle_id < pdb.principal_bundles_with_id.len());
+        });
+        sub_segments.iter().for_each(|seg| {
+            assert!(seg.bundle_id >= pdb.principal_bundles_with_id.len());
+            let parent_id = seg.parent_id.unwrap();
+            assert!(top_segments.iter().any(|p| p.ctg == seg.ctg
+                && p.bundle_id == parent_id
+                && p.bgn <= seg.bgn
+                && seg.end <= p.end));
+        });
+    }
+
+    #[test]
+    fn test_region_decomposition() {
+        use crate::ext::{BundleDecompLevelSpec, RegionDecompSpec, SeqIndexDB, ShmmrSpec};
+        let mut seq_index_db = SeqIndexDB::new();
+        let _ = seq_index_db.load_from_fastx(
+            "test/test_data/test_seqs.fa".to_string(),
+            24,
+            32,
+            2,
+            8,
+            true,
+        );
+        let spec = RegionDecompSpec {
+            gap_penalty_factor: 0.025,
+            max_count: 128,
+            max_query_count: 128,
+            max_target_count: 128,
+            max_aln_chain_span: 8,
+            merge_range_tol: 100000,
+            min_query_coverage: 0.5,
+            flank_length: 500,
+            decomp: BundleDecompLevelSpec {
+                shmmr_spec: ShmmrSpec {
+                    w: 16,
+                    k: 32,
+                    r: 1,
+                    min_span: 8,
+                    sketch: false,
+                },
+                min_cov: 0,
+                min_branch_size: 8,
+                bundle_length_cutoff: 100,
+                bundle_merge_distance: 1000,
+            },
+        };
+        let region_decomp = seq_index_db
+            .get_region_decomposition(
+                "test/test_data/test_seqs.fa".to_string(),
+                "NA21309#1#JAHEPC010000026.1:3279880-3319873".to_string(),
+                1000,
+                2000,
+                &spec,
+            )
+            .unwrap();
+        assert_eq!(region_decomp.query_range, (500, 2500));
+        assert!(region_decomp.hits.len() > 1);
+        // the query region itself is found with the same flanks
+        assert!(region_decomp
+            .hits
+            .iter()
+            .any(|hit| hit.sid == 0 && hit.bgn == 500 && hit.end == 2500 && hit.orientation == 0));
+        assert!(!region_decomp.segments.is_empty());
+        region_decomp.segments.iter().for_each(|seg| {
+            assert!(region_decomp.hits.iter().any(|hit| hit.name == seg.ctg));
+        });
+    }
+
+    #[test]
+    fn test_region_homologous_seqs_reverse_query() {
+        use crate::ext::{BundleDecompLevelSpec, RegionDecompSpec, SeqIndexDB, ShmmrSpec};
+        use crate::fasta_io::reverse_complement;
+        let mut seq_index_db = SeqIndexDB::new();
+        let _ = seq_index_db.load_from_fastx(
+            "test/test_data/test_seqs.fa".to_string(),
+            24,
+            32,
+            2,
+            8,
+            true,
+        );
+        let spec = RegionDecompSpec {
+            gap_penalty_factor: 0.025,
+            max_count: 128,
+            max_query_count: 128,
+            max_target_count: 128,
+            max_aln_chain_span: 8,
+            merge_range_tol: 100000,
+            min_query_coverage: 0.5,
+            flank_length: 0,
+            decomp: BundleDecompLevelSpec {
+                shmmr_spec: ShmmrSpec {
+                    w: 16,
+                    k: 32,
+                    r: 1,
+                    min_span: 8,
+                    sketch: false,
+                },
+                min_cov: 0,
+                min_branch_size: 8,
+                bundle_length_cutoff: 100,
+                bundle_merge_distance: 1000,
+            },
+        };
+        let seq = seq_index_db
+            .get_sub_seq(
+                "test/test_data/test_seqs.fa".to_string(),
+                "NA21309#1#JAHEPC010000026.1:3279880-3319873".to_string(),
+                500,
+                2500,
+            )
+            .unwrap();
+        let hits = seq_index_db
+            .query_region_homologous_seqs(&seq, &spec)
+            .unwrap();
+        let rc_hits = seq_index_db
+            .query_region_homologous_seqs(&reverse_complement(&seq), &spec)
+            .unwrap();
+        assert!(hits
+            .iter()
+            .any(|hit| hit.sid == 0 && hit.bgn == 500 && hit.end == 2500 && hit.orientation == 0));
+        // the reverse complemented query maps back to the same range in the other orientation
+        let hit = rc_hits
+            .iter()
+            .find(|hit| hit.sid == 0)
+            .expect("the source region is not found");
+        assert_eq!((hit.bgn, hit.end, hit.orientation), (500, 2500, 1));
+        assert_eq!(hit.seq, reverse_complement(&seq));
+        // every hit of the forward query is found with the flipped orientation
+        assert_eq!(hits.len(), rc_hits.len());
+        hits.iter().for_each(|hit| {
+            assert!(rc_hits.iter().any(|rc_hit| rc_hit.sid == hit.sid
+                && rc_hit.bgn == hit.bgn
+                && rc_hit.end == hit.end
+                && rc_hit.orientation == 1 - hit.orientation));
+        });
+    }
+
+    #[test]
+    fn test_principal_bundles_across_thread_counts() {
+        use crate::ext::{PrincipalBundleDB, SeqIndexDB};
+        use crate::graph_utils::ShmmrGraphNode;
+        use crate::seq_db::split_adj_list_by_weakly_connected_components;
+        let mut seq_index_db = SeqIndexDB::new();
+        let _ = seq_index_db.load_from_fastx(
+            "test/test_data/test_seqs.fa".to_string(),
+            48,
+            56,
+            4,
+            12,
+            true,
+        );
+        let pdbs = [1, 2, 4]
+            .into_iter()
+            .map(|n_threads| {
+                rayon::ThreadPoolBuilder::new()
+                    .num_threads(n_threads)
+                    .build()
+                    .unwrap()
+                    .install(|| {
+                        PrincipalBundleDB::from_seq_index_db(&seq_index_db, 0, 8, None).unwrap()
+                    })
+            })
+            .collect::<Vec<_>>();
+        assert!(!pdbs[0].principal_bundles_with_id.is_empty());
+        pdbs[1..].iter().for_each(|pdb| {
+            assert_eq!(
+                pdbs[0].principal_bundles_with_id,
+                pdb.principal_bundles_with_id
+            );
+            assert_eq!(
+                pdbs[0].vertex_to_bundle_id_direction_pos,
+                pdb.vertex_to_bundle_id_direction_pos
+            );
+        });
+
+        // a vertex and its reverse complement belong to the same component
+        let adj_list = vec![
+            (0, ShmmrGraphNode(1, 2, 0), ShmmrGraphNode(3, 4, 0)),
+            (1, ShmmrGraphNode(5, 6, 0), ShmmrGraphNode(7, 8, 0)),
+            (2, ShmmrGraphNode(4, 3, 1), ShmmrGraphNode(9, 10, 1)),
+            (3, ShmmrGraphNode(3, 4, 1), ShmmrGraphNode(2, 1, 1)),
+        ];
+        let components = split_adj_list_by_weakly_connected_components(&adj_list);
+        assert_eq!(components.len(), 3);
+        assert_eq!(components[0], vec![adj_list[0], adj_list[3]]);
+        assert_eq!(components[1], vec![adj_list[1]]);
+        assert_eq!(components[2], vec![adj_list[2]]);
+    }
+
+    #[test]
+    fn test_shmmr_pair_counts_of_seq() {
+        use crate::ext::SeqIndexDB;
+        let mut seq_index_db = SeqIndexDB::new();
+        let _ = seq_index_db.load_from_fastx(
+            "test/test_data/test_seqs.fa".to_string(),
+            48,
+            56,
+            4,
+            12,
+            true,
+        );
+        let seq = seq_index_db.get_seq_by_id(0).unwrap();
+        let counts = seq_index_db.get_shmmr_pair_counts_of_seq(seq).unwrap();
+        assert!(!counts.is_empty());
+        let frag_map = seq_index_db.get_shmmr_map_internal().unwrap();
+        counts.iter().for_each(|&(s0, s1, _, _, _, count)| {
+            assert!(count > 0);
+            assert_eq!(count, frag_map.get(&(s0, s1)).unwrap().len());
+        });
+        assert_eq!(seq_index_db.get_shmmr_pair_count((0, 0)), 0);
+    }
+
     #[test]
     fn test_seq_db_get_sub_read() {
         use seq_db::GetSeq;
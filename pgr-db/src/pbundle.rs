@@ -0,0 +1,1200 @@
+// The principal bundle segments of the contigs: the bed records, the distances and the dendrogram from aligning
+// the segments, and the SVG tracks, shared by `pgr-pbundle-decomp`, `pgr-pbundle-bed2dist`, `pgr-pbundle-bed2svg` and pgr-server
+
+use kodama::{linkage, Method};
+use rayon::prelude::*;
+use rustc_hash::FxHashMap;
+use serde::{Deserialize, Serialize};
+use std::io::{BufWriter, Write};
+use svg::node::{element, Node};
+use svg::Document;
+
+static CMAP: [&str; 97] = [
+    "#870098", "#00aaa5", "#3bff00", "#ec0000", "#00a2c3", "#00f400", "#ff1500", "#0092dd",
+    "#00dc00", "#ff8100", "#007ddd", "#00c700", "#ffb100", "#0038dd", "#00af00", "#fcd200",
+    "#0000d5", "#009a00", "#f1e700", "#0000b1", "#00a55d", "#d4f700", "#4300a2", "#00aa93",
+    "#a1ff00", "#dc0000", "#00aaab", "#1dff00", "#f40000", "#009fcb", "#00ef00", "#ff2d00",
+    "#008ddd", "#00d700", "#ff9900", "#0078dd", "#00c200", "#ffb900", "#0025dd", "#00aa00",
+    "#f9d700", "#0000c9", "#009b13", "#efed00", "#0300aa", "#00a773", "#ccf900", "#63009e",
+    "#00aa98", "#84ff00", "#e10000", "#00a7b3", "#00ff00", "#f90000", "#009bd7", "#00ea00",
+    "#ff4500", "#0088dd", "#00d200", "#ffa100", "#005ddd", "#00bc00", "#ffc100", "#0013dd",
+    "#00a400", "#f7dd00", "#0000c1", "#009f33", "#e8f000", "#1800a7", "#00aa88", "#c4fc00",
+    "#78009b", "#00aaa0", "#67ff00", "#e60000", "#00a4bb", "#00fa00", "#fe0000", "#0098dd",
+    "#00e200", "#ff5d00", "#0082dd", "#00cc00", "#ffa900", "#004bdd", "#00b400", "#ffc900",
+    "#0000dd", "#009f00", "#f4e200", "#0000b9", "#00a248", "#dcf400", "#2d00a4", "#00aa8d",
+    "#bcff00",
+];
+
+#[derive(PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Debug)]
+pub struct BundleSegment {
+    pub bgn: u32,
+    pub end: u32,
+    pub bundle_id: u32,
+    pub bundle_v_count: u32,
+    pub bundle_dir: u32,
+    pub bundle_v_bgn: u32,
+    pub bundle_v_end: u32,
+}
+
+/// the line of a segment in a principal bundle bed file (without the new line),
+/// the fourth column is `bundle_id:bundle_vertex_count:direction:bundle_v_bgn:bundle_v_end:(R|U)`
+pub fn bundle_bed_line(ctg: &str, seg: &BundleSegment, is_repeat: bool) -> String {
+    format!(
+        "{}\t{}\t{}\t{}:{}:{}:{}:{}:{}",
+        ctg,
+        seg.bgn,
+        seg.end,
+        seg.bundle_id,
+        seg.bundle_v_count,
+        seg.bundle_dir,
+        seg.bundle_v_bgn,
+        seg.bundle_v_end,
+        if is_repeat { "R" } else { "U" }
+    )
+}
+
+#[derive(Clone, Copy, Debug)]
+enum AlnType {
+    Match,
+    Insertion,
+    Deletion,
+}
+
+/// align the bundle segments of two contigs,
+/// return (the distance, the unmatched length, the aligned length, the best score, the offset of the alignment end)
+pub fn align_bundles(
+    q_bundles: &[BundleSegment],
+    t_bundles: &[BundleSegment],
+    local_aln: bool,
+) -> (f32, usize, usize, i64, isize) {
+    let q_count = q_bundles.len();
+    let t_count = t_bundles.len();
+    if q_count == 0 || t_count == 0 {
+        // nothing is aligned when a contig has no segment
+        let seg_len = |s: &BundleSegment| (s.end as i64 - s.bgn as i64).unsigned_abs() as usize;
+        let diff_len = q_bundles
+            .iter()
+            .chain(t_bundles)
+            .map(seg_len)
+            .sum::<usize>();
+        return (
+            diff_len as f32 / (diff_len + 1) as f32,
+            diff_len,
+            diff_len + 1,
+            0,
+            0,
+        );
+    }
+    let mut s_map = FxHashMap::<(usize, usize), i64>::default();
+    let mut t_map = FxHashMap::<(usize, usize), AlnType>::default();
+
+    // the scores wrap around when the first segments are not matched, the same as the release builds
+    // of `pgr-pbundle-bed2dist` have always done, so the distances stay the same
+    let mut get_aln_direction_with_best_score =
+        |q_idx: usize, t_idx: usize, s_map: &FxHashMap<(usize, usize), i64>| -> (AlnType, i64) {
+            let mut best = (AlnType::Match, i64::MIN);
+            let q_len = (q_bundles[q_idx].end as i64 - q_bundles[q_idx].bgn as i64).abs();
+            let t_len = (t_bundles[t_idx].end as i64 - t_bundles[t_idx].bgn as i64).abs();
+            let min_len = if q_len > t_len { t_len } else { q_len };
+            let q_b_seg = q_bundles[q_idx];
+            let t_b_seg = t_bundles[t_idx];
+            if q_idx == 0
+                && t_idx == 0
+                && (q_b_seg.bundle_id == t_b_seg.bundle_id)
+                && (q_b_seg.bundle_dir == t_b_seg.bundle_dir)
+            {
+                best = (AlnType::Match, 2 * min_len)
+            };
+            if q_idx > 0
+                && t_idx > 0
+                && q_b_seg.bundle_id == t_b_seg.bundle_id
+                && (q_b_seg.bundle_dir == t_b_seg.bundle_dir)
+            {
+                best = (
+                    AlnType::Match,
+                    (2 * min_len).wrapping_add(*s_map.get(&(q_idx - 1, t_idx - 1)).unwrap()),
+                )
+            };
+            if t_idx > 0 {
+                let score = (-2 * q_len).wrapping_add(*s_map.get(&(q_idx, t_idx - 1)).unwrap());
+                if score > best.1 {
+                    best = (AlnType::Deletion, score)
+                };
+            };
+            if q_idx > 0 {
+                let score = (-2 * t_len).wrapping_add(*s_map.get(&(q_idx - 1, t_idx)).unwrap());
+                if score > best.1 {
+                    best = (AlnType::Insertion, score)
+                }
+            }
+            t_map.insert((q_idx, t_idx), best.0);
+            best
+        };
+
+    let mut best_score = 0;
+    let mut best_q_idx = 0;
+    let mut best_t_idx = 0;
+
+    (0..t_count)
+        .flat_map(|t_idx| (0..q_count).map(move |q_idx| (q_idx, t_idx)))
+        .for_each(|(q_idx, t_idx)| {
+            let (_, score) = get_aln_direction_with_best_score(q_idx, t_idx, &s_map);
+
+            let score = if local_aln && score < 0 { 0 } else { score };
+
+            s_map.insert((q_idx, t_idx), score);
+
+            if score > best_score {
+                best_score = score;
+                best_q_idx = q_idx;
+                best_t_idx = t_idx;
+            }
+        });
+    let mut q_idx = if local_aln { best_q_idx } else { q_count - 1 };
+    let mut t_idx = if local_aln { best_t_idx } else { t_count - 1 };
+    let offset = q_bundles[q_idx].bgn as isize - t_bundles[t_idx].bgn as isize;
+    let mut diff_len = 0_usize;
+    let mut max_len = 1_usize;
+    // a match of the first segments moves out of the table and ends the trace back
+    while let Some(aln_type) = t_map.get(&(q_idx, t_idx)) {
+        if local_aln && *s_map.get(&(q_idx, t_idx)).unwrap_or(&0) == 0 {
+            break;
+        }
+        let (diff_len_delta, max_len_delta) = match aln_type {
+            AlnType::Match => {
+                let q_len = (q_bundles[q_idx].end as i64 - q_bundles[q_idx].bgn as i64).abs();
+                let t_len = (t_bundles[t_idx].end as i64 - t_bundles[t_idx].bgn as i64).abs();
+                let diff_len_delta = (q_len - t_len).unsigned_abs() as usize;
+                let max_len_delta = if q_len > t_len {
+                    q_len as usize
+                } else {
+                    t_len as usize
+                };
+                q_idx = q_idx.wrapping_sub(1);
+                t_idx = t_idx.wrapping_sub(1);
+                (diff_len_delta, max_len_delta)
+            }
+            AlnType::Insertion => {
+                let q_len = (q_bundles[q_idx].end as i64 - q_bundles[q_idx].bgn as i64).abs();
+                q_idx -= 1;
+                (q_len as usize, q_len as usize)
+            }
+            AlnType::Deletion => {
+                let t_len = (t_bundles[t_idx].end as i64 - t_bundles[t_idx].bgn as i64).abs();
+                t_idx -= 1;
+                (t_len as usize, t_len as usize)
+            }
+        };
+        diff_len += diff_len_delta;
+        max_len += max_len_delta;
+    }
+    (
+        diff_len as f32 / max_len as f32,
+        diff_len,
+        max_len,
+        best_score,
+        offset,
+    )
+}
+
+// the splitmix64 finalizer, it is used for hashing the bundle segment tokens for the sketches
+fn mix64(mut x: u64) -> u64 {
+    x = (x ^ (x >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    x = (x ^ (x >> 27)).wrapping_mul(0x94d049bb133111eb);
+    x ^ (x >> 31)
+}
+
+/// the bottom sketch of the tokens of `token_size` consecutive bundle segments, weighted by their lengths in `weight_unit`
+pub fn sketch_bundles(
+    bundles: &[BundleSegment],
+    token_size: usize,
+    weight_unit: u32,
+    sketch_size: usize,
+) -> Vec<u64> {
+    let token_size = token_size.max(1).min(bundles.len().max(1));
+    let weight_unit = weight_unit.max(1);
+    // the token hash is the smaller one of the hashes from the two strands, so a sequence and its reverse complement have the same tokens
+    let mut token_weights = FxHashMap::<u64, u32>::default();
+    bundles.windows(token_size).for_each(|segs| {
+        let fwd_hash = segs.iter().fold(0_u64, |h, s| {
+            mix64(h ^ (((s.bundle_id as u64) << 1) | (s.bundle_dir & 0x1) as u64))
+        });
+        let rev_hash = segs.iter().rev().fold(0_u64, |h, s| {
+            mix64(h ^ (((s.bundle_id as u64) << 1) | (1 - (s.bundle_dir & 0x1)) as u64))
+        });
+        let len = segs.iter().map(|s| s.end - s.bgn).sum::<u32>();
+        *token_weights.entry(fwd_hash.min(rev_hash)).or_default() +=
+            len.div_ceil(weight_unit).max(1);
+    });
+    // each token is expanded into weight copies, the Jaccard index of the expanded sets is the weighted Jaccard index of the tokens
+    let mut hashes = token_weights
+        .into_iter()
+        .flat_map(|(h, w)| (0..w as u64).map(move |c| mix64(h ^ mix64(c + 1))))
+        .collect::<Vec<_>>();
+    hashes.sort_unstable();
+    hashes.dedup();
+    hashes.truncate(sketch_size);
+    hashes
+}
+
+/// estimate the Jaccard index from the bottom sketch of the union of two sorted sketches,
+/// return (the number of the hashes in the union sketch, the number of the shared hashes)
+pub fn compare_sketches(sketch0: &[u64], sketch1: &[u64], sketch_size: usize) -> (usize, usize) {
+    let mut union_count = 0_usize;
+    let mut shared_count = 0_usize;
+    let (mut idx0, mut idx1) = (0_usize, 0_usize);
+    while union_count < sketch_size && (idx0 < sketch0.len() || idx1 < sketch1.len()) {
+        match (sketch0.get(idx0), sketch1.get(idx1)) {
+            (Some(h0), Some(h1)) if h0 == h1 => {
+                shared_count += 1;
+                idx0 += 1;
+                idx1 += 1;
+            }
+            (Some(h0), Some(h1)) if h0 < h1 => idx0 += 1,
+            (Some(_), None) => idx0 += 1,
+            _ => idx1 += 1,
+        }
+        union_count += 1;
+    }
+    (union_count, shared_count)
+}
+
+/// generate the neighbor-joining tree from a full distance matrix, the leaves are labeled by the indices
+pub fn neighbor_joining(dist_matrix: &[Vec<f32>]) -> String {
+    let mut nodes = (0..dist_matrix.len())
+        .map(|idx| format!("{}", idx))
+        .collect::<Vec<_>>();
+    let mut d = dist_matrix
+        .iter()
+        .map(|row| row.iter().map(|&v| v as f64).collect::<Vec<_>>())
+        .collect::<Vec<_>>();
+    while nodes.len() > 2 {
+        let n = nodes.len();
+        let r = d
+            .iter()
+            .map(|row| row.iter().sum::<f64>())
+            .collect::<Vec<_>>();
+        let mut best = (f64::MAX, 0_usize, 1_usize);
+        (0..n).for_each(|i| {
+            (i + 1..n).for_each(|j| {
+                let q = (n - 2) as f64 * d[i][j] - r[i] - r[j];
+                if q < best.0 {
+                    best = (q, i, j);
+                }
+            })
+        });
+        let (_, i, j) = best;
+        let li = (0.5 * d[i][j] + (r[i] - r[j]) / (2.0 * (n - 2) as f64)).max(0.0);
+        let lj = (d[i][j] - li).max(0.0);
+        let new_node = format!("({}:{}, {}:{})", nodes[i], li, nodes[j], lj);
+        // the new node takes the place of i and j is removed
+        let new_dist = (0..n)
+            .map(|k| 0.5 * (d[i][k] + d[j][k] - d[i][j]).max(0.0))
+            .collect::<Vec<_>>();
+        (0..n).for_each(|k| {
+            d[i][k] = new_dist[k];
+            d[k][i] = new_dist[k];
+        });
+        d[i][i] = 0.0;
+        nodes[i] = new_node;
+        nodes.remove(j);
+        d.remove(j);
+        d.iter_mut().for_each(|row| {
+            row.remove(j);
+        });
+    }
+    if nodes.len() == 2 {
+        format!(
+            "({}:{}, {}:{})",
+            nodes[0],
+            0.5 * d[0][1],
+            nodes[1],
+            0.5 * d[0][1]
+        )
+    } else {
+        nodes.pop().unwrap_or_default()
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub enum BundleDistanceMethod {
+    GlobalAlignment,
+    /// the distances for the clustering are from the best local alignment scores
+    LocalAlignment,
+    /// one minus the weighted Jaccard index of the bundle segment tokens estimated with MinHash sketches
+    Sketch {
+        token_size: usize,
+        sketch_size: usize,
+        weight_unit: u32,
+    },
+}
+
+/// a line of the `.dist` file, in the sketch mode, `diff`, `total` and `score` are the number of the unshared hashes,
+/// the hashes in the union sketch and the shared hashes, and `offset` is zero
+#[derive(Clone, Copy, Debug)]
+pub struct BundleDistanceRecord {
+    pub ctg_idx0: usize,
+    pub ctg_idx1: usize,
+    pub dist: f32,
+    pub diff: usize,
+    pub total: usize,
+    pub score: i64,
+    pub offset: isize,
+}
+
+/// the pairwise distances of the contigs
+pub struct BundleDistances {
+    pub n_ctg: usize,
+    /// the records of the contig pairs `(ctg_idx0, ctg_idx1)` with `ctg_idx0 <= ctg_idx1`
+    pub records: Vec<BundleDistanceRecord>,
+    /// the normalized distances of the contig pairs `(ctg_idx0, ctg_idx1)` with `ctg_idx0 < ctg_idx1` for the clustering
+    pub dist_map: FxHashMap<(usize, usize), f32>,
+    pub offset_map: FxHashMap<(usize, usize), isize>,
+}
+
+/// compute the distances between all pairs of the contigs from their sorted bundle segments
+pub fn get_bundle_distances(
+    ctg_bundles: &[Vec<BundleSegment>],
+    method: BundleDistanceMethod,
+) -> BundleDistances {
+    let n_ctg = ctg_bundles.len();
+    let sketches = if let BundleDistanceMethod::Sketch {
+        token_size,
+        sketch_size,
+        weight_unit,
+    } = method
+    {
+        ctg_bundles
+            .iter()
+            .map(|bundles| sketch_bundles(bundles, token_size, weight_unit, sketch_size))
+            .collect::<Vec<_>>()
+    } else {
+        vec![]
+    };
+    let records = (0..n_ctg)
+        .flat_map(|ctg_idx0| (ctg_idx0..n_ctg).map(move |ctg_idx1| (ctg_idx0, ctg_idx1)))
+        .collect::<Vec<_>>()
+        .into_par_iter()
+        .map(|(ctg_idx0, ctg_idx1)| {
+            let bundles0 = &ctg_bundles[ctg_idx0];
+            let bundles1 = &ctg_bundles[ctg_idx1];
+            let local_aln = match method {
+                BundleDistanceMethod::Sketch { sketch_size, .. } => {
+                    let (union_count, shared_count) =
+                        compare_sketches(&sketches[ctg_idx0], &sketches[ctg_idx1], sketch_size);
+                    return BundleDistanceRecord {
+                        ctg_idx0,
+                        ctg_idx1,
+                        dist: 1.0 - shared_count as f32 / union_count.max(1) as f32,
+                        diff: union_count - shared_count,
+                        total: union_count,
+                        score: shared_count as i64,
+                        offset: 0,
+                    };
+                }
+                BundleDistanceMethod::LocalAlignment => true,
+                BundleDistanceMethod::GlobalAlignment => false,
+            };
+            let (dist0, diff_len0, max_len0, best_score0, best_offset0) =
+                align_bundles(bundles0, bundles1, local_aln);
+            let (dist1, diff_len1, max_len1, best_score1, _best_offset1) =
+                align_bundles(bundles1, bundles0, local_aln);
+            let (dist, diff, total, score) = if dist0 > dist1 {
+                (dist0, diff_len0, max_len0, best_score0)
+            } else {
+                (dist1, diff_len1, max_len1, best_score1)
+            };
+            BundleDistanceRecord {
+                ctg_idx0,
+                ctg_idx1,
+                dist,
+                diff,
+                total,
+                score,
+                offset: best_offset0,
+            }
+        })
+        .collect::<Vec<_>>();
+
+    let mut dist_map = FxHashMap::<(usize, usize), f32>::default();
+    let mut offset_map = FxHashMap::<(usize, usize), isize>::default();
+    let mut min_dist = 0.0_f32;
+    let mut max_dist = 1.0_f32;
+    records
+        .iter()
+        .filter(|r| r.ctg_idx0 != r.ctg_idx1)
+        .for_each(|r| {
+            let (ctg_idx0, ctg_idx1) = (r.ctg_idx0, r.ctg_idx1);
+            if let BundleDistanceMethod::LocalAlignment = method {
+                let d = 1.0 / (r.score as f32 + 10.0).log10();
+                min_dist = if d < min_dist { d } else { min_dist };
+                max_dist = if d > max_dist { d } else { max_dist };
+                dist_map.insert((ctg_idx0, ctg_idx1), d);
+                offset_map.insert((ctg_idx0, ctg_idx1), r.offset);
+                offset_map.insert((ctg_idx1, ctg_idx0), -r.offset);
+            } else {
+                dist_map.insert((ctg_idx0, ctg_idx1), r.dist);
+                offset_map.insert((ctg_idx0, ctg_idx1), 0);
+                offset_map.insert((ctg_idx1, ctg_idx0), 0);
+            }
+        });
+
+    let w = max_dist - min_dist + 0.01;
+    dist_map.iter_mut().for_each(|(_k, v)| {
+        *v = (*v - min_dist + 0.01) / w;
+    });
+
+    BundleDistances {
+        n_ctg,
+        records,
+        dist_map,
+        offset_map,
+    }
+}
+
+impl BundleDistances {
+    /// the full symmetric matrix of the distances in the records, the rescaled `dist_map` is only used for the clustering
+    pub fn dist_matrix(&self) -> Vec<Vec<f32>> {
+        let mut dist_matrix = vec![vec![0.0_f32; self.n_ctg]; self.n_ctg];
+        self.records
+            .iter()
+            .filter(|r| r.ctg_idx0 != r.ctg_idx1)
+            .for_each(|r| {
+                dist_matrix[r.ctg_idx0][r.ctg_idx1] = r.dist;
+                dist_matrix[r.ctg_idx1][r.ctg_idx0] = r.dist;
+            });
+        dist_matrix
+    }
+
+    /// write the `.dist` file, each pair of the different contigs is written in both orders
+    pub fn write_dist<W: Write>(&self, out: &mut W, ctg_names: &[String]) -> std::io::Result<()> {
+        self.records.iter().try_for_each(|r| {
+            let ctg0 = &ctg_names[r.ctg_idx0];
+            let ctg1 = &ctg_names[r.ctg_idx1];
+            writeln!(
+                out,
+                "{} {} {} {} {} {} {}",
+                ctg0, ctg1, r.dist, r.diff, r.total, r.score, r.offset
+            )?;
+            if r.ctg_idx0 != r.ctg_idx1 {
+                writeln!(
+                    out,
+                    "{} {} {} {} {} {} {}",
+                    ctg1, ctg0, r.dist, r.diff, r.total, r.score, -r.offset
+                )?;
+            }
+            Ok(())
+        })
+    }
+
+    /// write the distance matrix in the (relaxed) PHYLIP format
+    pub fn write_phylip<W: Write>(&self, out: &mut W, ctg_names: &[String]) -> std::io::Result<()> {
+        writeln!(out, "{}", self.n_ctg)?;
+        self.dist_matrix()
+            .iter()
+            .zip(ctg_names)
+            .try_for_each(|(row, ctg)| {
+                let row = row.iter().map(|v| format!("{:.6}", v)).collect::<Vec<_>>();
+                writeln!(out, "{} {}", ctg, row.join(" "))
+            })
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct DendrogramStep {
+    pub cluster1: usize,
+    pub cluster2: usize,
+    pub dissimilarity: f32,
+    pub size: usize,
+}
+
+/// The average linkage clustering of the contigs from the normalized distances,
+/// the step `i` joins two clusters into the cluster `leaf_order.len() + i`
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct BundleDendrogram {
+    /// the leaves in the order of the tree, the larger cluster goes first at each join
+    pub leaf_order: Vec<usize>,
+    pub steps: Vec<DendrogramStep>,
+    /// (node id, position, height, size) of the leaves and the clusters for drawing the tree
+    #[serde(default)]
+    pub node_positions: Vec<(usize, f32, f32, usize)>,
+    /// the tree in the Newick format without the ending `;`
+    #[serde(default)]
+    pub newick: String,
+    /// (contig index, offset) of the contigs in the groups of the close neighbor leaves for aligning the tracks
+    #[serde(default)]
+    pub offsets: Vec<(usize, isize)>,
+}
+
+/// cluster the contigs with the average linkage, `leaf_labels` are the labels of the contigs in the Newick tree
+pub fn get_bundle_dendrogram(
+    distances: &BundleDistances,
+    leaf_labels: &[String],
+) -> BundleDendrogram {
+    let n_ctg = distances.n_ctg;
+    let dist_map = &distances.dist_map;
+    let offset_map = &distances.offset_map;
+    let mut dist_mat = vec![];
+    (0..n_ctg.saturating_sub(1)).for_each(|i| {
+        (i + 1..n_ctg).for_each(|j| {
+            dist_mat.push(*dist_map.get(&(i, j)).unwrap());
+        })
+    });
+    let steps = if n_ctg > 1 {
+        linkage(&mut dist_mat, n_ctg, Method::Average)
+            .steps()
+            .iter()
+            .map(|s| DendrogramStep {
+                cluster1: s.cluster1,
+                cluster2: s.cluster2,
+                dissimilarity: s.dissimilarity,
+                size: s.size,
+            })
+            .collect::<Vec<_>>()
+    } else {
+        vec![]
+    };
+
+    let mut node_data = FxHashMap::<usize, (String, Vec<usize>, f32)>::default();
+    (0..n_ctg).for_each(|ctg_idx| {
+        node_data.insert(
+            ctg_idx,
+            (leaf_labels[ctg_idx].clone(), vec![ctg_idx], 0.0_f32),
+        );
+    });
+
+    let mut last_node_id = 0_usize;
+    steps.iter().enumerate().for_each(|(c, s)| {
+        let (node_string1, nodes1, height1) = node_data.remove(&s.cluster1).unwrap();
+        let (node_string2, nodes2, height2) = node_data.remove(&s.cluster2).unwrap();
+        let new_node_id = c + n_ctg;
+        let mut nodes = Vec::<usize>::new();
+        let new_node_string = if nodes1.len() > nodes2.len() {
+            nodes.extend(nodes1);
+            nodes.extend(nodes2);
+            format!(
+                "({}:{}, {}:{})",
+                node_string1,
+                s.dissimilarity - height1,
+                node_string2,
+                s.dissimilarity - height2
+            )
+        } else {
+            nodes.extend(nodes2);
+            nodes.extend(nodes1);
+            format!(
+                "({}:{}, {}:{})",
+                node_string2,
+                s.dissimilarity - height2,
+                node_string1,
+                s.dissimilarity - height1
+            )
+        };
+        node_data.insert(new_node_id, (new_node_string, nodes, s.dissimilarity));
+        last_node_id = new_node_id;
+    });
+
+    let (newick, leaf_order, _) =
+        node_data
+            .remove(&last_node_id)
+            .unwrap_or(("".to_string(), vec![], 0.0));
+
+    // the offsets of a group of the leaves next to each other within the distance 0.25 are accumulated
+    // from the alignment offsets of the neighbors, the leaf starting a new group is left out
+    let mut node_position_size = FxHashMap::<usize, ((f32, f32), usize)>::default();
+    let mut offsets = Vec::<(usize, isize)>::new();
+    let mut position = 0.0_f32;
+    let mut offset = 0_isize;
+    let mut p_idx: Option<usize> = None;
+    let mut offset_group = Vec::<_>::new();
+    let mut group_min_offset = 100000_isize;
+    leaf_order.iter().for_each(|&ctg_idx| {
+        node_position_size.insert(ctg_idx, ((position, 0.0), 1));
+        position += 1.0;
+        if let Some(p_idx) = p_idx {
+            let (idx0, idx1) = if p_idx < ctg_idx {
+                (p_idx, ctg_idx)
+            } else {
+                (ctg_idx, p_idx)
+            };
+            if *dist_map.get(&(idx0, idx1)).unwrap_or(&1.0) < 0.25 {
+                offset += *offset_map.get(&(p_idx, ctg_idx)).unwrap_or(&0);
+                offset_group.push((ctg_idx, offset));
+                if offset < group_min_offset {
+                    group_min_offset = offset;
+                };
+            } else {
+                offset_group.iter().for_each(|&(ctg_idx, offset)| {
+                    offsets.push((ctg_idx, offset - group_min_offset));
+                });
+                group_min_offset = 100000_isize;
+                offset_group.clear();
+                offset = 0;
+            }
+        } else {
+            offset_group.push((ctg_idx, offset));
+        };
+        p_idx = Some(ctg_idx)
+    });
+    offset_group.iter().for_each(|&(ctg_idx, offset)| {
+        offsets.push((ctg_idx, offset - group_min_offset));
+    });
+
+    steps.iter().enumerate().for_each(|(c, s)| {
+        let ((pos0, _), size0) = *node_position_size.get(&s.cluster1).unwrap();
+        let ((pos1, _), size1) = *node_position_size.get(&s.cluster2).unwrap();
+
+        let pos = ((size0 as f32) * pos0 + (size1 as f32) * pos1) / ((size0 + size1) as f32);
+        node_position_size.insert(c + n_ctg, ((pos, s.dissimilarity), s.size));
+    });
+    let mut node_positions = node_position_size
+        .into_iter()
+        .map(|(vid, ((pos, h), size))| (vid, pos, h, size))
+        .collect::<Vec<_>>();
+    node_positions.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap());
+
+    BundleDendrogram {
+        leaf_order,
+        steps,
+        node_positions,
+        newick,
+        offsets,
+    }
+}
+
+impl BundleDendrogram {
+    /// write the `.ddg` file, the `L` lines are the leaves in order, the `I` lines are the joins of the clusters
+    /// and the `P` lines are the positions of the nodes
+    pub fn write_ddg<W: Write>(&self, out: &mut W, ctg_names: &[String]) -> std::io::Result<()> {
+        let n_ctg = ctg_names.len();
+        self.leaf_order
+            .iter()
+            .try_for_each(|&ctg_idx| writeln!(out, "L\t{}\t{}", ctg_idx, ctg_names[ctg_idx]))?;
+        self.steps.iter().enumerate().try_for_each(|(c, s)| {
+            writeln!(
+                out,
+                "I\t{}\t{}\t{}\t{}\t{}",
+                c + n_ctg,
+                s.cluster1,
+                s.cluster2,
+                s.size,
+                s.dissimilarity,
+            )
+        })?;
+        self.node_positions
+            .iter()
+            .try_for_each(|(vid, pos, h, size)| {
+                writeln!(out, "P\t{}\t{}\t{}\t{}", vid, pos, h, size)
+            })
+    }
+
+    /// write the `.offset` file
+    pub fn write_offsets<W: Write>(
+        &self,
+        out: &mut W,
+        ctg_names: &[String],
+    ) -> std::io::Result<()> {
+        self.offsets
+            .iter()
+            .try_for_each(|&(ctg_idx, offset)| writeln!(out, "{}\t{}", ctg_names[ctg_idx], offset))
+    }
+}
+
+/// a track of the bundle segments of a contig
+pub struct BundleSvgTrack {
+    pub ctg: String,
+    /// the text on the right of the track
+    pub annotation: String,
+    /// (bgn, end, bundle_id, direction)
+    pub segments: Vec<(u32, u32, u32, u32)>,
+    /// (bgn, end, title, color) of the annotation regions drawn under the segments
+    pub regions: Vec<(u32, u32, String, String)>,
+    /// the shift of the track in base pairs
+    pub offset: i64,
+}
+
+pub struct BundleSvgOptions {
+    /// the track range in base pair count
+    pub track_range: usize,
+    /// the track tick interval, default to the power of ten of a tenth of the track range
+    pub track_tick_interval: Option<usize>,
+    /// the track panel size in pixel
+    pub track_panel_width: usize,
+    /// the factor to increase height of each track
+    pub track_scaling: f32,
+    /// the left padding in pixel
+    pub left_padding: usize,
+    /// the stroke boundary width
+    pub stroke_width: f32,
+    /// leave the space for the annotation regions under the tracks
+    pub annotation_region_track: bool,
+    /// the stroke with for the annotation regions
+    pub annotation_region_stroke_width: f32,
+    /// the anotation panel width
+    pub annotation_panel_width: f32,
+    /// the factor to increase the border width for highlighting repetitive bundles
+    pub highlight_repeats: f32,
+    /// disable tooltips
+    pub no_tooltips: bool,
+    /// the factor to increase the width for highlighting bundle when clicked
+    pub h_factor: f32,
+}
+
+/// the default track range, the maximum end of the segments rounded up to 10kb
+pub fn get_track_range(max_range: usize) -> usize {
+    let r = ((max_range as f32 / 10000.0).ceil() * 10000.0) as usize;
+    if r < 10000 {
+        10000
+    } else {
+        r
+    }
+}
+
+/// draw the tracks from the top to the bottom with the tree of the dendrogram on the left,
+/// `bundle_parent` is the parent bundle of a bundle in a hierarchical bed file for the tooltips
+pub fn bundle_tracks_to_svg(
+    tracks: Vec<BundleSvgTrack>,
+    dendrogram: Option<&BundleDendrogram>,
+    bundle_parent: &FxHashMap<u32, String>,
+    options: &BundleSvgOptions,
+) -> Document {
+    let track_range = options.track_range;
+    let left_padding = options.left_padding;
+    let scaling_factor = options.track_panel_width as f32 / (track_range + 2 * left_padding) as f32;
+    let left_padding = left_padding as f32;
+    let stroke_width = options.stroke_width;
+
+    let mut y_offset = 0.0_f32;
+    let delta_y = if options.annotation_region_track {
+        22.0_f32 * options.track_scaling + options.annotation_region_stroke_width * 0.5
+    } else {
+        16.0_f32 * options.track_scaling
+    };
+
+    // generate the bundle path elements
+    let mut bundle_class_styles = FxHashMap::<String, String>::default();
+    #[allow(clippy::needless_collect)] // we do need to evaluate as we depend on the side effect to set y_offset right
+    #[allow(clippy::type_complexity)]
+    let ctg_with_svg_paths: Vec<(String, (Vec<element::Group>, Vec<element::Group>, element::Text))> = tracks
+        .into_iter()
+        .map(|track| {
+            let BundleSvgTrack { ctg, annotation, segments: bundle_segment, regions: annotation_segments, offset } = track;
+            let mut bundle_segment_count = FxHashMap::<u32, usize>::default();
+            bundle_segment.iter().for_each(|&(_bgn, _end, bundle_id, _direction)| {
+                let e = bundle_segment_count.entry(bundle_id).or_insert_with(|| 0);
+                *e += 1;
+            });
+
+            let paths: Vec<element::Group> = bundle_segment
+                .into_iter()
+                .map(|(bgn0, end0, bundle_id, direction)| {
+                    let mut bgn = (bgn0 as i64 + offset) as f32 * scaling_factor;
+                    let mut end = (end0 as i64 + offset) as f32 * scaling_factor;
+                    if direction == 1 {
+                        (bgn, end) = (end, bgn);
+                    }
+
+                    let arror_end = end;
+                    let halfwidth = 5.0 * options.track_scaling;
+                    let end =
+                        if direction == 0 {
+                            if end - halfwidth < bgn {
+                                bgn
+                            } else {
+                                end - halfwidth
+                            }
+                        } else if end + halfwidth > bgn {
+                            bgn
+                        } else {
+                            end + halfwidth
+                        };
+
+                    let bottom0 = -halfwidth * 0.6;
+                    let top0 = halfwidth * 0.6;
+                    let bottom1 = -halfwidth * 0.8;
+                    let top1 = halfwidth * 0.8;
+                    let center = 0 as f32;
+
+                    let bundle_class = format!("bundle_{bundle_id:05}");
+                    let bundle_rep_class = format!("bundle_{bundle_id:05} repeat");
+
+                    let bundle_color = CMAP[((bundle_id * 57) % 59) as usize];
+                    let stroke_color = CMAP[93 - ((bundle_id * 31) % 47) as usize];
+                    let css_string = format!(
+r#".{bundle_class} {{fill:{bundle_color}; stroke:{stroke_color}; stroke-width:{stroke_width}; fill-opacity:0.5}}"#);
+                    bundle_class_styles.entry(bundle_class.clone()).or_insert(css_string);
+
+                    let bundle_class = if *bundle_segment_count.get(&bundle_id).unwrap_or(&0) > 1 && options.highlight_repeats > 1.0001 {
+                        bundle_rep_class
+                    } else {
+                        bundle_class
+                    };
+
+                    let path_str = format!(
+					"M {bgn} {bottom0} L {bgn} {top0} L {end} {top0} L {end} {top1} L {arror_end} {center} L {end} {bottom1} L {end} {bottom0} Z");
+                    let mut p = element::Path::new()
+                        .set("d", path_str)
+                        .set("class", "bundle ".to_string() + bundle_class.as_str());
+                    let mut g = element::Group::new().set("transform", format!("translate({left_padding} {y_offset})"));
+                    if !options.no_tooltips { // it may be good idea to disable it for every large region visualization
+                        let title = if let Some(parent_id) = bundle_parent.get(&bundle_id) {
+                            format!("{}:{}-{}:{} (parent:{})", ctg, bgn0, end0, bundle_id, parent_id)
+                        } else {
+                            format!("{}:{}-{}:{}", ctg, bgn0, end0, bundle_id )
+                        };
+                        p.append(element::Title::new(title));
+                    };
+                    g.append(p);
+                    g
+                })
+                .collect();
+
+
+            let annotation_paths: Vec<element::Group> = annotation_segments
+                .into_iter()
+                .map(|(bgn, end, title, color)| {
+                    let bgn = (bgn as i64 + offset) as f32 * scaling_factor;
+                    let end = (end as i64 + offset) as f32 * scaling_factor;
+
+                    let stroke_color = color.as_str();
+                    let y = 8.0;
+                    let path_str = format!(
+					"M {bgn} {y} L {end} {y}");
+                    let mut p = element::Path::new()
+                        .set("class", "region")
+                        .set("stroke", stroke_color)
+                        .set("stroke-width", options.annotation_region_stroke_width)
+                        .set("d", path_str);
+                    let mut g = element::Group::new().set("transform", format!("translate({left_padding} {y_offset})"));
+                    if !options.no_tooltips { // it may be good idea to disable it for every large region visualization
+                        let title = element::Title::new(title);
+                        p.append(title);
+                    };
+                    g.append(p);
+                    g
+                })
+                .collect();
+
+
+            let text = element::Text::new(annotation)
+                .set("x", 20.0 + left_padding + track_range as f32 * scaling_factor)
+                .set("y", y_offset + 2.0)
+                .set("font-size", "10px")
+                .set("font-family", "monospace");
+            y_offset += delta_y;
+            (ctg, (paths, annotation_paths, text))
+        })
+        .collect();
+
+    let steps = dendrogram.map(|d| &d.steps[..]).unwrap_or(&[]);
+    let tree_width = if !steps.is_empty() {
+        0.15 * options.track_panel_width as f32
+    } else {
+        0.0
+    };
+
+    // start to construct the SVG element
+    let mut document = Document::new()
+        .set(
+            "viewBox",
+            (
+                -tree_width,
+                -32,
+                tree_width + options.track_panel_width as f32 + options.annotation_panel_width,
+                24.0 + y_offset,
+            ),
+        )
+        .set(
+            "width",
+            tree_width + options.track_panel_width as f32 + options.annotation_panel_width,
+        )
+        .set("height", 56.0 + y_offset)
+        .set("preserveAspectRatio", "none")
+        .set("id", "bundleViwer");
+
+    // insert CSS
+    let stroke_width_rep = stroke_width * options.highlight_repeats;
+    let stroke_width_hover = stroke_width * 2.0;
+    let stroke_width_hover_rep = stroke_width_rep * 2.0;
+    let mut css_strings = vec![
+        format!(".repeat {{stroke-width:{stroke_width_rep};}}"),
+        format!(".bundle:hover {{ stroke-width:{stroke_width_hover};}}"),
+        format!(".repeat:hover {{ stroke-width:{stroke_width_hover_rep};}}"),
+        ".region { stroke-opacity: 0.5 };".to_string(),
+    ];
+    css_strings.extend(bundle_class_styles.values().cloned());
+    let h_factor = options.h_factor;
+    css_strings.push(format!(
+        r#"path.highlighted {{transform: scaleY({h_factor}); fill-opacity:1}}"#
+    ));
+    let style = element::Style::new(css_strings.join("\n")).set("type", "text/css");
+    document.append(style);
+
+    // plot the hierarchical clustering tree
+    if let Some(dendrogram) = dendrogram {
+        let n_leaves = dendrogram.leaf_order.len();
+        let node_position_map = dendrogram
+            .node_positions
+            .iter()
+            .map(|&(vid, pos, h, size)| (vid, (pos, h, size)))
+            .collect::<FxHashMap<_, _>>();
+        steps.iter().enumerate().for_each(|(c, s)| {
+            let (n_pos, n_height, _) = *node_position_map.get(&(c + n_leaves)).unwrap();
+            let (c0_pos, c0_height, _) = *node_position_map.get(&s.cluster1).unwrap();
+            let (c1_pos, c1_height, _) = *node_position_map.get(&s.cluster2).unwrap();
+            let _n_pos = n_pos * delta_y;
+            let c0_pos = c0_pos * delta_y;
+            let c1_pos = c1_pos * delta_y;
+            let n_height = -0.8 * tree_width * n_height;
+            let c0_height = -0.8 * tree_width * c0_height;
+            let c1_height = -0.8 * tree_width * c1_height;
+            let path_str = format!(
+                "M {c0_height} {c0_pos} L {n_height} {c0_pos} L {n_height} {c1_pos} L {c1_height} {c1_pos}"
+            );
+            let path = element::Path::new()
+                .set("fill", "none")
+                .set("stroke", "#000")
+                .set("stroke-width", "1")
+                .set("d", path_str);
+            document.append(path);
+        });
+    }
+
+    // plot the scale bar
+    let right_end = track_range as f32 * scaling_factor + left_padding;
+    let scale_path_str =
+        format!("M {left_padding} -14 L {left_padding} -20 L {right_end} -20 L {right_end} -14 ");
+    let scale_path = element::Path::new()
+        .set("stroke", "#000")
+        .set("fill", "none")
+        .set("stroke-width", 1)
+        .set("d", scale_path_str);
+    document.append(scale_path);
+
+    let track_tick_interval = if let Some(tick_interval) = options.track_tick_interval {
+        tick_interval
+    } else {
+        let mut tick_interval = 1_usize;
+        let mut tmp = track_range as f32;
+        tmp *= 0.1;
+        while tmp > 1.01 {
+            tick_interval *= 10;
+            tmp *= 0.1;
+        }
+        tick_interval
+    };
+
+    let mut tickx = track_tick_interval;
+    loop {
+        if tickx > track_range {
+            break;
+        }
+        let x = tickx as f32 * scaling_factor + left_padding;
+        let tick_path_str = format!("M {x} -16 L {x} -20");
+        let tick_path = element::Path::new()
+            .set("stroke", "#000")
+            .set("fill", "none")
+            .set("stroke-width", 1)
+            .set("d", tick_path_str);
+        document.append(tick_path);
+        tickx += track_tick_interval;
+    }
+
+    let text = element::Text::new(format!("{} bps", track_range))
+        .set(
+            "x",
+            20.0 + left_padding + track_range as f32 * scaling_factor,
+        )
+        .set("y", -14)
+        .set("font-size", "10px")
+        .set("font-family", "sans-serif");
+    document.append(text);
+
+    // insert the bundle paths
+    ctg_with_svg_paths
+        .into_iter()
+        .for_each(|(_ctg, (paths, annotation_paths, text))| {
+            document.append(text);
+            paths.into_iter().for_each(|path| document.append(path));
+            annotation_paths
+                .into_iter()
+                .for_each(|path| document.append(path));
+        });
+
+    document
+}
+
+/// wrap the SVG document in a HTML page with the script for highlighting the bundles clicked
+pub fn svg_to_html_string(document: &Document) -> String {
+    let mut out_file = BufWriter::new(Vec::new());
+    let msg = "can't write the HTML doc";
+    writeln!(out_file, "<html><body>").expect(msg);
+    let jscript = r#"
+<script>
+document.addEventListener('readystatechange', event => {
+    if (event.target.readyState === "complete") {
+        var bundles = document.getElementsByClassName("bundle");
+        for (let i = 0; i < bundles.length; i++) {
+            bundles[i].onclick = function (e) {
+                // alert(e.target.classList);
+                let is_highlighted = false;
+                let bundle_id = "";
+                for (let cidx = 0; cidx < e.target.classList.length; cidx++) {
+                    if (e.target.classList[cidx] == "highlighted") {
+                        is_highlighted = true;
+                    }
+                    if (e.target.classList[cidx].match("bundle_")) {
+                        bundle_id = e.target.classList[cidx]
+                    }
+                };
+                var bundles2 = document.getElementsByClassName(bundle_id);
+                for (let j = 0; j < bundles2.length; j++) {
+                    if (is_highlighted) {
+                        bundles2[j].classList.remove("highlighted");
+                    } else {
+                        bundles2[j].classList.add("highlighted");
+                    }
+                }
+            };
+        }
+    }
+});
+</script>
+"#;
+    writeln!(out_file, "{}", jscript).expect(msg);
+    let mut svg_elment = BufWriter::new(Vec::new());
+    svg::write(&mut svg_elment, document).unwrap();
+    writeln!(
+        out_file,
+        "{}",
+        String::from_utf8_lossy(&svg_elment.into_inner().unwrap())
+    )
+    .expect(msg);
+    writeln!(out_file, "</body></html>").expect(msg);
+    let out_str = out_file.into_inner().unwrap();
+    String::from_utf8_lossy(&out_str[..]).to_string()
+}
+
+#[cfg(test)]
+mod test {
+    use crate::pbundle::{
+        align_bundles, bundle_bed_line, compare_sketches, get_bundle_dendrogram,
+        get_bundle_distances, neighbor_joining, sketch_bundles, BundleDistanceMethod,
+        BundleSegment,
+    };
+
+    fn segment(bgn: u32, end: u32, bundle_id: u32, bundle_dir: u32) -> BundleSegment {
+        BundleSegment {
+            bgn,
+            end,
+            bundle_id,
+            bundle_v_count: 10,
+            bundle_dir,
+            bundle_v_bgn: 0,
+            bundle_v_end: 9,
+        }
+    }
+
+    #[test]
+    fn test_bundle_bed_line() {
+        let line = bundle_bed_line("ctg0", &segment(100, 300, 3, 1), true);
+        assert_eq!(line, "ctg0\t100\t300\t3:10:1:0:9:R");
+    }
+
+    #[test]
+    fn test_align_bundles() {
+        let q = vec![
+            segment(0, 100, 0, 0),
+            segment(100, 300, 1, 0),
+            segment(300, 400, 2, 0),
+        ];
+        let (dist, diff_len, _, _, offset) = align_bundles(&q, &q, false);
+        assert_eq!(dist, 0.0);
+        assert_eq!(diff_len, 0);
+        assert_eq!(offset, 0);
+
+        // the middle segment is in the other direction
+        let t = vec![
+            segment(0, 100, 0, 0),
+            segment(100, 300, 1, 1),
+            segment(300, 400, 2, 0),
+        ];
+        let (dist, diff_len, _, _, _) = align_bundles(&q, &t, false);
+        assert!(diff_len > 0);
+        assert!(dist > 0.0);
+    }
+
+    #[test]
+    fn test_bundle_dendrogram() {
+        let ctg_bundles = vec![
+            vec![segment(0, 100, 0, 0), segment(100, 300, 1, 0)],
+            vec![segment(0, 1000, 5, 0)],
+            vec![segment(0, 100, 0, 0), segment(100, 310, 1, 0)],
+        ];
+        let distances = get_bundle_distances(&ctg_bundles, BundleDistanceMethod::GlobalAlignment);
+        assert_eq!(distances.records.len(), 6);
+        let labels = (0..3).map(|idx| format!("{}", idx)).collect::<Vec<_>>();
+        let dendrogram = get_bundle_dendrogram(&distances, &labels);
+        assert_eq!(dendrogram.steps.len(), 2);
+        assert_eq!(dendrogram.leaf_order.len(), 3);
+        // the two similar contigs are joined first
+        let first = &dendrogram.steps[0];
+        assert_eq!(first.cluster1.min(first.cluster2), 0);
+        assert_eq!(first.cluster1.max(first.cluster2), 2);
+        assert_eq!(dendrogram.leaf_order[2], 1);
+        assert_eq!(dendrogram.node_positions.len(), 5);
+        assert!(dendrogram.newick.starts_with('('));
+    }
+
+    #[test]
+    fn test_sketch_bundles() {
+        let bundles = vec![
+            segment(0, 100, 0, 0),
+            segment(100, 300, 1, 0),
+            segment(300, 400, 2, 1),
+            segment(400, 1000, 3, 0),
+        ];
+        let sketch = sketch_bundles(&bundles, 2, 50, 64);
+        assert!(!sketch.is_empty());
+        let (union_count, shared_count) = compare_sketches(&sketch, &sketch, 64);
+        assert_eq!(union_count, shared_count);
+
+        // the reverse complement has the segments in the reversed order and the other directions
+        let rc_bundles = bundles
+            .iter()
+            .rev()
+            .map(|s| segment(1000 - s.end, 1000 - s.bgn, s.bundle_id, 1 - s.bundle_dir))
+            .collect::<Vec<_>>();
+        assert_eq!(sketch_bundles(&rc_bundles, 2, 50, 64), sketch);
+
+        let distances = get_bundle_distances(
+            &[bundles.clone(), rc_bundles, bundles[..2].to_vec()],
+            BundleDistanceMethod::Sketch {
+                token_size: 2,
+                sketch_size: 64,
+                weight_unit: 50,
+            },
+        );
+        // the matrix has the distances of the records instead of the rescaled ones for the clustering
+        let dist_matrix = distances.dist_matrix();
+        assert_eq!(dist_matrix[0][0], 0.0);
+        assert_eq!(dist_matrix[0][1], 0.0);
+        assert!(dist_matrix[0][2] > 0.0 && dist_matrix[0][2] < 1.0);
+        assert_eq!(dist_matrix[2][0], dist_matrix[0][2]);
+    }
+
+    #[test]
+    fn test_neighbor_joining() {
+        // the additive distances of the tree ((0:1, 1:2):5, (2:3, 3:4))
+        let dist_matrix = vec![
+            vec![0.0, 3.0, 9.0, 10.0],
+            vec![3.0, 0.0, 10.0, 11.0],
+            vec![9.0, 10.0, 0.0, 7.0],
+            vec![10.0, 11.0, 7.0, 0.0],
+        ];
+        assert_eq!(
+            neighbor_joining(&dist_matrix),
+            "(((0:1, 1:2):5, 2:3):2, 3:2)"
+        );
+    }
+}
@@ -1072,15 +1072,71 @@ pub fn sort_adj_list_by_weighted_dfs(
     out
 }
 
+/// Split an adjacency list into the weakly connected components of the MAP-graph, a vertex and its
+/// reverse complement are in the same component. The components, and the pairs in each component,
+/// keep the order of their first appearance in `adj_list`.
+pub fn split_adj_list_by_weakly_connected_components(adj_list: &[AdjPair]) -> Vec<AdjList> {
+    fn find_root(parents: &mut [usize], mut idx: usize) -> usize {
+        while parents[idx] != idx {
+            parents[idx] = parents[parents[idx]];
+            idx = parents[idx];
+        }
+        idx
+    }
+
+    let mut vertex_idx = FxHashMap::<(u64, u64), usize>::default();
+    let mut parents = Vec::<usize>::new();
+    let mut get_idx = |v: &ShmmrGraphNode, parents: &mut Vec<usize>| {
+        *vertex_idx.entry((v.0, v.1)).or_insert_with(|| {
+            parents.push(parents.len());
+            parents.len() - 1
+        })
+    };
+    let pair_idx = adj_list
+        .iter()
+        .map(|(_sid, v, w)| {
+            let v_idx = get_idx(v, &mut parents);
+            let w_idx = get_idx(w, &mut parents);
+            let v_root = find_root(&mut parents, v_idx);
+            let w_root = find_root(&mut parents, w_idx);
+            // keep the smaller index as the root, so the root is the first vertex seen in the component
+            if v_root < w_root {
+                parents[w_root] = v_root;
+            } else {
+                parents[v_root] = w_root;
+            }
+            v_idx
+        })
+        .collect::<Vec<_>>();
+
+    let mut root_to_component = FxHashMap::<usize, usize>::default();
+    let mut components = Vec::<AdjList>::new();
+    adj_list.iter().zip(pair_idx).for_each(|(pair, v_idx)| {
+        let root = find_root(&mut parents, v_idx);
+        let component_idx = *root_to_component.entry(root).or_insert_with(|| {
+            components.push(AdjList::new());
+            components.len() - 1
+        });
+        components[component_idx].push(*pair);
+    });
+    components
+}
+
 pub fn get_principal_bundles_from_adj_list(
     frag_map: &ShmmrToFrags,
     adj_list: &[AdjPair],
     path_len_cutoff: usize,
 ) -> (Vec<Vec<ShmmrGraphNode>>, AdjList) {
     assert!(!adj_list.is_empty());
-    // println!("DBG: adj_list[0]: {:?}", adj_list[0]);
-    let s = adj_list[0].1;
-    let sorted_adj_list = sort_adj_list_by_weighted_dfs(frag_map, adj_list, s);
+    // traverse each weakly connected component in parallel from its first vertex in the adjacency list,
+    // the results are concatenated in the order of the components
+    let sorted_adj_list = split_adj_list_by_weakly_connected_components(adj_list)
+        .par_iter()
+        .map(|component| sort_adj_list_by_weighted_dfs(frag_map, component, component[0].1))
+        .collect::<Vec<_>>()
+        .into_iter()
+        .flatten()
+        .collect::<Vec<_>>();
 
     // println!("DGB: sorted_adj_list len: {}", sorted_adj_list.len());
 
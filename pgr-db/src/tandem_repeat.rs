@@ -0,0 +1,230 @@
+// Tandem repeat (STR / VNTR) context of the variants for a consistent allele representation
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TandemRepeat {
+    pub bgn: usize, // 0-based, inclusive
+    pub end: usize, // exclusive
+    pub unit: Vec<u8>,
+}
+
+impl TandemRepeat {
+    pub fn period(&self) -> usize {
+        self.unit.len()
+    }
+
+    pub fn copy_number(&self) -> f32 {
+        (self.end - self.bgn) as f32 / self.period() as f32
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct RepeatAnnotation {
+    pub repeat: TandemRepeat,
+    pub ref_copy_number: f32,
+    pub alt_copy_number: f32,
+    pub is_length_change: bool, // the variant only adds or removes whole copies of the repeat unit
+}
+
+// the maximum periodic interval with a period `period` containing the position `anchor`
+fn get_periodic_interval(seq: &[u8], anchor: usize, period: usize) -> (usize, usize) {
+    let seq_len = seq.len();
+    let mut end = anchor;
+    while end + period < seq_len && seq[end] == seq[end + period] {
+        end += 1;
+    }
+    let mut bgn = anchor;
+    while bgn > 0 && bgn - 1 + period < seq_len && seq[bgn - 1] == seq[bgn - 1 + period] {
+        bgn -= 1;
+    }
+    (bgn, std::cmp::min(end + period, seq_len))
+}
+
+fn is_rotation(s0: &[u8], s1: &[u8]) -> bool {
+    s0.len() == s1.len()
+        && (0..s0.len()).any(|shift| {
+            s0[shift..] == s1[..s1.len() - shift] && s0[..shift] == s1[s1.len() - shift..]
+        })
+}
+
+fn is_periodic(seq: &[u8], period: usize) -> bool {
+    !seq.is_empty()
+        && seq.len().is_multiple_of(period)
+        && (0..seq.len() - period).all(|i| seq[i] == seq[i + period])
+}
+
+/// Find the tandem repeat with the smallest period covering the interval `[bgn, end)` of `seq`
+///
+/// An empty interval (`bgn == end`) is used for an insertion between `bgn - 1` and `bgn`.
+pub fn find_tandem_repeat(
+    seq: &[u8],
+    bgn: usize,
+    end: usize,
+    max_period: usize,
+    min_copy_number: f32,
+    min_length: usize,
+) -> Option<TandemRepeat> {
+    if bgn > end || end > seq.len() || seq.is_empty() {
+        return None;
+    }
+    // for an insertion, we check the repeats on both sides of the insertion point
+    let anchors = if bgn == end {
+        vec![bgn.saturating_sub(1), std::cmp::min(bgn, seq.len() - 1)]
+    } else {
+        vec![bgn]
+    };
+    (1..=max_period).find_map(|period| {
+        anchors.iter().find_map(|&anchor| {
+            let (r_bgn, r_end) = get_periodic_interval(seq, anchor, period);
+            let r_len = r_end - r_bgn;
+            if r_bgn > bgn
+                || r_end < end
+                || r_len < 2 * period
+                || r_len < min_length
+                || (r_len as f32) < min_copy_number * period as f32
+            {
+                return None;
+            }
+            let unit = seq[r_bgn..r_bgn + period].to_vec();
+            // avoid reporting a repeat unit that is a repeat itself, e.g. "ATAT"
+            if (1..period).any(|p| period % p == 0 && is_periodic(&unit, p)) {
+                return None;
+            }
+            Some(TandemRepeat {
+                bgn: r_bgn,
+                end: r_end,
+                unit,
+            })
+        })
+    })
+}
+
+/// Annotate the tandem repeat context of a variant
+///
+/// `pos` is the 0-based position of the first base of `ref_allele` in `ref_seq`, the alleles can have
+/// a padding base as the VCF convention.
+pub fn annotate_variant(
+    ref_seq: &[u8],
+    pos: usize,
+    ref_allele: &[u8],
+    alt_allele: &[u8],
+    max_period: usize,
+    min_copy_number: f32,
+    min_length: usize,
+) -> Option<RepeatAnnotation> {
+    let prefix_len = ref_allele
+        .iter()
+        .zip(alt_allele.iter())
+        .take_while(|(r, a)| r == a)
+        .count();
+    let ref_allele = &ref_allele[prefix_len..];
+    let alt_allele = &alt_allele[prefix_len..];
+    let suffix_len = ref_allele
+        .iter()
+        .rev()
+        .zip(alt_allele.iter().rev())
+        .take_while(|(r, a)| r == a)
+        .count();
+    let ref_allele = &ref_allele[..ref_allele.len() - suffix_len];
+    let alt_allele = &alt_allele[..alt_allele.len() - suffix_len];
+    let bgn = pos + prefix_len;
+    let end = bgn + ref_allele.len();
+
+    let repeat = find_tandem_repeat(ref_seq, bgn, end, max_period, min_copy_number, min_length)?;
+    let period = repeat.period();
+    let ref_copy_number = repeat.copy_number();
+
+    // a pure insertion or deletion of whole copies of a rotation of the repeat unit
+    let indel_seq = if ref_allele.is_empty() {
+        Some(alt_allele)
+    } else if alt_allele.is_empty() {
+        Some(ref_allele)
+    } else {
+        None
+    };
+    let is_length_change = indel_seq
+        .map(|s| is_periodic(s, period) && is_rotation(&s[..period], &repeat.unit[..]))
+        .unwrap_or(false);
+    let alt_copy_number = if is_length_change {
+        let alt_len =
+            (repeat.end - repeat.bgn) as f32 + alt_allele.len() as f32 - ref_allele.len() as f32;
+        alt_len / period as f32
+    } else {
+        ref_copy_number
+    };
+
+    Some(RepeatAnnotation {
+        repeat,
+        ref_copy_number,
+        alt_copy_number,
+        is_length_change,
+    })
+}
+
+/// Represent a repeat length change as a single repeat expansion / contraction allele spanning the whole repeat
+///
+/// Return the 0-based position and the alleles with a padding base, or `None` if the variant is not a pure
+/// repeat length change.
+pub fn collapse_repeat_variant(
+    ref_seq: &[u8],
+    annotation: &RepeatAnnotation,
+    pos: usize,
+    ref_allele: &[u8],
+    alt_allele: &[u8],
+) -> Option<(usize, Vec<u8>, Vec<u8>)> {
+    let repeat = &annotation.repeat;
+    if !annotation.is_length_change
+        || repeat.bgn == 0
+        || pos < repeat.bgn - 1
+        || pos + ref_allele.len() > repeat.end
+    {
+        return None;
+    }
+    let new_pos = repeat.bgn - 1;
+    let new_ref = ref_seq[new_pos..repeat.end].to_vec();
+    let mut new_alt = ref_seq[new_pos..pos].to_vec();
+    new_alt.extend_from_slice(alt_allele);
+    new_alt.extend_from_slice(&ref_seq[pos + ref_allele.len()..repeat.end]);
+    Some((new_pos, new_ref, new_alt))
+}
+
+#[cfg(test)]
+mod test {
+    use crate::tandem_repeat::{annotate_variant, collapse_repeat_variant, find_tandem_repeat};
+
+    #[test]
+    fn test_find_tandem_repeat() {
+        let seq = b"GATTACAGCACACACACATTGGA";
+        let repeat = find_tandem_repeat(seq, 9, 11, 6, 2.0, 6).unwrap();
+        assert_eq!(repeat.bgn, 8);
+        assert_eq!(repeat.end, 18);
+        assert_eq!(repeat.unit, b"CA".to_vec());
+        assert_eq!(repeat.period(), 2);
+        assert!(find_tandem_repeat(seq, 1, 2, 6, 2.0, 6).is_none());
+    }
+
+    #[test]
+    fn test_annotate_and_collapse_variant() {
+        let seq = b"GATTACAGCACACACACATTGGA";
+        // delete one "CA" copy with a padding base
+        let annotation = annotate_variant(seq, 11, b"ACA", b"A", 6, 2.0, 6).unwrap();
+        assert!(annotation.is_length_change);
+        assert_eq!(annotation.repeat.unit, b"CA".to_vec());
+        assert_eq!(annotation.ref_copy_number, 5.0);
+        assert_eq!(annotation.alt_copy_number, 4.0);
+        let (pos, ref_allele, alt_allele) =
+            collapse_repeat_variant(seq, &annotation, 11, b"ACA", b"A").unwrap();
+        assert_eq!(pos, 7);
+        assert_eq!(ref_allele, b"GCACACACACA".to_vec());
+        assert_eq!(alt_allele, b"GCACACACA".to_vec());
+
+        // insert two copies
+        let annotation = annotate_variant(seq, 10, b"C", b"CACAC", 6, 2.0, 6).unwrap();
+        assert!(annotation.is_length_change);
+        assert_eq!(annotation.alt_copy_number, 7.0);
+
+        // a SNV in the repeat is not a length change
+        let annotation = annotate_variant(seq, 10, b"C", b"G", 6, 2.0, 6).unwrap();
+        assert!(!annotation.is_length_change);
+        assert!(collapse_repeat_variant(seq, &annotation, 10, b"C", b"G").is_none());
+    }
+}
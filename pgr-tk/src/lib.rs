@@ -17,7 +17,7 @@ use pyo3::Python;
 use rayon::prelude::*;
 use rustc_hash::{FxHashMap, FxHashSet};
 
-use pgr_db::ext::Backend;
+use pgr_db::ext::{Backend, BundleDecompLevelSpec, GFAPathOptions, RegionDecompSpec};
 
 /// Get the revision (git-hashtag) of the build
 #[pyfunction]
@@ -210,6 +210,51 @@ impl SeqIndexDB {
         Ok(())
     }
 
+    /// load and create the index from the path sequences of a GFA file (from the P and W lines)
+    ///
+    /// Parameters
+    /// ----------
+    ///
+    /// filepath : string
+    ///     the path to a GFA file or a gzipped GFA file
+    ///
+    /// w : int
+    ///     the window size of the shimmer index, default to 80
+    ///
+    /// k : int
+    ///     the k-mer size of the shimmer index, default to 56
+    ///
+    /// r : int
+    ///     the reduction factor of the shimmer index, default to 4
+    ///
+    /// min_span : int
+    ///     the min_span ofr the shimmer index, default to 8
+    ///
+    /// with_node_coordinates : bool
+    ///     return the coordinates of the graph segments in each path
+    ///
+    /// Returns
+    /// -------
+    ///
+    /// dict or None
+    ///     a dictionary that maps path_name -> list of (segment_name, orientation, bgn, end) if
+    ///     ``with_node_coordinates`` is true, the path names are ``sample#haplotype#sequence`` for the W lines
+    ///
+    #[pyo3(signature = (filepath, w=80, k=56, r=4, min_span=8, with_node_coordinates=false))]
+    pub fn load_from_gfa(
+        &mut self,
+        filepath: &str,
+        w: u32,
+        k: u32,
+        r: u32,
+        min_span: u32,
+        with_node_coordinates: bool,
+    ) -> PyResult<Option<FxHashMap<String, Vec<(String, u8, u32, u32)>>>> {
+        Ok(self
+            .db_internal
+            .load_from_gfa(filepath, w, k, r, min_span, with_node_coordinates)?)
+    }
+
 
     /// get a dictionary that maps (ctg_name, source) -> (id, len)
     #[getter]
@@ -1286,6 +1331,122 @@ impl SeqIndexDB {
         (principal_bundles, seqid_smps_with_bundle_id_seg_direction)
     }
 
+    /// Find the sequences homologous to a region and decompose them into principal bundles
+    ///
+    /// Parameters
+    /// ----------
+    /// sample_name : string
+    ///     the sample (source) name of the reference sequence
+    ///
+    /// ctg_name : string
+    ///     the contig name of the reference sequence
+    ///
+    /// bgn : int
+    ///     the start of the region
+    ///
+    /// end : int
+    ///     the end of the region
+    ///
+    /// flank_length : int
+    ///     the length added to both sides of the region for finding the homologous sequences
+    ///
+    /// min_query_coverage : float
+    ///     the minimum fraction of the query covered by the sparse alignment of a hit
+    ///
+    /// w, k, r, min_span : int
+    ///     the shimmer parameters for the decomposition
+    ///
+    /// min_cov, min_branch_size : int
+    ///     the parameters to build the principal bundles, see ``get_principal_bundles()``
+    ///
+    /// bundle_length_cutoff, bundle_merge_distance : int
+    ///     the minimum length of a bundle segment and the distance to merge the segments of the same bundle
+    ///
+    /// Returns
+    /// -------
+    /// tuple
+    ///     (``query_range``, ``hits``, ``segments``), ``query_range`` is the (bgn, end) of the region with the flanks
+    ///
+    ///     ``hits`` = list of (name, sequence_id, bgn, end, orientation)
+    ///
+    ///     ``segments`` = list of (name, bgn, end, bundle_id, bundle_size, direction, bundle_v_bgn, bundle_v_end, is_repeat)
+    ///     where the name is the name of the hit
+    ///
+    #[pyo3(signature = (sample_name, ctg_name, bgn, end, flank_length=10000, min_query_coverage=0.5,
+        w=48, k=56, r=4, min_span=12, min_cov=0, min_branch_size=8, bundle_length_cutoff=2500, bundle_merge_distance=10000))]
+    #[allow(clippy::too_many_arguments, clippy::type_complexity)]
+    pub fn get_region_decomposition(
+        &self,
+        sample_name: String,
+        ctg_name: String,
+        bgn: usize,
+        end: usize,
+        flank_length: usize,
+        min_query_coverage: f32,
+        w: u32,
+        k: u32,
+        r: u32,
+        min_span: u32,
+        min_cov: usize,
+        min_branch_size: usize,
+        bundle_length_cutoff: usize,
+        bundle_merge_distance: usize,
+    ) -> PyResult<(
+        (usize, usize),
+        Vec<(String, u32, u32, u32, u32)>,
+        Vec<(String, u32, u32, usize, usize, u32, usize, usize, bool)>,
+    )> {
+        let spec = RegionDecompSpec {
+            gap_penalty_factor: 0.025,
+            max_count: 128,
+            max_query_count: 128,
+            max_target_count: 128,
+            max_aln_chain_span: 8,
+            merge_range_tol: 100000,
+            min_query_coverage,
+            flank_length,
+            decomp: BundleDecompLevelSpec {
+                shmmr_spec: ShmmrSpec {
+                    w,
+                    k,
+                    r,
+                    min_span,
+                    sketch: false,
+                },
+                min_cov,
+                min_branch_size,
+                bundle_length_cutoff,
+                bundle_merge_distance,
+            },
+        };
+        let region_decomp = self
+            .db_internal
+            .get_region_decomposition(sample_name, ctg_name, bgn, end, &spec)?;
+        let hits = region_decomp
+            .hits
+            .into_iter()
+            .map(|hit| (hit.name, hit.sid, hit.bgn, hit.end, hit.orientation))
+            .collect();
+        let segments = region_decomp
+            .segments
+            .into_iter()
+            .map(|seg| {
+                (
+                    seg.ctg,
+                    seg.bgn,
+                    seg.end,
+                    seg.bundle_id,
+                    seg.bundle_size,
+                    seg.direction,
+                    seg.bundle_v_bgn,
+                    seg.bundle_v_end,
+                    seg.is_repeat,
+                )
+            })
+            .collect();
+        Ok((region_decomp.query_range, hits, segments))
+    }
+
     /// Convert the adjacent list of the shimmer graph shimmer_pair -> GFA
     ///
     /// Parameters
@@ -1296,22 +1457,55 @@ impl SeqIndexDB {
     /// filepath : string
     ///     the path to the output file
     ///
+    /// with_paths : bool
+    ///     write a path line for every sequence
+    ///
+    /// use_p_lines : bool
+    ///     write the paths as ``P`` lines instead of ``W`` lines with the PanSN sample/haplotype/contig names
+    ///
+    /// reference_sample : string
+    ///     if specified, add the rGFA ``SN/SO/SR`` tags to the segments relative to the sequences of the sample,
+    ///     the shimmer pair of a segment is written in the ``SP`` tag
+    ///
+    /// with_sequences : bool
+    ///     write the segment sequences instead of ``*`` and a path line for every sequence, a segment uses the
+    ///     fragment of the first sequence having its shimmer pair
+    ///
     /// Returns
     /// -------
     ///
     /// None
     ///     The data is written into the file at filepath
     ///
-    #[pyo3(signature = (min_count, filepath, method="from_fragmap", keeps=None))]
+    #[pyo3(signature = (min_count, filepath, method="from_fragmap", keeps=None, with_paths=false, use_p_lines=false, reference_sample=None, with_sequences=false))]
+    #[allow(clippy::too_many_arguments)]
     pub fn generate_mapg_gfa(
         &self,
         min_count: usize,
         filepath: &str,
         method: &str,
         keeps: Option<Vec<u32>>,
+        with_paths: bool,
+        use_p_lines: bool,
+        reference_sample: Option<String>,
+        with_sequences: bool,
     ) -> PyResult<()> {
-        self.db_internal
-            .generate_mapg_gfa(min_count, filepath, method, keeps)?;
+        let path_options = GFAPathOptions {
+            use_p_lines,
+            reference_sample,
+            with_sequences,
+        };
+        self.db_internal.generate_mapg_gfa_with_paths(
+            min_count,
+            filepath,
+            method,
+            keeps,
+            if with_paths || with_sequences {
+                Some(&path_options)
+            } else {
+                None
+            },
+        )?;
         Ok(())
     }
 
@@ -1349,25 +1543,54 @@ impl SeqIndexDB {
     /// filepath : string
     ///     the path to the output file
     ///
+    /// with_paths : bool
+    ///     write a path line for every sequence
+    ///
+    /// use_p_lines : bool
+    ///     write the paths as ``P`` lines instead of ``W`` lines with the PanSN sample/haplotype/contig names
+    ///
+    /// reference_sample : string
+    ///     if specified, add the rGFA ``SN/SO/SR`` tags to the segments relative to the sequences of the sample,
+    ///     the shimmer pair of a segment is written in the ``SP`` tag
+    ///
+    /// with_sequences : bool
+    ///     write the segment sequences instead of ``*`` and a path line for every sequence, a segment uses the
+    ///     fragment of the first sequence having its shimmer pair
+    ///
     /// Returns
     /// -------
     ///
     /// None
     ///     The data is written into the file at filepath
     ///     
-    #[pyo3(signature = (min_count, path_len_cutoff, filepath, keeps=None))]
+    #[pyo3(signature = (min_count, path_len_cutoff, filepath, keeps=None, with_paths=false, use_p_lines=false, reference_sample=None, with_sequences=false))]
+    #[allow(clippy::too_many_arguments)]
     pub fn generate_principal_mapg_gfa(
         &self,
         min_count: usize,
         path_len_cutoff: usize,
         filepath: &str,
         keeps: Option<Vec<u32>>,
+        with_paths: bool,
+        use_p_lines: bool,
+        reference_sample: Option<String>,
+        with_sequences: bool,
     ) -> PyResult<()> {
-        self.db_internal.generate_principal_mapg_gfa(
+        let path_options = GFAPathOptions {
+            use_p_lines,
+            reference_sample,
+            with_sequences,
+        };
+        self.db_internal.generate_principal_mapg_gfa_with_paths(
             min_count,
             path_len_cutoff,
             filepath,
             keeps,
+            if with_paths || with_sequences {
+                Some(&path_options)
+            } else {
+                None
+            },
         )?;
         Ok(())
     }
@@ -1418,6 +1641,148 @@ impl SeqIndexDB {
     }
 }
 
+/// A class that stores a frozen set of principal bundles
+///
+/// The principal bundles can be built from a ``SeqIndexDB`` or loaded from a ``.pdb`` file
+/// generated by ``pgr-pbundle-decomp``. New sequences can be decomposed against the same
+/// set of bundles without rebuilding the MAP graph. For example::
+///
+///     >>> pdb = PrincipalBundleDB.load_from_file("HLA-A.pdb")
+///     >>> smps = pdb.decompose_seq(seq)
+///
+#[pyclass]
+struct PrincipalBundleDB {
+    /// Rust internal:
+    db_internal: pgr_db::ext::PrincipalBundleDB,
+}
+
+#[pymethods]
+impl PrincipalBundleDB {
+    /// load the principal bundles from a ``.pdb`` file
+    ///
+    /// Parameters
+    /// ----------
+    /// filepath : string
+    ///     the path to the ``.pdb`` file
+    ///
+    /// Returns
+    /// -------
+    /// PrincipalBundleDB
+    ///
+    #[staticmethod]
+    #[pyo3(signature = (filepath))]
+    pub fn load_from_file(filepath: String) -> PyResult<Self> {
+        let db_internal = pgr_db::ext::PrincipalBundleDB::load_from_file(&filepath)?;
+        Ok(PrincipalBundleDB { db_internal })
+    }
+
+    /// build the principal bundles from the sequences in a ``SeqIndexDB``
+    ///
+    /// Parameters
+    /// ----------
+    /// seq_index_db : SeqIndexDB
+    ///     the sequence database with a fasta or memory backend
+    ///
+    /// min_count : int
+    ///     minimum coverage count to be included in the graph
+    ///
+    /// path_len_cut_off : int
+    ///     remove short path less than path_len_cut_off when generating the principal path
+    ///
+    /// Returns
+    /// -------
+    /// PrincipalBundleDB
+    ///
+    #[staticmethod]
+    #[pyo3(signature = (seq_index_db, min_count, path_len_cutoff, keeps=None))]
+    pub fn from_seq_index_db(
+        seq_index_db: &SeqIndexDB,
+        min_count: usize,
+        path_len_cutoff: usize,
+        keeps: Option<Vec<u32>>,
+    ) -> PyResult<Self> {
+        let db_internal = pgr_db::ext::PrincipalBundleDB::from_seq_index_db(
+            &seq_index_db.db_internal,
+            min_count,
+            path_len_cutoff,
+            keeps,
+        )?;
+        Ok(PrincipalBundleDB { db_internal })
+    }
+
+    /// write the principal bundles to a ``.pdb`` file
+    #[pyo3(signature = (filepath))]
+    pub fn write_to_file(&self, filepath: String) -> PyResult<()> {
+        self.db_internal.write_to_file(&filepath)?;
+        Ok(())
+    }
+
+    /// get the shimmer specification (w, k, r, min_span, sketch) used for building the bundles
+    pub fn get_shmmr_spec(&self) -> (u32, u32, u32, u32, bool) {
+        let spec = &self.db_internal.shmmr_spec;
+        (spec.w, spec.k, spec.r, spec.min_span, spec.sketch)
+    }
+
+    /// get the list of (principal_bundle_id, ave_bundle_position, list_bundle_vertex)
+    pub fn get_principal_bundles(&self) -> Vec<(usize, usize, Vec<(u64, u64, u8)>)> {
+        self.db_internal.principal_bundles_with_id.clone()
+    }
+
+    /// get the (principal_bundle_id, direction, order_in_the_bundle) of a shimmer pair
+    #[pyo3(signature = (shmmr_pair))]
+    pub fn get_bundle_by_shmmr_pair(&self, shmmr_pair: (u64, u64)) -> Option<(usize, u8, usize)> {
+        self.db_internal.get_bundle_by_shmmr_pair(shmmr_pair)
+    }
+
+    /// get the list of (principal_bundle_id, ave_bundle_position, number_of_vertices) of all bundles
+    pub fn get_bundle_stats(&self) -> Vec<(usize, usize, usize)> {
+        self.db_internal.get_bundle_stats()
+    }
+
+    /// decompose a sequence with the principal bundles
+    ///
+    /// Parameters
+    /// ----------
+    /// seq : list of bytes
+    ///     the sequence
+    ///
+    /// Returns
+    /// -------
+    /// list
+    ///     the elements of the list are ((hash0:u64, hash1:u64, pos0:u32, pos0:u32, direction:0),
+    ///                                   (principal_bundle_id, direction, order_in_the_bundle))
+    ///
+    #[pyo3(signature = (seq))]
+    pub fn decompose_seq(
+        &self,
+        seq: Vec<u8>,
+    ) -> Vec<((u64, u64, u32, u32, u8), Option<(usize, u8, usize)>)> {
+        self.db_internal.decompose_seq(seq)
+    }
+
+    /// decompose all sequences in a ``SeqIndexDB`` indexed with the same shimmer specification
+    ///
+    /// Returns
+    /// -------
+    /// list
+    ///     list of (sequence_id, decomposition), the decomposition is the same as ``decompose_seq()``
+    ///
+    #[pyo3(signature = (seq_index_db))]
+    pub fn decompose_seq_index_db(
+        &self,
+        seq_index_db: &SeqIndexDB,
+    ) -> PyResult<
+        Vec<(
+            u32,
+            Vec<((u64, u64, u32, u32, u8), Option<(usize, u8, usize)>)>,
+        )>,
+    > {
+        Ok(self
+            .db_internal
+            .decompose_seq_index_db(&seq_index_db.db_internal)?)
+    }
+}
+
 /// A PyO3 class wrapping an existing AGC file for reading
 ///
 /// Example::
@@ -2000,6 +2365,7 @@ pub fn shmmr_sparse_aln_consensus(
 #[pymodule]
 fn pgrtk(_: Python, m: &PyModule) -> PyResult<()> {
     m.add_class::<SeqIndexDB>()?;
+    m.add_class::<PrincipalBundleDB>()?;
     #[cfg(feature = "with_agc")]
     m.add_class::<AGCFile>()?;
     m.add_function(wrap_pyfunction!(sparse_aln, m)?)?;
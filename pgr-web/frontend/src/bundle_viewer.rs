@@ -0,0 +1,500 @@
+// bundle_viewer.rs
+//
+// An interactive view of the principal bundle decomposition of the matched sequences,
+// the tracks can be zoomed with the mouse wheel and panned by dragging, a click on a bundle
+// segment highlights the bundle in all tracks. The genes mapped from the reference are drawn
+// under the bundle segments.
+
+use crate::{
+    BundleDendrogram, GeneBedRecord, MatchSummary, PrincipalBundleBedRecord,
+    TargetMatchPrincipalBundles,
+};
+use dioxus::prelude::*;
+use rustc_hash::FxHashMap;
+
+static CMAP: [&str; 97] = [
+    "#870098", "#00aaa5", "#3bff00", "#ec0000", "#00a2c3", "#00f400", "#ff1500", "#0092dd",
+    "#00dc00", "#ff8100", "#007ddd", "#00c700", "#ffb100", "#0038dd", "#00af00", "#fcd200",
+    "#0000d5", "#009a00", "#f1e700", "#0000b1", "#00a55d", "#d4f700", "#4300a2", "#00aa93",
+    "#a1ff00", "#dc0000", "#00aaab", "#1dff00", "#f40000", "#009fcb", "#00ef00", "#ff2d00",
+    "#008ddd", "#00d700", "#ff9900", "#0078dd", "#00c200", "#ffb900", "#0025dd", "#00aa00",
+    "#f9d700", "#0000c9", "#009b13", "#efed00", "#0300aa", "#00a773", "#ccf900", "#63009e",
+    "#00aa98", "#84ff00", "#e10000", "#00a7b3", "#00ff00", "#f90000", "#009bd7", "#00ea00",
+    "#ff4500", "#0088dd", "#00d200", "#ffa100", "#005ddd", "#00bc00", "#ffc100", "#0013dd",
+    "#00a400", "#f7dd00", "#0000c1", "#009f33", "#e8f000", "#1800a7", "#00aa88", "#c4fc00",
+    "#78009b", "#00aaa0", "#67ff00", "#e60000", "#00a4bb", "#00fa00", "#fe0000", "#0098dd",
+    "#00e200", "#ff5d00", "#0082dd", "#00cc00", "#ffa900", "#004bdd", "#00b400", "#ffc900",
+    "#0000dd", "#009f00", "#f4e200", "#0000b9", "#00a248", "#dcf400", "#2d00a4", "#00aa8d",
+    "#bcff00",
+];
+
+const TRACK_PANEL_WIDTH: f64 = 1200.0;
+const TREE_PANEL_WIDTH: f64 = 160.0;
+const LABEL_PANEL_WIDTH: f64 = 480.0;
+const RULER_HEIGHT: f64 = 32.0;
+const TRACK_HEIGHT: f64 = 16.0;
+// the smallest visible range in bps
+const MIN_VIEW_SPAN: f64 = 100.0;
+
+/// the matched region of a track on its contig, it is only known if the contig has one matched region
+struct TrackRegion {
+    t_bgn: u32,
+    t_end: u32,
+    reversed: bool,
+}
+
+struct SegmentShape {
+    y: f64,
+    path: String,
+    b_id: u32,
+    stroke_width: f64,
+    tooltip: String,
+}
+
+/// zoom the view, a range of the fractions of the track range, around the center at a fraction of the view
+fn zoom_view(view: (f64, f64), center: f64, factor: f64, min_span: f64) -> (f64, f64) {
+    let span = ((view.1 - view.0) * factor).clamp(min_span, 1.0);
+    let center_pos = view.0 + center * (view.1 - view.0);
+    let bgn = (center_pos - center * span).clamp(0.0, 1.0 - span);
+    (bgn, bgn + span)
+}
+
+fn pan_view(view: (f64, f64), shift: f64) -> (f64, f64) {
+    let span = view.1 - view.0;
+    let bgn = (view.0 + shift).clamp(0.0, 1.0 - span);
+    (bgn, bgn + span)
+}
+
+fn get_tick_interval(span: f64) -> f64 {
+    let raw_interval = span / 10.0;
+    let base = 10_f64.powf(raw_interval.log10().floor());
+    [1.0, 2.0, 5.0, 10.0]
+        .into_iter()
+        .map(|m| m * base)
+        .find(|&interval| interval >= raw_interval)
+        .unwrap_or(10.0 * base)
+}
+
+/// the position of the pointer as a fraction of the width of the track panel
+fn get_pointer_fraction(client_x: f64) -> Option<f64> {
+    let rect = web_sys::window()?
+        .document()?
+        .get_element_by_id("bundle_tracks")?
+        .get_bounding_client_rect();
+    if rect.width() > 0.0 {
+        Some(((client_x - rect.left()) / rect.width()).clamp(0.0, 1.0))
+    } else {
+        None
+    }
+}
+
+/// the arrow of a bundle segment, it points to the left for the reversed segments
+fn bundle_arrow_path(bgn: f64, end: f64, reversed: bool) -> String {
+    let (bgn, end) = if reversed { (end, bgn) } else { (bgn, end) };
+    let half_width = 5.0;
+    let arrow_end = end;
+    let end = if !reversed {
+        if end - half_width < bgn {
+            bgn
+        } else {
+            end - half_width
+        }
+    } else if end + half_width > bgn {
+        bgn
+    } else {
+        end + half_width
+    };
+    let bottom0 = -half_width * 0.6;
+    let top0 = half_width * 0.6;
+    let bottom1 = -half_width * 0.8;
+    let top1 = half_width * 0.8;
+    format!(
+        "M {bgn} {bottom0} L {bgn} {top0} L {end} {top0} L {end} {top1} L {arrow_end} 0 L {end} {bottom1} L {end} {bottom0} Z"
+    )
+}
+
+struct GeneShape {
+    y: f64,
+    bgn: f64,
+    end: f64,
+    color: &'static str,
+    tooltip: String,
+}
+
+/// the genes on the forward strand of the reference are blue and the others are red as the svg from the server
+fn gene_color(gene: &GeneBedRecord) -> &'static str {
+    if gene.strand == "-" {
+        "#d62728"
+    } else {
+        "#1f77b4"
+    }
+}
+
+fn segment_tooltip(r: &PrincipalBundleBedRecord, region: Option<&TrackRegion>) -> String {
+    let direction = if r.b_direction == 0 { "+" } else { "-" };
+    let r_type = if r.r_type == "R" { "repeat" } else { "unique" };
+    let mut tooltip = format!(
+        "{}:{}-{} bundle {} ({}), bundle size {}, bundle vertices {}-{}, {}",
+        r.ctg, r.bgn, r.end, r.b_id, direction, r.b_size, r.b_bgn, r.b_end, r_type
+    );
+    if let Some(region) = region {
+        let (bgn, end) = if region.reversed {
+            (
+                region.t_end.saturating_sub(r.end),
+                region.t_end.saturating_sub(r.bgn),
+            )
+        } else {
+            (region.t_bgn + r.bgn, region.t_bgn + r.end)
+        };
+        tooltip += &format!(", contig coordinates {}-{}", bgn, end);
+    }
+    tooltip
+}
+
+/// the row of each track and the paths of the tree, the tracks are in the order of the tree leaves if the tree is given
+fn get_track_layout(
+    n_tracks: usize,
+    dendrogram: Option<&BundleDendrogram>,
+) -> (Vec<usize>, Vec<String>) {
+    let dendrogram = match dendrogram {
+        Some(dendrogram) => dendrogram,
+        None => return ((0..n_tracks).collect(), vec![]),
+    };
+    let mut rows = vec![0_usize; n_tracks];
+    dendrogram
+        .leaf_order
+        .iter()
+        .enumerate()
+        .for_each(|(row, &track_idx)| rows[track_idx] = row);
+
+    let max_height = dendrogram
+        .steps
+        .iter()
+        .map(|s| s.dissimilarity as f64)
+        .fold(0.0, f64::max)
+        .max(1e-6);
+    let track_y = |row: usize| RULER_HEIGHT + (row as f64 + 0.5) * TRACK_HEIGHT;
+    // (x, y, the number of the leaves) of the tree nodes, the leaves are on the right
+    let mut nodes = (0..n_tracks)
+        .map(|track_idx| {
+            (
+                track_idx,
+                (TREE_PANEL_WIDTH - 4.0, track_y(rows[track_idx]), 1_usize),
+            )
+        })
+        .collect::<FxHashMap<_, _>>();
+    let paths = dendrogram
+        .steps
+        .iter()
+        .enumerate()
+        .filter_map(|(c, s)| {
+            let (x1, y1, size1) = nodes.get(&s.cluster1).copied()?;
+            let (x2, y2, size2) = nodes.get(&s.cluster2).copied()?;
+            let x = 4.0 + (TREE_PANEL_WIDTH - 8.0) * (1.0 - s.dissimilarity as f64 / max_height);
+            let y = (size1 as f64 * y1 + size2 as f64 * y2) / (size1 + size2) as f64;
+            nodes.insert(c + n_tracks, (x, y, size1 + size2));
+            Some(format!("M {x1} {y1} L {x} {y1} L {x} {y2} L {x2} {y2}"))
+        })
+        .collect();
+    (rows, paths)
+}
+
+#[inline_props]
+pub fn bundle_viewer<'a>(
+    cx: Scope<'a>,
+    targets: &'a UseState<Option<TargetMatchPrincipalBundles>>,
+) -> Element<'a> {
+    // the visible range as the fractions of the track range
+    let view = use_state(cx, || (0.0_f64, 1.0_f64));
+    let highlighted = use_state(cx, || <Option<u32>>::None);
+    let tooltip = use_state(cx, || <Option<(f64, f64, String)>>::None);
+    let sort_by_tree = use_state(cx, || true);
+    let pointer = use_ref(cx, || <Option<f64>>::None);
+    // (the pointer position, the view) when the dragging starts
+    let drag = use_ref(cx, || <Option<(f64, (f64, f64))>>::None);
+    let dragged = use_ref(cx, || false);
+
+    let targets = match targets.get() {
+        Some(targets) => targets,
+        None => return cx.render(rsx! { div { id: "bundle_viewer" } }),
+    };
+
+    let n_tracks = targets.bundle_bed_records.len();
+    let track_range = targets
+        .bundle_bed_records
+        .iter()
+        .flatten()
+        .map(|r| r.bgn.max(r.end))
+        .max()
+        .unwrap_or(0)
+        .max(1) as f64
+        * 1.05;
+    let min_span = (MIN_VIEW_SPAN / track_range).min(1.0);
+    let (view_bgn, view_end) = (view.get().0 * track_range, view.get().1 * track_range);
+    let scaling_factor = TRACK_PANEL_WIDTH / (view_end - view_bgn);
+
+    let sid_to_ctg = targets
+        .sid_ctg_src
+        .iter()
+        .map(|(sid, ctg, _src)| (*sid, ctg.as_str()))
+        .collect::<FxHashMap<_, _>>();
+    let mut ctg_hits = FxHashMap::<&str, Vec<&MatchSummary>>::default();
+    targets.match_summary.iter().for_each(|(sid, hits)| {
+        if let Some(ctg) = sid_to_ctg.get(sid) {
+            ctg_hits.entry(ctg).or_default().extend(hits.iter());
+        }
+    });
+
+    let dendrogram = if *sort_by_tree.get() {
+        targets
+            .dendrogram
+            .as_ref()
+            .filter(|d| d.leaf_order.len() == n_tracks)
+    } else {
+        None
+    };
+    let tree_width = if dendrogram.is_some() {
+        TREE_PANEL_WIDTH
+    } else {
+        0.0
+    };
+    let (rows, tree_paths) = get_track_layout(n_tracks, dendrogram);
+    let svg_width = tree_width + TRACK_PANEL_WIDTH + LABEL_PANEL_WIDTH;
+    let svg_height = RULER_HEIGHT + n_tracks as f64 * TRACK_HEIGHT + 8.0;
+
+    let labels = targets
+        .bundle_bed_records
+        .iter()
+        .enumerate()
+        .map(|(track_idx, records)| {
+            let ctg = records.first().map_or("NA", |r| r.ctg.as_str()).to_string();
+            let y = RULER_HEIGHT + (rows[track_idx] as f64 + 0.5) * TRACK_HEIGHT + 3.0;
+            (ctg, y)
+        })
+        .collect::<Vec<_>>();
+
+    let segments = targets
+        .bundle_bed_records
+        .iter()
+        .enumerate()
+        .flat_map(|(track_idx, records)| {
+            let y = RULER_HEIGHT + (rows[track_idx] as f64 + 0.5) * TRACK_HEIGHT;
+            let region = records
+                .first()
+                .and_then(|r| ctg_hits.get(r.ctg.as_str()))
+                .filter(|hits| hits.len() == 1)
+                .map(|hits| TrackRegion {
+                    t_bgn: hits[0].t_bgn,
+                    t_end: hits[0].t_end,
+                    reversed: hits[0].reversed,
+                });
+            records
+                .iter()
+                .filter(|r| (r.end as f64) >= view_bgn && (r.bgn as f64) <= view_end)
+                .map(|r| SegmentShape {
+                    y,
+                    path: bundle_arrow_path(
+                        (r.bgn as f64 - view_bgn) * scaling_factor,
+                        (r.end as f64 - view_bgn) * scaling_factor,
+                        r.b_direction == 1,
+                    ),
+                    b_id: r.b_id,
+                    stroke_width: if r.r_type == "R" { 1.2 } else { 1.0 },
+                    tooltip: segment_tooltip(r, region.as_ref()),
+                })
+                .collect::<Vec<_>>()
+        })
+        .collect::<Vec<_>>();
+
+    let genes = targets
+        .gene_records
+        .iter()
+        .take(n_tracks)
+        .enumerate()
+        .flat_map(|(track_idx, genes)| {
+            let y = RULER_HEIGHT + (rows[track_idx] as f64 + 0.5) * TRACK_HEIGHT + 6.0;
+            genes
+                .iter()
+                .filter(|g| (g.end as f64) >= view_bgn && (g.bgn as f64) <= view_end)
+                .map(|g| GeneShape {
+                    y,
+                    bgn: ((g.bgn as f64 - view_bgn) * scaling_factor).max(0.0),
+                    end: ((g.end as f64 - view_bgn) * scaling_factor).min(TRACK_PANEL_WIDTH),
+                    color: gene_color(g),
+                    tooltip: format!("{} ({}) {}:{}-{}", g.name, g.strand, g.ctg, g.bgn, g.end),
+                })
+                .collect::<Vec<_>>()
+        })
+        .collect::<Vec<_>>();
+
+    let tick_interval = get_tick_interval(view_end - view_bgn);
+    let ticks = (0..)
+        .map(|i| ((view_bgn / tick_interval).ceil() + i as f64) * tick_interval)
+        .take_while(|&tick| tick <= view_end)
+        .map(|tick| (tick, (tick - view_bgn) * scaling_factor))
+        .collect::<Vec<_>>();
+    let view_label = format!("{:.0}-{:.0} of {:.0} bps", view_bgn, view_end, track_range);
+    let sort_label = if *sort_by_tree.get() {
+        "Sort by Contig Name"
+    } else {
+        "Sort by Dendrogram"
+    };
+    let button_class = "middle none center rounded-lg px-2 py-1 bg-blue-600 text-white";
+
+    cx.render(rsx! {
+        div { class: "flex flex-col px-8 py-2", id: "bundle_viewer",
+            div { class: "flex flex-row space-x-2 py-1",
+                button { class: "{button_class}",
+                    onclick: move |_| view.set(zoom_view(*view.get(), 0.5, 0.5, min_span)),
+                    "Zoom In"
+                }
+                button { class: "{button_class}",
+                    onclick: move |_| view.set(zoom_view(*view.get(), 0.5, 2.0, min_span)),
+                    "Zoom Out"
+                }
+                button { class: "{button_class}",
+                    onclick: move |_| view.set((0.0, 1.0)),
+                    "Reset View"
+                }
+                button { class: "{button_class}",
+                    onclick: move |_| sort_by_tree.set(!*sort_by_tree.get()),
+                    "{sort_label}"
+                }
+                button { class: "{button_class}",
+                    onclick: move |_| highlighted.set(None),
+                    "Clear Highlight"
+                }
+                p { class: "px-2 py-1", "{view_label}" }
+            }
+            div { class: "overflow-auto max-h-[650px] bg-white cursor-grab",
+                prevent_default: "onwheel",
+                onwheel: move |evt| {
+                    let factor = if evt.delta().strip_units().y > 0.0 { 1.25 } else { 0.8 };
+                    let center = (*pointer.read()).unwrap_or(0.5);
+                    view.set(zoom_view(*view.get(), center, factor, min_span));
+                },
+                onmousedown: move |evt| {
+                    let x = evt.client_coordinates().x;
+                    *drag.write_silent() = Some((x, *view.get()));
+                    *dragged.write_silent() = false;
+                },
+                onmousemove: move |evt| {
+                    let x = evt.client_coordinates().x;
+                    *pointer.write_silent() = get_pointer_fraction(x);
+                    let drag_start = *drag.read();
+                    if let (Some((x0, view0)), Some(fraction0), Some(fraction)) =
+                        (drag_start, get_pointer_fraction(x0), get_pointer_fraction(x))
+                    {
+                        if (x - x0).abs() > 3.0 {
+                            *dragged.write_silent() = true;
+                            tooltip.set(None);
+                            view.set(pan_view(view0, (fraction0 - fraction) * (view0.1 - view0.0)));
+                        }
+                    }
+                },
+                onmouseup: move |_| *drag.write_silent() = None,
+                onmouseleave: move |_| {
+                    *drag.write_silent() = None;
+                    tooltip.set(None);
+                },
+                svg {
+                    width: "{svg_width}",
+                    height: "{svg_height}",
+                    view_box: "0 0 {svg_width} {svg_height}",
+                    g { id: "bundle_tree",
+                        tree_paths.iter().map(|d| rsx! {
+                            path { d: "{d}", fill: "none", stroke: "#333", stroke_width: "1" }
+                        })
+                    }
+                    svg {
+                        id: "bundle_tracks",
+                        x: "{tree_width}",
+                        y: "0",
+                        width: "{TRACK_PANEL_WIDTH}",
+                        height: "{svg_height}",
+                        path { d: "M 0 20 L {TRACK_PANEL_WIDTH} 20", stroke: "#000", stroke_width: "1" }
+                        ticks.iter().map(|(tick, x)| rsx! {
+                            g {
+                                path { d: "M {x} 16 L {x} 20", stroke: "#000", stroke_width: "1" }
+                                text { x: "{x}", y: "12", font_size: "10px", font_family: "sans-serif", "{tick:.0}" }
+                            }
+                        })
+                        genes.into_iter().map(|gene| {
+                            let GeneShape { y, bgn, end, color, tooltip: text } = gene;
+                            rsx! {
+                                path {
+                                    class: "gene",
+                                    d: "M {bgn} {y} L {end} {y}",
+                                    stroke: "{color}",
+                                    stroke_width: "2.5",
+                                    onmouseenter: move |evt| {
+                                        if drag.read().is_none() {
+                                            let p = evt.client_coordinates();
+                                            tooltip.set(Some((p.x, p.y, text.clone())));
+                                        }
+                                    },
+                                    onmouseleave: move |_| tooltip.set(None),
+                                }
+                            }
+                        })
+                        segments.into_iter().map(|segment| {
+                            let SegmentShape { y, path, b_id, stroke_width, tooltip: text } = segment;
+                            let fill = CMAP[((b_id * 57) % 59) as usize];
+                            let stroke = CMAP[93 - ((b_id * 31) % 47) as usize];
+                            let (fill_opacity, stroke_width, transform) = match *highlighted.get() {
+                                Some(h_id) if h_id == b_id => (1.0, stroke_width * 2.0, "scale(1 1.5)"),
+                                Some(_) => (0.15, stroke_width, ""),
+                                None => (0.5, stroke_width, ""),
+                            };
+                            rsx! {
+                                g { transform: "translate(0 {y})",
+                                    path {
+                                        class: "bundle",
+                                        d: "{path}",
+                                        fill: "{fill}",
+                                        fill_opacity: "{fill_opacity}",
+                                        stroke: "{stroke}",
+                                        stroke_width: "{stroke_width}",
+                                        transform: "{transform}",
+                                        onmouseenter: move |evt| {
+                                            if drag.read().is_none() {
+                                                let p = evt.client_coordinates();
+                                                tooltip.set(Some((p.x, p.y, text.clone())));
+                                            }
+                                        },
+                                        onmouseleave: move |_| tooltip.set(None),
+                                        onclick: move |_| {
+                                            if !*dragged.read() {
+                                                if *highlighted.get() == Some(b_id) {
+                                                    highlighted.set(None);
+                                                } else {
+                                                    highlighted.set(Some(b_id));
+                                                }
+                                            }
+                                        },
+                                    }
+                                }
+                            }
+                        })
+                    }
+                    labels.iter().map(|(ctg, y)| {
+                        let x = tree_width + TRACK_PANEL_WIDTH + 10.0;
+                        rsx! {
+                            text { x: "{x}", y: "{y}", font_size: "10px", font_family: "monospace", "{ctg}" }
+                        }
+                    })
+                }
+            }
+            tooltip.get().as_ref().map(|(x, y, text)| {
+                let (x, y) = (x + 12.0, y + 12.0);
+                rsx! {
+                    div {
+                        class: "fixed z-10 px-2 py-1 text-xs bg-white border border-gray-400 rounded pointer-events-none",
+                        style: "left: {x}px; top: {y}px;",
+                        "{text}"
+                    }
+                }
+            })
+        }
+    })
+}
@@ -1,14 +1,14 @@
 // main.rs
 
+mod bundle_viewer;
+
+use bundle_viewer::bundle_viewer;
 use dioxus::prelude::*;
-use futures_lite::stream::StreamExt;
-use futures_util::sink::SinkExt;
 use rustc_hash::FxHashMap;
 use serde::{Deserialize, Serialize};
 use wasm_bindgen::JsCast;
 use std::collections::HashMap;
 use itertools::Itertools;
-use {pharos::*, wasm_bindgen::UnwrapThrowExt, ws_stream_wasm::*};
 //use pgr_db::aln::{self, HitPair};
 //type HitPair = ((u32, u32, u8), (u32, u32, u8)); //(bgn1, end1, orientation1),  (bgn2, end2, orientation2)
 
@@ -32,6 +32,24 @@ pub struct TargetMatchPrincipalBundles {
     pub match_summary: Vec<(u32, Vec<MatchSummary>)>, // (q_id, vec[(q_bgn, q_end, t_bgn, t_end, num_hits, reversed)])
     pub sid_ctg_src: Vec<(u32, String, String)>,
     pub bundle_bed_records: Vec<Vec<PrincipalBundleBedRecord>>,
+    #[serde(default)]
+    pub dendrogram: Option<BundleDendrogram>,
+    #[serde(default)]
+    pub gene_records: Vec<Vec<GeneBedRecord>>,
+}
+
+#[derive(Deserialize, Clone, Debug)]
+pub struct DendrogramStep {
+    pub cluster1: usize,
+    pub cluster2: usize,
+    pub dissimilarity: f32,
+    pub size: usize,
+}
+
+#[derive(Deserialize, Clone, Debug)]
+pub struct BundleDendrogram {
+    pub leaf_order: Vec<usize>,
+    pub steps: Vec<DendrogramStep>,
 }
 
 #[derive(Deserialize, Clone, Debug)]
@@ -47,6 +65,15 @@ pub struct PrincipalBundleBedRecord {
     pub r_type: String,
 }
 
+#[derive(Deserialize, Clone, Debug)]
+pub struct GeneBedRecord {
+    pub ctg: String,
+    pub bgn: u32,
+    pub end: u32,
+    pub name: String,
+    pub strand: String,
+}
+
 #[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
 pub struct ShmmrSpec {
     pub w: u32,
@@ -77,6 +104,34 @@ pub struct SequenceQuerySpec {
     pub bundle_merge_distance: usize,
 }
 
+#[derive(Deserialize, Clone, Debug)]
+pub struct DatabaseInfo {
+    pub name: String,
+    pub shmmr_spec: ShmmrSpec,
+    pub is_default: bool,
+}
+
+#[derive(Deserialize, Clone, Debug)]
+pub struct NamedQuery {
+    pub name: String,
+    pub kind: String,
+    pub spec: SequenceQuerySpec,
+}
+
+#[derive(Deserialize, Clone, Debug)]
+pub struct JobStatus {
+    pub id: u64,
+    pub state: String,
+    pub progress: f32,
+    pub stage: String,
+    pub error: Option<String>,
+}
+
+/// the interval of polling the status of a query job
+const JOB_POLL_INTERVAL_MS: u32 = 500;
+/// the error of a job that doesn't match any target sequence
+const NO_RESULT_ERROR: &str = "no result is generated";
+
 #[derive(Clone)]
 struct QueryState(String);
 
@@ -84,15 +139,41 @@ pub fn base_url() -> String {
     web_sys::window().unwrap().location().origin().unwrap()
 }
 
+fn api_v1_url(segments: &[&str]) -> String {
+    let mut url = url::Url::parse(&base_url()).unwrap();
+    url.path_segments_mut()
+        .unwrap()
+        .pop_if_empty()
+        .extend(&["api", "v1"])
+        .extend(segments);
+    url.to_string()
+}
+
+fn named_query_url(db_name: &str, name: &str) -> String {
+    let mut url = url::Url::parse(&base_url()).unwrap();
+    url.path_segments_mut()
+        .unwrap()
+        .pop_if_empty()
+        .extend(&["api", "v1", "db", db_name, "rois", name]);
+    url.to_string()
+}
+
+fn export_url(db_name: &str, format: &str, query: &SequenceQuerySpec) -> String {
+    let mut url = url::Url::parse(&base_url()).unwrap();
+    url.path_segments_mut()
+        .unwrap()
+        .pop_if_empty()
+        .extend(&["api", "v1", "db", db_name, "export", format]);
+    url.set_query(Some(&serde_qs::to_string(query).unwrap()));
+    url.to_string()
+}
+
 fn main() {
     dioxus_web::launch(app);
     wasm_logger::init(wasm_logger::Config::default());
 }
 
 fn app(cx: Scope) -> Element {
-    let roi_json = include_str!("data/ROIs.json");
-    let rois: FxHashMap<String, SequenceQuerySpec> = serde_json::from_str(roi_json).unwrap();
-
     let query = use_state(cx, || SequenceQuerySpec {
         source: "hg19_tagged.fa".to_string(),
         ctg: "chr1_hg19".to_string(),
@@ -122,7 +203,38 @@ fn app(cx: Scope) -> Element {
     //let labels = kvs.iter().map(|(k, _v)| k.clone()).collect::<Vec<_>>();
 
     let selected_label = use_state(cx, || "".to_string());
-    let rois = use_state(cx, || rois );
+    let rois = use_state(cx, FxHashMap::<String, SequenceQuerySpec>::default);
+    let db_name = use_state(cx, || "default".to_string());
+
+    use_future(cx, (), |_| {
+        let rois = rois.to_owned();
+        let db_name = db_name.to_owned();
+        let query = query.to_owned();
+        async move {
+            let databases = match reqwest::get(base_url() + "/api/v1/databases").await {
+                Ok(response) => response.json::<Vec<DatabaseInfo>>().await.unwrap_or_default(),
+                Err(_) => vec![],
+            };
+            let name = match databases.into_iter().find(|db| db.is_default) {
+                Some(db) => {
+                    // use the default shimmer parameters of the database in the query form
+                    let mut new_query = query.get().clone();
+                    new_query.w = db.shmmr_spec.w;
+                    new_query.k = db.shmmr_spec.k;
+                    new_query.r = db.shmmr_spec.r;
+                    new_query.min_span = db.shmmr_spec.min_span;
+                    new_query.sketch = db.shmmr_spec.sketch;
+                    query.set(new_query);
+                    db.name
+                }
+                None => db_name.get().clone(),
+            };
+            if let Some(queries) = get_named_queries(&name, "").await {
+                rois.set(queries);
+            }
+            db_name.set(name);
+        }
+    });
 
 
     cx.render(
@@ -137,11 +249,14 @@ fn app(cx: Scope) -> Element {
 
                     div { class: "flex flex-row",
 
-                        div { class: "basis-4/6", id: "query_results", query_results { targets: targets } }
+                        div { class: "basis-4/6", id: "query_results",
+                            bundle_viewer { targets: targets }
+                            query_results { targets: targets }
+                        }
 
                         div { class: "basis-2/6",
                             div { class: "p-1", id: "query_status", "Status: {query_state}" }
-                            div { class: "p-1", query_preset { rois: rois, selected_label: selected_label } }
+                            div { class: "p-1", query_preset { db_name: db_name, rois: rois, selected_label: selected_label } }
                             button {
                                 class: "p-1",
                                 id: "query_button",
@@ -155,14 +270,18 @@ fn app(cx: Scope) -> Element {
                                 },
                                 "Set Query Parameters"
                             }
+                            div { class: "p-1", id: "save_query",
+                                save_query { db_name: db_name, query: query, rois: rois, selected_label: selected_label, query_state: query_state }
+                            }
                             div { class: "p-1", id: "set_parameters", set_parameters { query: query } }
                             div { class: "flex flex-row p-1",
                                 div { class: "basis-1/2 p-1",
-                                    update_query { query: query, targets: targets, query_state: query_state }
+                                    update_query { db_name: db_name, query: query, targets: targets, query_state: query_state }
                                 }
                                 br {}
                                 div { class: "basis-1/2 p-1", id: "get_html", get_html { query: query } }
                             }
+                            div { class: "p-1", id: "export_files", export_files { db_name: db_name, query: query } }
                         }
                     }
                 }
@@ -174,6 +293,7 @@ fn app(cx: Scope) -> Element {
 #[inline_props]
 fn query_preset<'a>(
     cx: Scope<'a>,
+    db_name: &'a UseState<String>,
     rois: &'a UseState<FxHashMap<String, SequenceQuerySpec>>,
     selected_label: &'a UseState<String>,
 ) -> Element<'a> {
@@ -192,7 +312,7 @@ fn query_preset<'a>(
                     autocomplete: "on",
                     class: "form-select appearance-none  w-full px-3 py-1.5 focus:text-gray-700 focus:bg-white focus:border-blue-600 focus:outline-none",
                     oninput: |evt| {
-                        get_preset_list(cx, &evt.value.clone(), rois);
+                        get_preset_list(cx, &evt.value.clone(), db_name, rois);
                         selected_label.set(evt.value.clone());
        
                     },
@@ -233,23 +353,37 @@ fn data_list<'a>(
     // ) 
 }
 
-fn get_preset_list<'a, T>(cx: Scope<'a, T>, message: &'a str, rois: &'a UseState<FxHashMap<String, SequenceQuerySpec>>) {
+/// get the ROI presets and the saved queries of a database with the names starting with the prefix
+async fn get_named_queries(
+    db_name: &str,
+    prefix: &str,
+) -> Option<FxHashMap<String, SequenceQuerySpec>> {
+    let url = format!("{}/api/v1/db/{}/rois", base_url(), db_name);
+    let queries = reqwest::Client::new()
+        .get(url)
+        .query(&[("prefix", prefix)])
+        .send()
+        .await
+        .ok()?
+        .json::<Vec<NamedQuery>>()
+        .await
+        .ok()?;
+    Some(queries.into_iter().map(|q| (q.name, q.spec)).collect())
+}
+
+fn get_preset_list<'a, T>(
+    cx: Scope<'a, T>,
+    message: &'a str,
+    db_name: &'a UseState<String>,
+    rois: &'a UseState<FxHashMap<String, SequenceQuerySpec>>,
+) {
     let message = message.to_string();
+    let db_name = db_name.get().clone();
     let rois = rois.to_owned();
-    
-    cx.spawn(async move {
-        let (mut _ws, mut wsio) = WsMeta::connect("ws://omnix:3000/ws", None)
-            .await
-            .expect_throw("Could not create websocket");
 
-        //let mut evts = ws.observe(ObserveConfig::default()).await.expect("observe");
-        wsio.send(WsMessage::Text(message))
-            .await
-            .expect_throw("Failed to write to websocket");
-        let msg = wsio.next().await;
-        let result = msg.expect_throw("Stream closed");
-        if let WsMessage::Text(result) = result.clone() {
-            rois.set(serde_json::from_str(&result[..]).unwrap());
+    cx.spawn(async move {
+        if let Some(queries) = get_named_queries(&db_name, &message).await {
+            rois.set(queries);
         }
 
         // let window = web_sys::window().expect("global window does not exists");    
@@ -264,8 +398,85 @@ fn get_preset_list<'a, T>(cx: Scope<'a, T>, message: &'a str, rois: &'a UseState
     });
 }
 
+/// save the query with the name, or delete the saved query of the name, and refresh the preset list
+fn update_named_query<'a, T>(
+    cx: Scope<'a, T>,
+    delete: bool,
+    db_name: &'a UseState<String>,
+    name: &str,
+    query: &SequenceQuerySpec,
+    rois: &'a UseState<FxHashMap<String, SequenceQuerySpec>>,
+    query_state: &'a UseState<String>,
+) {
+    let db_name = db_name.get().clone();
+    let name = name.to_string();
+    let query = query.clone();
+    let rois = rois.to_owned();
+    let query_state = query_state.to_owned();
+
+    cx.spawn(async move {
+        let client = reqwest::Client::new();
+        let url = named_query_url(&db_name, &name);
+        let request = if delete {
+            client.delete(url)
+        } else {
+            client.put(url).json(&query)
+        };
+        let action = if delete { "delete" } else { "save" };
+        match request.send().await {
+            Ok(response) if response.status().is_success() => {
+                query_state.set(format!("Query {} {}d", name, action));
+            }
+            Ok(response) => {
+                let error = response.text().await.unwrap_or_default();
+                query_state.set(format!("Can't {} query {}: {}", action, name, error));
+            }
+            Err(e) => {
+                log::debug!("{:?}", e);
+            }
+        };
+        if let Some(queries) = get_named_queries(&db_name, "").await {
+            rois.set(queries);
+        }
+    })
+}
+
+#[inline_props]
+fn save_query<'a>(
+    cx: Scope<'a>,
+    db_name: &'a UseState<String>,
+    query: &'a UseState<SequenceQuerySpec>,
+    rois: &'a UseState<FxHashMap<String, SequenceQuerySpec>>,
+    selected_label: &'a UseState<String>,
+    query_state: &'a UseState<String>,
+) -> Element<'a> {
+    cx.render(rsx! {
+        div { class: "flex flex-row p-0",
+            div { class: "basis-1/2 p-1",
+                button {
+                    class: "middle none center w-full rounded-lg px-2 py-1.5 bg-blue-600 text-white",
+                    onclick: move |_evt| {
+                        update_named_query(cx, false, db_name, selected_label.get(), query.get(), rois, query_state);
+                    },
+                    "Save Query as Preset Name"
+                }
+            }
+            div { class: "basis-1/2 p-1",
+                button {
+                    class: "middle none center w-full rounded-lg px-2 py-1.5 bg-blue-600 text-white",
+                    onclick: move |_evt| {
+                        update_named_query(cx, true, db_name, selected_label.get(), query.get(), rois, query_state);
+                    },
+                    "Delete Saved Query"
+                }
+            }
+        }
+    })
+}
+
 fn get_targets<'a, T>(
     cx: Scope<'a, T>,
+    db_name: String,
     query: &'a SequenceQuerySpec,
     targets: &'a UseState<Option<TargetMatchPrincipalBundles>>,
     query_state: &'a UseState<String>,
@@ -275,28 +486,77 @@ fn get_targets<'a, T>(
     let query_state = query_state.to_owned();
 
     cx.spawn(async move {
-        let client = reqwest::Client::new();
-        let url = base_url() + "/api/post_query_for_json_data";
-        let response = client
-            .post(url)
-            .json(&query)
-            .send()
-            .await
-            .unwrap()
-            .json::<Option<TargetMatchPrincipalBundles>>()
-            .await;
-        match response {
+        match run_query_job(&db_name, &query, &query_state).await {
             Ok(val) => {
                 targets.set(val);
                 query_state.set("Query results fetched".into());
             }
             Err(e) => {
                 log::debug!("{:?}", e);
+                query_state.set(e);
             }
         };
     })
 }
 
+/// submit the query as a job of the database, poll the job status until it finishes and fetch the result,
+/// a job finished without any match gives `None`
+async fn run_query_job(
+    db_name: &str,
+    query: &SequenceQuerySpec,
+    query_state: &UseState<String>,
+) -> Result<Option<TargetMatchPrincipalBundles>, String> {
+    let client = reqwest::Client::new();
+    let response = client
+        .post(api_v1_url(&["db", db_name, "jobs"]))
+        .json(query)
+        .send()
+        .await
+        .map_err(|e| e.to_string())?;
+    let mut status = get_job_status(response).await?;
+    let status_url = api_v1_url(&["jobs", &status.id.to_string()]);
+    while status.state == "queued" || status.state == "running" {
+        query_state.set(format!(
+            "getting query results: {} ({:.0}%)",
+            status.stage,
+            status.progress * 100.0
+        ));
+        gloo_timers::future::TimeoutFuture::new(JOB_POLL_INTERVAL_MS).await;
+        let response = client
+            .get(&status_url)
+            .send()
+            .await
+            .map_err(|e| e.to_string())?;
+        status = get_job_status(response).await?;
+    }
+    match status.state.as_str() {
+        "done" => client
+            .get(api_v1_url(&["jobs", &status.id.to_string(), "result"]))
+            .send()
+            .await
+            .map_err(|e| e.to_string())?
+            .json::<TargetMatchPrincipalBundles>()
+            .await
+            .map(Some)
+            .map_err(|e| e.to_string()),
+        "failed" if status.error.as_deref() == Some(NO_RESULT_ERROR) => Ok(None),
+        state => Err(format!(
+            "the query job is {}: {}",
+            state,
+            status.error.unwrap_or_default()
+        )),
+    }
+}
+
+async fn get_job_status(response: reqwest::Response) -> Result<JobStatus, String> {
+    if response.status().is_success() {
+        response.json::<JobStatus>().await.map_err(|e| e.to_string())
+    } else {
+        let error = response.text().await.unwrap_or_default();
+        Err(format!("fail to submit or check the query job: {}", error))
+    }
+}
+
 #[inline_props]
 pub fn query_results<'a>(
     cx: Scope<'a>,
@@ -549,9 +809,45 @@ pub fn get_html<'a>(cx: Scope<'a>, query: &'a UseState<SequenceQuerySpec>) -> El
     })
 }
 
+#[inline_props]
+pub fn export_files<'a>(
+    cx: Scope<'a>,
+    db_name: &'a UseState<String>,
+    query: &'a UseState<SequenceQuerySpec>,
+) -> Element<'a> {
+    let query = query.current().as_ref().clone();
+    let db_name = db_name.get().clone();
+    let formats = [
+        ("fasta", "FASTA"),
+        ("bed", "BED"),
+        ("dist", "Distances"),
+        ("nwk", "Newick"),
+        ("svg", "SVG"),
+    ];
+
+    cx.render({
+        rsx! {
+            div { class: "flex flex-row flex-wrap",
+                formats.iter().map(|(format, label)| {
+                    let file_url = export_url(&db_name, format, &query);
+                    rsx! {
+                        div { class: "basis-1/5 p-1",
+                            button {
+                                class: "middle none center w-full rounded-lg px-2 py-1.5 bg-blue-600 text-white",
+                                a { class: "w-full", href: "{file_url}", download: "", p { "{label}" } }
+                            }
+                        }
+                    }
+                })
+            }
+        }
+    })
+}
+
 #[inline_props]
 pub fn update_query<'a>(
     cx: Scope<'a>,
+    db_name: &'a UseState<String>,
     query: &'a UseState<SequenceQuerySpec>,
     targets: &'a UseState<Option<TargetMatchPrincipalBundles>>,
     query_state: &'a UseState<String>,
@@ -568,7 +864,7 @@ pub fn update_query<'a>(
                 //disabled: "false",
                 onclick: move |_evt| {
                     let query0 = query.get();
-                    get_targets(cx, query0, targets, query_state);
+                    get_targets(cx, db_name.get().clone(), query0, targets, query_state);
                     query_state.set("getting query results".to_string());
                 },
                 "Update"
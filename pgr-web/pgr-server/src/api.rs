@@ -0,0 +1,782 @@
+use std::sync::Arc;
+
+use axum::{
+    extract::{Path, Query},
+    http::{header, StatusCode},
+    response::{IntoResponse, Response},
+    routing::{get, post},
+    Json, Router,
+};
+use pgr_db::ext::SeqIndexDB;
+use rustc_hash::FxHashMap;
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, OpenApi, ToSchema};
+
+use crate::databases::DatabaseRegistry;
+
+/// The OpenAPI document of the versioned (`/api/v1`) endpoints
+#[derive(OpenApi)]
+#[openapi(
+    paths(
+        crate::databases::get_databases,
+        get_samples,
+        get_ctgs,
+        get_sub_seq_fasta,
+        post_query_hits,
+        get_shmmr_pair_counts,
+        get_openapi_json,
+        crate::jobs::post_job,
+        crate::jobs::get_jobs,
+        crate::jobs::get_job,
+        crate::jobs::delete_job,
+        crate::jobs::get_job_result,
+        crate::cache::get_cache_stats,
+        crate::exports::get_export,
+        crate::rois::get_named_queries,
+        crate::rois::put_named_query,
+        crate::rois::delete_named_query
+    ),
+    components(schemas(
+        crate::databases::DatabaseBackend,
+        crate::databases::DatabaseInfo,
+        crate::bundle_processing::ShmmrSpec,
+        SampleInfo,
+        ContigInfo,
+        HitQuery,
+        HitFormat,
+        QueryHit,
+        ShmmrPairCount,
+        ApiErrorMessage,
+        crate::bundle_processing::SequenceQuerySpec,
+        crate::jobs::JobState,
+        crate::jobs::JobStatus,
+        crate::cache::CacheStats,
+        crate::cache::ResultCacheStats,
+        crate::exports::ExportFormat,
+        crate::rois::QueryKind,
+        crate::rois::NamedQuery
+    )),
+    tags((name = "pgr-server", description = "the sequence and the shimmer index query API"))
+)]
+pub struct ApiDoc;
+
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct ApiErrorMessage {
+    pub error: String,
+}
+
+/// The error of the versioned endpoints, it is returned as a JSON `{"error": message}` with the status code
+#[derive(Debug)]
+pub struct ApiError(pub StatusCode, pub String);
+
+impl ApiError {
+    pub fn bad_request<S: Into<String>>(msg: S) -> Self {
+        ApiError(StatusCode::BAD_REQUEST, msg.into())
+    }
+
+    pub fn not_found<S: Into<String>>(msg: S) -> Self {
+        ApiError(StatusCode::NOT_FOUND, msg.into())
+    }
+}
+
+impl From<std::io::Error> for ApiError {
+    fn from(err: std::io::Error) -> Self {
+        ApiError(StatusCode::INTERNAL_SERVER_ERROR, err.to_string())
+    }
+}
+
+impl From<tokio::task::JoinError> for ApiError {
+    fn from(err: tokio::task::JoinError) -> Self {
+        ApiError(StatusCode::INTERNAL_SERVER_ERROR, err.to_string())
+    }
+}
+
+impl From<serde_json::Error> for ApiError {
+    fn from(err: serde_json::Error) -> Self {
+        ApiError(StatusCode::INTERNAL_SERVER_ERROR, err.to_string())
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        (self.0, Json(ApiErrorMessage { error: self.1 })).into_response()
+    }
+}
+
+#[derive(Serialize, Deserialize, ToSchema, Clone)]
+pub struct SampleInfo {
+    pub sample: String,
+    pub n_ctgs: usize,
+    pub total_length: u64,
+}
+
+#[derive(Serialize, Deserialize, ToSchema, Clone)]
+pub struct ContigInfo {
+    pub sid: u32,
+    pub sample: String,
+    pub ctg: String,
+    pub length: u32,
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct ContigListQuery {
+    /// only list the contigs of the sample
+    pub sample: Option<String>,
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct SeqRangeQuery {
+    pub sample: String,
+    pub ctg: String,
+    /// the begin of the range, default to 0
+    pub bgn: Option<usize>,
+    /// the end of the range, default to the contig length
+    pub end: Option<usize>,
+}
+
+#[derive(Serialize, Deserialize, ToSchema, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum HitFormat {
+    #[default]
+    Json,
+    Bed,
+    Paf,
+}
+
+/// A query of the homologous hits, the query sequence is either `seq` or the range `sample:ctg:bgn-end` in the database
+#[derive(Serialize, Deserialize, ToSchema, Clone, Debug)]
+pub struct HitQuery {
+    pub name: Option<String>,
+    pub seq: Option<String>,
+    pub sample: Option<String>,
+    pub ctg: Option<String>,
+    pub bgn: Option<usize>,
+    pub end: Option<usize>,
+    #[serde(default = "default_gap_penalty_factor")]
+    pub gap_penalty_factor: f32,
+    #[serde(default = "default_max_count")]
+    pub max_count: u32,
+    #[serde(default = "default_max_count")]
+    pub max_query_count: u32,
+    #[serde(default = "default_max_count")]
+    pub max_target_count: u32,
+    #[serde(default = "default_max_aln_chain_span")]
+    pub max_aln_chain_span: u32,
+    #[serde(default)]
+    pub format: HitFormat,
+}
+
+fn default_gap_penalty_factor() -> f32 {
+    0.025
+}
+
+fn default_max_count() -> u32 {
+    128
+}
+
+fn default_max_aln_chain_span() -> u32 {
+    8
+}
+
+/// An aligned chain of the shimmer pair anchors between the query and a target contig
+#[derive(Serialize, Deserialize, ToSchema, Clone, Debug)]
+pub struct QueryHit {
+    pub sid: u32,
+    pub sample: String,
+    pub ctg: String,
+    pub ctg_len: u32,
+    pub t_bgn: u32,
+    pub t_end: u32,
+    pub q_bgn: u32,
+    pub q_end: u32,
+    pub orientation: u32,
+    pub anchor_count: usize,
+    /// the number of the query bases covered by the anchors
+    pub q_covered: u32,
+    pub score: f32,
+}
+
+#[derive(Serialize, Deserialize, ToSchema, Clone)]
+pub struct ShmmrPairCount {
+    pub hash0: u64,
+    pub hash1: u64,
+    pub bgn: u32,
+    pub end: u32,
+    pub orientation: u8,
+    pub count: usize,
+}
+
+/// build the router of the versioned endpoints, the database is selected by the `db_name` in the path,
+/// the sequences fetched or queried by a request are limited to `max_seq_length` bases
+pub fn api_v1_router(registry: Arc<DatabaseRegistry>, max_seq_length: usize) -> Router {
+    Router::new()
+        .route(
+            "/api/v1/databases",
+            get({
+                let registry = registry.clone();
+                move |identity| crate::databases::get_databases(identity, registry)
+            }),
+        )
+        .route(
+            "/api/v1/db/:db_name/samples",
+            get({
+                let registry = registry.clone();
+                move |db_name| get_samples(db_name, registry)
+            }),
+        )
+        .route(
+            "/api/v1/db/:db_name/ctgs",
+            get({
+                let registry = registry.clone();
+                move |db_name, params| get_ctgs(db_name, params, registry)
+            }),
+        )
+        .route(
+            "/api/v1/db/:db_name/seq",
+            get({
+                let registry = registry.clone();
+                move |db_name, params| get_sub_seq_fasta(db_name, params, registry, max_seq_length)
+            }),
+        )
+        .route(
+            "/api/v1/db/:db_name/query",
+            post({
+                let registry = registry.clone();
+                move |db_name, params| post_query_hits(db_name, params, registry, max_seq_length)
+            }),
+        )
+        .route(
+            "/api/v1/db/:db_name/shmmr_pair_counts",
+            get({
+                let registry = registry.clone();
+                move |db_name, params| {
+                    get_shmmr_pair_counts(db_name, params, registry, max_seq_length)
+                }
+            }),
+        )
+        .route("/api/v1/openapi.json", get(get_openapi_json))
+}
+
+fn get_ctg_info(seq_db: &SeqIndexDB) -> Vec<ContigInfo> {
+    let mut ctgs = seq_db
+        .seq_info
+        .as_ref()
+        .map(|seq_info| {
+            seq_info
+                .iter()
+                .map(|(sid, (ctg, sample, length))| ContigInfo {
+                    sid: *sid,
+                    sample: sample.clone().unwrap_or_default(),
+                    ctg: ctg.clone(),
+                    length: *length,
+                })
+                .collect::<Vec<_>>()
+        })
+        .unwrap_or_default();
+    ctgs.sort_by_key(|c| c.sid);
+    ctgs
+}
+
+/// check the range in a contig and its length limit, return (sid, ctg_len, bgn, end)
+fn check_seq_range(
+    seq_db: &SeqIndexDB,
+    sample: &str,
+    ctg: &str,
+    (bgn, end): (Option<usize>, Option<usize>),
+    max_length: usize,
+) -> Result<(u32, u32, usize, usize), ApiError> {
+    let &(sid, ctg_len) = seq_db
+        .seq_index
+        .as_ref()
+        .and_then(|seq_index| seq_index.get(&(ctg.to_string(), Some(sample.to_string()))))
+        .ok_or_else(|| ApiError::not_found(format!("contig {} not found in {}", ctg, sample)))?;
+    let bgn = bgn.unwrap_or(0);
+    let end = end.unwrap_or(ctg_len as usize);
+    if bgn >= end || end > ctg_len as usize {
+        return Err(ApiError::bad_request(format!(
+            "invalid range {}-{} for contig {} of length {}",
+            bgn, end, ctg, ctg_len
+        )));
+    }
+    check_seq_length(end - bgn, max_length)?;
+    Ok((sid, ctg_len, bgn, end))
+}
+
+fn check_seq_length(length: usize, max_length: usize) -> Result<(), ApiError> {
+    if length > max_length {
+        return Err(ApiError::bad_request(format!(
+            "the sequence length {} is larger than the limit {}",
+            length, max_length
+        )));
+    }
+    Ok(())
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/db/{db_name}/samples",
+    tag = "pgr-server",
+    params(("db_name" = String, Path, description = "the database name")),
+    responses(
+        (status = 200, description = "the samples with the number of the contigs and the total length", body = [SampleInfo]),
+        (status = 404, description = "database not found", body = ApiErrorMessage)
+    )
+)]
+pub async fn get_samples(
+    Path(db_name): Path<String>,
+    registry: Arc<DatabaseRegistry>,
+) -> Result<Json<Vec<SampleInfo>>, ApiError> {
+    let seq_db = registry.get(&db_name).await?.seq_db.clone();
+    let mut samples = FxHashMap::<String, (usize, u64)>::default();
+    get_ctg_info(&seq_db).into_iter().for_each(|c| {
+        let e = samples.entry(c.sample).or_default();
+        e.0 += 1;
+        e.1 += c.length as u64;
+    });
+    let mut samples = samples
+        .into_iter()
+        .map(|(sample, (n_ctgs, total_length))| SampleInfo {
+            sample,
+            n_ctgs,
+            total_length,
+        })
+        .collect::<Vec<_>>();
+    samples.sort_by(|a, b| a.sample.cmp(&b.sample));
+    Ok(Json(samples))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/db/{db_name}/ctgs",
+    tag = "pgr-server",
+    params(("db_name" = String, Path, description = "the database name"), ContigListQuery),
+    responses(
+        (status = 200, description = "the contigs with the lengths ordered by the sequence id", body = [ContigInfo]),
+        (status = 404, description = "database not found", body = ApiErrorMessage)
+    )
+)]
+pub async fn get_ctgs(
+    Path(db_name): Path<String>,
+    Query(query): Query<ContigListQuery>,
+    registry: Arc<DatabaseRegistry>,
+) -> Result<Json<Vec<ContigInfo>>, ApiError> {
+    let seq_db = registry.get(&db_name).await?.seq_db.clone();
+    let ctgs = get_ctg_info(&seq_db)
+        .into_iter()
+        .filter(|c| match query.sample.as_ref() {
+            Some(sample) => c.sample == *sample,
+            None => true,
+        })
+        .collect::<Vec<_>>();
+    Ok(Json(ctgs))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/db/{db_name}/seq",
+    tag = "pgr-server",
+    params(("db_name" = String, Path, description = "the database name"), SeqRangeQuery),
+    responses(
+        (status = 200, description = "the sub-sequence in the FASTA format", body = String, content_type = "text/plain"),
+        (status = 400, description = "invalid range or the range is longer than the limit", body = ApiErrorMessage),
+        (status = 404, description = "database or contig not found", body = ApiErrorMessage)
+    )
+)]
+pub async fn get_sub_seq_fasta(
+    Path(db_name): Path<String>,
+    Query(query): Query<SeqRangeQuery>,
+    registry: Arc<DatabaseRegistry>,
+    max_seq_length: usize,
+) -> Result<Response, ApiError> {
+    let seq_db = registry.get(&db_name).await?.seq_db.clone();
+    let (_sid, _ctg_len, bgn, end) = check_seq_range(
+        &seq_db,
+        &query.sample,
+        &query.ctg,
+        (query.bgn, query.end),
+        max_seq_length,
+    )?;
+    let fasta = tokio::task::spawn_blocking(move || -> Result<String, ApiError> {
+        let seq = seq_db.get_sub_seq(query.sample.clone(), query.ctg.clone(), bgn, end)?;
+        Ok(format!(
+            ">{}::{}_{}_{}\n{}\n",
+            query.sample,
+            query.ctg,
+            bgn,
+            end,
+            String::from_utf8_lossy(&seq)
+        ))
+    })
+    .await??;
+    Ok(([(header::CONTENT_TYPE, "text/plain")], fasta).into_response())
+}
+
+fn hits_to_bed(q_name: &str, hits: &[QueryHit]) -> String {
+    hits.iter()
+        .map(|h| {
+            format!(
+                "{}\t{}\t{}\t{}:{}-{}\t{}\t{}\n",
+                h.ctg,
+                h.t_bgn,
+                h.t_end,
+                q_name,
+                h.q_bgn,
+                h.q_end,
+                h.anchor_count,
+                if h.orientation == 0 { '+' } else { '-' }
+            )
+        })
+        .collect()
+}
+
+fn hits_to_paf(q_name: &str, q_len: usize, hits: &[QueryHit]) -> String {
+    hits.iter()
+        .map(|h| {
+            let block_len = (h.q_end - h.q_bgn).max(h.t_end - h.t_bgn);
+            format!(
+                "{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t255\tcm:i:{}\n",
+                q_name,
+                q_len,
+                h.q_bgn,
+                h.q_end,
+                if h.orientation == 0 { '+' } else { '-' },
+                h.ctg,
+                h.ctg_len,
+                h.t_bgn,
+                h.t_end,
+                h.q_covered,
+                block_len,
+                h.anchor_count
+            )
+        })
+        .collect()
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/db/{db_name}/query",
+    tag = "pgr-server",
+    params(("db_name" = String, Path, description = "the database name")),
+    request_body = HitQuery,
+    responses(
+        (status = 200, description = "the hits as JSON, or as BED or PAF text with `format`", body = [QueryHit]),
+        (status = 400, description = "invalid query or the query is longer than the limit", body = ApiErrorMessage),
+        (status = 404, description = "database or contig not found", body = ApiErrorMessage)
+    )
+)]
+pub async fn post_query_hits(
+    Path(db_name): Path<String>,
+    Json(query): Json<HitQuery>,
+    registry: Arc<DatabaseRegistry>,
+    max_seq_length: usize,
+) -> Result<Response, ApiError> {
+    let seq_db = registry.get(&db_name).await?.seq_db.clone();
+    let q_range = match (&query.seq, &query.sample, &query.ctg) {
+        (Some(seq), _, _) => {
+            check_seq_length(seq.len(), max_seq_length)?;
+            None
+        }
+        (None, Some(sample), Some(ctg)) => {
+            let (_sid, _ctg_len, bgn, end) =
+                check_seq_range(&seq_db, sample, ctg, (query.bgn, query.end), max_seq_length)?;
+            Some((bgn, end))
+        }
+        _ => {
+            return Err(ApiError::bad_request(
+                "either `seq` or `sample` and `ctg` should be specified",
+            ))
+        }
+    };
+    if seq_db.shmmr_spec.is_none() {
+        return Err(ApiError::bad_request("the database has no shimmer index"));
+    }
+    tokio::task::spawn_blocking(move || query_hits(&seq_db, &query, q_range)).await?
+}
+
+// fetch the query sequence and map it to the database, `q_range` is the range of the contig in the query if the
+// sequence is not given
+fn query_hits(
+    seq_db: &SeqIndexDB,
+    query: &HitQuery,
+    q_range: Option<(usize, usize)>,
+) -> Result<Response, ApiError> {
+    let (q_name, q_seq) = match (&query.seq, &query.sample, &query.ctg, q_range) {
+        (None, Some(sample), Some(ctg), Some((bgn, end))) => (
+            query
+                .name
+                .clone()
+                .unwrap_or_else(|| format!("{}::{}_{}_{}", sample, ctg, bgn, end)),
+            seq_db.get_sub_seq(sample.clone(), ctg.clone(), bgn, end)?,
+        ),
+        _ => (
+            query.name.clone().unwrap_or_else(|| "query".to_string()),
+            query
+                .seq
+                .as_deref()
+                .unwrap_or_default()
+                .as_bytes()
+                .to_ascii_uppercase(),
+        ),
+    };
+
+    let query_results = if seq_db.get_shmmr_map_internal().is_some() {
+        seq_db.query_fragment_to_hps(
+            &q_seq,
+            query.gap_penalty_factor,
+            Some(query.max_count),
+            Some(query.max_query_count),
+            Some(query.max_target_count),
+            Some(query.max_aln_chain_span),
+            None,
+            false,
+        )
+    } else {
+        seq_db.query_fragment_to_hps_from_mmap_file(
+            &q_seq,
+            query.gap_penalty_factor,
+            Some(query.max_count),
+            Some(query.max_query_count),
+            Some(query.max_target_count),
+            Some(query.max_aln_chain_span),
+            None,
+            false,
+        )
+    };
+
+    let seq_info = seq_db.seq_info.as_ref();
+    let mut hits = query_results
+        .unwrap_or_default()
+        .into_iter()
+        .flat_map(|(sid, alns)| {
+            let (ctg, sample, ctg_len) = seq_info
+                .and_then(|seq_info| seq_info.get(&sid))
+                .cloned()
+                .unwrap_or_default();
+            alns.into_iter()
+                .filter(|(_score, aln)| !aln.is_empty())
+                .map(|(score, mut aln)| {
+                    let f_count = aln.iter().filter(|hp| hp.0 .2 == hp.1 .2).count();
+                    let orientation = if f_count * 2 > aln.len() { 0 } else { 1 };
+                    aln.sort();
+                    let q_bgn = aln.iter().map(|hp| hp.0 .0).min().unwrap();
+                    let q_end = aln.iter().map(|hp| hp.0 .1).max().unwrap();
+                    let t_bgn = aln.iter().map(|hp| hp.1 .0).min().unwrap();
+                    let t_end = aln.iter().map(|hp| hp.1 .1).max().unwrap();
+                    // the union of the anchor ranges on the query
+                    let (q_covered, _) =
+                        aln.iter()
+                            .fold((0_u32, 0_u32), |(covered, covered_end), hp| {
+                                let bgn = hp.0 .0.max(covered_end);
+                                let end = hp.0 .1.max(covered_end);
+                                (covered + end - bgn, end)
+                            });
+                    QueryHit {
+                        sid,
+                        sample: sample.clone().unwrap_or_default(),
+                        ctg: ctg.clone(),
+                        ctg_len,
+                        t_bgn,
+                        t_end,
+                        q_bgn,
+                        q_end,
+                        orientation,
+                        anchor_count: aln.len(),
+                        q_covered,
+                        score,
+                    }
+                })
+                .collect::<Vec<_>>()
+        })
+        .collect::<Vec<_>>();
+    hits.sort_by_key(|h| (h.sid, h.t_bgn, h.t_end));
+
+    let response = match query.format {
+        HitFormat::Json => Json(hits).into_response(),
+        HitFormat::Bed => (
+            [(header::CONTENT_TYPE, "text/plain")],
+            hits_to_bed(&q_name, &hits),
+        )
+            .into_response(),
+        HitFormat::Paf => (
+            [(header::CONTENT_TYPE, "text/plain")],
+            hits_to_paf(&q_name, q_seq.len(), &hits),
+        )
+            .into_response(),
+    };
+    Ok(response)
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/db/{db_name}/shmmr_pair_counts",
+    tag = "pgr-server",
+    params(("db_name" = String, Path, description = "the database name"), SeqRangeQuery),
+    responses(
+        (status = 200, description = "the shimmer pairs in the range with the number of the indexed fragments", body = [ShmmrPairCount]),
+        (status = 400, description = "invalid range or the range is longer than the limit", body = ApiErrorMessage),
+        (status = 404, description = "database or contig not found", body = ApiErrorMessage)
+    )
+)]
+pub async fn get_shmmr_pair_counts(
+    Path(db_name): Path<String>,
+    Query(query): Query<SeqRangeQuery>,
+    registry: Arc<DatabaseRegistry>,
+    max_seq_length: usize,
+) -> Result<Json<Vec<ShmmrPairCount>>, ApiError> {
+    let seq_db = registry.get(&db_name).await?.seq_db.clone();
+    let (_sid, _ctg_len, bgn, end) = check_seq_range(
+        &seq_db,
+        &query.sample,
+        &query.ctg,
+        (query.bgn, query.end),
+        max_seq_length,
+    )?;
+    let counts = tokio::task::spawn_blocking(move || -> Result<_, ApiError> {
+        let seq = seq_db.get_sub_seq(query.sample.clone(), query.ctg.clone(), bgn, end)?;
+        Ok(seq_db
+            .get_shmmr_pair_counts_of_seq(seq)
+            .ok_or_else(|| ApiError::bad_request("the database has no shimmer index"))?
+            .into_iter()
+            .map(
+                |(hash0, hash1, p0, p1, orientation, count)| ShmmrPairCount {
+                    hash0,
+                    hash1,
+                    bgn: p0 + bgn as u32,
+                    end: p1 + bgn as u32,
+                    orientation,
+                    count,
+                },
+            )
+            .collect::<Vec<_>>())
+    })
+    .await??;
+    Ok(Json(counts))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/openapi.json",
+    tag = "pgr-server",
+    responses(
+        (status = 200, description = "the OpenAPI document of the versioned endpoints")
+    )
+)]
+pub async fn get_openapi_json() -> Json<utoipa::openapi::OpenApi> {
+    Json(ApiDoc::openapi())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::databases::{DatabaseBackend, DatabaseConfig, DatabaseListConfig};
+    use axum::body::Body;
+    use axum::http::{Method, Request};
+    use tower::ServiceExt;
+
+    const TEST_DB_PREFIX: &str = concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/../../pgr-db/test/test_data/test_seqs_frag"
+    );
+
+    fn setup(max_seq_length: usize) -> Router {
+        let registry = Arc::new(
+            DatabaseRegistry::new(DatabaseListConfig {
+                default: None,
+                databases: vec![DatabaseConfig::new(
+                    "test".to_string(),
+                    DatabaseBackend::Frg,
+                    TEST_DB_PREFIX.to_string(),
+                )],
+            })
+            .unwrap(),
+        );
+        api_v1_router(registry, max_seq_length)
+    }
+
+    async fn send(
+        app: &Router,
+        method: Method,
+        uri: &str,
+        body: Option<serde_json::Value>,
+    ) -> (StatusCode, Vec<u8>) {
+        let request = Request::builder().method(method).uri(uri);
+        let request = match body {
+            Some(body) => request
+                .header("content-type", "application/json")
+                .body(Body::from(serde_json::to_vec(&body).unwrap())),
+            None => request.body(Body::empty()),
+        }
+        .unwrap();
+        let res = app.clone().oneshot(request).await.unwrap();
+        let status = res.status();
+        let body = hyper::body::to_bytes(res.into_body()).await.unwrap();
+        (status, body.to_vec())
+    }
+
+    fn encode(value: &str) -> String {
+        value
+            .bytes()
+            .map(|b| match b {
+                b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' => {
+                    (b as char).to_string()
+                }
+                _ => format!("%{:02X}", b),
+            })
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_seq_length_limit() {
+        let app = setup(1000);
+        let (status, body) = send(&app, Method::GET, "/api/v1/db/test/ctgs", None).await;
+        assert_eq!(status, StatusCode::OK);
+        let ctgs: Vec<ContigInfo> = serde_json::from_slice(&body).unwrap();
+        let ctg = &ctgs[0];
+        let uri = |end: usize| {
+            format!(
+                "/api/v1/db/test/seq?sample={}&ctg={}&bgn=0&end={}",
+                encode(&ctg.sample),
+                encode(&ctg.ctg),
+                end
+            )
+        };
+
+        let (status, body) = send(&app, Method::GET, &uri(1000), None).await;
+        assert_eq!(status, StatusCode::OK);
+        let fasta = String::from_utf8(body).unwrap();
+        assert_eq!(fasta.lines().nth(1).unwrap().len(), 1000);
+        let (status, _) = send(&app, Method::GET, &uri(1001), None).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+
+        let query = |bgn: usize, end: usize| serde_json::json!({"sample": ctg.sample, "ctg": ctg.ctg, "bgn": bgn, "end": end});
+        let (status, body) = send(
+            &app,
+            Method::POST,
+            "/api/v1/db/test/query",
+            Some(query(0, 1000)),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        let hits: Vec<QueryHit> = serde_json::from_slice(&body).unwrap();
+        assert!(hits.iter().any(|h| h.sid == ctg.sid));
+        let (status, _) = send(
+            &app,
+            Method::POST,
+            "/api/v1/db/test/query",
+            Some(query(0, 2000)),
+        )
+        .await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        let seq = "A".repeat(1001);
+        let (status, _) = send(
+            &app,
+            Method::POST,
+            "/api/v1/db/test/query",
+            Some(serde_json::json!({ "seq": seq })),
+        )
+        .await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+    }
+}
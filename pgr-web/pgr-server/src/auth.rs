@@ -0,0 +1,721 @@
+use std::fs::{File, OpenOptions};
+use std::io::{Error, Write};
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+use axum::{
+    body::Body,
+    extract::ConnectInfo,
+    http::{header, HeaderValue, Request, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use hmac::{Hmac, Mac};
+use rustc_hash::{FxHashMap, FxHashSet};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::api::ApiError;
+
+/// The configuration of the authentication, the access control, the rate limiting and the audit logging, e.g.
+/// ```json
+/// {
+///     "api_keys": [
+///         {"name": "alice", "key_sha256": "<the hex SHA-256 digest of the key>", "databases": ["hprc-y1"]},
+///         {"name": "ops", "key_sha256": "<the hex SHA-256 digest of the key>", "databases": ["*"], "admin": true}
+///     ],
+///     "token_secret": "<the HMAC secret of the HS256 signed tokens>",
+///     "public_databases": ["t2t"],
+///     "rate_limit": {"requests_per_minute": 600, "burst": 100},
+///     "audit_log": "/var/log/pgr-server/audit.jsonl",
+///     "allowed_origins": ["https://pgr-tk.example.org"]
+/// }
+/// ```
+/// A database list may contain `"*"` for all databases.
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct AuthConfig {
+    #[serde(default)]
+    pub api_keys: Vec<ApiKeyConfig>,
+    /// the secret for verifying the HS256 signed tokens (JWT) with the claims `sub`, `databases`, optional `exp` and `admin`
+    pub token_secret: Option<String>,
+    /// the databases readable without the credentials
+    #[serde(default)]
+    pub public_databases: Vec<String>,
+    pub rate_limit: Option<RateLimitConfig>,
+    /// the file that the audit records are appended to as JSON lines
+    pub audit_log: Option<String>,
+    /// the origins allowed by the CORS policy, all origins are allowed if it is not set
+    pub allowed_origins: Option<Vec<String>>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct ApiKeyConfig {
+    pub name: String,
+    /// the hex SHA-256 digest of the key, so the keys are not stored in the configuration file
+    pub key_sha256: String,
+    pub databases: Vec<String>,
+    /// an admin can see and cancel the jobs of the other users
+    #[serde(default)]
+    pub admin: bool,
+}
+
+/// The token bucket of each client, a client is an authenticated user or an IP address
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct RateLimitConfig {
+    pub requests_per_minute: u32,
+    pub burst: u32,
+}
+
+#[derive(Deserialize)]
+struct TokenHeader {
+    alg: String,
+}
+
+#[derive(Deserialize)]
+struct TokenClaims {
+    sub: String,
+    #[serde(default)]
+    databases: Vec<String>,
+    exp: Option<u64>,
+    #[serde(default)]
+    admin: bool,
+}
+
+/// The client of a request, it is inserted into the request extensions by `auth_middleware`
+#[derive(Clone, Debug)]
+pub struct Identity {
+    /// the user name, `None` for an anonymous client
+    pub name: Option<String>,
+    databases: FxHashSet<String>,
+    pub admin: bool,
+}
+
+impl Identity {
+    /// the identity used when the authentication is not configured
+    pub fn unrestricted() -> Self {
+        Identity {
+            name: None,
+            databases: ["*".to_string()].into_iter().collect(),
+            admin: false,
+        }
+    }
+
+    pub(crate) fn new(
+        name: Option<String>,
+        databases: &[String],
+        public_databases: &[String],
+        admin: bool,
+    ) -> Self {
+        Identity {
+            name,
+            admin,
+            databases: databases
+                .iter()
+                .chain(public_databases.iter())
+                .cloned()
+                .collect(),
+        }
+    }
+
+    pub fn can_read(&self, db_name: &str) -> bool {
+        self.databases.contains("*") || self.databases.contains(db_name)
+    }
+
+    /// whether a resource created by `owner` is accessible, the anonymous clients share their resources
+    pub fn can_access(&self, owner: Option<&str>) -> bool {
+        self.admin || self.name.as_deref() == owner
+    }
+}
+
+#[derive(Serialize)]
+struct AuditRecord<'a> {
+    time: f64,
+    client: &'a str,
+    user: Option<&'a str>,
+    method: &'a str,
+    path: &'a str,
+    query: Option<String>,
+    database: Option<&'a str>,
+    /// the `source`/`sample`, `ctg`, `bgn`, `end` and `padding` of a JSON request body
+    region: Option<serde_json::Value>,
+    status: u16,
+}
+
+pub struct AuthState {
+    config: AuthConfig,
+    default_db: String,
+    /// the API keys by the digests
+    api_keys: FxHashMap<String, ApiKeyConfig>,
+    /// client -> (tokens, last refill)
+    buckets: Mutex<FxHashMap<String, (f64, Instant)>>,
+    audit_log: Option<Mutex<File>>,
+}
+
+/// the largest JSON request body parsed for the audit records
+const MAX_AUDITED_BODY_SIZE: u64 = 1 << 20;
+
+fn get_sha256_hex(key: &str) -> String {
+    Sha256::digest(key.as_bytes())
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+impl AuthState {
+    pub fn new(config: AuthConfig, default_db: String) -> Result<Self, Error> {
+        let api_keys = config
+            .api_keys
+            .iter()
+            .map(|key| (key.key_sha256.to_lowercase(), key.clone()))
+            .collect();
+        let audit_log = match config.audit_log.as_ref() {
+            Some(path) => Some(Mutex::new(
+                OpenOptions::new().create(true).append(true).open(path)?,
+            )),
+            None => None,
+        };
+        Ok(AuthState {
+            config,
+            default_db,
+            api_keys,
+            buckets: Mutex::new(FxHashMap::default()),
+            audit_log,
+        })
+    }
+
+    pub fn from_config_file(path: &str, default_db: String) -> Result<Self, Error> {
+        let config: AuthConfig = serde_json::from_slice(&std::fs::read(path)?)?;
+        Self::new(config, default_db)
+    }
+
+    pub fn allowed_origins(&self) -> Option<Vec<HeaderValue>> {
+        self.config.allowed_origins.as_ref().map(|origins| {
+            origins
+                .iter()
+                .filter_map(|origin| origin.parse::<HeaderValue>().ok())
+                .collect()
+        })
+    }
+
+    fn verify_token(&self, token: &str) -> Result<Identity, String> {
+        let secret = self
+            .config
+            .token_secret
+            .as_ref()
+            .ok_or_else(|| "the signed tokens are not accepted".to_string())?;
+        let parts = token.split('.').collect::<Vec<_>>();
+        if parts.len() != 3 {
+            return Err("malformed token".to_string());
+        }
+        let decode = |part: &str| {
+            URL_SAFE_NO_PAD
+                .decode(part)
+                .map_err(|_| "malformed token".to_string())
+        };
+        let header: TokenHeader =
+            serde_json::from_slice(&decode(parts[0])?).map_err(|_| "malformed token")?;
+        if header.alg != "HS256" {
+            return Err(format!("unsupported token algorithm {}", header.alg));
+        }
+        let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).unwrap();
+        mac.update(parts[0].as_bytes());
+        mac.update(b".");
+        mac.update(parts[1].as_bytes());
+        mac.verify_slice(&decode(parts[2])?)
+            .map_err(|_| "invalid token signature".to_string())?;
+        let claims: TokenClaims =
+            serde_json::from_slice(&decode(parts[1])?).map_err(|_| "malformed token claims")?;
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+        if matches!(claims.exp, Some(exp) if exp <= now) {
+            return Err("the token is expired".to_string());
+        }
+        Ok(Identity::new(
+            Some(claims.sub),
+            &claims.databases,
+            &self.config.public_databases,
+            claims.admin,
+        ))
+    }
+
+    /// authenticate a credential, a signed token has three `.` separated parts, otherwise it is an API key
+    fn authenticate(&self, credential: Option<&str>) -> Result<Identity, String> {
+        match credential {
+            None => Ok(Identity::new(
+                None,
+                &[],
+                &self.config.public_databases,
+                false,
+            )),
+            Some(token) if token.matches('.').count() == 2 => self.verify_token(token),
+            Some(key) => self
+                .api_keys
+                .get(&get_sha256_hex(key))
+                .map(|key| {
+                    Identity::new(
+                        Some(key.name.clone()),
+                        &key.databases,
+                        &self.config.public_databases,
+                        key.admin,
+                    )
+                })
+                .ok_or_else(|| "invalid API key".to_string()),
+        }
+    }
+
+    /// take a token from the bucket of the client, return the seconds to wait if the bucket is empty
+    fn check_rate_limit(&self, client: &str) -> Result<(), u64> {
+        let limit = match self.config.rate_limit.as_ref() {
+            Some(limit) => limit,
+            None => return Ok(()),
+        };
+        let rate = limit.requests_per_minute as f64 / 60.0;
+        let burst = limit.burst.max(1) as f64;
+        let now = Instant::now();
+        let mut buckets = self.buckets.lock().unwrap();
+        if buckets.len() > 100_000 {
+            // drop the buckets that are full again
+            buckets.retain(|_, (tokens, t)| {
+                *tokens + now.duration_since(*t).as_secs_f64() * rate < burst
+            });
+        }
+        let (tokens, t) = buckets.entry(client.to_string()).or_insert((burst, now));
+        *tokens = (*tokens + now.duration_since(*t).as_secs_f64() * rate).min(burst);
+        *t = now;
+        if *tokens >= 1.0 {
+            *tokens -= 1.0;
+            Ok(())
+        } else if rate > 0.0 {
+            Err(((1.0 - *tokens) / rate).ceil() as u64)
+        } else {
+            Err(60)
+        }
+    }
+
+    fn write_audit_record(&self, record: &AuditRecord) {
+        if let Some(audit_log) = self.audit_log.as_ref() {
+            if let Ok(mut line) = serde_json::to_vec(record) {
+                line.push(b'\n');
+                let _ = audit_log.lock().unwrap().write_all(&line);
+            }
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct CredentialQuery {
+    access_token: Option<String>,
+    db: Option<String>,
+}
+
+/// the database name of a request, it is in the path of the versioned routes, or it is the `db` query parameter
+/// of the unversioned routes with the default database as the default
+fn get_requested_database(
+    path: &str,
+    query: &CredentialQuery,
+    default_db: &str,
+) -> Result<Option<String>, ApiError> {
+    if let Some(rest) = path.strip_prefix("/api/v1/db/") {
+        let db_name = rest.split('/').next().unwrap_or_default();
+        // the path is decoded by the router, so an encoded name could pass the check with another name
+        if !db_name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '.' || c == '_' || c == '-')
+        {
+            return Err(ApiError::bad_request(format!(
+                "invalid database name `{}`",
+                db_name
+            )));
+        }
+        Ok(Some(db_name.to_string()))
+    } else if path.starts_with("/api/") && !path.starts_with("/api/v1/") {
+        Ok(Some(
+            query.db.clone().unwrap_or_else(|| default_db.to_string()),
+        ))
+    } else {
+        Ok(None)
+    }
+}
+
+/// remove the access token from the query string written to the audit log
+fn redact_query(query: &str) -> String {
+    query
+        .split('&')
+        .filter(|kv| !kv.starts_with("access_token="))
+        .collect::<Vec<_>>()
+        .join("&")
+}
+
+/// the region fields of a JSON request body
+fn get_region(body: &[u8]) -> Option<serde_json::Value> {
+    let value: serde_json::Value = serde_json::from_slice(body).ok()?;
+    let object = value.as_object()?;
+    let region = ["source", "sample", "ctg", "bgn", "end", "padding"]
+        .iter()
+        .filter_map(|&k| object.get(k).map(|v| (k.to_string(), v.clone())))
+        .collect::<serde_json::Map<_, _>>();
+    if region.is_empty() {
+        None
+    } else {
+        Some(serde_json::Value::Object(region))
+    }
+}
+
+/// authenticate the client, apply the rate limit, check the read permission of the requested database and
+/// write the audit record of the request, the identity of the client is inserted into the request extensions
+pub async fn auth_middleware(
+    req: Request<Body>,
+    next: Next<Body>,
+    auth: Arc<AuthState>,
+) -> Response {
+    let path = req.uri().path().to_string();
+    if !path.starts_with("/api/") && path != "/ws" {
+        // the static files
+        let mut req = req;
+        req.extensions_mut().insert(Identity::unrestricted());
+        return next.run(req).await;
+    }
+
+    let query_string = req.uri().query().map(|q| q.to_string());
+    // parsed as the `Query` extractor does, so the handlers see the same database
+    let query = match serde_urlencoded::from_str::<CredentialQuery>(
+        query_string.as_deref().unwrap_or(""),
+    ) {
+        Ok(query) => query,
+        Err(err) => return ApiError::bad_request(err.to_string()).into_response(),
+    };
+    let header_credential = req
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "))
+        .or_else(|| req.headers().get("x-api-key").and_then(|v| v.to_str().ok()))
+        .map(|v| v.trim().to_string());
+    let credential = header_credential.or_else(|| query.access_token.clone());
+    let addr = req
+        .extensions()
+        .get::<ConnectInfo<SocketAddr>>()
+        .map_or_else(|| "unknown".to_string(), |info| info.0.ip().to_string());
+    let method = req.method().to_string();
+
+    let identity = auth.authenticate(credential.as_deref());
+    let database = get_requested_database(&path, &query, &auth.default_db);
+    // the denied requests are audited with the requested database too
+    let user = identity.as_ref().ok().and_then(|i| i.name.clone());
+    let requested_database = database.as_ref().ok().cloned().flatten();
+    let client = match identity.as_ref() {
+        Ok(Identity {
+            name: Some(name), ..
+        }) => format!("user:{}", name),
+        _ => format!("ip:{}", addr),
+    };
+
+    let checked = match (identity, database) {
+        (Err(err), _) => Err(ApiError(StatusCode::UNAUTHORIZED, err)),
+        (_, Err(err)) => Err(err),
+        (Ok(identity), Ok(database)) => match auth.check_rate_limit(&client) {
+            Err(wait) => Err(ApiError(
+                StatusCode::TOO_MANY_REQUESTS,
+                format!("too many requests, please retry after {} seconds", wait),
+            )),
+            Ok(()) => match database {
+                Some(db_name) if !identity.can_read(&db_name) => {
+                    if identity.name.is_none() {
+                        Err(ApiError(
+                            StatusCode::UNAUTHORIZED,
+                            format!("database {} requires the credentials", db_name),
+                        ))
+                    } else {
+                        Err(ApiError(
+                            StatusCode::FORBIDDEN,
+                            format!("no read permission on database {}", db_name),
+                        ))
+                    }
+                }
+                _ => Ok(identity),
+            },
+        },
+    };
+
+    let (region, response) = match checked {
+        Err(err) => (None, err.into_response()),
+        Ok(identity) => {
+            let mut req = req;
+            let audit_body = auth.audit_log.is_some()
+                && req
+                    .headers()
+                    .get(header::CONTENT_LENGTH)
+                    .and_then(|v| v.to_str().ok())
+                    .and_then(|v| v.parse::<u64>().ok())
+                    .is_some_and(|len| len <= MAX_AUDITED_BODY_SIZE);
+            let region = if audit_body {
+                let (parts, body) = req.into_parts();
+                let bytes = hyper::body::to_bytes(body).await.unwrap_or_default();
+                let region = get_region(&bytes);
+                req = Request::from_parts(parts, Body::from(bytes));
+                region
+            } else {
+                None
+            };
+            req.extensions_mut().insert(identity);
+            (region, next.run(req).await)
+        }
+    };
+
+    let mut response = response;
+    if response.status() == StatusCode::UNAUTHORIZED {
+        response
+            .headers_mut()
+            .insert(header::WWW_AUTHENTICATE, HeaderValue::from_static("Bearer"));
+    }
+    auth.write_audit_record(&AuditRecord {
+        time: SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs_f64(),
+        client: &addr,
+        user: user.as_deref(),
+        method: &method,
+        path: &path,
+        query: query_string.as_deref().map(redact_query),
+        database: requested_database.as_deref(),
+        region,
+        status: response.status().as_u16(),
+    });
+    response
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use axum::{extract::Extension, middleware, routing::get, Router};
+    use std::time::Duration;
+    use tower::ServiceExt;
+
+    const SECRET: &str = "test-secret";
+    const API_KEY: &str = "test-api-key";
+
+    fn test_config() -> AuthConfig {
+        AuthConfig {
+            api_keys: vec![ApiKeyConfig {
+                name: "alice".to_string(),
+                key_sha256: get_sha256_hex(API_KEY).to_uppercase(),
+                databases: vec!["private".to_string()],
+                admin: false,
+            }],
+            token_secret: Some(SECRET.to_string()),
+            public_databases: vec!["public".to_string()],
+            rate_limit: None,
+            audit_log: None,
+            allowed_origins: None,
+        }
+    }
+
+    fn sign_token(secret: &str, header: &str, claims: &str) -> String {
+        let header = URL_SAFE_NO_PAD.encode(header);
+        let claims = URL_SAFE_NO_PAD.encode(claims);
+        let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).unwrap();
+        mac.update(format!("{}.{}", header, claims).as_bytes());
+        let signature = URL_SAFE_NO_PAD.encode(mac.finalize().into_bytes());
+        format!("{}.{}.{}", header, claims, signature)
+    }
+
+    #[test]
+    fn test_verify_token() {
+        let auth = AuthState::new(test_config(), "public".to_string()).unwrap();
+        let header = r#"{"alg":"HS256","typ":"JWT"}"#;
+        let claims = r#"{"sub":"bob","databases":["private"]}"#;
+
+        let identity = auth
+            .verify_token(&sign_token(SECRET, header, claims))
+            .unwrap();
+        assert_eq!(identity.name.as_deref(), Some("bob"));
+        assert!(identity.can_read("private"));
+        assert!(identity.can_read("public"));
+        assert!(!identity.can_read("other"));
+        assert!(!identity.admin);
+        assert!(identity.can_access(Some("bob")));
+        assert!(!identity.can_access(Some("alice")));
+        let admin_claims = r#"{"sub":"carol","databases":["*"],"admin":true}"#;
+        let identity = auth
+            .verify_token(&sign_token(SECRET, header, admin_claims))
+            .unwrap();
+        assert!(identity.can_access(Some("alice")));
+        assert!(identity.can_access(None));
+
+        assert_eq!(
+            auth.verify_token(&sign_token("other-secret", header, claims))
+                .unwrap_err(),
+            "invalid token signature"
+        );
+        let expired = r#"{"sub":"bob","databases":["private"],"exp":1}"#;
+        assert_eq!(
+            auth.verify_token(&sign_token(SECRET, header, expired))
+                .unwrap_err(),
+            "the token is expired"
+        );
+        let unexpired = r#"{"sub":"bob","exp":99999999999}"#;
+        assert!(auth
+            .verify_token(&sign_token(SECRET, header, unexpired))
+            .is_ok());
+        let none_header = r#"{"alg":"none"}"#;
+        assert_eq!(
+            auth.verify_token(&sign_token(SECRET, none_header, claims))
+                .unwrap_err(),
+            "unsupported token algorithm none"
+        );
+        assert!(auth.verify_token("a.b").is_err());
+        assert!(auth.verify_token("a.b.c").is_err());
+
+        let mut config = test_config();
+        config.token_secret = None;
+        let auth = AuthState::new(config, "public".to_string()).unwrap();
+        assert!(auth
+            .verify_token(&sign_token(SECRET, header, claims))
+            .is_err());
+    }
+
+    #[test]
+    fn test_authenticate_api_key() {
+        let auth = AuthState::new(test_config(), "public".to_string()).unwrap();
+        // the digests in the configuration are case insensitive
+        let identity = auth.authenticate(Some(API_KEY)).unwrap();
+        assert_eq!(identity.name.as_deref(), Some("alice"));
+        assert!(identity.can_read("private"));
+        assert!(identity.can_read("public"));
+        assert_eq!(
+            auth.authenticate(Some("wrong-key")).unwrap_err(),
+            "invalid API key"
+        );
+
+        let identity = auth.authenticate(None).unwrap();
+        assert!(identity.name.is_none());
+        assert!(identity.can_read("public"));
+        assert!(!identity.can_read("private"));
+    }
+
+    #[test]
+    fn test_check_rate_limit() {
+        let mut config = test_config();
+        config.rate_limit = Some(RateLimitConfig {
+            requests_per_minute: 60,
+            burst: 2,
+        });
+        let auth = AuthState::new(config, "public".to_string()).unwrap();
+        assert_eq!(auth.check_rate_limit("ip:a"), Ok(()));
+        assert_eq!(auth.check_rate_limit("ip:a"), Ok(()));
+        assert_eq!(auth.check_rate_limit("ip:a"), Err(1));
+        // the buckets are per client
+        assert_eq!(auth.check_rate_limit("ip:b"), Ok(()));
+
+        // one token is refilled per second
+        auth.buckets.lock().unwrap().get_mut("ip:a").unwrap().1 -= Duration::from_millis(1500);
+        assert_eq!(auth.check_rate_limit("ip:a"), Ok(()));
+        assert_eq!(auth.check_rate_limit("ip:a"), Err(1));
+        // the bucket is not refilled beyond the burst
+        auth.buckets.lock().unwrap().get_mut("ip:a").unwrap().1 -= Duration::from_secs(60);
+        assert_eq!(auth.check_rate_limit("ip:a"), Ok(()));
+        assert_eq!(auth.check_rate_limit("ip:a"), Ok(()));
+        assert_eq!(auth.check_rate_limit("ip:a"), Err(1));
+    }
+
+    #[test]
+    fn test_get_requested_database() {
+        let query = |db: Option<&str>| CredentialQuery {
+            access_token: None,
+            db: db.map(|db| db.to_string()),
+        };
+        let get_db = |path: &str, db: Option<&str>| {
+            get_requested_database(path, &query(db), "default").map_err(|err| err.0)
+        };
+        assert_eq!(
+            get_db("/api/v1/db/hprc-y1/samples", Some("other")),
+            Ok(Some("hprc-y1".to_string()))
+        );
+        assert_eq!(
+            get_db("/api/v1/db/hprc_y1.v2", None),
+            Ok(Some("hprc_y1.v2".to_string()))
+        );
+        assert_eq!(
+            get_db("/api/v1/db/a%2Fb/samples", None),
+            Err(StatusCode::BAD_REQUEST)
+        );
+        assert_eq!(
+            get_db("/api/query_sdb", Some("other")),
+            Ok(Some("other".to_string()))
+        );
+        assert_eq!(
+            get_db("/api/query_sdb", None),
+            Ok(Some("default".to_string()))
+        );
+        assert_eq!(get_db("/api/v1/databases", None), Ok(None));
+        assert_eq!(get_db("/ws", None), Ok(None));
+    }
+
+    #[tokio::test]
+    async fn test_auth_middleware() {
+        let audit_log = std::env::temp_dir().join("test_auth_middleware_audit.jsonl");
+        let _ = std::fs::remove_file(&audit_log);
+        let mut config = test_config();
+        config.audit_log = Some(audit_log.to_str().unwrap().to_string());
+        let auth = Arc::new(AuthState::new(config, "public".to_string()).unwrap());
+        let app = Router::new()
+            .route(
+                "/api/v1/db/:db_name/samples",
+                get(|Extension(identity): Extension<Identity>| async move {
+                    identity.name.unwrap_or_default()
+                }),
+            )
+            .layer(middleware::from_fn(move |req, next| {
+                auth_middleware(req, next, auth.clone())
+            }));
+        let send = |uri: &str, key: Option<&str>| {
+            let request = Request::builder().uri(uri);
+            let request = match key {
+                Some(key) => request.header("x-api-key", key),
+                None => request,
+            };
+            app.clone().oneshot(request.body(Body::empty()).unwrap())
+        };
+
+        let res = send("/api/v1/db/public/samples", None).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        let res = send("/api/v1/db/private/samples", None).await.unwrap();
+        assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+        assert_eq!(res.headers()[header::WWW_AUTHENTICATE], "Bearer");
+        let res = send("/api/v1/db/private/samples", Some(API_KEY))
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        let body = hyper::body::to_bytes(res.into_body()).await.unwrap();
+        assert_eq!(&body[..], b"alice");
+        let res = send("/api/v1/db/other/samples", Some(API_KEY))
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::FORBIDDEN);
+        let res = send("/api/v1/db/public/samples", Some("wrong-key"))
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+        let token = format!("/api/v1/db/private/samples?access_token={}", API_KEY);
+        let res = send(&token, None).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+
+        let records = std::fs::read_to_string(&audit_log)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str::<serde_json::Value>(line).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(records.len(), 6);
+        assert_eq!(records[1]["database"], "private");
+        assert_eq!(records[1]["status"], 401);
+        assert_eq!(records[2]["user"], "alice");
+        // the access token is not written to the audit log
+        assert_eq!(records[5]["query"], "");
+        assert_eq!(records[5]["user"], "alice");
+    }
+}
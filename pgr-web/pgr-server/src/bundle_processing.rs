@@ -1,28 +1,22 @@
-use std::io::{BufWriter, Write};
 use std::sync::Arc;
 
-use pgr_db::ext::{get_principal_bundle_decomposition, SeqIndexDB};
+use pgr_db::ext::{
+    get_principal_bundle_decomposition, group_smps_by_principal_bundle_id, PrincipalBundleDB,
+    SeqIndexDB,
+};
+use pgr_db::pbundle::{
+    self, bundle_bed_line, bundle_tracks_to_svg, get_bundle_distances, svg_to_html_string,
+    BundleDendrogram, BundleDistanceMethod, BundleDistances, BundleSegment, BundleSvgOptions,
+    BundleSvgTrack,
+};
 use rayon::prelude::*;
 use rustc_hash::FxHashMap;
 use serde::{Deserialize, Serialize};
-use svg::node::{self, element, Node};
 use svg::Document;
+use utoipa::{IntoParams, ToSchema};
+
+use crate::genes::{map_genes_to_target, GeneAnnotation, GeneBedRecord};
 
-static CMAP: [&str; 97] = [
-    "#870098", "#00aaa5", "#3bff00", "#ec0000", "#00a2c3", "#00f400", "#ff1500", "#0092dd",
-    "#00dc00", "#ff8100", "#007ddd", "#00c700", "#ffb100", "#0038dd", "#00af00", "#fcd200",
-    "#0000d5", "#009a00", "#f1e700", "#0000b1", "#00a55d", "#d4f700", "#4300a2", "#00aa93",
-    "#a1ff00", "#dc0000", "#00aaab", "#1dff00", "#f40000", "#009fcb", "#00ef00", "#ff2d00",
-    "#008ddd", "#00d700", "#ff9900", "#0078dd", "#00c200", "#ffb900", "#0025dd", "#00aa00",
-    "#f9d700", "#0000c9", "#009b13", "#efed00", "#0300aa", "#00a773", "#ccf900", "#63009e",
-    "#00aa98", "#84ff00", "#e10000", "#00a7b3", "#00ff00", "#f90000", "#009bd7", "#00ea00",
-    "#ff4500", "#0088dd", "#00d200", "#ffa100", "#005ddd", "#00bc00", "#ffc100", "#0013dd",
-    "#00a400", "#f7dd00", "#0000c1", "#009f33", "#e8f000", "#1800a7", "#00aa88", "#c4fc00",
-    "#78009b", "#00aaa0", "#67ff00", "#e60000", "#00a4bb", "#00fa00", "#fe0000", "#0098dd",
-    "#00e200", "#ff5d00", "#0082dd", "#00cc00", "#ffa900", "#004bdd", "#00b400", "#ffc900",
-    "#0000dd", "#009f00", "#f4e200", "#0000b9", "#00a248", "#dcf400", "#2d00a4", "#00aa8d",
-    "#bcff00",
-];
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct MatchSummary {
     pub q_bgn: u32,
@@ -33,12 +27,19 @@ pub struct MatchSummary {
     pub reversed: bool,
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, Clone)]
 pub struct TargetMatchPrincipalBundles {
     pub query: SequenceQuerySpec,
     pub match_summary: Vec<(u32, Vec<MatchSummary>)>, // (t_id, vec[(q_bgn, q_end, t_bgn, t_end, num_hits, reversed)])
     pub sid_ctg_src: Vec<(u32, String, String)>,
     pub bundle_bed_records: Vec<Vec<PrincipalBundleBedRecord>>,
+    /// the clustering of `bundle_bed_records` by the bundle segment distances, it is missing in the results cached by the older versions
+    #[serde(default)]
+    pub dendrogram: Option<BundleDendrogram>,
+    /// the genes of the database's gene annotation mapped onto the tracks through the hits, in the same order as `bundle_bed_records`,
+    /// it is empty if the database has no gene annotation or the query is not on the annotated reference
+    #[serde(default)]
+    pub gene_records: Vec<Vec<GeneBedRecord>>,
 }
 
 #[derive(Deserialize, Serialize, Clone)]
@@ -54,7 +55,21 @@ pub struct PrincipalBundleBedRecord {
     pub r_type: String,
 }
 
-#[derive(Clone, Debug, Serialize, Deserialize)]
+impl PrincipalBundleBedRecord {
+    pub fn to_bundle_segment(&self) -> BundleSegment {
+        BundleSegment {
+            bgn: self.bgn,
+            end: self.end,
+            bundle_id: self.b_id,
+            bundle_v_count: self.b_size as u32,
+            bundle_dir: self.b_direction,
+            bundle_v_bgn: self.b_bgn as u32,
+            bundle_v_end: self.b_end as u32,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, ToSchema)]
 pub struct ShmmrSpec {
     pub w: u32,
     pub k: u32,
@@ -63,7 +78,8 @@ pub struct ShmmrSpec {
     pub sketch: bool,
 }
 
-#[derive(Deserialize, Serialize, Debug, Clone)]
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq, ToSchema, IntoParams)]
+#[into_params(parameter_in = Query)]
 pub struct SequenceQuerySpec {
     pub source: String,
     pub ctg: String,
@@ -83,89 +99,98 @@ pub struct SequenceQuerySpec {
     pub bundle_merge_distance: usize,
 }
 
-#[allow(clippy::type_complexity)]
-fn group_smps_by_principle_bundle_id(
-    smps: &[((u64, u64, u32, u32, u8), Option<(usize, u8, usize)>)],
-    bundle_length_cutoff: usize,
-    bundle_merge_distance: usize,
-) -> Vec<Vec<((u64, u64, u32, u32, u8), usize, u32, usize)>> {
-    let mut pre_bundle_id: Option<usize> = None;
-    let mut pre_direction: Option<u32> = None;
-    let mut all_partitions = vec![];
-    let mut new_partition = vec![];
-    smps.iter().for_each(|&(smp, bundle_info)| {
-        if bundle_info.is_none() {
-            return;
-        };
-        let bundle_info = bundle_info.unwrap();
-        let d = if smp.4 == bundle_info.1 { 0_u32 } else { 1_u32 };
-        let bid = bundle_info.0;
-        let bpos = bundle_info.2;
-        if pre_bundle_id.is_none() {
-            new_partition.clear();
-            new_partition.push((smp, bid, d, bpos));
-            pre_bundle_id = Some(bid);
-            pre_direction = Some(d);
-            return;
-        };
-        if bid != pre_bundle_id.unwrap() || d != pre_direction.unwrap() {
-            let l = new_partition.len();
-            if new_partition[l - 1].0 .3 as usize - new_partition[0].0 .2 as usize
-                > bundle_length_cutoff
-            {
-                all_partitions.push(new_partition.clone());
-                new_partition.clear();
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct PrincipalBundleDecompQuery {
+    pub ctg: String,
+    pub seq: String,
+    pub bundle_length_cutoff: usize,
+    pub bundle_merge_distance: usize,
+}
+
+/// the (sid, t_bgn, t_end, orientation, ctg) of the matched target sequences
+fn get_sub_seq_ranges(
+    match_summary: &[(u32, Vec<MatchSummary>)],
+    seq_db: &SeqIndexDB,
+) -> Vec<(u32, u32, u32, u32, String)> {
+    match_summary
+        .iter()
+        .flat_map(|(sid, hits)| {
+            let (ctg, _src, _ctg_len) = seq_db.seq_info.as_ref().unwrap().get(sid).unwrap();
+            hits.iter().map(move |v| {
+                (
+                    *sid,
+                    v.t_bgn,
+                    v.t_end,
+                    if v.reversed { 1 } else { 0 },
+                    ctg.clone(),
+                )
+            })
+        })
+        .collect()
+}
+
+/// fetch the matched target sequences in the orientation of the query, they are named by the contigs
+fn get_sub_seqs(
+    sub_seq_ranges: &[(u32, u32, u32, u32, String)],
+    seq_db: &SeqIndexDB,
+) -> Vec<(String, Vec<u8>)> {
+    sub_seq_ranges
+        .par_iter()
+        .map(|(sid, b, e, orientation, target_seq_name)| {
+            let target_seq = seq_db
+                .get_sub_seq_by_id(*sid, *b as usize, *e as usize)
+                .unwrap();
+            let target_seq = if *orientation == 1 {
+                pgr_db::fasta_io::reverse_complement(&target_seq)
             } else {
-                new_partition.clear();
+                target_seq
             };
-            pre_bundle_id = Some(bid);
-            pre_direction = Some(d);
-        };
-        new_partition.push((smp, bid, d, bpos));
-    });
-    let l = new_partition.len();
-    if l > 0
-        && new_partition[l - 1].0 .3 as usize - new_partition[0].0 .2 as usize
-            > bundle_length_cutoff
-    {
-        all_partitions.push(new_partition);
-    };
-
-    let mut rtn_partitions = vec![];
+            (target_seq_name.into(), target_seq)
+        })
+        .collect::<Vec<(String, Vec<u8>)>>()
+}
 
-    if all_partitions.is_empty() {
-        return rtn_partitions;
-    }
-    let mut partition = all_partitions[0].clone();
-    (1..all_partitions.len()).for_each(|idx| {
-        let p = all_partitions[idx].clone();
-        let p_len = partition.len();
-        let p_end = partition[p_len - 1].0 .3;
-        let p_bid = partition[p_len - 1].1;
-        let p_d = partition[p_len - 1].2;
-        let np_bgn = p[0].0 .2;
-        let np_bid = p[0].1;
-        let np_d = p[0].2;
-        if p_bid == np_bid
-            && p_d == np_d
-            && (np_bgn as i64 - p_end as i64).abs() < bundle_merge_distance as i64
-        {
-            partition.extend(p);
-        } else {
-            rtn_partitions.push(partition.clone());
-            partition = p;
-        }
-    });
-    if !partition.is_empty() {
-        rtn_partitions.push(partition);
-    }
-    rtn_partitions
+/// The reason that a decomposition stops before it finishes
+#[derive(Debug, Clone)]
+pub enum DecompositionStop {
+    Cancelled,
+    LimitExceeded(String),
 }
 
 pub fn get_target_and_principal_bundle_decomposition(
     seq_query_spec: &SequenceQuerySpec,
     seq_db: Arc<SeqIndexDB>,
+    genes: Option<&GeneAnnotation>,
 ) -> Option<TargetMatchPrincipalBundles> {
+    get_target_and_principal_bundle_decomposition_with_progress(
+        seq_query_spec,
+        seq_db,
+        genes,
+        None,
+        &|_, _| true,
+    )
+    .unwrap_or(None)
+}
+
+/// The same as `get_target_and_principal_bundle_decomposition()`, but the `progress` callback is called with the fraction
+/// of the work done and the stage name between the stages, it stops the computation if it returns `false`.
+/// If `max_total_seq_length` is specified, the computation stops when the matched target sequences are longer than it in total.
+/// The genes of `genes` are mapped onto the matched sequences if the query is on the annotated reference.
+pub fn get_target_and_principal_bundle_decomposition_with_progress(
+    seq_query_spec: &SequenceQuerySpec,
+    seq_db: Arc<SeqIndexDB>,
+    genes: Option<&GeneAnnotation>,
+    max_total_seq_length: Option<usize>,
+    progress: &(dyn Fn(f32, &str) -> bool + Sync),
+) -> Result<Option<TargetMatchPrincipalBundles>, DecompositionStop> {
+    let check_progress = |fraction: f32, stage: &str| {
+        if progress(fraction, stage) {
+            Ok(())
+        } else {
+            Err(DecompositionStop::Cancelled)
+        }
+    };
+    check_progress(0.0, "fetching the query sequence")?;
     let sample_name = seq_query_spec.source.clone();
     let ctg_name = seq_query_spec.ctg.clone();
     let padding = seq_query_spec.padding;
@@ -186,16 +211,21 @@ pub fn get_target_and_principal_bundle_decomposition(
     } else {
         seq_query_spec.bgn - padding
     };
-    let q_seq_end = if seq_query_spec.end + padding > seq_len as usize {
-        seq_len as usize
-    } else {
-        seq_query_spec.end + padding
-    };
+    let q_seq_end = seq_query_spec
+        .end
+        .saturating_add(padding)
+        .min(seq_len as usize);
+
+    let query_genes = genes.map_or(vec![], |genes| {
+        genes.get_genes(&sample_name, &ctg_name, q_seq_bgn as u32, q_seq_end as u32)
+    });
 
     let sub_seq = seq_db
         .get_sub_seq(sample_name, ctg_name, q_seq_bgn, q_seq_end)
         .unwrap();
 
+    check_progress(0.05, "querying the index")?;
+
     // println!(
     //     "DBG: sub_seq_len {:?} {} {}",
     //     sub_seq.len(),
@@ -338,6 +368,7 @@ pub fn get_target_and_principal_bundle_decomposition(
         None
     };
 
+    check_progress(0.4, "summarizing the matches")?;
     let sid_ctg_src = if let Some(aln_range) = aln_range.as_ref() {
         aln_range
             .keys()
@@ -351,71 +382,79 @@ pub fn get_target_and_principal_bundle_decomposition(
         vec![]
     };
 
-    let (sub_seq_range_for_fasta, match_summary) = if let Some(aln_range) = aln_range {
-        let mut sub_seq_range_for_fasta = Vec::<(u32, u32, u32, u32, String)>::new();
-        let match_summary = aln_range
+    // the genes of the hits are in the same order as the matched sequences from `get_sub_seq_ranges()`
+    let (match_summary, hit_gene_records) = if let Some(aln_range) = aln_range {
+        let (match_summary, hit_gene_records): (Vec<_>, Vec<_>) = aln_range
             .into_iter()
             .map(|(sid, rgns)| {
                 let (ctg, _src, _ctg_len) = seq_db.seq_info.as_ref().unwrap().get(&sid).unwrap();
-                let hits = rgns
+                let (hits, gene_records): (Vec<_>, Vec<_>) = rgns
                     .into_iter()
                     .map(|(b, e, _, orientation, mut aln)| {
                         aln.sort();
                         let q_bgn = aln[0].0 .0;
                         let q_end = aln[aln.len() - 1].0 .1;
-
-                        MatchSummary {
-                            q_bgn,
-                            q_end,
-                            t_bgn: b,
-                            t_end: e,
-                            num_hits: aln.len(),
-                            reversed: orientation == 1,
-                        }
+                        let gene_records = map_genes_to_target(
+                            &query_genes,
+                            q_seq_bgn as u32,
+                            &aln,
+                            ctg,
+                            (b, e),
+                            orientation == 1,
+                        );
+
+                        (
+                            MatchSummary {
+                                q_bgn,
+                                q_end,
+                                t_bgn: b,
+                                t_end: e,
+                                num_hits: aln.len(),
+                                reversed: orientation == 1,
+                            },
+                            gene_records,
+                        )
                     })
-                    .filter(|v| {
+                    .filter(|(v, _)| {
                         (v.num_hits > 100)
                             & ((v.t_end - v.t_bgn) as f32 / (v.q_end - v.q_bgn) as f32 > 0.6)
                     })
-                    .collect::<Vec<MatchSummary>>();
-                hits.iter().for_each(|v| {
-                    sub_seq_range_for_fasta.push((
-                        sid,
-                        v.t_bgn,
-                        v.t_end,
-                        if v.reversed { 1 } else { 0 },
-                        ctg.clone(),
-                    ))
-                });
-                (sid, hits)
+                    .unzip();
+                ((sid, hits), gene_records)
             })
-            .collect::<Vec<(u32, Vec<MatchSummary>)>>();
-        (sub_seq_range_for_fasta, match_summary)
+            .unzip();
+        (
+            match_summary,
+            hit_gene_records.into_iter().flatten().collect::<Vec<_>>(),
+        )
     } else {
         (vec![], vec![])
     };
+    let sub_seq_range_for_fasta = get_sub_seq_ranges(&match_summary, &seq_db);
 
-    let seq_list = sub_seq_range_for_fasta
-        .par_iter()
-        .map(|(sid, b, e, orientation, target_seq_name)| {
-            let target_seq = seq_db
-                .get_sub_seq_by_id(*sid, *b as usize, *e as usize)
-                .unwrap();
-            let target_seq = if *orientation == 1 {
-                pgr_db::fasta_io::reverse_complement(&target_seq)
-            } else {
-                target_seq
-            };
-            (target_seq_name.into(), target_seq)
-        })
-        .collect::<Vec<(String, Vec<u8>)>>();
+    let total_seq_length = sub_seq_range_for_fasta
+        .iter()
+        .map(|(_, b, e, _, _)| (e - b) as usize)
+        .sum::<usize>();
+    if let Some(max_total_seq_length) = max_total_seq_length {
+        if total_seq_length > max_total_seq_length {
+            return Err(DecompositionStop::LimitExceeded(format!(
+                "the total length of the matched sequences {} is larger than the limit {}",
+                total_seq_length, max_total_seq_length
+            )));
+        }
+    }
+
+    check_progress(0.45, "fetching the matched sequences")?;
+    let seq_list = get_sub_seqs(&sub_seq_range_for_fasta, &seq_db);
 
+    check_progress(0.55, "building the principal bundles")?;
     let mut new_seq_db = SeqIndexDB::new();
     let shmmr_spec = ShmmrSpec {
         w: seq_query_spec.w,
         k: seq_query_spec.k,
         r: seq_query_spec.r,
-        min_span: seq_query_spec.r,
+        min_span: seq_query_spec.min_span,
         sketch: seq_query_spec.sketch,
     };
 
@@ -442,6 +481,7 @@ pub fn get_target_and_principal_bundle_decomposition(
         .map(|v| (v.0, v.2.len()))
         .collect::<FxHashMap<usize, usize>>();
 
+    check_progress(0.85, "decomposing the sequences")?;
     let sid_smps =
         get_principal_bundle_decomposition(&vertex_to_bundle_id_direction_pos, &new_seq_db);
     let sid_smps: FxHashMap<u32, Vec<_>> = sid_smps.into_iter().collect();
@@ -462,7 +502,7 @@ pub fn get_target_and_principal_bundle_decomposition(
         .map(|(sid, sdata)| {
             let (ctg, _src, _len) = sdata;
             let smps = sid_smps.get(sid).unwrap();
-            let smp_partitions = group_smps_by_principle_bundle_id(
+            let smp_partitions = group_smps_by_principal_bundle_id(
                 smps,
                 seq_query_spec.bundle_length_cutoff,
                 seq_query_spec.bundle_merge_distance,
@@ -508,265 +548,235 @@ pub fn get_target_and_principal_bundle_decomposition(
         })
         .collect::<Vec<Vec<PrincipalBundleBedRecord>>>();
 
-    Some(TargetMatchPrincipalBundles {
+    let gene_records = if query_genes.is_empty() {
+        vec![]
+    } else {
+        seq_info
+            .iter()
+            .map(|(sid, _)| hit_gene_records[*sid as usize].clone())
+            .collect::<Vec<_>>()
+    };
+
+    check_progress(0.95, "clustering the sequences")?;
+    let dendrogram = get_bundle_dendrogram(&bundle_bed_records);
+
+    check_progress(1.0, "done")?;
+    Ok(Some(TargetMatchPrincipalBundles {
         query: (*seq_query_spec).clone(),
         match_summary,
         sid_ctg_src,
         bundle_bed_records,
-    })
+        dendrogram: Some(dendrogram),
+        gene_records,
+    }))
 }
 
-pub fn pb_data_to_html_string(targets: &TargetMatchPrincipalBundles) -> String {
-    let mut target_lengths = targets
-        .match_summary
+/// the contig names of the tracks, a track without any bundle segment is named `NA`
+fn get_track_names(bundle_bed_records: &[Vec<PrincipalBundleBedRecord>]) -> Vec<String> {
+    bundle_bed_records
         .iter()
-        .flat_map(|v| v.1.iter().map(|v| v.t_end - v.t_bgn).collect::<Vec<u32>>())
-        .collect::<Vec<u32>>();
+        .map(|v| match v.first() {
+            Some(r) => r.ctg.clone(),
+            None => "NA".to_string(),
+        })
+        .collect()
+}
 
-    target_lengths.sort();
-    let max_length = target_lengths.last().unwrap_or(&200000);
+/// the distances of the tracks from the global alignments of the bundle segments as `pgr-pbundle-bed2dist`
+pub fn get_track_distances(
+    bundle_bed_records: &[Vec<PrincipalBundleBedRecord>],
+) -> BundleDistances {
+    let ctg_bundles = bundle_bed_records
+        .iter()
+        .map(|v| v.iter().map(|r| r.to_bundle_segment()).collect::<Vec<_>>())
+        .collect::<Vec<_>>();
+    get_bundle_distances(&ctg_bundles, BundleDistanceMethod::GlobalAlignment)
+}
 
-    let ctg_data_vec = targets.bundle_bed_records.iter().map(|v| {
-        let b_segements = v
-            .iter()
-            .map(|r| (r.bgn, r.end, r.b_id, r.b_direction))
-            .collect::<Vec<_>>();
-        let ctg = if let Some(r) = v.get(0) {
-            r.ctg.clone()
-        } else {
-            "NA".to_string()
-        };
-        (ctg.clone(), ctg, b_segements)
-    });
+/// cluster the contigs by the distances of their bundle segments for ordering the tracks,
+/// the leaves of the Newick tree are the quoted contig names
+pub fn get_bundle_dendrogram(
+    bundle_bed_records: &[Vec<PrincipalBundleBedRecord>],
+) -> BundleDendrogram {
+    let leaf_labels = get_track_names(bundle_bed_records)
+        .into_iter()
+        .map(|ctg| format!("'{}'", ctg.replace('\'', "''")))
+        .collect::<Vec<_>>();
+    pbundle::get_bundle_dendrogram(&get_track_distances(bundle_bed_records), &leaf_labels)
+}
 
-    let track_scaling = 1.0;
-    let stroke_width = 1.0;
-    let left_padding = 50.0;
-    let no_tooltips = false;
-    let highlight_repeats = 1.2;
-    let mut y_offset = 0.0_f32;
-    let track_range = *max_length as f32 * 1.05;
-    //let track_range = 1200000.0;
-    let track_panel_width = 1200.0;
-    let annotation_panel_width = 800.0;
-    let tree_width = 0.0;
-    let h_factor = 1.5;
-    let scaling_factor = track_panel_width as f32 / (track_range + 2.0 * left_padding);
-    let delta_y = 16.0_f32 * track_scaling;
-
-    let mut bundle_class_styles = FxHashMap::<String, String>::default();
-
-    let ctg_with_svg_paths: Vec<(String, (Vec<element::Group>, element::Text))>  = ctg_data_vec
-        .map(|(ctg, annotation, bundle_segment)| {
-            let mut bundle_segment_count = FxHashMap::<u32, usize>::default();
-            bundle_segment.iter().for_each(|&(_bgn, _end, bundle_id, _direction)| {
-                let e = bundle_segment_count.entry(bundle_id).or_insert_with(|| 0);
-                *e += 1;
-            });
+/// the dendrogram of a result, it is computed again for the results cached without the tree layout by the older versions
+fn get_result_dendrogram(targets: &TargetMatchPrincipalBundles) -> BundleDendrogram {
+    match targets.dendrogram.as_ref() {
+        Some(dendrogram)
+            if dendrogram.leaf_order.len() == targets.bundle_bed_records.len()
+                && dendrogram.node_positions.len()
+                    == dendrogram.leaf_order.len() + dendrogram.steps.len() =>
+        {
+            dendrogram.clone()
+        }
+        _ => get_bundle_dendrogram(&targets.bundle_bed_records),
+    }
+}
 
-            // let offset = *ctg_to_offset.get(&ctg).unwrap_or(&0);
-            let offset = 0;
-            let paths: Vec<element::Group> = bundle_segment
-                .into_iter()
-                .map(|(bgn0, end0, bundle_id, direction)| {
-                    let mut bgn = (bgn0 as i64 + offset) as f32 * scaling_factor;
-                    let mut end = (end0 as i64 + offset) as f32 * scaling_factor;
-                    if direction == 1 {
-                        (bgn, end) = (end, bgn);
-                    }
+/// decompose a new sequence with the pre-computed principal bundles
+pub fn get_principal_bundle_db_decomposition(
+    query: &PrincipalBundleDecompQuery,
+    pdb: &PrincipalBundleDB,
+) -> Vec<PrincipalBundleBedRecord> {
+    let k = pdb.shmmr_spec.k;
+    let bid_to_size = pdb
+        .principal_bundles_with_id
+        .iter()
+        .map(|v| (v.0, v.2.len()))
+        .collect::<FxHashMap<usize, usize>>();
+    let smps = pdb.decompose_seq(query.seq.as_bytes().to_ascii_uppercase());
+    let smp_partitions = group_smps_by_principal_bundle_id(
+        &smps,
+        query.bundle_length_cutoff,
+        query.bundle_merge_distance,
+    );
+    let mut ctg_bundle_count = FxHashMap::<usize, usize>::default();
+    smp_partitions.iter().for_each(|p| {
+        let bid = p[0].1;
+        *ctg_bundle_count.entry(bid).or_insert_with(|| 0) += 1;
+    });
+    smp_partitions
+        .into_iter()
+        .map(|p| {
+            let b = p[0].0 .2;
+            let e = p[p.len() - 1].0 .3 + k;
+            let bid = p[0].1;
+            let is_repeat = if *ctg_bundle_count.get(&bid).unwrap_or(&0) > 1 {
+                "R"
+            } else {
+                "U"
+            };
+            PrincipalBundleBedRecord {
+                ctg: query.ctg.clone(),
+                bgn: b,
+                end: e,
+                b_id: bid as u32,
+                b_size: bid_to_size[&bid],
+                b_direction: p[0].2,
+                b_bgn: p[0].3,
+                b_end: p[p.len() - 1].3,
+                r_type: is_repeat.to_string(),
+            }
+        })
+        .collect()
+}
 
-                    let arror_end = end;
-                    let half_width = 5.0 * track_scaling;
-                    let end =
-                        if direction == 0 {
-                            if end - half_width < bgn {
-                                bgn
-                            } else {
-                                end - half_width
-                            }
-                        } else if end + half_width > bgn {
-                            bgn
-                        } else {
-                            end + half_width
-                        };
-
-                    let bottom0 = -half_width * 0.6;
-                    let top0 = half_width * 0.6;
-                    let bottom1 = -half_width * 0.8;
-                    let top1 = half_width * 0.8;
-                    let center = 0 as f32;
-
-                    let bundle_class = format!("bundle_{bundle_id:05}");
-                    let bundle_rep_class = format!("bundle_{bundle_id:05} repeat");
-
-                    let bundle_color = CMAP[((bundle_id * 57) % 59) as usize];
-                    let stroke_color = CMAP[93 - ((bundle_id * 31) % 47) as usize];
-                    let css_string = format!(
-r#".{bundle_class} {{fill:{bundle_color}; stroke:{stroke_color}; stroke-width:{stroke_width}; fill-opacity:0.5}}"#);
-                    bundle_class_styles.entry(bundle_class.clone()).or_insert(css_string);
-
-                    let bundle_class = if *bundle_segment_count.get(&bundle_id).unwrap_or(&0) > 1 && highlight_repeats > 1.0001 {
-                        bundle_rep_class
-                    } else {
-                        bundle_class
-                    };
+/// a gene as an annotation region of the svg tracks, the genes on the forward strand of the reference are blue and the others are red
+fn gene_region(gene: &GeneBedRecord) -> (u32, u32, String, String) {
+    let color = if gene.strand == "-" {
+        "#d62728"
+    } else {
+        "#1f77b4"
+    };
+    (
+        gene.bgn,
+        gene.end,
+        format!("{} ({}) {}-{}", gene.name, gene.strand, gene.bgn, gene.end),
+        color.to_string(),
+    )
+}
 
-                    let path_str = format!(
-					"M {bgn} {bottom0} L {bgn} {top0} L {end} {top0} L {end} {top1} L {arror_end} {center} L {end} {bottom1} L {end} {bottom0} Z");
-                    let mut p = element::Path::new()
-                        .set("d", path_str)
-                        .set("class", "bundle ".to_string() + bundle_class.as_str());
-                    let mut g = element::Group::new().set("transform", format!("translate({left_padding} {y_offset})"));
-                    if !no_tooltips { // it may be good idea to disable it for every large region visualization
-                        p.append(element::Title::new(format!("{}:{}-{}:{}", ctg, bgn0, end0, bundle_id )));
-                    };
-                    g.append(p);
-                    g
-                })
-                .collect();
-
-            let text = element::Text::new(annotation)
-                .set("x", 20.0 + left_padding + track_range * scaling_factor)
-                .set("y", y_offset + 2.0)
-                .set("font-size", "10px")
-                .set("font-family", "monospace");
-            y_offset += delta_y;
-            (ctg, (paths, text))
+/// draw the tracks in the order of the dendrogram with the tree with `pgr-pbundle-bed2svg`'s renderer
+pub fn pb_data_to_svg_document(targets: &TargetMatchPrincipalBundles) -> Document {
+    let max_length = targets
+        .match_summary
+        .iter()
+        .flat_map(|v| v.1.iter().map(|v| v.t_end - v.t_bgn))
+        .max()
+        .unwrap_or(200000);
+
+    let dendrogram = get_result_dendrogram(targets);
+    let track_names = get_track_names(&targets.bundle_bed_records);
+    let tracks = dendrogram
+        .leaf_order
+        .iter()
+        .map(|&idx| BundleSvgTrack {
+            ctg: track_names[idx].clone(),
+            annotation: track_names[idx].clone(),
+            segments: targets.bundle_bed_records[idx]
+                .iter()
+                .map(|r| (r.bgn, r.end, r.b_id, r.b_direction))
+                .collect(),
+            regions: targets
+                .gene_records
+                .get(idx)
+                .map_or(vec![], |genes| genes.iter().map(gene_region).collect()),
+            offset: 0,
         })
-        .collect();
-
-    // start to construct the SVG element
-
-    let mut document = Document::new()
-        .set(
-            "viewBox",
-            (
-                -tree_width,
-                -32,
-                tree_width + track_panel_width as f32 + annotation_panel_width as f32,
-                24.0 + y_offset,
-            ),
-        )
-        .set(
-            "width",
-            tree_width + track_panel_width as f32 + annotation_panel_width as f32,
-        )
-        .set("height", 56.0 + y_offset)
-        .set("preserveAspectRatio", "none")
-        .set("id", "bundleViwer");
-
-    // insert CSS
-    let stroke_width_rep = stroke_width * highlight_repeats;
-    let stroke_width_hover = stroke_width * 2.0;
-    let stroke_width_hover_rep = stroke_width_rep * 2.0;
-    let mut css_strings = vec![
-        format!(".repeat {{stroke-width:{stroke_width_rep};}}"),
-        format!(".bundle:hover {{ stroke-width:{stroke_width_hover};}}"),
-        format!(".repeat:hover {{ stroke-width:{stroke_width_hover_rep};}}"),
-        format!(".region {{ stroke-opacity: 0.5 }};"),
-    ];
-    css_strings.extend(bundle_class_styles.values().cloned());
-    //let h_factor = h_factor;
-    css_strings.push(format!(
-        r#"path.highlighted {{transform: scaleY({h_factor}); fill-opacity:1}}"#
-    ));
-    let style = element::Style::new(css_strings.join("\n")).set("type", "text/css");
-    document.append(style);
-
-    let track_tick_interval: Option<usize> = None;
-    let track_tick_interval = if let Some(tick_interval) = track_tick_interval {
-        tick_interval
-    } else {
-        let mut tick_interval = 1_usize;
-        let mut tmp = track_range;
-        tmp *= 0.1;
-        while tmp > 1.01 {
-            tick_interval *= 10;
-            tmp *= 0.1;
-        }
-        tick_interval
+        .collect::<Vec<_>>();
+
+    let options = BundleSvgOptions {
+        track_range: (max_length as f32 * 1.05) as usize,
+        track_tick_interval: None,
+        track_panel_width: 1200,
+        track_scaling: 1.0,
+        left_padding: 50,
+        stroke_width: 1.0,
+        annotation_region_track: !targets.gene_records.is_empty(),
+        annotation_region_stroke_width: 2.5,
+        annotation_panel_width: 800.0,
+        highlight_repeats: 1.2,
+        no_tooltips: false,
+        h_factor: 1.5,
     };
+    bundle_tracks_to_svg(tracks, Some(&dendrogram), &FxHashMap::default(), &options)
+}
 
-    let mut tickx = track_tick_interval;
-    loop {
-        if tickx > track_range as usize {
-            break;
-        }
-        let x = tickx as f32 * scaling_factor + left_padding;
-        let tick_path_str = format!("M {x} -16 L {x} -20");
-        let tick_path = element::Path::new()
-            .set("stroke", "#000")
-            .set("fill", "none")
-            .set("stroke-width", 1)
-            .set("d", tick_path_str);
-        document.append(tick_path);
-        tickx += track_tick_interval;
-    }
+pub fn pb_data_to_html_string(targets: &TargetMatchPrincipalBundles) -> String {
+    svg_to_html_string(&pb_data_to_svg_document(targets))
+}
 
-    let text = element::Text::new(format!("{} bps", track_range))
-        .set("x", 20.0 + left_padding + scaling_factor)
-        .set("y", -14)
-        .set("font-size", "10px")
-        .set("font-family", "sans-serif");
-    document.append(text);
+pub fn pb_data_to_svg_string(targets: &TargetMatchPrincipalBundles) -> String {
+    pb_data_to_svg_document(targets).to_string()
+}
 
-    // insert the bundle paths
-    ctg_with_svg_paths
-        .into_iter()
-        .for_each(|(_ctg, (paths, text))| {
-            // println!("{}", ctg);
-            document.append(text);
-            paths.into_iter().for_each(|path| document.append(path));
-        });
+/// the principal bundle bed file of the tracks as the one from `pgr-pbundle-decomp`
+pub fn pb_data_to_bed_string(targets: &TargetMatchPrincipalBundles) -> String {
+    targets
+        .bundle_bed_records
+        .iter()
+        .flatten()
+        .map(|r| bundle_bed_line(&r.ctg, &r.to_bundle_segment(), r.r_type == "R") + "\n")
+        .collect()
+}
 
-    let out_str = Vec::new();
-    let mut out_file = BufWriter::new(out_str);
-    let msg = "can't write the HTML doc";
-    writeln!(out_file, "<html><body>").expect(msg);
-    let jscript = r#"
-<script>
-document.addEventListener('readystatechange', event => {
-    if (event.target.readyState === "complete") {
-        var bundles = document.getElementsByClassName("bundle");
-        for (let i = 0; i < bundles.length; i++) {
-            bundles[i].onclick = function (e) {
-                // alert(e.target.classList);
-                let is_highlighted = false;
-                let bundle_id = "";
-                for (let cidx = 0; cidx < e.target.classList.length; cidx++) {
-                    if (e.target.classList[cidx] == "highlighted") {
-                        is_highlighted = true;
-                    }
-                    if (e.target.classList[cidx].match("bundle_")) {
-                        bundle_id = e.target.classList[cidx]
-                    }
-                };
-                var bundles2 = document.getElementsByClassName(bundle_id);
-                for (let j = 0; j < bundles2.length; j++) {
-                    if (is_highlighted) {
-                        bundles2[j].classList.remove("highlighted");
-                    } else {
-                        bundles2[j].classList.add("highlighted");
-                    }
-                }
-            };
-        }
-    }
-});
-</script>
-"#;
-    writeln!(out_file, "{}", jscript).expect(msg);
-    let mut svg_elment = BufWriter::new(Vec::new());
-    svg::write(&mut svg_elment, &document).unwrap();
-    writeln!(
-        out_file,
-        "{}",
-        String::from_utf8_lossy(&svg_elment.into_inner().unwrap())
-    )
-    .expect(msg);
-    writeln!(out_file, "</body></html>").expect(msg);
+/// the `.dist` file of `pgr-pbundle-bed2dist` for the tracks
+pub fn pb_data_to_dist_string(targets: &TargetMatchPrincipalBundles) -> String {
+    let mut out = Vec::new();
+    get_track_distances(&targets.bundle_bed_records)
+        .write_dist(&mut out, &get_track_names(&targets.bundle_bed_records))
+        .expect("can't write the distances");
+    String::from_utf8_lossy(&out).to_string()
+}
 
-    let _ = out_file.flush();
-    let out_str = out_file.into_inner().unwrap();
-    String::from_utf8_lossy(&out_str[..]).to_string()
+pub fn pb_data_to_newick_string(targets: &TargetMatchPrincipalBundles) -> String {
+    format!("{};\n", get_result_dendrogram(targets).newick)
+}
+
+/// the matched target sequences decomposed into the tracks in the FASTA format
+pub fn pb_data_to_fasta_string(
+    targets: &TargetMatchPrincipalBundles,
+    seq_db: &SeqIndexDB,
+) -> String {
+    let sub_seq_ranges = get_sub_seq_ranges(&targets.match_summary, seq_db);
+    get_sub_seqs(&sub_seq_ranges, seq_db)
+        .into_iter()
+        .zip(sub_seq_ranges.iter())
+        .map(|((name, seq), (_sid, b, e, orientation, _ctg))| {
+            format!(
+                ">{} {}-{} {}\n{}\n",
+                name,
+                b,
+                e,
+                if *orientation == 0 { '+' } else { '-' },
+                String::from_utf8_lossy(&seq)
+            )
+        })
+        .collect()
 }
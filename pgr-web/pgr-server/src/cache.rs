@@ -0,0 +1,467 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::hash::Hasher;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use axum::Json;
+use rustc_hash::{FxHashMap, FxHasher};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::bundle_processing::{
+    get_target_and_principal_bundle_decomposition, pb_data_to_html_string, SequenceQuerySpec,
+    TargetMatchPrincipalBundles,
+};
+use crate::databases::Database;
+
+/// the statistics of a cache
+#[derive(Serialize, Deserialize, Clone, Debug, Default, ToSchema)]
+pub struct CacheStats {
+    pub entries: usize,
+    pub bytes: usize,
+    pub capacity_bytes: usize,
+    pub hits: u64,
+    pub misses: u64,
+    /// the number of the misses in the memory found in the spilled files
+    pub disk_hits: u64,
+    pub evictions: u64,
+    /// the number of the evicted entries written to the spill directory
+    pub spills: u64,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, ToSchema)]
+pub struct ResultCacheStats {
+    pub data: CacheStats,
+    pub html: CacheStats,
+}
+
+/// A least recently used cache bounded by the total size of the values
+struct LruCache<V> {
+    capacity_bytes: usize,
+    entries: FxHashMap<u64, (String, Arc<V>, usize, u64)>, // key hash -> (key, value, size, last used tick)
+    order: BTreeMap<u64, u64>,                             // last used tick -> key hash
+    tick: u64,
+    stats: CacheStats,
+}
+
+impl<V> LruCache<V> {
+    fn new(capacity_bytes: usize) -> Self {
+        LruCache {
+            capacity_bytes,
+            entries: FxHashMap::default(),
+            order: BTreeMap::new(),
+            tick: 0,
+            stats: CacheStats {
+                capacity_bytes,
+                ..Default::default()
+            },
+        }
+    }
+
+    fn get(&mut self, key_hash: u64, key: &str) -> Option<Arc<V>> {
+        self.tick += 1;
+        let tick = self.tick;
+        match self.entries.get_mut(&key_hash) {
+            Some(entry) if entry.0 == key => {
+                self.order.remove(&entry.3);
+                self.order.insert(tick, key_hash);
+                entry.3 = tick;
+                self.stats.hits += 1;
+                Some(entry.1.clone())
+            }
+            _ => {
+                self.stats.misses += 1;
+                None
+            }
+        }
+    }
+
+    /// insert a value and return the evicted entries
+    fn insert(
+        &mut self,
+        key_hash: u64,
+        key: String,
+        value: Arc<V>,
+        size: usize,
+    ) -> Vec<(u64, String, Arc<V>)> {
+        if size > self.capacity_bytes {
+            return vec![];
+        }
+        self.tick += 1;
+        if let Some(old) = self.entries.remove(&key_hash) {
+            self.order.remove(&old.3);
+            self.stats.bytes -= old.2;
+        }
+        let mut evicted = vec![];
+        while self.stats.bytes + size > self.capacity_bytes {
+            let (_tick, evicted_hash) = self.order.pop_first().unwrap();
+            let (evicted_key, evicted_value, evicted_size, _) =
+                self.entries.remove(&evicted_hash).unwrap();
+            self.stats.bytes -= evicted_size;
+            self.stats.evictions += 1;
+            evicted.push((evicted_hash, evicted_key, evicted_value));
+        }
+        self.order.insert(self.tick, key_hash);
+        self.entries.insert(key_hash, (key, value, size, self.tick));
+        self.stats.bytes += size;
+        self.stats.entries = self.entries.len();
+        evicted
+    }
+
+    fn stats(&self) -> CacheStats {
+        let mut stats = self.stats.clone();
+        stats.entries = self.entries.len();
+        stats
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct SpilledEntry {
+    key: String,
+    data: TargetMatchPrincipalBundles,
+}
+
+/// A content-addressed cache of the principal bundle query results and the rendered HTML, the cache key is the
+/// normalized query and the identity of the database, so the databases served by the server share one cache. The evicted
+/// results are written to the spill directory if it is specified, so they can be reused later, also after the server is
+/// restarted with the same database.
+pub struct ResultCache {
+    spill_dir: Option<PathBuf>,
+    data: Mutex<LruCache<TargetMatchPrincipalBundles>>,
+    html: Mutex<LruCache<String>>,
+}
+
+/// get a string identifying the database from the sizes and the modification times of the index files
+pub fn get_database_identity(data_path_prefix: &str) -> String {
+    let mut identity = vec![data_path_prefix.to_string()];
+    ["mdb", "midx", "sdx", "frg", "agc"].iter().for_each(|ext| {
+        let path = format!("{}.{}", data_path_prefix, ext);
+        if let Some(file_identity) = get_file_identity(&path) {
+            identity.push(format!("{}:{}", ext, file_identity));
+        }
+    });
+    identity.join("|")
+}
+
+/// the size and the modification time of a file
+pub fn get_file_identity(path: &str) -> Option<String> {
+    let metadata = fs::metadata(path).ok()?;
+    let mtime = metadata
+        .modified()
+        .ok()
+        .and_then(|t| t.duration_since(std::time::UNIX_EPOCH).ok())
+        .map_or(0, |d| d.as_secs());
+    Some(format!("{}:{}", metadata.len(), mtime))
+}
+
+fn hash_key(key: &str) -> u64 {
+    let mut hasher = FxHasher::default();
+    hasher.write(key.as_bytes());
+    hasher.finish()
+}
+
+impl ResultCache {
+    /// `capacity_bytes` is split evenly between the query results and the rendered HTML
+    pub fn new(capacity_bytes: usize, spill_dir: Option<PathBuf>) -> Self {
+        if let Some(spill_dir) = spill_dir.as_ref() {
+            fs::create_dir_all(spill_dir).expect("can't create the cache spill directory");
+        }
+        ResultCache {
+            spill_dir,
+            data: Mutex::new(LruCache::new(capacity_bytes - capacity_bytes / 2)),
+            html: Mutex::new(LruCache::new(capacity_bytes / 2)),
+        }
+    }
+
+    /// the cache key of a query, the padding is applied to the query range and clipped by the contig length,
+    /// so the queries of the same padded range share the result
+    pub fn get_key(&self, spec: &SequenceQuerySpec, db: &Database) -> String {
+        let ctg_len = db
+            .seq_db
+            .seq_index
+            .as_ref()
+            .and_then(|seq_index| seq_index.get(&(spec.ctg.clone(), Some(spec.source.clone()))))
+            .map_or(0, |&(_sid, len)| len as usize);
+        let q_bgn = spec.bgn.saturating_sub(spec.padding);
+        let q_end = spec.end.saturating_add(spec.padding).min(ctg_len);
+        let mut normalized_spec = spec.clone();
+        normalized_spec.bgn = q_bgn;
+        normalized_spec.end = q_end;
+        normalized_spec.padding = 0;
+        format!(
+            "{}\n{}",
+            db.identity,
+            serde_json::to_string(&normalized_spec).unwrap()
+        )
+    }
+
+    fn spill_path(&self, key_hash: u64) -> Option<PathBuf> {
+        self.spill_dir
+            .as_ref()
+            .map(|dir| dir.join(format!("{:016x}.json", key_hash)))
+    }
+
+    fn read_spilled(&self, key_hash: u64, key: &str) -> Option<TargetMatchPrincipalBundles> {
+        let path = self.spill_path(key_hash)?;
+        let spilled: SpilledEntry = serde_json::from_slice(&fs::read(path).ok()?).ok()?;
+        if spilled.key == key {
+            Some(spilled.data)
+        } else {
+            None
+        }
+    }
+
+    fn write_spilled(path: &Path, key: String, data: &TargetMatchPrincipalBundles) {
+        #[derive(Serialize)]
+        struct SpilledEntryRef<'a> {
+            key: String,
+            data: &'a TargetMatchPrincipalBundles,
+        }
+        if let Ok(json) = serde_json::to_vec(&SpilledEntryRef { key, data }) {
+            let _ = fs::write(path, json);
+        }
+    }
+
+    pub fn get_data(&self, key: &str) -> Option<Arc<TargetMatchPrincipalBundles>> {
+        let key_hash = hash_key(key);
+        if let Some(data) = self.data.lock().unwrap().get(key_hash, key) {
+            return Some(data);
+        }
+        let data = self.read_spilled(key_hash, key)?;
+        self.data.lock().unwrap().stats.disk_hits += 1;
+        let data = Arc::new(data);
+        self.insert_data(key.to_string(), data.clone());
+        Some(data)
+    }
+
+    pub fn insert_data(&self, key: String, data: Arc<TargetMatchPrincipalBundles>) {
+        let key_hash = hash_key(&key);
+        let size = serde_json::to_vec(data.as_ref()).map_or(0, |json| json.len());
+        let evicted = self.data.lock().unwrap().insert(key_hash, key, data, size);
+        evicted
+            .into_iter()
+            .for_each(|(evicted_hash, evicted_key, evicted_data)| {
+                if let Some(path) = self.spill_path(evicted_hash) {
+                    if !path.exists() {
+                        Self::write_spilled(&path, evicted_key, &evicted_data);
+                        self.data.lock().unwrap().stats.spills += 1;
+                    }
+                }
+            });
+    }
+
+    pub fn get_html(&self, key: &str) -> Option<Arc<String>> {
+        self.html.lock().unwrap().get(hash_key(key), key)
+    }
+
+    pub fn insert_html(&self, key: String, html: Arc<String>) {
+        let size = html.len();
+        self.html
+            .lock()
+            .unwrap()
+            .insert(hash_key(&key), key, html, size);
+    }
+
+    /// get the query result from the cache or compute it
+    pub fn get_or_compute_data(
+        &self,
+        spec: &SequenceQuerySpec,
+        db: &Database,
+    ) -> Option<Arc<TargetMatchPrincipalBundles>> {
+        let key = self.get_key(spec, db);
+        let data = match self.get_data(&key) {
+            Some(data) => data,
+            None => {
+                let data = Arc::new(get_target_and_principal_bundle_decomposition(
+                    spec,
+                    db.seq_db.clone(),
+                    db.genes.as_ref().as_ref(),
+                )?);
+                self.insert_data(key, data.clone());
+                data
+            }
+        };
+        Some(with_query(data, spec))
+    }
+
+    /// get the rendered HTML of a query from the cache or render it
+    pub fn get_or_render_html(
+        &self,
+        spec: &SequenceQuerySpec,
+        db: &Database,
+    ) -> Option<Arc<String>> {
+        let key = self.get_key(spec, db);
+        if let Some(html) = self.get_html(&key) {
+            return Some(html);
+        }
+        let data = self.get_or_compute_data(spec, db)?;
+        let html = Arc::new(pb_data_to_html_string(&data));
+        self.insert_html(key, html.clone());
+        Some(html)
+    }
+
+    pub fn stats(&self) -> ResultCacheStats {
+        ResultCacheStats {
+            data: self.data.lock().unwrap().stats(),
+            html: self.html.lock().unwrap().stats(),
+        }
+    }
+}
+
+/// a cached result is shared by the queries with the same padded range, the query in the result is
+/// replaced with the one from the request
+pub fn with_query(
+    data: Arc<TargetMatchPrincipalBundles>,
+    spec: &SequenceQuerySpec,
+) -> Arc<TargetMatchPrincipalBundles> {
+    if data.query == *spec {
+        data
+    } else {
+        let mut data = data.as_ref().clone();
+        data.query = spec.clone();
+        Arc::new(data)
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/cache/stats",
+    tag = "pgr-server",
+    responses(
+        (status = 200, description = "the statistics of the query result and the HTML caches", body = ResultCacheStats)
+    )
+)]
+pub async fn get_cache_stats(cache: Arc<ResultCache>) -> Json<ResultCacheStats> {
+    Json(cache.stats())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::databases::{DatabaseBackend, DatabaseConfig};
+
+    fn test_data(ctg: &str) -> TargetMatchPrincipalBundles {
+        TargetMatchPrincipalBundles {
+            query: SequenceQuerySpec {
+                source: "test".to_string(),
+                ctg: ctg.to_string(),
+                bgn: 0,
+                end: 1000,
+                padding: 100,
+                merge_range_tol: 100,
+                w: 48,
+                k: 56,
+                r: 4,
+                min_span: 12,
+                sketch: false,
+                min_cov: 2,
+                min_branch_size: 8,
+                bundle_length_cutoff: 500,
+                bundle_merge_distance: 10000,
+            },
+            match_summary: vec![],
+            sid_ctg_src: vec![(0, ctg.to_string(), "test".to_string())],
+            bundle_bed_records: vec![],
+            dendrogram: None,
+            gene_records: vec![],
+        }
+    }
+
+    fn insert(cache: &mut LruCache<String>, key: &str, size: usize) -> Vec<String> {
+        cache
+            .insert(
+                hash_key(key),
+                key.to_string(),
+                Arc::new(key.to_string()),
+                size,
+            )
+            .into_iter()
+            .map(|(_, key, _)| key)
+            .collect()
+    }
+
+    #[test]
+    fn test_lru_eviction_order() {
+        let mut cache = LruCache::<String>::new(300);
+        assert!(insert(&mut cache, "a", 100).is_empty());
+        assert!(insert(&mut cache, "b", 100).is_empty());
+        assert!(insert(&mut cache, "c", 100).is_empty());
+        // "a" is used after "b", so "b" is the least recently used one
+        assert!(cache.get(hash_key("a"), "a").is_some());
+        assert_eq!(insert(&mut cache, "d", 100), vec!["b"]);
+        assert!(cache.get(hash_key("b"), "b").is_none());
+        // a larger entry evicts more than one entry
+        assert_eq!(insert(&mut cache, "e", 200), vec!["c", "a"]);
+        let stats = cache.stats();
+        assert_eq!(stats.entries, 2);
+        assert_eq!(stats.evictions, 3);
+        assert_eq!(stats.hits, 1);
+        assert_eq!(stats.misses, 1);
+    }
+
+    #[test]
+    fn test_lru_byte_accounting() {
+        let mut cache = LruCache::<String>::new(300);
+        insert(&mut cache, "a", 100);
+        insert(&mut cache, "b", 50);
+        assert_eq!(cache.stats().bytes, 150);
+        // replacing an entry releases its old size
+        assert!(insert(&mut cache, "a", 200).is_empty());
+        assert_eq!(cache.stats().bytes, 250);
+        assert_eq!(cache.stats().entries, 2);
+        // an entry larger than the capacity is not cached
+        assert!(insert(&mut cache, "c", 301).is_empty());
+        assert!(cache.get(hash_key("c"), "c").is_none());
+        assert_eq!(cache.stats().bytes, 250);
+        assert_eq!(insert(&mut cache, "d", 100), vec!["b"]);
+        assert_eq!(cache.stats().bytes, 300);
+        assert_eq!(cache.stats().capacity_bytes, 300);
+    }
+
+    #[test]
+    fn test_spill_round_trip() {
+        let spill_dir =
+            std::env::temp_dir().join(format!("pgr-cache-spill-{}", std::process::id()));
+        let data1 = test_data("ctg1");
+        let size = serde_json::to_vec(&data1).unwrap().len();
+        // only one result fits in the data cache
+        let cache = ResultCache::new(3 * size, Some(spill_dir.clone()));
+        assert_eq!(
+            cache.stats().data.capacity_bytes + cache.stats().html.capacity_bytes,
+            3 * size
+        );
+        cache.insert_data("key1".to_string(), Arc::new(data1));
+        cache.insert_data("key2".to_string(), Arc::new(test_data("ctg2")));
+        assert_eq!(cache.stats().data.spills, 1);
+        assert_eq!(cache.stats().data.entries, 1);
+
+        let data = cache.get_data("key1").unwrap();
+        assert_eq!(data.query.ctg, "ctg1");
+        assert_eq!(data.sid_ctg_src[0].1, "ctg1");
+        assert_eq!(cache.stats().data.disk_hits, 1);
+        // a spilled file is only used for the same key
+        assert!(cache.read_spilled(hash_key("key1"), "key3").is_none());
+        fs::remove_dir_all(spill_dir).unwrap();
+    }
+
+    #[test]
+    fn test_key_min_span() {
+        let db = Database::load(DatabaseConfig::new(
+            "test".to_string(),
+            DatabaseBackend::Frg,
+            concat!(
+                env!("CARGO_MANIFEST_DIR"),
+                "/../../pgr-db/test/test_data/test_seqs_frag"
+            )
+            .to_string(),
+        ))
+        .unwrap();
+        let cache = ResultCache::new(1 << 20, None);
+        let spec = test_data("ctg1").query;
+        let mut spec2 = spec.clone();
+        spec2.min_span = 24;
+        // the bundles are built with the minimum span of the query
+        assert_ne!(cache.get_key(&spec, &db), cache.get_key(&spec2, &db));
+    }
+}
@@ -0,0 +1,332 @@
+use std::io::{Error, ErrorKind};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use axum::{extract::Extension, http::StatusCode, Json};
+use pgr_db::ext::{PrincipalBundleDB, SeqIndexDB};
+use serde::{Deserialize, Serialize};
+use tokio::sync::OnceCell;
+use utoipa::ToSchema;
+
+use crate::api::ApiError;
+use crate::auth::Identity;
+use crate::bundle_processing::ShmmrSpec;
+use crate::cache::{get_database_identity, get_file_identity};
+use crate::genes::{GeneAnnotation, GeneAnnotationConfig};
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum DatabaseBackend {
+    /// the AGC archive with the shimmer index (`prefix.agc`, `prefix.mdb`, `prefix.midx`)
+    Agc,
+    /// the fragment database (`prefix.frg`, `prefix.mdb`, `prefix.midx`, `prefix.sdx`)
+    Frg,
+}
+
+/// The configuration of a database served by the server
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct DatabaseConfig {
+    /// the name used in the routes, only the ASCII letters, digits, `.`, `_` and `-` are allowed
+    pub name: String,
+    pub backend: DatabaseBackend,
+    /// the path prefix of the index files
+    pub prefix: String,
+    #[serde(default)]
+    pub description: String,
+    /// the default shimmer parameters of the principal bundle decomposition queries
+    #[serde(default = "default_shmmr_spec")]
+    pub shmmr_spec: ShmmrSpec,
+    /// a pre-computed principal bundle (.pdb) file for decomposing new sequences
+    pub pdb_file: Option<String>,
+    /// a JSON file or a directory of the JSON files of the named regions of interest as the query presets
+    pub roi_path: Option<String>,
+    /// the genes of a reference sample shown as a track under the principal bundles of the matched sequences
+    pub gene_annotation: Option<GeneAnnotationConfig>,
+}
+
+impl DatabaseConfig {
+    pub fn new(name: String, backend: DatabaseBackend, prefix: String) -> Self {
+        DatabaseConfig {
+            name,
+            backend,
+            prefix,
+            description: String::new(),
+            shmmr_spec: default_shmmr_spec(),
+            pdb_file: None,
+            roi_path: None,
+            gene_annotation: None,
+        }
+    }
+}
+
+fn default_shmmr_spec() -> ShmmrSpec {
+    ShmmrSpec {
+        w: 48,
+        k: 56,
+        r: 4,
+        min_span: 12,
+        sketch: false,
+    }
+}
+
+/// The configuration file of the databases, e.g.
+/// ```json
+/// {
+///     "default": "hprc-y1",
+///     "databases": [
+///         {"name": "hprc-y1", "backend": "agc", "prefix": "/data/pgr-tk-HGRP-y1-evaluation-set-v0",
+///          "description": "HPRC year 1 assemblies with GRCh38 and CHM13", "roi_path": "/data/ROIs",
+///          "gene_annotation": {"file": "/data/gencode.v43.basic.annotation.gtf.gz", "source": "GRCh38"}},
+///         {"name": "ecoli", "backend": "frg", "prefix": "/data/ecoli_frg",
+///          "shmmr_spec": {"w": 80, "k": 56, "r": 4, "min_span": 64, "sketch": false}}
+///     ]
+/// }
+/// ```
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct DatabaseListConfig {
+    /// the database used by the routes without a database name, default to the first one
+    pub default: Option<String>,
+    pub databases: Vec<DatabaseConfig>,
+}
+
+/// The description of a database returned by the API, the file paths are not exposed
+#[derive(Serialize, Deserialize, Clone, Debug, ToSchema)]
+pub struct DatabaseInfo {
+    pub name: String,
+    pub backend: DatabaseBackend,
+    pub description: String,
+    pub shmmr_spec: ShmmrSpec,
+    pub has_principal_bundles: bool,
+    pub has_gene_annotation: bool,
+    pub is_default: bool,
+    /// whether the database has been loaded, a database is loaded when it is used the first time
+    pub loaded: bool,
+}
+
+/// A loaded database
+pub struct Database {
+    pub config: DatabaseConfig,
+    /// the identity of the index files, it is a part of the cache keys
+    pub identity: String,
+    pub seq_db: Arc<SeqIndexDB>,
+    pub pdb: Arc<Option<PrincipalBundleDB>>,
+    pub genes: Arc<Option<GeneAnnotation>>,
+    /// the time spent on loading the database
+    pub load_time: Duration,
+}
+
+impl Database {
+    pub fn load(config: DatabaseConfig) -> Result<Self, Error> {
+        let start = Instant::now();
+        let mut identity = get_database_identity(&config.prefix);
+        if let Some(gene_annotation) = config.gene_annotation.as_ref() {
+            // the cached results include the genes
+            identity.push_str(&format!(
+                "|genes:{}:{}:{}",
+                gene_annotation.source,
+                gene_annotation.file,
+                get_file_identity(&gene_annotation.file).unwrap_or_default()
+            ));
+        }
+        let mut seq_db = SeqIndexDB::new();
+        match config.backend {
+            DatabaseBackend::Frg => seq_db.load_from_frg_index(config.prefix.clone())?,
+            #[cfg(feature = "with_agc")]
+            DatabaseBackend::Agc => seq_db.load_from_agc_index(config.prefix.clone())?,
+            #[cfg(not(feature = "with_agc"))]
+            DatabaseBackend::Agc => {
+                return Err(Error::new(
+                    ErrorKind::Unsupported,
+                    "the server is compiled with only frg file support",
+                ))
+            }
+        };
+        let pdb = match config.pdb_file.as_ref() {
+            Some(pdb_file) => Some(PrincipalBundleDB::load_from_file(pdb_file)?),
+            None => None,
+        };
+        let genes = match config.gene_annotation.as_ref() {
+            Some(gene_annotation) => Some(GeneAnnotation::load_from_file(gene_annotation)?),
+            None => None,
+        };
+        Ok(Database {
+            config,
+            identity,
+            seq_db: Arc::new(seq_db),
+            pdb: Arc::new(pdb),
+            genes: Arc::new(genes),
+            load_time: start.elapsed(),
+        })
+    }
+}
+
+/// The loading state of a database for the metrics
+pub struct DatabaseLoadStats {
+    pub name: String,
+    /// the time spent on loading the database, it is `None` if the database is not loaded
+    pub load_time: Option<Duration>,
+    /// the number of the failed loadings
+    pub failures: u64,
+}
+
+/// The databases served by the server, a database is loaded when it is used the first time
+pub struct DatabaseRegistry {
+    default: String,
+    databases: Vec<(DatabaseConfig, OnceCell<Arc<Database>>, AtomicU64)>,
+}
+
+impl DatabaseRegistry {
+    pub fn new(config: DatabaseListConfig) -> Result<Self, Error> {
+        let invalid = |msg: String| Error::new(ErrorKind::InvalidData, msg);
+        let mut names = rustc_hash::FxHashSet::default();
+        for db in config.databases.iter() {
+            if db.name.is_empty()
+                || !db
+                    .name
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '.' || c == '_' || c == '-')
+            {
+                return Err(invalid(format!("invalid database name `{}`", db.name)));
+            }
+            if !names.insert(db.name.clone()) {
+                return Err(invalid(format!("duplicated database name `{}`", db.name)));
+            }
+        }
+        let default = match config.default {
+            Some(default) if names.contains(&default) => default,
+            Some(default) => {
+                return Err(invalid(format!(
+                    "the default database `{}` is not listed",
+                    default
+                )))
+            }
+            None => config
+                .databases
+                .first()
+                .ok_or_else(|| invalid("no database is listed".to_string()))?
+                .name
+                .clone(),
+        };
+        Ok(DatabaseRegistry {
+            default,
+            databases: config
+                .databases
+                .into_iter()
+                .map(|db| (db, OnceCell::new(), AtomicU64::new(0)))
+                .collect(),
+        })
+    }
+
+    pub fn from_config_file(path: &str) -> Result<Self, Error> {
+        let config: DatabaseListConfig = serde_json::from_slice(&std::fs::read(path)?)?;
+        Self::new(config)
+    }
+
+    pub fn default_name(&self) -> &str {
+        &self.default
+    }
+
+    pub fn configs(&self) -> impl Iterator<Item = &DatabaseConfig> {
+        self.databases.iter().map(|(config, _, _)| config)
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.configs().any(|config| config.name == name)
+    }
+
+    pub fn list(&self) -> Vec<DatabaseInfo> {
+        self.databases
+            .iter()
+            .map(|(config, db, _)| DatabaseInfo {
+                name: config.name.clone(),
+                backend: config.backend,
+                description: config.description.clone(),
+                shmmr_spec: config.shmmr_spec.clone(),
+                has_principal_bundles: config.pdb_file.is_some(),
+                has_gene_annotation: config.gene_annotation.is_some(),
+                is_default: config.name == self.default,
+                loaded: db.initialized(),
+            })
+            .collect()
+    }
+
+    /// whether a database has been loaded
+    pub fn is_loaded(&self, name: &str) -> bool {
+        self.databases
+            .iter()
+            .any(|(config, db, _)| config.name == name && db.initialized())
+    }
+
+    pub fn load_stats(&self) -> Vec<DatabaseLoadStats> {
+        self.databases
+            .iter()
+            .map(|(config, db, failures)| DatabaseLoadStats {
+                name: config.name.clone(),
+                load_time: db.get().map(|db| db.load_time),
+                failures: failures.load(Ordering::Relaxed),
+            })
+            .collect()
+    }
+
+    /// get a database by its name and load it if it is not loaded yet, a failed loading is retried
+    /// at the next request
+    pub async fn get(&self, name: &str) -> Result<Arc<Database>, ApiError> {
+        let (config, db, failures) = self
+            .databases
+            .iter()
+            .find(|(config, _, _)| config.name == name)
+            .ok_or_else(|| ApiError::not_found(format!("database {} not found", name)))?;
+        db.get_or_try_init(|| async {
+            let config = config.clone();
+            tracing::info!(database = %config.name, prefix = %config.prefix, "loading database");
+            let load_error = |err: String| {
+                failures.fetch_add(1, Ordering::Relaxed);
+                tracing::error!(database = %name, error = %err, "can't load database");
+                ApiError(
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    format!("can't load database {}: {}", name, err),
+                )
+            };
+            // the loaders panic on some of the missing or broken files
+            let db = tokio::task::spawn_blocking(move || Database::load(config))
+                .await
+                .map_err(|err| load_error(err.to_string()))?
+                .map_err(|err| load_error(err.to_string()))?;
+            tracing::info!(
+                database = %name,
+                seconds = db.load_time.as_secs_f64(),
+                "database loaded"
+            );
+            Ok(Arc::new(db))
+        })
+        .await
+        .cloned()
+    }
+
+    /// get a database by its name or the default database if the name is not specified
+    pub async fn get_or_default(&self, name: Option<&str>) -> Result<Arc<Database>, ApiError> {
+        self.get(name.unwrap_or(&self.default)).await
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/databases",
+    tag = "pgr-server",
+    responses(
+        (status = 200, description = "the databases readable by the client", body = [DatabaseInfo])
+    )
+)]
+pub async fn get_databases(
+    Extension(identity): Extension<Identity>,
+    registry: Arc<DatabaseRegistry>,
+) -> Json<Vec<DatabaseInfo>> {
+    Json(
+        registry
+            .list()
+            .into_iter()
+            .filter(|db| identity.can_read(&db.name))
+            .collect(),
+    )
+}
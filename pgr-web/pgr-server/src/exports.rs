@@ -0,0 +1,148 @@
+use std::sync::Arc;
+
+use axum::{
+    extract::{Path, Query},
+    http::{header, StatusCode},
+    response::{IntoResponse, Response},
+    routing::get,
+    Router,
+};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::api::ApiError;
+use crate::bundle_processing::{
+    pb_data_to_bed_string, pb_data_to_dist_string, pb_data_to_fasta_string,
+    pb_data_to_newick_string, pb_data_to_svg_string, SequenceQuerySpec,
+};
+use crate::cache::ResultCache;
+use crate::databases::DatabaseRegistry;
+
+/// The downloadable files of a query result
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportFormat {
+    /// the matched target sequences
+    Fasta,
+    /// the principal bundle segments as the bed file of `pgr-pbundle-decomp`
+    Bed,
+    /// the distances between the sequences as the `.dist` file of `pgr-pbundle-bed2dist`
+    Dist,
+    /// the average linkage tree of the sequences in the Newick format
+    Nwk,
+    /// the bundle tracks as the SVG of `pgr-pbundle-bed2svg`
+    Svg,
+}
+
+impl ExportFormat {
+    fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "fasta" => Some(ExportFormat::Fasta),
+            "bed" => Some(ExportFormat::Bed),
+            "dist" => Some(ExportFormat::Dist),
+            "nwk" => Some(ExportFormat::Nwk),
+            "svg" => Some(ExportFormat::Svg),
+            _ => None,
+        }
+    }
+
+    fn extension(&self) -> &'static str {
+        match self {
+            ExportFormat::Fasta => "fa",
+            ExportFormat::Bed => "bed",
+            ExportFormat::Dist => "dist",
+            ExportFormat::Nwk => "nwk",
+            ExportFormat::Svg => "svg",
+        }
+    }
+
+    fn content_type(&self) -> &'static str {
+        match self {
+            ExportFormat::Svg => "image/svg+xml",
+            _ => "text/plain",
+        }
+    }
+}
+
+/// the file name `<source>_<ctg>_<bgn>_<end>.<ext>` with the characters other than `[A-Za-z0-9._-]` replaced by `_`
+fn get_file_name(spec: &SequenceQuerySpec, format: ExportFormat) -> String {
+    format!(
+        "{}_{}_{}_{}.{}",
+        spec.source,
+        spec.ctg,
+        spec.bgn,
+        spec.end,
+        format.extension()
+    )
+    .chars()
+    .map(|c| {
+        if c.is_ascii_alphanumeric() || c == '.' || c == '_' || c == '-' {
+            c
+        } else {
+            '_'
+        }
+    })
+    .collect()
+}
+
+pub fn export_router(registry: Arc<DatabaseRegistry>, cache: Arc<ResultCache>) -> Router {
+    Router::new().route(
+        "/api/v1/db/:db_name/export/:format",
+        get(move |path, params| get_export(path, params, registry, cache)),
+    )
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/db/{db_name}/export/{format}",
+    tag = "pgr-server",
+    params(
+        ("db_name" = String, Path, description = "the database name"),
+        ("format" = ExportFormat, Path, description = "the file to download"),
+        SequenceQuerySpec
+    ),
+    responses(
+        (status = 200, description = "the file of the query result as an attachment, the query result is shared with the cache", body = String, content_type = "text/plain"),
+        (status = 400, description = "unknown format", body = crate::api::ApiErrorMessage),
+        (status = 404, description = "database not found or no result for the query", body = crate::api::ApiErrorMessage)
+    )
+)]
+pub async fn get_export(
+    Path((db_name, format)): Path<(String, String)>,
+    Query(spec): Query<SequenceQuerySpec>,
+    registry: Arc<DatabaseRegistry>,
+    cache: Arc<ResultCache>,
+) -> Result<Response, ApiError> {
+    let format = ExportFormat::from_name(&format).ok_or_else(|| {
+        ApiError::bad_request(format!(
+            "unknown format {}, it should be one of fasta, bed, dist, nwk and svg",
+            format
+        ))
+    })?;
+    let db = registry.get(&db_name).await?;
+    let file_name = get_file_name(&spec, format);
+    let content = tokio::task::spawn_blocking(move || {
+        let data = cache.get_or_compute_data(&spec, &db)?;
+        Some(match format {
+            ExportFormat::Fasta => pb_data_to_fasta_string(&data, &db.seq_db),
+            ExportFormat::Bed => pb_data_to_bed_string(&data),
+            ExportFormat::Dist => pb_data_to_dist_string(&data),
+            ExportFormat::Nwk => pb_data_to_newick_string(&data),
+            ExportFormat::Svg => pb_data_to_svg_string(&data),
+        })
+    })
+    .await
+    .map_err(|err| ApiError(StatusCode::INTERNAL_SERVER_ERROR, err.to_string()))?
+    .ok_or_else(|| ApiError::not_found("no result for the query"))?;
+    Ok((
+        [
+            (header::CONTENT_TYPE, format.content_type().to_string()),
+            (
+                header::CONTENT_DISPOSITION,
+                format!("attachment; filename=\"{}\"", file_name),
+            ),
+        ],
+        content,
+    )
+        .into_response())
+}
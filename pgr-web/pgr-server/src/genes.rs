@@ -0,0 +1,311 @@
+use std::fs::File;
+use std::io::{BufRead, BufReader, Error, ErrorKind};
+
+use flate2::read::MultiGzDecoder;
+use pgr_db::aln::HitPair;
+use rustc_hash::FxHashMap;
+use serde::{Deserialize, Serialize};
+
+/// The gene annotation of a database, e.g.
+/// ```json
+/// {"file": "/data/gencode.v43.basic.annotation.gtf.gz", "source": "GRCh38"}
+/// ```
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct GeneAnnotationConfig {
+    /// a bgzipped GTF or GFF3 file, only the `gene` features are used
+    pub file: String,
+    /// the sample (source) name of the reference that the coordinates of the annotation are on
+    pub source: String,
+}
+
+/// A gene on the reference, the coordinates are 0-based and half-open
+#[derive(Clone, Debug)]
+pub struct GeneFeature {
+    pub bgn: u32,
+    pub end: u32,
+    pub strand: char,
+    pub name: String,
+}
+
+/// The genes of the reference contigs sorted by the start positions
+pub struct GeneAnnotation {
+    pub source: String,
+    ctg_genes: FxHashMap<String, Vec<GeneFeature>>,
+}
+
+/// A gene mapped onto a track of a query result, the coordinates are on the matched target sequence in the orientation of the query
+#[derive(Deserialize, Serialize, Clone, Debug)]
+pub struct GeneBedRecord {
+    pub ctg: String,
+    pub bgn: u32,
+    pub end: u32,
+    pub name: String,
+    pub strand: String,
+}
+
+/// get the gene name from the attributes of a GTF (`gene_name "..."`) or GFF3 (`Name=...`) record,
+/// fall back to the gene id
+fn get_gene_name(attributes: &str) -> Option<String> {
+    let mut name = None;
+    let mut id = None;
+    attributes
+        .split(';')
+        .map(|field| field.trim())
+        .filter(|field| !field.is_empty())
+        .for_each(|field| {
+            let (key, value) = match field.split_once('=') {
+                Some((key, value)) => (key, value),
+                None => field.split_once(' ').unwrap_or((field, "")),
+            };
+            let value = value.trim().trim_matches('"').to_string();
+            match key.trim() {
+                "gene_name" | "Name" => name = Some(value),
+                "gene_id" | "ID" => id = Some(value),
+                _ => {}
+            }
+        });
+    name.or(id)
+}
+
+impl GeneAnnotation {
+    pub fn load_from_file(config: &GeneAnnotationConfig) -> Result<Self, Error> {
+        let reader = BufReader::new(MultiGzDecoder::new(File::open(&config.file)?));
+        Self::load_from_reader(reader, config)
+    }
+
+    /// parse the uncompressed GTF or GFF3 records of the annotation file in `config`
+    fn load_from_reader<R: BufRead>(
+        reader: R,
+        config: &GeneAnnotationConfig,
+    ) -> Result<Self, Error> {
+        let mut ctg_genes = FxHashMap::<String, Vec<GeneFeature>>::default();
+        for (line_number, line) in reader.lines().enumerate() {
+            let line = line?;
+            if line.starts_with('#') || line.trim().is_empty() {
+                continue;
+            }
+            let fields = line.split('\t').collect::<Vec<_>>();
+            let parse_err = || {
+                Error::new(
+                    ErrorKind::InvalidData,
+                    format!(
+                        "can't parse line {} of the gene annotation file {}",
+                        line_number + 1,
+                        config.file
+                    ),
+                )
+            };
+            if fields.len() < 9 {
+                return Err(parse_err());
+            }
+            if fields[2] != "gene" {
+                continue;
+            }
+            let bgn = fields[3].parse::<u32>().map_err(|_| parse_err())?;
+            let end = fields[4].parse::<u32>().map_err(|_| parse_err())?;
+            if bgn == 0 || end < bgn {
+                return Err(parse_err());
+            }
+            let name = get_gene_name(fields[8]).unwrap_or_else(|| "NA".to_string());
+            ctg_genes
+                .entry(fields[0].to_string())
+                .or_default()
+                .push(GeneFeature {
+                    bgn: bgn - 1,
+                    end,
+                    strand: fields[6].chars().next().unwrap_or('.'),
+                    name,
+                });
+        }
+        ctg_genes
+            .values_mut()
+            .for_each(|genes| genes.sort_by_key(|g| (g.bgn, g.end)));
+        Ok(GeneAnnotation {
+            source: config.source.clone(),
+            ctg_genes,
+        })
+    }
+
+    /// the genes overlapping with the region `bgn..end` of a reference contig
+    pub fn get_genes(&self, source: &str, ctg: &str, bgn: u32, end: u32) -> Vec<&GeneFeature> {
+        if source != self.source {
+            return vec![];
+        }
+        self.ctg_genes.get(ctg).map_or(vec![], |genes| {
+            genes
+                .iter()
+                .take_while(|g| g.bgn < end)
+                .filter(|g| g.end > bgn)
+                .collect()
+        })
+    }
+}
+
+/// map the genes on the query sequence to a matched target range `t_bgn..t_end` through the hits,
+/// `q_offset` is the position of the query sequence on the reference contig,
+/// a gene is mapped to the span of the target ends of the hits whose query ends are inside the gene
+pub fn map_genes_to_target(
+    genes: &[&GeneFeature],
+    q_offset: u32,
+    hits: &[HitPair],
+    ctg: &str,
+    (t_bgn, t_end): (u32, u32),
+    reversed: bool,
+) -> Vec<GeneBedRecord> {
+    genes
+        .iter()
+        .filter_map(|g| {
+            let g_bgn = g.bgn.saturating_sub(q_offset);
+            let g_end = g.end.saturating_sub(q_offset);
+            let (bgn, end) = hits
+                .iter()
+                .filter(|hp| hp.0 .0 >= g_bgn && hp.0 .1 <= g_end)
+                .fold(None, |span: Option<(u32, u32)>, hp| {
+                    let (b, e) = (hp.1 .0.max(t_bgn), hp.1 .1.min(t_end));
+                    Some(span.map_or((b, e), |(sb, se)| (sb.min(b), se.max(e))))
+                })?;
+            if end <= bgn {
+                return None;
+            }
+            let (bgn, end) = if reversed {
+                (t_end - end, t_end - bgn)
+            } else {
+                (bgn - t_bgn, end - t_bgn)
+            };
+            Some(GeneBedRecord {
+                ctg: ctg.to_string(),
+                bgn,
+                end,
+                name: g.name.clone(),
+                strand: g.strand.to_string(),
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const TEST_GTF: &str = "\
+##description: a test annotation
+chr1\tHAVANA\tgene\t101\t200\t.\t+\t.\tgene_id \"ENSG01\"; gene_type \"protein_coding\"; gene_name \"GENE1\";
+chr1\tHAVANA\ttranscript\t101\t200\t.\t+\t.\tgene_id \"ENSG01\"; transcript_id \"ENST01\"; gene_name \"GENE1\";
+chr1\tHAVANA\tgene\t301\t400\t.\t-\t.\tgene_id \"ENSG02\"; gene_type \"lncRNA\";
+chr2\tHAVANA\tgene\t11\t20\t.\t+\t.\tgene_id \"ENSG03\"; gene_name \"GENE3\";
+";
+
+    const TEST_GFF3: &str = "\
+##gff-version 3
+chr1\tENSEMBL\tgene\t301\t400\t.\t-\t.\tID=gene:ENSG02;biotype=lncRNA
+chr1\tENSEMBL\tgene\t101\t200\t.\t+\t.\tID=gene:ENSG01;Name=GENE1;biotype=protein_coding
+chr1\tENSEMBL\tmRNA\t101\t200\t.\t+\t.\tID=transcript:ENST01;Parent=gene:ENSG01
+";
+
+    fn load_test_annotation(records: &str) -> Result<GeneAnnotation, Error> {
+        let config = GeneAnnotationConfig {
+            file: "test.gtf.gz".to_string(),
+            source: "ref".to_string(),
+        };
+        GeneAnnotation::load_from_reader(records.as_bytes(), &config)
+    }
+
+    #[test]
+    fn test_get_gene_name() {
+        assert_eq!(
+            get_gene_name(r#"gene_id "ENSG01"; gene_name "GENE1";"#).as_deref(),
+            Some("GENE1")
+        );
+        assert_eq!(
+            get_gene_name(r#"gene_id "ENSG02"; gene_type "lncRNA";"#).as_deref(),
+            Some("ENSG02")
+        );
+        assert_eq!(
+            get_gene_name("ID=gene:ENSG01;Name=GENE1;biotype=protein_coding").as_deref(),
+            Some("GENE1")
+        );
+        assert_eq!(
+            get_gene_name("ID=gene:ENSG02;biotype=lncRNA").as_deref(),
+            Some("gene:ENSG02")
+        );
+        assert_eq!(get_gene_name("biotype=lncRNA"), None);
+    }
+
+    #[test]
+    fn test_get_genes() {
+        for records in [TEST_GTF, TEST_GFF3] {
+            let annotation = load_test_annotation(records).unwrap();
+            let genes = annotation.get_genes("ref", "chr1", 150, 350);
+            assert_eq!(genes.len(), 2);
+            assert_eq!(
+                (genes[0].bgn, genes[0].end, genes[0].strand),
+                (100, 200, '+')
+            );
+            assert_eq!(genes[0].name, "GENE1");
+            assert_eq!(
+                (genes[1].bgn, genes[1].end, genes[1].strand),
+                (300, 400, '-')
+            );
+            assert!(genes[1].name.ends_with("ENSG02"));
+            // the ends are exclusive
+            assert!(annotation.get_genes("ref", "chr1", 200, 300).is_empty());
+            assert!(annotation.get_genes("other", "chr1", 150, 350).is_empty());
+            assert!(annotation.get_genes("ref", "chr3", 0, 1000).is_empty());
+        }
+        let annotation = load_test_annotation(TEST_GTF).unwrap();
+        assert_eq!(annotation.get_genes("ref", "chr2", 0, 100)[0].name, "GENE3");
+
+        assert!(load_test_annotation("chr1\tHAVANA\tgene\t101\t200\n").is_err());
+        assert!(
+            load_test_annotation("chr1\tHAVANA\tgene\t0\t200\t.\t+\t.\tgene_id \"G\";").is_err()
+        );
+        assert!(
+            load_test_annotation("chr1\tHAVANA\tgene\t201\t200\t.\t+\t.\tgene_id \"G\";").is_err()
+        );
+    }
+
+    #[test]
+    fn test_map_genes_to_target() {
+        let gene = GeneFeature {
+            bgn: 1100,
+            end: 1200,
+            strand: '+',
+            name: "GENE1".to_string(),
+        };
+        let genes = vec![&gene];
+        // the query sequence starts at 1050 of the reference contig, the gene is at 50..150 of the query
+        let q_offset = 1050;
+
+        // only the hits inside the gene are used
+        let hits: Vec<HitPair> = vec![
+            ((40, 56, 0), (1040, 1056, 0)),
+            ((60, 76, 0), (1060, 1076, 0)),
+            ((120, 136, 0), (1120, 1136, 0)),
+            ((140, 156, 0), (1140, 1156, 0)),
+        ];
+        let records = map_genes_to_target(&genes, q_offset, &hits, "ctg1", (1000, 1200), false);
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].ctg, "ctg1");
+        assert_eq!((records[0].bgn, records[0].end), (60, 136));
+        assert_eq!(records[0].name, "GENE1");
+        assert_eq!(records[0].strand, "+");
+
+        // a reversed match, the positions are on the reverse complement of the target range
+        let hits: Vec<HitPair> = vec![
+            ((60, 76, 0), (1124, 1140, 1)),
+            ((120, 136, 0), (1064, 1080, 1)),
+        ];
+        let records = map_genes_to_target(&genes, q_offset, &hits, "ctg1", (1000, 1200), true);
+        assert_eq!(records.len(), 1);
+        assert_eq!((records[0].bgn, records[0].end), (60, 136));
+
+        // the hits are clipped to the target range
+        let records = map_genes_to_target(&genes, q_offset, &hits, "ctg1", (1070, 1130), true);
+        assert_eq!((records[0].bgn, records[0].end), (0, 60));
+
+        // no hits inside the gene
+        let records =
+            map_genes_to_target(&genes, q_offset, &hits[..0], "ctg1", (1000, 1200), false);
+        assert!(records.is_empty());
+    }
+}
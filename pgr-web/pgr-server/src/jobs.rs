@@ -0,0 +1,830 @@
+use std::sync::{
+    atomic::{AtomicBool, AtomicU64, Ordering},
+    Arc, Mutex,
+};
+use std::time::{Duration, Instant};
+
+use axum::{
+    extract::{Extension, Path},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    routing::{get, post},
+    Json, Router,
+};
+use rustc_hash::FxHashMap;
+use serde::{Deserialize, Serialize};
+use tokio::sync::{broadcast, Semaphore};
+use utoipa::ToSchema;
+
+use crate::api::ApiError;
+use crate::auth::Identity;
+use crate::bundle_processing::{
+    get_target_and_principal_bundle_decomposition_with_progress, DecompositionStop,
+    SequenceQuerySpec, TargetMatchPrincipalBundles,
+};
+use crate::cache::{with_query, ResultCache};
+use crate::databases::{Database, DatabaseRegistry};
+
+/// The limits of the job queue
+#[derive(Debug, Clone)]
+pub struct JobLimits {
+    /// the number of the jobs running at the same time
+    pub max_running_jobs: usize,
+    /// the number of the jobs waiting or running, a new job is rejected beyond it
+    pub max_pending_jobs: usize,
+    /// the wall time limit of a job
+    pub max_job_time: Duration,
+    /// the limit of the query region length including the padding
+    pub max_query_length: usize,
+    /// the limit of the total length of the matched sequences loaded by a job
+    pub max_total_seq_length: usize,
+    /// how long a finished job and its result are kept
+    pub result_ttl: Duration,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum JobState {
+    Queued,
+    Running,
+    Done,
+    Failed,
+    Cancelled,
+    TimedOut,
+}
+
+impl JobState {
+    pub fn is_finished(&self) -> bool {
+        !matches!(self, JobState::Queued | JobState::Running)
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, ToSchema)]
+pub struct JobStatus {
+    pub id: u64,
+    /// the name of the database queried by the job
+    pub database: String,
+    pub state: JobState,
+    /// the fraction of the work done
+    pub progress: f32,
+    pub stage: String,
+    pub error: Option<String>,
+    /// the seconds since the job was submitted
+    pub elapsed: f32,
+}
+
+struct JobEntry {
+    status: JobStatus,
+    /// the user submitting the job, `None` for an anonymous client
+    owner: Option<String>,
+    cancel: Arc<AtomicBool>,
+    submitted: Instant,
+    finished: Option<Instant>,
+    result: Option<Arc<TargetMatchPrincipalBundles>>,
+}
+
+/// A queue of the principal bundle decomposition jobs that run on a bounded pool of blocking threads
+pub struct JobQueue {
+    cache: Arc<ResultCache>,
+    limits: JobLimits,
+    jobs: Mutex<FxHashMap<u64, JobEntry>>,
+    next_id: AtomicU64,
+    workers: Arc<Semaphore>,
+    updates: broadcast::Sender<JobStatus>,
+    /// new jobs are rejected when the server is shutting down
+    shutting_down: AtomicBool,
+}
+
+impl JobQueue {
+    pub fn new(cache: Arc<ResultCache>, limits: JobLimits) -> Arc<Self> {
+        let (updates, _) = broadcast::channel(1024);
+        Arc::new(JobQueue {
+            cache,
+            workers: Arc::new(Semaphore::new(limits.max_running_jobs.max(1))),
+            limits,
+            jobs: Mutex::new(FxHashMap::default()),
+            next_id: AtomicU64::new(1),
+            updates,
+            shutting_down: AtomicBool::new(false),
+        })
+    }
+
+    /// subscribe to the status updates of all jobs
+    pub fn subscribe(&self) -> broadcast::Receiver<JobStatus> {
+        self.updates.subscribe()
+    }
+
+    /// update a job and broadcast the new status, a finished job is not updated anymore
+    fn update<F: FnOnce(&mut JobEntry)>(&self, id: u64, f: F) -> Option<JobStatus> {
+        let mut jobs = self.jobs.lock().unwrap();
+        let job = jobs.get_mut(&id)?;
+        if job.status.state.is_finished() {
+            return None;
+        }
+        f(job);
+        if job.status.state.is_finished() {
+            job.finished = Some(Instant::now());
+        }
+        job.status.elapsed = job.submitted.elapsed().as_secs_f32();
+        let status = job.status.clone();
+        let _ = self.updates.send(status.clone());
+        Some(status)
+    }
+
+    fn purge_expired_jobs(&self) {
+        let ttl = self.limits.result_ttl;
+        self.jobs
+            .lock()
+            .unwrap()
+            .retain(|_, job| !matches!(job.finished, Some(t) if t.elapsed() > ttl));
+    }
+
+    pub fn submit(
+        self: &Arc<Self>,
+        db: Arc<Database>,
+        spec: SequenceQuerySpec,
+        owner: Option<String>,
+    ) -> Result<JobStatus, ApiError> {
+        if self.shutting_down.load(Ordering::SeqCst) {
+            return Err(ApiError(
+                StatusCode::SERVICE_UNAVAILABLE,
+                "the server is shutting down".to_string(),
+            ));
+        }
+        if spec.bgn >= spec.end {
+            return Err(ApiError::bad_request("the query range is empty"));
+        }
+        let query_length = spec
+            .padding
+            .checked_mul(2)
+            .and_then(|padding| padding.checked_add(spec.end - spec.bgn))
+            .ok_or_else(|| ApiError::bad_request("the query padding is too large"))?;
+        if query_length > self.limits.max_query_length {
+            return Err(ApiError::bad_request(format!(
+                "the query length {} with the padding is larger than the limit {}",
+                query_length, self.limits.max_query_length
+            )));
+        }
+        self.purge_expired_jobs();
+
+        let id = self.next_id.fetch_add(1, Ordering::SeqCst);
+        let cancel = Arc::new(AtomicBool::new(false));
+        let status = JobStatus {
+            id,
+            database: db.config.name.clone(),
+            state: JobState::Queued,
+            progress: 0.0,
+            stage: "queued".to_string(),
+            error: None,
+            elapsed: 0.0,
+        };
+        {
+            let mut jobs = self.jobs.lock().unwrap();
+            let pending = jobs
+                .values()
+                .filter(|job| !job.status.state.is_finished())
+                .count();
+            if pending >= self.limits.max_pending_jobs {
+                return Err(ApiError(
+                    StatusCode::SERVICE_UNAVAILABLE,
+                    "too many pending jobs, please try again later".to_string(),
+                ));
+            }
+            jobs.insert(
+                id,
+                JobEntry {
+                    status: status.clone(),
+                    owner,
+                    cancel: cancel.clone(),
+                    submitted: Instant::now(),
+                    finished: None,
+                    result: None,
+                },
+            );
+        }
+
+        let queue = self.clone();
+        tokio::spawn(async move { queue.run_job(id, db, spec, cancel).await });
+        Ok(status)
+    }
+
+    async fn run_job(
+        self: Arc<Self>,
+        id: u64,
+        db: Arc<Database>,
+        spec: SequenceQuerySpec,
+        cancel: Arc<AtomicBool>,
+    ) {
+        let permit = self.workers.clone().acquire_owned().await.unwrap();
+        if cancel.load(Ordering::SeqCst) {
+            return;
+        }
+        self.update(id, |job| {
+            job.status.state = JobState::Running;
+            job.status.stage = "started".to_string();
+        });
+
+        let cache_key = self.cache.get_key(&spec, &db);
+        if let Some(result) = self.cache.get_data(&cache_key) {
+            let result = with_query(result, &spec);
+            self.update(id, |job| {
+                job.status.state = JobState::Done;
+                job.status.progress = 1.0;
+                job.status.stage = "done (cached)".to_string();
+                job.result = Some(result);
+            });
+            return;
+        }
+
+        let queue = self.clone();
+        let job_cancel = cancel.clone();
+        let task = tokio::task::spawn_blocking(move || {
+            // the worker slot is held until the computation really stops, even if the job has timed out
+            let _permit = permit;
+            let progress = |fraction: f32, stage: &str| {
+                if job_cancel.load(Ordering::SeqCst) {
+                    return false;
+                }
+                queue.update(id, |job| {
+                    job.status.progress = fraction;
+                    job.status.stage = stage.to_string();
+                });
+                true
+            };
+            get_target_and_principal_bundle_decomposition_with_progress(
+                &spec,
+                db.seq_db.clone(),
+                db.genes.as_ref().as_ref(),
+                Some(queue.limits.max_total_seq_length),
+                &progress,
+            )
+        });
+
+        let (state, error, result) =
+            match tokio::time::timeout(self.limits.max_job_time, task).await {
+                Err(_) => {
+                    cancel.store(true, Ordering::SeqCst);
+                    (
+                        JobState::TimedOut,
+                        Some(format!(
+                            "the job takes longer than {} seconds",
+                            self.limits.max_job_time.as_secs()
+                        )),
+                        None,
+                    )
+                }
+                Ok(Err(err)) => (
+                    JobState::Failed,
+                    Some(format!("the job fails: {}", err)),
+                    None,
+                ),
+                Ok(Ok(Ok(Some(result)))) => {
+                    let result = Arc::new(result);
+                    self.cache.insert_data(cache_key, result.clone());
+                    (JobState::Done, None, Some(result))
+                }
+                Ok(Ok(Ok(None))) => (
+                    JobState::Failed,
+                    Some("no result is generated".to_string()),
+                    None,
+                ),
+                Ok(Ok(Err(DecompositionStop::Cancelled))) => (JobState::Cancelled, None, None),
+                Ok(Ok(Err(DecompositionStop::LimitExceeded(msg)))) => {
+                    (JobState::Failed, Some(msg), None)
+                }
+            };
+        self.update(id, |job| {
+            job.status.state = state;
+            job.status.error = error;
+            if state == JobState::Done {
+                job.status.progress = 1.0;
+            }
+            job.result = result;
+        });
+    }
+
+    pub fn status(&self, id: u64) -> Option<JobStatus> {
+        self.jobs.lock().unwrap().get(&id).map(|job| {
+            let mut status = job.status.clone();
+            status.elapsed = job
+                .finished
+                .unwrap_or_else(Instant::now)
+                .duration_since(job.submitted)
+                .as_secs_f32();
+            status
+        })
+    }
+
+    /// whether the job is readable by the client, only the user submitting the job or an admin can access it
+    pub fn can_access(&self, identity: &Identity, id: u64) -> bool {
+        self.jobs.lock().unwrap().get(&id).is_some_and(|job| {
+            identity.can_read(&job.status.database) && identity.can_access(job.owner.as_deref())
+        })
+    }
+
+    pub fn list(&self) -> Vec<JobStatus> {
+        let mut ids = self
+            .jobs
+            .lock()
+            .unwrap()
+            .keys()
+            .copied()
+            .collect::<Vec<_>>();
+        ids.sort();
+        ids.into_iter().filter_map(|id| self.status(id)).collect()
+    }
+
+    /// cancel a job, a running job stops at its next stage
+    pub fn cancel(&self, id: u64) -> Option<JobStatus> {
+        let cancel = self.jobs.lock().unwrap().get(&id)?.cancel.clone();
+        cancel.store(true, Ordering::SeqCst);
+        self.update(id, |job| {
+            job.status.state = JobState::Cancelled;
+        });
+        self.status(id)
+    }
+
+    pub fn result(&self, id: u64) -> Option<(JobStatus, Option<Arc<TargetMatchPrincipalBundles>>)> {
+        let result = self.jobs.lock().unwrap().get(&id)?.result.clone();
+        Some((self.status(id)?, result))
+    }
+
+    /// the number of the queued or running jobs
+    pub fn pending_jobs(&self) -> usize {
+        self.jobs
+            .lock()
+            .unwrap()
+            .values()
+            .filter(|job| !job.status.state.is_finished())
+            .count()
+    }
+
+    /// stop accepting new jobs and wait for the pending jobs to finish, the jobs still pending after `timeout` are cancelled
+    pub async fn shutdown(&self, timeout: Duration) {
+        self.shutting_down.store(true, Ordering::SeqCst);
+        let start = Instant::now();
+        while self.pending_jobs() > 0 && start.elapsed() < timeout {
+            tokio::time::sleep(Duration::from_millis(200)).await;
+        }
+        let pending_ids = self
+            .jobs
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|(_, job)| !job.status.state.is_finished())
+            .map(|(id, _)| *id)
+            .collect::<Vec<_>>();
+        if !pending_ids.is_empty() {
+            tracing::warn!(jobs = pending_ids.len(), "cancelling the unfinished jobs");
+        }
+        pending_ids.into_iter().for_each(|id| {
+            self.cancel(id);
+        });
+    }
+}
+
+/// build the router of the job endpoints, the jobs are submitted to a database and their ids are unique in the server
+pub fn job_router(job_queue: Arc<JobQueue>, registry: Arc<DatabaseRegistry>) -> Router {
+    Router::new()
+        .route(
+            "/api/v1/db/:db_name/jobs",
+            post({
+                let job_queue = job_queue.clone();
+                move |db_name, identity, params| {
+                    post_job(db_name, identity, params, job_queue, registry)
+                }
+            }),
+        )
+        .route(
+            "/api/v1/jobs",
+            get({
+                let job_queue = job_queue.clone();
+                move |identity| get_jobs(identity, job_queue)
+            }),
+        )
+        .route(
+            "/api/v1/jobs/:id",
+            get({
+                let job_queue = job_queue.clone();
+                move |identity, params| get_job(identity, params, job_queue)
+            })
+            .delete({
+                let job_queue = job_queue.clone();
+                move |identity, params| delete_job(identity, params, job_queue)
+            }),
+        )
+        .route(
+            "/api/v1/jobs/:id/result",
+            get({
+                let job_queue = job_queue.clone();
+                move |identity, params| get_job_result(identity, params, job_queue)
+            }),
+        )
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/db/{db_name}/jobs",
+    tag = "pgr-server",
+    params(("db_name" = String, Path, description = "the database name")),
+    request_body = SequenceQuerySpec,
+    responses(
+        (status = 202, description = "the job is queued", body = JobStatus),
+        (status = 400, description = "invalid query", body = crate::api::ApiErrorMessage),
+        (status = 404, description = "database not found", body = crate::api::ApiErrorMessage),
+        (status = 503, description = "too many pending jobs", body = crate::api::ApiErrorMessage)
+    )
+)]
+pub async fn post_job(
+    Path(db_name): Path<String>,
+    Extension(identity): Extension<Identity>,
+    Json(spec): Json<SequenceQuerySpec>,
+    job_queue: Arc<JobQueue>,
+    registry: Arc<DatabaseRegistry>,
+) -> Result<(StatusCode, Json<JobStatus>), ApiError> {
+    let db = registry.get(&db_name).await?;
+    let status = job_queue.submit(db, spec, identity.name)?;
+    Ok((StatusCode::ACCEPTED, Json(status)))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/jobs",
+    tag = "pgr-server",
+    responses(
+        (status = 200, description = "the status of the jobs of the client kept in the server", body = [JobStatus])
+    )
+)]
+pub async fn get_jobs(
+    Extension(identity): Extension<Identity>,
+    job_queue: Arc<JobQueue>,
+) -> Json<Vec<JobStatus>> {
+    Json(
+        job_queue
+            .list()
+            .into_iter()
+            .filter(|s| job_queue.can_access(&identity, s.id))
+            .collect(),
+    )
+}
+
+/// the job is not found if its database is not readable by the client or it is submitted by another user
+fn check_job_access(identity: &Identity, job_queue: &JobQueue, id: u64) -> Result<(), ApiError> {
+    if job_queue.can_access(identity, id) {
+        Ok(())
+    } else {
+        Err(ApiError::not_found(format!("job {} not found", id)))
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/jobs/{id}",
+    tag = "pgr-server",
+    params(("id" = u64, Path, description = "the job id")),
+    responses(
+        (status = 200, description = "the job status", body = JobStatus),
+        (status = 404, description = "job not found", body = crate::api::ApiErrorMessage)
+    )
+)]
+pub async fn get_job(
+    Extension(identity): Extension<Identity>,
+    Path(id): Path<u64>,
+    job_queue: Arc<JobQueue>,
+) -> Result<Json<JobStatus>, ApiError> {
+    check_job_access(&identity, &job_queue, id)?;
+    job_queue
+        .status(id)
+        .map(Json)
+        .ok_or_else(|| ApiError::not_found(format!("job {} not found", id)))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/api/v1/jobs/{id}",
+    tag = "pgr-server",
+    params(("id" = u64, Path, description = "the job id")),
+    responses(
+        (status = 200, description = "the job status after the cancellation", body = JobStatus),
+        (status = 404, description = "job not found", body = crate::api::ApiErrorMessage)
+    )
+)]
+pub async fn delete_job(
+    Extension(identity): Extension<Identity>,
+    Path(id): Path<u64>,
+    job_queue: Arc<JobQueue>,
+) -> Result<Json<JobStatus>, ApiError> {
+    check_job_access(&identity, &job_queue, id)?;
+    job_queue
+        .cancel(id)
+        .map(Json)
+        .ok_or_else(|| ApiError::not_found(format!("job {} not found", id)))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/jobs/{id}/result",
+    tag = "pgr-server",
+    params(("id" = u64, Path, description = "the job id")),
+    responses(
+        (status = 200, description = "the principal bundle decomposition of the query region and its matches"),
+        (status = 404, description = "job not found", body = crate::api::ApiErrorMessage),
+        (status = 409, description = "the job is not done", body = JobStatus)
+    )
+)]
+pub async fn get_job_result(
+    Extension(identity): Extension<Identity>,
+    Path(id): Path<u64>,
+    job_queue: Arc<JobQueue>,
+) -> Result<Response, ApiError> {
+    check_job_access(&identity, &job_queue, id)?;
+    let (status, result) = job_queue
+        .result(id)
+        .ok_or_else(|| ApiError::not_found(format!("job {} not found", id)))?;
+    match result {
+        Some(result) => Ok(Json(result.as_ref()).into_response()),
+        None => Ok((StatusCode::CONFLICT, Json(status)).into_response()),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::databases::{DatabaseBackend, DatabaseConfig, DatabaseListConfig};
+    use axum::body::Body;
+    use axum::http::{Method, Request};
+    use tower::ServiceExt;
+
+    const TEST_DB_PREFIX: &str = concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/../../pgr-db/test/test_data/test_seqs_frag"
+    );
+
+    fn test_limits() -> JobLimits {
+        JobLimits {
+            max_running_jobs: 1,
+            max_pending_jobs: 2,
+            max_job_time: Duration::from_secs(60),
+            max_query_length: 100_000,
+            max_total_seq_length: 10_000_000,
+            result_ttl: Duration::from_secs(60),
+        }
+    }
+
+    fn test_spec() -> SequenceQuerySpec {
+        SequenceQuerySpec {
+            source: "test".to_string(),
+            ctg: "test_ctg".to_string(),
+            bgn: 0,
+            end: 1000,
+            padding: 100,
+            merge_range_tol: 100,
+            w: 48,
+            k: 56,
+            r: 4,
+            min_span: 12,
+            sketch: false,
+            min_cov: 2,
+            min_branch_size: 8,
+            bundle_length_cutoff: 500,
+            bundle_merge_distance: 10000,
+        }
+    }
+
+    fn setup() -> (
+        Arc<JobQueue>,
+        Arc<DatabaseRegistry>,
+        Arc<ResultCache>,
+        Router,
+    ) {
+        let registry = Arc::new(
+            DatabaseRegistry::new(DatabaseListConfig {
+                default: None,
+                databases: vec![DatabaseConfig::new(
+                    "test".to_string(),
+                    DatabaseBackend::Frg,
+                    TEST_DB_PREFIX.to_string(),
+                )],
+            })
+            .unwrap(),
+        );
+        let cache = Arc::new(ResultCache::new(1 << 20, None));
+        let job_queue = JobQueue::new(cache.clone(), test_limits());
+        let app = job_router(job_queue.clone(), registry.clone())
+            .layer(Extension(Identity::unrestricted()));
+        (job_queue, registry, cache, app)
+    }
+
+    async fn send(
+        app: &Router,
+        method: Method,
+        uri: &str,
+        body: Option<&SequenceQuerySpec>,
+    ) -> (StatusCode, serde_json::Value) {
+        let request = Request::builder().method(method).uri(uri);
+        let request = match body {
+            Some(spec) => request
+                .header("content-type", "application/json")
+                .body(Body::from(serde_json::to_vec(spec).unwrap())),
+            None => request.body(Body::empty()),
+        }
+        .unwrap();
+        let response = app.clone().oneshot(request).await.unwrap();
+        let status = response.status();
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        (status, serde_json::from_slice(&body).unwrap())
+    }
+
+    async fn submit(app: &Router, spec: &SequenceQuerySpec) -> (StatusCode, serde_json::Value) {
+        send(app, Method::POST, "/api/v1/db/test/jobs", Some(spec)).await
+    }
+
+    #[tokio::test]
+    async fn test_submit_status_and_cancel() {
+        let (job_queue, _registry, _cache, app) = setup();
+        // hold all workers so the jobs stay in the queue
+        let _workers = job_queue
+            .workers
+            .clone()
+            .acquire_many_owned(1)
+            .await
+            .unwrap();
+
+        let (status, job) = submit(&app, &test_spec()).await;
+        assert_eq!(status, StatusCode::ACCEPTED);
+        assert_eq!(job["state"], "queued");
+        assert_eq!(job["database"], "test");
+        let id = job["id"].as_u64().unwrap();
+
+        let (status, job) = send(&app, Method::GET, &format!("/api/v1/jobs/{}", id), None).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(job["id"].as_u64(), Some(id));
+        assert_eq!(job["state"], "queued");
+
+        let (status, jobs) = send(&app, Method::GET, "/api/v1/jobs", None).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(jobs.as_array().unwrap().len(), 1);
+
+        let (status, job) = send(
+            &app,
+            Method::GET,
+            &format!("/api/v1/jobs/{}/result", id),
+            None,
+        )
+        .await;
+        assert_eq!(status, StatusCode::CONFLICT);
+        assert_eq!(job["state"], "queued");
+
+        let (status, job) = send(&app, Method::DELETE, &format!("/api/v1/jobs/{}", id), None).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(job["state"], "cancelled");
+
+        let (status, job) = send(
+            &app,
+            Method::GET,
+            &format!("/api/v1/jobs/{}/result", id),
+            None,
+        )
+        .await;
+        assert_eq!(status, StatusCode::CONFLICT);
+        assert_eq!(job["state"], "cancelled");
+
+        let (status, _) = send(&app, Method::GET, "/api/v1/jobs/12345", None).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_pending_job_limit() {
+        let (job_queue, _registry, _cache, app) = setup();
+        let _workers = job_queue
+            .workers
+            .clone()
+            .acquire_many_owned(1)
+            .await
+            .unwrap();
+
+        let mut ids = vec![];
+        for _ in 0..test_limits().max_pending_jobs {
+            let (status, job) = submit(&app, &test_spec()).await;
+            assert_eq!(status, StatusCode::ACCEPTED);
+            ids.push(job["id"].as_u64().unwrap());
+        }
+        let (status, err) = submit(&app, &test_spec()).await;
+        assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+        assert!(err["error"]
+            .as_str()
+            .unwrap()
+            .contains("too many pending jobs"));
+
+        // a cancelled job is not pending anymore
+        let (status, _) = send(
+            &app,
+            Method::DELETE,
+            &format!("/api/v1/jobs/{}", ids[0]),
+            None,
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        let (status, _) = submit(&app, &test_spec()).await;
+        assert_eq!(status, StatusCode::ACCEPTED);
+
+        let mut spec = test_spec();
+        spec.end = spec.bgn;
+        let (status, _) = submit(&app, &spec).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        let mut spec = test_spec();
+        spec.padding = usize::MAX / 2 + 1;
+        let (status, err) = submit(&app, &spec).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(err["error"], "the query padding is too large");
+        spec.padding = usize::MAX / 2 - 10;
+        let (status, _) = submit(&app, &spec).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        let (status, _) = send(
+            &app,
+            Method::POST,
+            "/api/v1/db/unknown/jobs",
+            Some(&test_spec()),
+        )
+        .await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_job_owner() {
+        let (job_queue, registry, _cache, _app) = setup();
+        let _workers = job_queue
+            .workers
+            .clone()
+            .acquire_many_owned(1)
+            .await
+            .unwrap();
+        let user_app = |name: &str, admin: bool| {
+            let identity = Identity::new(Some(name.to_string()), &["test".to_string()], &[], admin);
+            job_router(job_queue.clone(), registry.clone()).layer(Extension(identity))
+        };
+        let alice = user_app("alice", false);
+        let bob = user_app("bob", false);
+        let admin = user_app("carol", true);
+
+        let (status, job) = submit(&alice, &test_spec()).await;
+        assert_eq!(status, StatusCode::ACCEPTED);
+        let uri = format!("/api/v1/jobs/{}", job["id"].as_u64().unwrap());
+
+        // another user can't see, fetch or cancel the job
+        let (status, _) = send(&bob, Method::DELETE, &uri, None).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        let (status, _) = send(&bob, Method::GET, &uri, None).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        let (status, _) = send(&bob, Method::GET, &format!("{}/result", uri), None).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        let (_, jobs) = send(&bob, Method::GET, "/api/v1/jobs", None).await;
+        assert!(jobs.as_array().unwrap().is_empty());
+        let (_, job) = send(&alice, Method::GET, &uri, None).await;
+        assert_eq!(job["state"], "queued");
+
+        let (_, jobs) = send(&alice, Method::GET, "/api/v1/jobs", None).await;
+        assert_eq!(jobs.as_array().unwrap().len(), 1);
+        let (status, job) = send(&admin, Method::DELETE, &uri, None).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(job["state"], "cancelled");
+    }
+
+    #[tokio::test]
+    async fn test_cached_job_result() {
+        let (_job_queue, registry, cache, app) = setup();
+        let spec = test_spec();
+        let db = registry.get("test").await.unwrap();
+        cache.insert_data(
+            cache.get_key(&spec, &db),
+            Arc::new(TargetMatchPrincipalBundles {
+                query: spec.clone(),
+                match_summary: vec![],
+                sid_ctg_src: vec![(0, "test_ctg".to_string(), "test".to_string())],
+                bundle_bed_records: vec![],
+                dendrogram: None,
+                gene_records: vec![],
+            }),
+        );
+
+        let (status, job) = submit(&app, &spec).await;
+        assert_eq!(status, StatusCode::ACCEPTED);
+        let id = job["id"].as_u64().unwrap();
+        let uri = format!("/api/v1/jobs/{}", id);
+        let mut job = job;
+        for _ in 0..100 {
+            if job["state"] == "done" {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+            job = send(&app, Method::GET, &uri, None).await.1;
+        }
+        assert_eq!(job["state"], "done");
+        assert_eq!(job["stage"], "done (cached)");
+
+        let (status, result) = send(&app, Method::GET, &format!("{}/result", uri), None).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(result["sid_ctg_src"][0][1], "test_ctg");
+        assert_eq!(result["query"]["end"], 1000);
+    }
+}
@@ -1,30 +1,47 @@
+pub mod api;
+pub mod auth;
 pub mod bundle_processing;
+pub mod cache;
+pub mod databases;
+pub mod exports;
+pub mod genes;
+pub mod jobs;
+pub mod metrics;
+pub mod rois;
 
+use api::ApiError;
+use auth::{AuthConfig, AuthState, Identity};
 use axum::{
     body::{boxed, Body},
     extract::ws::{WebSocket, WebSocketUpgrade},
-    extract::Query,
+    extract::{Extension, Query},
     http::{Response, StatusCode},
-    response,
+    middleware, response,
     response::Html,
     routing::{get, post},
     Json, Router,
 };
 use bundle_processing::*;
+use cache::ResultCache;
 use clap::{self, Parser};
-use pgr_db::ext::*;
+use databases::{DatabaseBackend, DatabaseConfig, DatabaseListConfig, DatabaseRegistry};
+use jobs::{JobLimits, JobQueue};
+use metrics::ServerMetrics;
+use rois::{NamedQueryStore, UserQueryLimits};
 use rustc_hash::FxHashMap;
+use serde::Deserialize;
 use std::net::SocketAddr;
 use std::{
     net::{IpAddr, Ipv6Addr},
     path::PathBuf,
     str::FromStr,
     sync::Arc,
+    time::Duration,
 };
 use tokio::fs;
 use tower::{ServiceBuilder, ServiceExt};
 use tower_http::cors::Any;
-use tower_http::cors::CorsLayer;
+use tower_http::cors::{AllowOrigin, CorsLayer};
 use tower_http::services::ServeDir;
 use tower_http::trace::TraceLayer;
 use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
@@ -44,6 +61,11 @@ struct Opt {
     #[clap(long = "static-dir", default_value = "./dist")]
     static_dir: String,
 
+    /// set the path to a JSON file listing the databases to serve, `--data-path-prefix`, `--frg-file` and `--pdb-file`
+    /// are ignored if it is set
+    #[clap(short = 'c', long = "config")]
+    config: Option<String>,
+
     /// set data_path_prefix
     #[clap(
         short = 'd',
@@ -55,6 +77,76 @@ struct Opt {
     /// set the listen port
     #[clap(short = 'f', long = "frg-file")]
     frg_file: bool,
+
+    /// set the path to a pre-computed principal bundle (.pdb) file for decomposing new sequences
+    #[clap(long = "pdb-file")]
+    pdb_file: Option<String>,
+
+    /// set the path to a JSON file or a directory of the JSON files of the ROI presets, the built-in presets are
+    /// used if it is not set, it is ignored if `--config` is set
+    #[clap(long = "roi-path")]
+    roi_path: Option<String>,
+
+    /// set the JSON file for storing the queries saved by the users, they are kept only in the memory if it is not set
+    #[clap(long = "user-query-file")]
+    user_query_file: Option<String>,
+
+    /// set the number of the queries a user can save, the anonymous clients can only save the queries when
+    /// `--auth-config` is not set
+    #[clap(long = "max-user-queries", default_value_t = 1000)]
+    max_user_queries: usize,
+
+    /// set the path to a JSON file of the API keys, the token secret, the database permissions, the rate limit
+    /// and the audit log, all databases are readable without the credentials if it is not set
+    #[clap(long = "auth-config")]
+    auth_config: Option<String>,
+
+    /// set the number of the decomposition jobs running at the same time
+    #[clap(long = "max-running-jobs", default_value_t = 2)]
+    max_running_jobs: usize,
+
+    /// set the number of the waiting or running jobs, new jobs are rejected beyond it
+    #[clap(long = "max-pending-jobs", default_value_t = 64)]
+    max_pending_jobs: usize,
+
+    /// set the wall time limit (in seconds) of a job
+    #[clap(long = "max-job-time", default_value_t = 600)]
+    max_job_time: u64,
+
+    /// set the limit of the query region length (including the padding) of a job
+    #[clap(long = "max-job-query-length", default_value_t = 2_000_000)]
+    max_job_query_length: usize,
+
+    /// set the limit of the sequence length fetched or queried by a request of the `/api/v1/db/{db_name}/seq`,
+    /// `/api/v1/db/{db_name}/query` and `/api/v1/db/{db_name}/shmmr_pair_counts` endpoints
+    #[clap(long = "max-seq-length", default_value_t = 2_000_000)]
+    max_seq_length: usize,
+
+    /// set the limit of the total length of the matched sequences loaded by a job
+    #[clap(long = "max-job-seq-length", default_value_t = 500_000_000)]
+    max_job_seq_length: usize,
+
+    /// set how long (in seconds) a finished job and its result are kept
+    #[clap(long = "job-result-ttl", default_value_t = 3600)]
+    job_result_ttl: u64,
+
+    /// set the size limit (in MB) of the in-memory query result cache, it is split evenly between the query results and
+    /// the rendered HTML, 0 disables the cache
+    #[clap(long = "cache-size", default_value_t = 256)]
+    cache_size: usize,
+
+    /// set the directory for writing the query results evicted from the in-memory cache
+    #[clap(long = "cache-dir")]
+    cache_dir: Option<String>,
+
+    /// write the logs as JSON lines instead of the human readable text
+    #[clap(long = "json-logs")]
+    json_logs: bool,
+
+    /// set how long (in seconds) the server waits for the in-flight requests and jobs after SIGTERM or Ctrl-C,
+    /// the unfinished jobs are cancelled after it
+    #[clap(long = "shutdown-timeout", default_value_t = 60)]
+    shutdown_timeout: u64,
 }
 
 #[tokio::main]
@@ -63,52 +155,176 @@ async fn main() {
 
     tracing_subscriber::registry()
         .with(tracing_subscriber::EnvFilter::new(
-            std::env::var("RUST_LOG")
-                .unwrap_or_else(|_| "example_tracing_aka_logging=debug,tower_http=debug".into()),
+            std::env::var("RUST_LOG").unwrap_or_else(|_| "pgr_server=info,tower_http=debug".into()),
         ))
-        .with(tracing_subscriber::fmt::layer())
+        .with(
+            opt.json_logs
+                .then(|| tracing_subscriber::fmt::layer().json()),
+        )
+        .with((!opt.json_logs).then(tracing_subscriber::fmt::layer))
         .init();
 
-    let mut seq_db = SeqIndexDB::new();
+    let registry = match opt.config.as_ref() {
+        Some(config) => DatabaseRegistry::from_config_file(config).unwrap_or_else(|err| {
+            panic!("can't read the database config file {}: {}", config, err)
+        }),
+        None => {
+            #[cfg(not(feature = "with_agc"))]
+            if !opt.frg_file {
+                panic!("This command is compiled with only frg file support, please specify `--frg-file");
+            }
+            let backend = if opt.frg_file {
+                DatabaseBackend::Frg
+            } else {
+                DatabaseBackend::Agc
+            };
+            let mut config =
+                DatabaseConfig::new("default".to_string(), backend, opt.data_path_prefix.clone());
+            config.pdb_file = opt.pdb_file.clone();
+            config.roi_path = opt.roi_path.clone();
+            DatabaseRegistry::new(DatabaseListConfig {
+                default: None,
+                databases: vec![config],
+            })
+            .unwrap()
+        }
+    };
+    let named_queries = NamedQueryStore::new(
+        &registry,
+        opt.user_query_file.as_ref().map(PathBuf::from),
+        opt.config.is_none(),
+        UserQueryLimits {
+            require_user: opt.auth_config.is_some(),
+            max_queries_per_user: opt.max_user_queries,
+        },
+    )
+    .unwrap_or_else(|err| panic!("can't read the user query file: {}", err));
+    let named_queries = Arc::new(named_queries);
+    let auth = match opt.auth_config.as_ref() {
+        Some(auth_config) => {
+            AuthState::from_config_file(auth_config, registry.default_name().to_string())
+                .unwrap_or_else(|err| {
+                    panic!("can't read the auth config file {}: {}", auth_config, err)
+                })
+        }
+        None => AuthState::new(
+            AuthConfig {
+                public_databases: vec!["*".to_string()],
+                ..Default::default()
+            },
+            registry.default_name().to_string(),
+        )
+        .unwrap(),
+    };
+    let auth = Arc::new(auth);
+    let registry = Arc::new(registry);
 
-    if opt.frg_file {
-        let _ = seq_db.load_from_frg_index(opt.data_path_prefix);
-    } else {
-        #[cfg(feature = "with_agc")]
-        let _ = seq_db.load_from_agc_index(opt.data_path_prefix);
+    let cache = Arc::new(ResultCache::new(
+        opt.cache_size << 20,
+        opt.cache_dir.as_ref().map(PathBuf::from),
+    ));
 
-        #[cfg(not(feature = "with_agc"))]
-        panic!("This command is compiled with only frg file support, please specify `--frg-file");
-    }
+    let job_queue = JobQueue::new(
+        cache.clone(),
+        JobLimits {
+            max_running_jobs: opt.max_running_jobs,
+            max_pending_jobs: opt.max_pending_jobs,
+            max_job_time: Duration::from_secs(opt.max_job_time),
+            max_query_length: opt.max_job_query_length,
+            max_total_seq_length: opt.max_job_seq_length,
+            result_ttl: Duration::from_secs(opt.job_result_ttl),
+        },
+    );
+    let metrics = Arc::new(ServerMetrics::new());
+    // the server is ready after the default database is loaded
+    tokio::spawn({
+        let registry = registry.clone();
+        async move {
+            let _ = registry.get_or_default(None).await;
+        }
+    });
 
-    let seq_db = Arc::new(seq_db);
     // build our application with a route
     let app = Router::new()
         .route(
             "/api/get_number_of_ctgs",
             get({
-                let seq_db = seq_db.clone();
-                move || get_number_of_ctgs(seq_db)
+                let registry = registry.clone();
+                move |db| get_number_of_ctgs(db, registry)
             }),
         )
         .route(
             "/api/post_query_for_json_data",
             post({
-                let seq_db = seq_db.clone();
-                move |params| post_query_for_json_data(params, seq_db)
+                let registry = registry.clone();
+                let cache = cache.clone();
+                move |db, params| post_query_for_json_data(db, params, registry, cache)
             }),
         )
         .route(
             "/api/get_html_by_query",
             get({
-                let seq_db = seq_db.clone();
-                move |params| get_html_by_query(params, seq_db)
+                let registry = registry.clone();
+                let cache = cache.clone();
+                move |db, params| get_html_by_query(db, params, registry, cache)
+            }),
+        )
+        .route(
+            "/api/get_principal_bundle_stats",
+            get({
+                let registry = registry.clone();
+                move |db| get_principal_bundle_stats(db, registry)
+            }),
+        )
+        .route(
+            "/api/post_principal_bundle_decomposition",
+            post({
+                let registry = registry.clone();
+                move |db, params| post_principal_bundle_decomposition(db, params, registry)
+            }),
+        )
+        .route(
+            "/ws",
+            get({
+                let job_queue = job_queue.clone();
+                let named_queries = named_queries.clone();
+                move |identity, ws| ws_handler(identity, ws, job_queue, named_queries)
             }),
         )
-        .route("/ws", get(ws_handler))
+        .merge(api::api_v1_router(registry.clone(), opt.max_seq_length))
+        .merge(jobs::job_router(job_queue.clone(), registry.clone()))
+        .merge(exports::export_router(registry.clone(), cache.clone()))
+        .merge(rois::named_query_router(
+            named_queries.clone(),
+            registry.clone(),
+        ))
+        .route(
+            "/api/v1/cache/stats",
+            get({
+                let cache = cache.clone();
+                move || cache::get_cache_stats(cache)
+            }),
+        )
+        .merge(metrics::health_router(
+            metrics.clone(),
+            registry.clone(),
+            cache.clone(),
+            job_queue.clone(),
+        ))
+        .layer(middleware::from_fn({
+            let auth = auth.clone();
+            move |req, next| auth::auth_middleware(req, next, auth.clone())
+        }))
+        .layer(middleware::from_fn({
+            let metrics = metrics.clone();
+            move |req, next| metrics::metrics_middleware(req, next, metrics.clone())
+        }))
         .layer(
             CorsLayer::new()
-                .allow_origin(Any)
+                .allow_origin(match auth.allowed_origins() {
+                    Some(origins) => AllowOrigin::list(origins),
+                    None => Any.into(),
+                })
                 //.allow_origin("http://127.0.0.1:8080".parse::<HeaderValue>().unwrap())
                 .allow_methods(Any)
                 .allow_headers(Any),
@@ -141,7 +357,7 @@ async fn main() {
                 }
                 Err(_err) => Response::builder()
                     .status(StatusCode::INTERNAL_SERVER_ERROR)
-                    .body(boxed(Body::from(format!("internal errors"))))
+                    .body(boxed(Body::from("internal errors".to_string())))
                     .expect("error response"),
             }
         }));
@@ -151,11 +367,73 @@ async fn main() {
         IpAddr::from_str(opt.addr.as_str()).unwrap_or(IpAddr::V6(Ipv6Addr::LOCALHOST)),
         opt.port,
     ));
-    println!("listening on {}", addr);
-    axum::Server::bind(&addr)
-        .serve(app.into_make_service())
-        .await
-        .unwrap();
+    let server = match axum::Server::try_bind(&addr) {
+        Ok(server) => server,
+        Err(err) => {
+            tracing::error!(%addr, error = %err, "can't listen on the address");
+            std::process::exit(1);
+        }
+    };
+    tracing::info!(%addr, "listening");
+    let shutdown_timeout = Duration::from_secs(opt.shutdown_timeout);
+    let (signaled_tx, signaled_rx) = tokio::sync::oneshot::channel::<()>();
+    let served = server
+        .serve(app.into_make_service_with_connect_info::<SocketAddr>())
+        .with_graceful_shutdown({
+            let metrics = metrics.clone();
+            async move {
+                shutdown_signal().await;
+                tracing::info!(
+                    requests = metrics.in_flight_requests(),
+                    "shutting down, waiting for the in-flight requests"
+                );
+                metrics.set_shutting_down();
+                let _ = signaled_tx.send(());
+            }
+        });
+    tokio::pin!(served);
+    // the server stops accepting new connections after the signal, the in-flight requests are given `shutdown_timeout` to finish
+    let served = tokio::select! {
+        biased;
+        served = &mut served => Some(served),
+        _ = signaled_rx => tokio::time::timeout(shutdown_timeout, &mut served).await.ok(),
+    };
+    match served {
+        Some(Ok(())) => {}
+        Some(Err(err)) => {
+            tracing::error!(error = %err, "the server stops with an error");
+            std::process::exit(1);
+        }
+        None => tracing::warn!("the in-flight requests are not finished in time"),
+    }
+    tracing::info!(
+        jobs = job_queue.pending_jobs(),
+        "waiting for the pending jobs"
+    );
+    job_queue.shutdown(shutdown_timeout).await;
+    tracing::info!("the server is stopped");
+}
+
+/// wait for SIGTERM or Ctrl-C
+async fn shutdown_signal() {
+    let ctrl_c = async {
+        tokio::signal::ctrl_c()
+            .await
+            .expect("can't install the Ctrl-C handler");
+    };
+    #[cfg(unix)]
+    let terminate = async {
+        tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())
+            .expect("can't install the SIGTERM handler")
+            .recv()
+            .await;
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+    tokio::select! {
+        _ = ctrl_c => {},
+        _ = terminate => {},
+    }
 }
 
 /*
@@ -169,69 +447,187 @@ async fn handler(seq_db: Arc<SeqIndexDB>) -> impl IntoResponse {
 }
 */
 
-async fn get_number_of_ctgs(seq_db: Arc<SeqIndexDB>) -> Json<usize> {
-    let n_ctg = seq_db.seq_index.as_ref().unwrap().len();
-    Json(n_ctg)
+/// the database of the unversioned routes, default to the default database
+#[derive(Deserialize)]
+struct DatabaseSelection {
+    db: Option<String>,
+}
+
+async fn get_number_of_ctgs(
+    Query(selection): Query<DatabaseSelection>,
+    registry: Arc<DatabaseRegistry>,
+) -> Result<Json<usize>, ApiError> {
+    let db = registry.get_or_default(selection.db.as_deref()).await?;
+    let n_ctg = db.seq_db.seq_index.as_ref().unwrap().len();
+    Ok(Json(n_ctg))
 }
 
 async fn post_query_for_json_data(
+    Query(selection): Query<DatabaseSelection>,
     Json(seq_query_spec): Json<Option<SequenceQuerySpec>>,
-    seq_db: Arc<SeqIndexDB>,
-) -> Json<Option<TargetMatchPrincipalBundles>> {
+    registry: Arc<DatabaseRegistry>,
+    cache: Arc<ResultCache>,
+) -> Result<Json<Option<Arc<TargetMatchPrincipalBundles>>>, ApiError> {
     if seq_query_spec.is_none() {
-        return Json(None);
+        return Ok(Json(None));
     };
 
     let seq_query_spec = seq_query_spec.unwrap();
-    println!("{:?}", seq_query_spec);
-    Json(get_target_and_principal_bundle_decomposition(
-        &seq_query_spec,
-        seq_db,
-    ))
+    tracing::debug!(query = ?seq_query_spec, "query");
+    let db = registry.get_or_default(selection.db.as_deref()).await?;
+    let data = tokio::task::spawn_blocking(move || cache.get_or_compute_data(&seq_query_spec, &db))
+        .await
+        .map_err(|err| ApiError(StatusCode::INTERNAL_SERVER_ERROR, err.to_string()))?;
+    Ok(Json(data))
 }
 
 async fn get_html_by_query(
+    Query(selection): Query<DatabaseSelection>,
     Query(seq_query_spec): Query<SequenceQuerySpec>,
-    seq_db: Arc<SeqIndexDB>,
-) -> Html<String> {
+    registry: Arc<DatabaseRegistry>,
+    cache: Arc<ResultCache>,
+) -> Result<Html<String>, ApiError> {
     //if seq_query_spec.is_none() {
     //    return Html("<html><body>No Query Yet</body></html>".into());
     //};
 
     //let seq_query_spec = seq_query_spec.unwrap();
-    println!("{:?}", seq_query_spec);
+    tracing::debug!(query = ?seq_query_spec, "query");
+
+    let db = registry.get_or_default(selection.db.as_deref()).await?;
+    let output =
+        tokio::task::spawn_blocking(move || cache.get_or_render_html(&seq_query_spec, &db))
+            .await
+            .map_err(|err| ApiError(StatusCode::INTERNAL_SERVER_ERROR, err.to_string()))?
+            .ok_or_else(|| ApiError::not_found("no result for the query"))?;
+
+    Ok(Html(output.as_ref().clone()))
+}
 
-    let data = get_target_and_principal_bundle_decomposition(&seq_query_spec, seq_db);
-    let output = pb_data_to_html_string(&data.unwrap());
+async fn get_principal_bundle_stats(
+    Query(selection): Query<DatabaseSelection>,
+    registry: Arc<DatabaseRegistry>,
+) -> Result<Json<Option<Vec<(usize, usize, usize)>>>, ApiError> {
+    let db = registry.get_or_default(selection.db.as_deref()).await?;
+    Ok(Json(
+        db.pdb.as_ref().as_ref().map(|pdb| pdb.get_bundle_stats()),
+    ))
+}
 
-    Html(output)
+async fn post_principal_bundle_decomposition(
+    Query(selection): Query<DatabaseSelection>,
+    Json(query): Json<Option<PrincipalBundleDecompQuery>>,
+    registry: Arc<DatabaseRegistry>,
+) -> Result<Json<Option<Vec<PrincipalBundleBedRecord>>>, ApiError> {
+    let db = registry.get_or_default(selection.db.as_deref()).await?;
+    let records = tokio::task::spawn_blocking(move || match (query, db.pdb.as_ref()) {
+        (Some(query), Some(pdb)) => Some(get_principal_bundle_db_decomposition(&query, pdb)),
+        _ => None,
+    })
+    .await
+    .map_err(|err| ApiError(StatusCode::INTERNAL_SERVER_ERROR, err.to_string()))?;
+    Ok(Json(records))
 }
 
-async fn ws_handler(ws: WebSocketUpgrade) -> response::Response {
-    ws.on_upgrade(ws_handle_socket)
+async fn ws_handler(
+    Extension(identity): Extension<Identity>,
+    ws: WebSocketUpgrade,
+    job_queue: Arc<JobQueue>,
+    named_queries: Arc<NamedQueryStore>,
+) -> response::Response {
+    ws.on_upgrade(move |socket| ws_handle_socket(socket, identity, job_queue, named_queries))
 }
 
-use std::cell::OnceCell;
-// Todo... read the ROIs.json into the String than using static
-static ROI_JSON: &str = include_str!("ROIs.json");
+#[derive(Deserialize)]
+struct JobSubscription {
+    job_id: u64,
+}
 
-async fn ws_handle_socket(mut socket: WebSocket) {
-    let ROI: OnceCell<FxHashMap<String, SequenceQuerySpec>> = OnceCell::new();
-    let _ = ROI.set(serde_json::from_str(ROI_JSON).unwrap());
+#[derive(Deserialize)]
+struct NamedQueryLookup {
+    db: Option<String>,
+    prefix: String,
+}
 
+/// send the status of a job to the websocket whenever it changes until the job finishes,
+/// return `false` if the client is disconnected
+async fn ws_stream_job_status(
+    socket: &mut WebSocket,
+    identity: &Identity,
+    job_queue: &JobQueue,
+    job_id: u64,
+) -> bool {
+    let mut updates = job_queue.subscribe();
+    // the jobs of the databases not readable by the client or of the other users are not found
+    let mut status = job_queue
+        .status(job_id)
+        .filter(|_| job_queue.can_access(identity, job_id));
+    loop {
+        let msg = serde_json::to_string(&status).unwrap();
+        if socket
+            .send(axum::extract::ws::Message::Text(msg))
+            .await
+            .is_err()
+        {
+            return false;
+        }
+        match status {
+            Some(s) if !s.state.is_finished() => {}
+            _ => return true,
+        }
+        status = loop {
+            match updates.recv().await {
+                Ok(s) if s.id == job_id => break Some(s),
+                Ok(_) => continue,
+                Err(tokio::sync::broadcast::error::RecvError::Lagged(_)) => {
+                    break job_queue.status(job_id)
+                }
+                Err(tokio::sync::broadcast::error::RecvError::Closed) => return true,
+            }
+        };
+    }
+}
+
+async fn ws_handle_socket(
+    mut socket: WebSocket,
+    identity: Identity,
+    job_queue: Arc<JobQueue>,
+    named_queries: Arc<NamedQueryStore>,
+) {
     while let Some(msg) = socket.recv().await {
         let msg = if let Ok(msg) = msg {
-            println!("WS msg: {:?}", msg);
+            tracing::debug!(message = ?msg, "websocket message");
             if let axum::extract::ws::Message::Text(msg) = msg {
-                if !msg.is_empty() {
-                    let roi = ROI.get().unwrap();
-                    let keys = roi.keys();
-                    let mut keys = keys.filter(|&s| (*s).starts_with(&msg)).collect::<Vec<_>>();
-                    keys.sort();
+                // a `{"job_id": id}` message subscribes to the status of a job, other messages are the prefixes of the
+                // named queries, `{"db": name, "prefix": prefix}` or a plain prefix for the default database
+                if let Ok(subscription) = serde_json::from_str::<JobSubscription>(&msg) {
+                    if ws_stream_job_status(&mut socket, &identity, &job_queue, subscription.job_id)
+                        .await
+                    {
+                        continue;
+                    } else {
+                        return;
+                    }
+                }
+                let (db, prefix) = match serde_json::from_str::<NamedQueryLookup>(&msg) {
+                    Ok(lookup) => (lookup.db, lookup.prefix),
+                    Err(_) => (None, msg),
+                };
+                let db = db.as_deref().unwrap_or(named_queries.default_db());
+                if !prefix.is_empty() && identity.can_read(db) {
+                    // the ROI files may be reloaded
+                    let queries = tokio::task::spawn_blocking({
+                        let named_queries = named_queries.clone();
+                        let db = db.to_string();
+                        let user = identity.name.clone();
+                        move || named_queries.list(&db, &prefix, user.as_deref())
+                    })
+                    .await
+                    .unwrap_or_default();
                     let json = serde_json::to_string(
-                        &keys
-                            .iter()
-                            .map(|&k| ((*k).clone(), roi.get(k).unwrap().clone()))
+                        &queries
+                            .into_iter()
+                            .map(|q| (q.name, q.spec))
                             .collect::<FxHashMap<_, _>>(),
                     )
                     .unwrap();
@@ -0,0 +1,594 @@
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::atomic::{AtomicBool, AtomicI64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+use axum::{
+    body::Body,
+    extract::MatchedPath,
+    http::{header, Request, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+    routing::get,
+    Router,
+};
+
+use crate::api::ApiError;
+use crate::cache::{CacheStats, ResultCache};
+use crate::databases::DatabaseRegistry;
+use crate::jobs::JobQueue;
+
+/// the upper bounds (in seconds) of the request latency histogram buckets
+const LATENCY_BUCKETS: [f64; 14] = [
+    0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0, 60.0, 300.0,
+];
+
+/// (name, type, help, value) of a metric of the result cache
+type CacheMetric = (
+    &'static str,
+    &'static str,
+    &'static str,
+    fn(&CacheStats) -> f64,
+);
+
+#[derive(Default)]
+struct LatencyHistogram {
+    bucket_counts: [u64; LATENCY_BUCKETS.len()],
+    count: u64,
+    sum: f64,
+}
+
+impl LatencyHistogram {
+    fn observe(&mut self, seconds: f64) {
+        self.bucket_counts
+            .iter_mut()
+            .zip(LATENCY_BUCKETS.iter())
+            .filter(|(_, &bound)| seconds <= bound)
+            .for_each(|(count, _)| *count += 1);
+        self.count += 1;
+        self.sum += seconds;
+    }
+}
+
+/// The request metrics and the shutdown state of the server, they are exported in the Prometheus text format
+pub struct ServerMetrics {
+    started: Instant,
+    /// (method, route, status) -> the number of the requests
+    requests: Mutex<BTreeMap<(String, String, u16), u64>>,
+    /// (method, route) -> the latencies of the requests
+    latencies: Mutex<BTreeMap<(String, String), LatencyHistogram>>,
+    in_flight: AtomicI64,
+    shutting_down: AtomicBool,
+}
+
+impl Default for ServerMetrics {
+    fn default() -> Self {
+        ServerMetrics {
+            started: Instant::now(),
+            requests: Mutex::new(BTreeMap::new()),
+            latencies: Mutex::new(BTreeMap::new()),
+            in_flight: AtomicI64::new(0),
+            shutting_down: AtomicBool::new(false),
+        }
+    }
+}
+
+impl ServerMetrics {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn record(&self, method: String, route: String, status: u16, seconds: f64) {
+        *self
+            .requests
+            .lock()
+            .unwrap()
+            .entry((method.clone(), route.clone(), status))
+            .or_insert(0) += 1;
+        self.latencies
+            .lock()
+            .unwrap()
+            .entry((method, route))
+            .or_default()
+            .observe(seconds);
+    }
+
+    /// mark the server as shutting down, it is not ready for the new requests anymore
+    pub fn set_shutting_down(&self) {
+        self.shutting_down.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_shutting_down(&self) -> bool {
+        self.shutting_down.load(Ordering::SeqCst)
+    }
+
+    pub fn in_flight_requests(&self) -> i64 {
+        self.in_flight.load(Ordering::SeqCst)
+    }
+
+    /// render the metrics in the Prometheus text exposition format
+    pub fn render(
+        &self,
+        registry: &DatabaseRegistry,
+        cache: &ResultCache,
+        job_queue: &JobQueue,
+    ) -> String {
+        let mut out = String::new();
+        let metric_header = |out: &mut String, name: &str, kind: &str, help: &str| {
+            writeln!(out, "# HELP {} {}", name, help).unwrap();
+            writeln!(out, "# TYPE {} {}", name, kind).unwrap();
+        };
+
+        metric_header(
+            &mut out,
+            "pgr_uptime_seconds",
+            "gauge",
+            "the seconds since the server started",
+        );
+        writeln!(
+            out,
+            "pgr_uptime_seconds {}",
+            self.started.elapsed().as_secs_f64()
+        )
+        .unwrap();
+
+        metric_header(
+            &mut out,
+            "pgr_http_requests_total",
+            "counter",
+            "the number of the finished requests",
+        );
+        self.requests
+            .lock()
+            .unwrap()
+            .iter()
+            .for_each(|((method, route, status), count)| {
+                writeln!(
+                    out,
+                    "pgr_http_requests_total{{method=\"{}\",route=\"{}\",status=\"{}\"}} {}",
+                    method,
+                    escape_label(route),
+                    status,
+                    count
+                )
+                .unwrap();
+            });
+
+        metric_header(
+            &mut out,
+            "pgr_http_request_duration_seconds",
+            "histogram",
+            "the latencies of the requests",
+        );
+        self.latencies
+            .lock()
+            .unwrap()
+            .iter()
+            .for_each(|((method, route), histogram)| {
+                let labels = format!("method=\"{}\",route=\"{}\"", method, escape_label(route));
+                LATENCY_BUCKETS
+                    .iter()
+                    .zip(histogram.bucket_counts.iter())
+                    .for_each(|(bound, count)| {
+                        writeln!(
+                            out,
+                            "pgr_http_request_duration_seconds_bucket{{{},le=\"{}\"}} {}",
+                            labels, bound, count
+                        )
+                        .unwrap();
+                    });
+                writeln!(
+                    out,
+                    "pgr_http_request_duration_seconds_bucket{{{},le=\"+Inf\"}} {}",
+                    labels, histogram.count
+                )
+                .unwrap();
+                writeln!(
+                    out,
+                    "pgr_http_request_duration_seconds_sum{{{}}} {}",
+                    labels, histogram.sum
+                )
+                .unwrap();
+                writeln!(
+                    out,
+                    "pgr_http_request_duration_seconds_count{{{}}} {}",
+                    labels, histogram.count
+                )
+                .unwrap();
+            });
+
+        metric_header(
+            &mut out,
+            "pgr_http_requests_in_flight",
+            "gauge",
+            "the number of the requests being processed",
+        );
+        writeln!(
+            out,
+            "pgr_http_requests_in_flight {}",
+            self.in_flight_requests()
+        )
+        .unwrap();
+
+        let cache_stats = cache.stats();
+        let caches = [("data", &cache_stats.data), ("html", &cache_stats.html)];
+        let cache_metrics: [CacheMetric; 8] = [
+            (
+                "pgr_cache_hits_total",
+                "counter",
+                "the number of the lookups found in the memory",
+                |s| s.hits as f64,
+            ),
+            (
+                "pgr_cache_misses_total",
+                "counter",
+                "the number of the lookups not found in the memory",
+                |s| s.misses as f64,
+            ),
+            (
+                "pgr_cache_disk_hits_total",
+                "counter",
+                "the number of the misses found in the spilled files",
+                |s| s.disk_hits as f64,
+            ),
+            (
+                "pgr_cache_evictions_total",
+                "counter",
+                "the number of the evicted entries",
+                |s| s.evictions as f64,
+            ),
+            (
+                "pgr_cache_entries",
+                "gauge",
+                "the number of the entries in the memory",
+                |s| s.entries as f64,
+            ),
+            (
+                "pgr_cache_bytes",
+                "gauge",
+                "the estimated size of the entries in the memory",
+                |s| s.bytes as f64,
+            ),
+            (
+                "pgr_cache_capacity_bytes",
+                "gauge",
+                "the size limit of the entries in the memory",
+                |s| s.capacity_bytes as f64,
+            ),
+            (
+                "pgr_cache_hit_ratio",
+                "gauge",
+                "the fraction of the lookups found in the memory or the spilled files",
+                |s| {
+                    if s.hits + s.misses > 0 {
+                        (s.hits + s.disk_hits) as f64 / (s.hits + s.misses) as f64
+                    } else {
+                        0.0
+                    }
+                },
+            ),
+        ];
+        cache_metrics.iter().for_each(|(name, kind, help, value)| {
+            metric_header(&mut out, name, kind, help);
+            caches.iter().for_each(|(cache_name, stats)| {
+                writeln!(out, "{}{{cache=\"{}\"}} {}", name, cache_name, value(stats)).unwrap();
+            });
+        });
+
+        let load_stats = registry.load_stats();
+        metric_header(
+            &mut out,
+            "pgr_database_loaded",
+            "gauge",
+            "whether the database is loaded",
+        );
+        load_stats.iter().for_each(|db| {
+            writeln!(
+                out,
+                "pgr_database_loaded{{db=\"{}\"}} {}",
+                escape_label(&db.name),
+                db.load_time.is_some() as u8
+            )
+            .unwrap();
+        });
+        metric_header(
+            &mut out,
+            "pgr_database_load_seconds",
+            "gauge",
+            "the time spent on loading the database",
+        );
+        load_stats
+            .iter()
+            .filter_map(|db| Some((&db.name, db.load_time?)))
+            .for_each(|(name, load_time)| {
+                writeln!(
+                    out,
+                    "pgr_database_load_seconds{{db=\"{}\"}} {}",
+                    escape_label(name),
+                    load_time.as_secs_f64()
+                )
+                .unwrap();
+            });
+        metric_header(
+            &mut out,
+            "pgr_database_load_failures_total",
+            "counter",
+            "the number of the failed loadings of the database",
+        );
+        load_stats.iter().for_each(|db| {
+            writeln!(
+                out,
+                "pgr_database_load_failures_total{{db=\"{}\"}} {}",
+                escape_label(&db.name),
+                db.failures
+            )
+            .unwrap();
+        });
+
+        metric_header(
+            &mut out,
+            "pgr_jobs_pending",
+            "gauge",
+            "the number of the queued or running jobs",
+        );
+        writeln!(out, "pgr_jobs_pending {}", job_queue.pending_jobs()).unwrap();
+        out
+    }
+}
+
+fn escape_label(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+/// count a request as in flight until it finishes or the client goes away
+struct InFlightGuard<'a>(&'a AtomicI64);
+
+impl<'a> InFlightGuard<'a> {
+    fn new(in_flight: &'a AtomicI64) -> Self {
+        in_flight.fetch_add(1, Ordering::SeqCst);
+        InFlightGuard(in_flight)
+    }
+}
+
+impl Drop for InFlightGuard<'_> {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// count the requests and measure their latencies by the matched routes, the requests to the static files
+/// are counted as the route `fallback`
+pub async fn metrics_middleware(
+    req: Request<Body>,
+    next: Next<Body>,
+    metrics: Arc<ServerMetrics>,
+) -> Response {
+    let start = Instant::now();
+    let method = req.method().to_string();
+    let route = req
+        .extensions()
+        .get::<MatchedPath>()
+        .map_or_else(|| "fallback".to_string(), |path| path.as_str().to_string());
+    let res = {
+        let _in_flight = InFlightGuard::new(&metrics.in_flight);
+        next.run(req).await
+    };
+    metrics.record(
+        method,
+        route,
+        res.status().as_u16(),
+        start.elapsed().as_secs_f64(),
+    );
+    res
+}
+
+/// build the router of the health checks and the metrics, they are not under `/api/` so they don't need the credentials
+pub fn health_router(
+    metrics: Arc<ServerMetrics>,
+    registry: Arc<DatabaseRegistry>,
+    cache: Arc<ResultCache>,
+    job_queue: Arc<JobQueue>,
+) -> Router {
+    Router::new()
+        .route("/healthz", get(|| async { "ok" }))
+        .route(
+            "/readyz",
+            get({
+                let metrics = metrics.clone();
+                let registry = registry.clone();
+                move || get_readiness(metrics, registry)
+            }),
+        )
+        .route(
+            "/metrics",
+            get(move || async move {
+                (
+                    [(
+                        header::CONTENT_TYPE,
+                        "text/plain; version=0.0.4; charset=utf-8",
+                    )],
+                    metrics.render(&registry, &cache, &job_queue),
+                )
+            }),
+        )
+}
+
+/// the server is ready when the default database is loaded and it is not shutting down
+async fn get_readiness(
+    metrics: Arc<ServerMetrics>,
+    registry: Arc<DatabaseRegistry>,
+) -> Result<impl IntoResponse, ApiError> {
+    if metrics.is_shutting_down() {
+        return Err(ApiError(
+            StatusCode::SERVICE_UNAVAILABLE,
+            "the server is shutting down".to_string(),
+        ));
+    }
+    if !registry.is_loaded(registry.default_name()) {
+        return Err(ApiError(
+            StatusCode::SERVICE_UNAVAILABLE,
+            format!("database {} is not loaded", registry.default_name()),
+        ));
+    }
+    Ok("ready")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::databases::{DatabaseBackend, DatabaseConfig, DatabaseListConfig};
+    use crate::jobs::JobLimits;
+    use axum::middleware;
+    use std::time::Duration;
+    use tower::ServiceExt;
+
+    const TEST_DB_PREFIX: &str = concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/../../pgr-db/test/test_data/test_seqs_frag"
+    );
+
+    fn test_state() -> (Arc<DatabaseRegistry>, Arc<ResultCache>, Arc<JobQueue>) {
+        let registry = Arc::new(
+            DatabaseRegistry::new(DatabaseListConfig {
+                default: None,
+                databases: vec![DatabaseConfig::new(
+                    "test".to_string(),
+                    DatabaseBackend::Frg,
+                    TEST_DB_PREFIX.to_string(),
+                )],
+            })
+            .unwrap(),
+        );
+        let cache = Arc::new(ResultCache::new(1 << 20, None));
+        let job_queue = JobQueue::new(
+            cache.clone(),
+            JobLimits {
+                max_running_jobs: 1,
+                max_pending_jobs: 2,
+                max_job_time: Duration::from_secs(60),
+                max_query_length: 100_000,
+                max_total_seq_length: 10_000_000,
+                result_ttl: Duration::from_secs(60),
+            },
+        );
+        (registry, cache, job_queue)
+    }
+
+    async fn get_status(app: &Router, uri: &str) -> StatusCode {
+        let request = Request::builder().uri(uri).body(Body::empty()).unwrap();
+        app.clone().oneshot(request).await.unwrap().status()
+    }
+
+    #[test]
+    fn test_latency_histogram() {
+        let mut histogram = LatencyHistogram::default();
+        [0.003, 0.03, 0.05, 0.7, 1000.0]
+            .iter()
+            .for_each(|&seconds| histogram.observe(seconds));
+        // the buckets are cumulative and the upper bounds are inclusive
+        assert_eq!(
+            histogram.bucket_counts,
+            [1, 1, 1, 3, 3, 3, 3, 4, 4, 4, 4, 4, 4, 4]
+        );
+        assert_eq!(histogram.count, 5);
+        assert!((histogram.sum - 1000.783).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_render() {
+        assert_eq!(escape_label("a\\b\"c\nd"), "a\\\\b\\\"c\\nd");
+
+        let (registry, cache, job_queue) = test_state();
+        let metrics = ServerMetrics::new();
+        metrics.record("GET".to_string(), "/a\"b".to_string(), 200, 0.02);
+        metrics.record("GET".to_string(), "/a\"b".to_string(), 200, 2.0);
+        metrics.record("POST".to_string(), "fallback".to_string(), 404, 0.001);
+        cache.insert_html("key".to_string(), Arc::new("<html></html>".to_string()));
+        assert!(cache.get_html("key").is_some());
+        assert!(cache.get_html("missing").is_none());
+
+        let out = metrics.render(&registry, &cache, &job_queue);
+        let lines = out.lines().collect::<Vec<_>>();
+        [
+            "# TYPE pgr_http_requests_total counter",
+            "pgr_http_requests_total{method=\"GET\",route=\"/a\\\"b\",status=\"200\"} 2",
+            "pgr_http_requests_total{method=\"POST\",route=\"fallback\",status=\"404\"} 1",
+            "# TYPE pgr_http_request_duration_seconds histogram",
+            "pgr_http_request_duration_seconds_bucket{method=\"GET\",route=\"/a\\\"b\",le=\"0.01\"} 0",
+            "pgr_http_request_duration_seconds_bucket{method=\"GET\",route=\"/a\\\"b\",le=\"0.025\"} 1",
+            "pgr_http_request_duration_seconds_bucket{method=\"GET\",route=\"/a\\\"b\",le=\"+Inf\"} 2",
+            "pgr_http_request_duration_seconds_count{method=\"GET\",route=\"/a\\\"b\"} 2",
+            "pgr_http_requests_in_flight 0",
+            "pgr_cache_hits_total{cache=\"html\"} 1",
+            "pgr_cache_misses_total{cache=\"html\"} 1",
+            "pgr_cache_hit_ratio{cache=\"html\"} 0.5",
+            "pgr_cache_hit_ratio{cache=\"data\"} 0",
+            "pgr_database_loaded{db=\"test\"} 0",
+            "pgr_database_load_failures_total{db=\"test\"} 0",
+            "pgr_jobs_pending 0",
+        ]
+        .iter()
+        .for_each(|line| assert!(lines.contains(line), "missing line: {}", line));
+        // the load time is only reported for the loaded databases
+        assert!(!out.contains("pgr_database_load_seconds{"));
+    }
+
+    #[tokio::test]
+    async fn test_readiness() {
+        let (registry, cache, job_queue) = test_state();
+        let metrics = Arc::new(ServerMetrics::new());
+        let app = health_router(metrics.clone(), registry.clone(), cache, job_queue);
+
+        assert_eq!(get_status(&app, "/healthz").await, StatusCode::OK);
+        // the default database is not loaded yet
+        assert_eq!(
+            get_status(&app, "/readyz").await,
+            StatusCode::SERVICE_UNAVAILABLE
+        );
+        registry.get("test").await.unwrap();
+        assert_eq!(get_status(&app, "/readyz").await, StatusCode::OK);
+        metrics.set_shutting_down();
+        assert_eq!(
+            get_status(&app, "/readyz").await,
+            StatusCode::SERVICE_UNAVAILABLE
+        );
+        // the health check doesn't depend on the readiness
+        assert_eq!(get_status(&app, "/healthz").await, StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn test_metrics_middleware() {
+        let metrics = Arc::new(ServerMetrics::new());
+        let app = Router::new()
+            .route("/api/v1/db/:db/items", get(|| async { "items" }))
+            .layer(middleware::from_fn({
+                let metrics = metrics.clone();
+                move |req, next| metrics_middleware(req, next, metrics.clone())
+            }));
+
+        assert_eq!(get_status(&app, "/api/v1/db/a/items").await, StatusCode::OK);
+        assert_eq!(get_status(&app, "/api/v1/db/b/items").await, StatusCode::OK);
+        assert_eq!(get_status(&app, "/unknown").await, StatusCode::NOT_FOUND);
+
+        let requests = metrics.requests.lock().unwrap().clone();
+        assert_eq!(
+            requests.get(&("GET".to_string(), "/api/v1/db/:db/items".to_string(), 200)),
+            Some(&2)
+        );
+        assert_eq!(
+            requests.get(&("GET".to_string(), "fallback".to_string(), 404)),
+            Some(&1)
+        );
+        assert_eq!(requests.len(), 2);
+        let latencies = metrics.latencies.lock().unwrap();
+        assert_eq!(
+            latencies[&("GET".to_string(), "/api/v1/db/:db/items".to_string())].count,
+            2
+        );
+        assert_eq!(metrics.in_flight_requests(), 0);
+    }
+}
@@ -0,0 +1,732 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::Error;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime};
+
+use axum::{
+    extract::{Extension, Path, Query},
+    http::StatusCode,
+    routing::{get, put},
+    Json, Router,
+};
+use rustc_hash::FxHashMap;
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
+
+use crate::api::ApiError;
+use crate::auth::Identity;
+use crate::bundle_processing::SequenceQuerySpec;
+use crate::databases::DatabaseRegistry;
+
+/// the presets used when the server serves a single database without a ROI file
+static BUILTIN_ROI_JSON: &str = include_str!("ROIs.json");
+
+/// the minimum interval between two checks of the changes of the ROI files
+const ROI_RELOAD_INTERVAL: Duration = Duration::from_secs(10);
+
+type RoiMap = FxHashMap<String, SequenceQuerySpec>;
+/// the files with the sizes and the modification times
+type RoiFiles = Vec<(PathBuf, u64, Option<SystemTime>)>;
+/// database name -> user name -> query name -> query, the anonymous user is keyed by an empty name
+type UserQueries = BTreeMap<String, BTreeMap<String, BTreeMap<String, SequenceQuerySpec>>>;
+
+/// the user query file, the older versions keep the queries of all users together by the database names
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum UserQueryFile {
+    ByUser(UserQueries),
+    Shared(BTreeMap<String, BTreeMap<String, SequenceQuerySpec>>),
+}
+
+impl From<UserQueryFile> for UserQueries {
+    fn from(file: UserQueryFile) -> Self {
+        match file {
+            UserQueryFile::ByUser(user_queries) => user_queries,
+            // the shared queries are kept as the anonymous user's
+            UserQueryFile::Shared(queries) => queries
+                .into_iter()
+                .map(|(db_name, queries)| {
+                    (db_name, [(String::new(), queries)].into_iter().collect())
+                })
+                .collect(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum QueryKind {
+    /// a region of interest from the ROI files of the database, it can't be changed through the API
+    Preset,
+    /// a query saved by a user
+    User,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, ToSchema)]
+pub struct NamedQuery {
+    pub name: String,
+    pub kind: QueryKind,
+    pub spec: SequenceQuerySpec,
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct NamedQueryListQuery {
+    /// only list the queries with the name starting with the prefix
+    pub prefix: Option<String>,
+}
+
+/// The ROI presets of a database loaded from a JSON file `{name: query}` or all `*.json` files in a directory,
+/// they are reloaded when the files are changed
+struct RoiPresets {
+    path: Option<PathBuf>,
+    /// the files and the presets loaded from them
+    loaded: Mutex<(RoiFiles, Arc<RoiMap>)>,
+    /// the last time the files are checked for the changes
+    last_checked: Mutex<Option<Instant>>,
+    reload_interval: Duration,
+}
+
+impl RoiPresets {
+    fn new(path: Option<PathBuf>) -> Self {
+        let presets = RoiPresets {
+            path,
+            loaded: Mutex::new((vec![], Arc::new(RoiMap::default()))),
+            last_checked: Mutex::new(None),
+            reload_interval: ROI_RELOAD_INTERVAL,
+        };
+        presets.get();
+        presets
+    }
+
+    fn builtin() -> Self {
+        RoiPresets {
+            path: None,
+            loaded: Mutex::new((
+                vec![],
+                Arc::new(serde_json::from_str(BUILTIN_ROI_JSON).unwrap()),
+            )),
+            last_checked: Mutex::new(None),
+            reload_interval: ROI_RELOAD_INTERVAL,
+        }
+    }
+
+    fn list_files(path: &PathBuf) -> RoiFiles {
+        let mut files = if path.is_dir() {
+            fs::read_dir(path)
+                .map(|entries| {
+                    entries
+                        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+                        .filter(|p| p.extension().is_some_and(|ext| ext == "json"))
+                        .collect::<Vec<_>>()
+                })
+                .unwrap_or_default()
+        } else {
+            vec![path.clone()]
+        };
+        files.sort();
+        files
+            .into_iter()
+            .filter_map(|p| {
+                let metadata = fs::metadata(&p).ok()?;
+                Some((p, metadata.len(), metadata.modified().ok()))
+            })
+            .collect()
+    }
+
+    /// get the presets, the files are loaded again if they are changed since the last check, they are checked
+    /// at most once per `reload_interval`, the presets are not changed if a file fails to be parsed
+    fn get(&self) -> Arc<RoiMap> {
+        let path = match self.path.as_ref() {
+            Some(path) => path,
+            None => return self.loaded.lock().unwrap().1.clone(),
+        };
+        {
+            let mut last_checked = self.last_checked.lock().unwrap();
+            if last_checked.is_some_and(|checked| checked.elapsed() < self.reload_interval) {
+                return self.loaded.lock().unwrap().1.clone();
+            }
+            *last_checked = Some(Instant::now());
+        }
+        let files = Self::list_files(path);
+        let mut loaded = self.loaded.lock().unwrap();
+        if loaded.0 == files {
+            return loaded.1.clone();
+        }
+        let mut presets = RoiMap::default();
+        for (file, _, _) in files.iter() {
+            match fs::read(file)
+                .map_err(|err| err.to_string())
+                .and_then(|json| {
+                    serde_json::from_slice::<RoiMap>(&json).map_err(|err| err.to_string())
+                }) {
+                Ok(rois) => presets.extend(rois),
+                Err(err) => {
+                    tracing::warn!(file = %file.to_string_lossy(), error = %err, "can't load the ROI file");
+                    // keep the presets, the files are loaded again after they are changed
+                    loaded.0 = files;
+                    return loaded.1.clone();
+                }
+            }
+        }
+        tracing::info!(
+            presets = presets.len(),
+            path = %path.to_string_lossy(),
+            "ROI presets loaded"
+        );
+        *loaded = (files, Arc::new(presets));
+        loaded.1.clone()
+    }
+}
+
+/// The limits of the queries saved by the users
+#[derive(Debug, Clone)]
+pub struct UserQueryLimits {
+    /// the anonymous clients can't save or delete the queries, it is set when the authentication is configured
+    pub require_user: bool,
+    /// the number of the queries a user can save in all databases
+    pub max_queries_per_user: usize,
+}
+
+/// The named queries of the databases, the ROI presets from the files and the queries saved by the users. A user
+/// only sees the queries saved by itself, the clients without the credentials share the queries of the anonymous user.
+/// The saved queries are written to a JSON file `{database name: {user name: {name: query}}}` if the file is specified.
+pub struct NamedQueryStore {
+    default_db: String,
+    presets: FxHashMap<String, RoiPresets>,
+    user_query_file: Option<PathBuf>,
+    /// the queries and the number of the changes
+    user_queries: Mutex<(UserQueries, u64)>,
+    /// the number of the changes written to the file, the file is only written by one writer at a time
+    written_version: Mutex<u64>,
+    limits: UserQueryLimits,
+}
+
+impl NamedQueryStore {
+    /// `builtin_presets` uses the built-in ROIs as the presets of the default database if it has no ROI file
+    pub fn new(
+        registry: &DatabaseRegistry,
+        user_query_file: Option<PathBuf>,
+        builtin_presets: bool,
+        limits: UserQueryLimits,
+    ) -> Result<Self, Error> {
+        let presets = registry
+            .configs()
+            .map(|config| {
+                let presets = match config.roi_path.as_ref() {
+                    Some(path) => RoiPresets::new(Some(PathBuf::from(path))),
+                    None if builtin_presets && config.name == registry.default_name() => {
+                        RoiPresets::builtin()
+                    }
+                    None => RoiPresets::new(None),
+                };
+                (config.name.clone(), presets)
+            })
+            .collect();
+        let user_queries = match user_query_file.as_ref() {
+            Some(path) if path.exists() => {
+                serde_json::from_slice::<UserQueryFile>(&fs::read(path)?)?.into()
+            }
+            _ => UserQueries::new(),
+        };
+        Ok(NamedQueryStore {
+            default_db: registry.default_name().to_string(),
+            presets,
+            user_query_file,
+            user_queries: Mutex::new((user_queries, 0)),
+            written_version: Mutex::new(0),
+            limits,
+        })
+    }
+
+    pub fn default_db(&self) -> &str {
+        &self.default_db
+    }
+
+    fn presets(&self, db_name: &str) -> Arc<RoiMap> {
+        self.presets
+            .get(db_name)
+            .map(|presets| presets.get())
+            .unwrap_or_default()
+    }
+
+    /// list the presets and the queries saved by the user (`None` for the anonymous user) of a database ordered by
+    /// the names, a user query is hidden by a preset of the same name
+    pub fn list(&self, db_name: &str, prefix: &str, user: Option<&str>) -> Vec<NamedQuery> {
+        let presets = self.presets(db_name);
+        let mut queries = presets
+            .iter()
+            .filter(|(name, _)| name.starts_with(prefix))
+            .map(|(name, spec)| NamedQuery {
+                name: name.clone(),
+                kind: QueryKind::Preset,
+                spec: spec.clone(),
+            })
+            .collect::<Vec<_>>();
+        if let Some(user_queries) = self
+            .user_queries
+            .lock()
+            .unwrap()
+            .0
+            .get(db_name)
+            .and_then(|users| users.get(user.unwrap_or_default()))
+        {
+            queries.extend(
+                user_queries
+                    .iter()
+                    .filter(|(name, _)| name.starts_with(prefix) && !presets.contains_key(*name))
+                    .map(|(name, spec)| NamedQuery {
+                        name: name.clone(),
+                        kind: QueryKind::User,
+                        spec: spec.clone(),
+                    }),
+            );
+        }
+        queries.sort_by(|a, b| a.name.cmp(&b.name));
+        queries
+    }
+
+    /// write a snapshot of the queries after the change `version` to the file, the lock of the queries is not held
+    /// while writing, a snapshot older than the written one is skipped
+    fn write_user_queries(&self, user_queries: UserQueries, version: u64) -> Result<(), Error> {
+        if let Some(path) = self.user_query_file.as_ref() {
+            let mut written_version = self.written_version.lock().unwrap();
+            if *written_version >= version {
+                return Ok(());
+            }
+            // write to a temporary file first, so the store is not truncated if the server stops in the middle
+            let tmp_path = path.with_extension("tmp");
+            fs::write(&tmp_path, serde_json::to_vec_pretty(&user_queries)?)?;
+            fs::rename(tmp_path, path)?;
+            *written_version = version;
+        }
+        Ok(())
+    }
+
+    /// bump the version of the queries after a change, return a snapshot to be written if there is a file
+    fn commit_change(&self, user_queries: &mut (UserQueries, u64)) -> Option<(UserQueries, u64)> {
+        user_queries.1 += 1;
+        self.user_query_file
+            .as_ref()
+            .map(|_| (user_queries.0.clone(), user_queries.1))
+    }
+
+    /// get the key of the user's queries, the anonymous user can't change the queries if `require_user` is set
+    fn get_writer(&self, user: Option<&str>) -> Result<String, ApiError> {
+        match user {
+            Some(user) => Ok(user.to_string()),
+            None if self.limits.require_user => Err(ApiError(
+                StatusCode::UNAUTHORIZED,
+                "the credentials are required for saving or deleting a query".to_string(),
+            )),
+            None => Ok(String::new()),
+        }
+    }
+
+    /// save a query of the user (`None` for the anonymous user), return `true` if it is a new one
+    pub fn save(
+        &self,
+        db_name: &str,
+        name: &str,
+        spec: SequenceQuerySpec,
+        user: Option<&str>,
+    ) -> Result<bool, ApiError> {
+        let user = self.get_writer(user)?;
+        if name.is_empty() || name.len() > 128 || name.chars().any(|c| c.is_control()) {
+            return Err(ApiError::bad_request(format!(
+                "invalid query name `{}`",
+                name
+            )));
+        }
+        if self.presets(db_name).contains_key(name) {
+            return Err(ApiError(
+                StatusCode::CONFLICT,
+                format!("{} is a preset of database {}", name, db_name),
+            ));
+        }
+        let mut locked = self.user_queries.lock().unwrap();
+        let user_queries = &mut locked.0;
+        let is_new = !user_queries
+            .get(db_name)
+            .and_then(|users| users.get(&user))
+            .is_some_and(|queries| queries.contains_key(name));
+        if is_new {
+            let saved = user_queries
+                .values()
+                .filter_map(|users| users.get(&user))
+                .map(|queries| queries.len())
+                .sum::<usize>();
+            if saved >= self.limits.max_queries_per_user {
+                return Err(ApiError(
+                    StatusCode::FORBIDDEN,
+                    format!(
+                        "{} queries are saved, please delete some of them before saving a new one",
+                        saved
+                    ),
+                ));
+            }
+        }
+        user_queries
+            .entry(db_name.to_string())
+            .or_default()
+            .entry(user)
+            .or_default()
+            .insert(name.to_string(), spec);
+        let snapshot = self.commit_change(&mut locked);
+        drop(locked);
+        if let Some((user_queries, version)) = snapshot {
+            self.write_user_queries(user_queries, version)?;
+        }
+        Ok(is_new)
+    }
+
+    /// delete a query of the user (`None` for the anonymous user) and return it
+    pub fn delete(
+        &self,
+        db_name: &str,
+        name: &str,
+        user: Option<&str>,
+    ) -> Result<NamedQuery, ApiError> {
+        let user = self.get_writer(user)?;
+        if self.presets(db_name).contains_key(name) {
+            return Err(ApiError(
+                StatusCode::CONFLICT,
+                format!("{} is a preset of database {}", name, db_name),
+            ));
+        }
+        let mut locked = self.user_queries.lock().unwrap();
+        let user_queries = &mut locked.0;
+        let not_found = || ApiError::not_found(format!("query {} not found", name));
+        let users = user_queries.get_mut(db_name).ok_or_else(not_found)?;
+        let spec = users
+            .get_mut(&user)
+            .and_then(|queries| queries.remove(name))
+            .ok_or_else(not_found)?;
+        users.retain(|_, queries| !queries.is_empty());
+        if users.is_empty() {
+            user_queries.remove(db_name);
+        }
+        let snapshot = self.commit_change(&mut locked);
+        drop(locked);
+        if let Some((user_queries, version)) = snapshot {
+            self.write_user_queries(user_queries, version)?;
+        }
+        Ok(NamedQuery {
+            name: name.to_string(),
+            kind: QueryKind::User,
+            spec,
+        })
+    }
+}
+
+fn check_database(registry: &DatabaseRegistry, db_name: &str) -> Result<(), ApiError> {
+    if registry.contains(db_name) {
+        Ok(())
+    } else {
+        Err(ApiError::not_found(format!(
+            "database {} not found",
+            db_name
+        )))
+    }
+}
+
+/// build the router of the named query endpoints
+pub fn named_query_router(store: Arc<NamedQueryStore>, registry: Arc<DatabaseRegistry>) -> Router {
+    Router::new()
+        .route(
+            "/api/v1/db/:db_name/rois",
+            get({
+                let store = store.clone();
+                let registry = registry.clone();
+                move |identity, db_name, params| {
+                    get_named_queries(identity, db_name, params, store, registry)
+                }
+            }),
+        )
+        .route(
+            "/api/v1/db/:db_name/rois/:name",
+            put({
+                let store = store.clone();
+                let registry = registry.clone();
+                move |identity, path, params| {
+                    put_named_query(identity, path, params, store, registry)
+                }
+            })
+            .delete({
+                let store = store.clone();
+                let registry = registry.clone();
+                move |identity, path| delete_named_query(identity, path, store, registry)
+            }),
+        )
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/db/{db_name}/rois",
+    tag = "pgr-server",
+    params(("db_name" = String, Path, description = "the database name"), NamedQueryListQuery),
+    responses(
+        (status = 200, description = "the ROI presets and the queries saved by the client ordered by the names", body = [NamedQuery]),
+        (status = 404, description = "database not found", body = crate::api::ApiErrorMessage)
+    )
+)]
+pub async fn get_named_queries(
+    Extension(identity): Extension<Identity>,
+    Path(db_name): Path<String>,
+    Query(query): Query<NamedQueryListQuery>,
+    store: Arc<NamedQueryStore>,
+    registry: Arc<DatabaseRegistry>,
+) -> Result<Json<Vec<NamedQuery>>, ApiError> {
+    check_database(&registry, &db_name)?;
+    // the ROI files may be reloaded
+    let queries = tokio::task::spawn_blocking(move || {
+        store.list(
+            &db_name,
+            query.prefix.as_deref().unwrap_or(""),
+            identity.name.as_deref(),
+        )
+    })
+    .await?;
+    Ok(Json(queries))
+}
+
+#[utoipa::path(
+    put,
+    path = "/api/v1/db/{db_name}/rois/{name}",
+    tag = "pgr-server",
+    params(
+        ("db_name" = String, Path, description = "the database name"),
+        ("name" = String, Path, description = "the query name")
+    ),
+    request_body = SequenceQuerySpec,
+    responses(
+        (status = 201, description = "a new query is saved", body = NamedQuery),
+        (status = 200, description = "the saved query is replaced", body = NamedQuery),
+        (status = 400, description = "invalid query name", body = crate::api::ApiErrorMessage),
+        (status = 401, description = "the credentials are required", body = crate::api::ApiErrorMessage),
+        (status = 403, description = "too many saved queries", body = crate::api::ApiErrorMessage),
+        (status = 404, description = "database not found", body = crate::api::ApiErrorMessage),
+        (status = 409, description = "the name is used by a preset", body = crate::api::ApiErrorMessage)
+    )
+)]
+pub async fn put_named_query(
+    Extension(identity): Extension<Identity>,
+    Path((db_name, name)): Path<(String, String)>,
+    Json(spec): Json<SequenceQuerySpec>,
+    store: Arc<NamedQueryStore>,
+    registry: Arc<DatabaseRegistry>,
+) -> Result<(StatusCode, Json<NamedQuery>), ApiError> {
+    check_database(&registry, &db_name)?;
+    // the ROI files may be reloaded and the user query file is written
+    let is_new = tokio::task::spawn_blocking({
+        let name = name.clone();
+        let spec = spec.clone();
+        move || store.save(&db_name, &name, spec, identity.name.as_deref())
+    })
+    .await??;
+    let status = if is_new {
+        StatusCode::CREATED
+    } else {
+        StatusCode::OK
+    };
+    Ok((
+        status,
+        Json(NamedQuery {
+            name,
+            kind: QueryKind::User,
+            spec,
+        }),
+    ))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/api/v1/db/{db_name}/rois/{name}",
+    tag = "pgr-server",
+    params(
+        ("db_name" = String, Path, description = "the database name"),
+        ("name" = String, Path, description = "the query name")
+    ),
+    responses(
+        (status = 200, description = "the deleted query", body = NamedQuery),
+        (status = 401, description = "the credentials are required", body = crate::api::ApiErrorMessage),
+        (status = 404, description = "database or query not found", body = crate::api::ApiErrorMessage),
+        (status = 409, description = "a preset can't be deleted", body = crate::api::ApiErrorMessage)
+    )
+)]
+pub async fn delete_named_query(
+    Extension(identity): Extension<Identity>,
+    Path((db_name, name)): Path<(String, String)>,
+    store: Arc<NamedQueryStore>,
+    registry: Arc<DatabaseRegistry>,
+) -> Result<Json<NamedQuery>, ApiError> {
+    check_database(&registry, &db_name)?;
+    let query = tokio::task::spawn_blocking(move || {
+        store.delete(&db_name, &name, identity.name.as_deref())
+    })
+    .await??;
+    Ok(Json(query))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::databases::{DatabaseBackend, DatabaseConfig, DatabaseListConfig};
+
+    fn test_store(user_query_file: Option<PathBuf>, limits: UserQueryLimits) -> NamedQueryStore {
+        let registry = DatabaseRegistry::new(DatabaseListConfig {
+            default: None,
+            databases: vec![DatabaseConfig::new(
+                "test".to_string(),
+                DatabaseBackend::Frg,
+                "test".to_string(),
+            )],
+        })
+        .unwrap();
+        NamedQueryStore::new(&registry, user_query_file, false, limits).unwrap()
+    }
+
+    fn test_spec() -> SequenceQuerySpec {
+        serde_json::from_str::<RoiMap>(BUILTIN_ROI_JSON)
+            .unwrap()
+            .into_values()
+            .next()
+            .unwrap()
+    }
+
+    fn names(queries: Vec<NamedQuery>) -> Vec<String> {
+        queries.into_iter().map(|q| q.name).collect()
+    }
+
+    #[test]
+    fn test_user_queries_by_user() {
+        let store = test_store(
+            None,
+            UserQueryLimits {
+                require_user: false,
+                max_queries_per_user: 10,
+            },
+        );
+        assert!(store
+            .save("test", "q1", test_spec(), Some("alice"))
+            .unwrap());
+        assert!(!store
+            .save("test", "q1", test_spec(), Some("alice"))
+            .unwrap());
+        assert!(store.save("test", "q2", test_spec(), None).unwrap());
+        assert_eq!(names(store.list("test", "", Some("alice"))), vec!["q1"]);
+        assert_eq!(names(store.list("test", "", None)), vec!["q2"]);
+        assert!(store.list("test", "", Some("bob")).is_empty());
+
+        assert_eq!(
+            store.delete("test", "q1", Some("bob")).err().unwrap().0,
+            StatusCode::NOT_FOUND
+        );
+        assert_eq!(
+            store.delete("test", "q1", Some("alice")).unwrap().name,
+            "q1"
+        );
+        assert!(store.list("test", "", Some("alice")).is_empty());
+    }
+
+    #[test]
+    fn test_user_query_limits() {
+        let store = test_store(
+            None,
+            UserQueryLimits {
+                require_user: true,
+                max_queries_per_user: 2,
+            },
+        );
+        assert_eq!(
+            store.save("test", "q1", test_spec(), None).err().unwrap().0,
+            StatusCode::UNAUTHORIZED
+        );
+        assert_eq!(
+            store.delete("test", "q1", None).err().unwrap().0,
+            StatusCode::UNAUTHORIZED
+        );
+
+        assert!(store
+            .save("test", "q1", test_spec(), Some("alice"))
+            .unwrap());
+        assert!(store
+            .save("test", "q2", test_spec(), Some("alice"))
+            .unwrap());
+        assert_eq!(
+            store
+                .save("test", "q3", test_spec(), Some("alice"))
+                .err()
+                .unwrap()
+                .0,
+            StatusCode::FORBIDDEN
+        );
+        // replacing a saved query or saving by another user is not limited
+        assert!(!store
+            .save("test", "q2", test_spec(), Some("alice"))
+            .unwrap());
+        assert!(store.save("test", "q3", test_spec(), Some("bob")).unwrap());
+        store.delete("test", "q1", Some("alice")).unwrap();
+        assert!(store
+            .save("test", "q3", test_spec(), Some("alice"))
+            .unwrap());
+    }
+
+    #[test]
+    fn test_user_query_file() {
+        let path =
+            std::env::temp_dir().join(format!("pgr-user-queries-{}.json", std::process::id()));
+        let limits = UserQueryLimits {
+            require_user: false,
+            max_queries_per_user: 10,
+        };
+        // the queries in the file of the older versions are kept as the anonymous user's
+        let shared = [(
+            "test",
+            [("q0", test_spec())]
+                .into_iter()
+                .collect::<BTreeMap<_, _>>(),
+        )]
+        .into_iter()
+        .collect::<BTreeMap<_, _>>();
+        fs::write(&path, serde_json::to_vec(&shared).unwrap()).unwrap();
+        let store = test_store(Some(path.clone()), limits.clone());
+        assert_eq!(names(store.list("test", "", None)), vec!["q0"]);
+        store
+            .save("test", "q1", test_spec(), Some("alice"))
+            .unwrap();
+
+        let store = test_store(Some(path.clone()), limits);
+        assert_eq!(names(store.list("test", "", None)), vec!["q0"]);
+        assert_eq!(names(store.list("test", "", Some("alice"))), vec!["q1"]);
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_roi_reload() {
+        let path = std::env::temp_dir().join(format!("pgr-rois-{}.json", std::process::id()));
+        let write_rois = |names: &[&str]| {
+            let rois = names
+                .iter()
+                .map(|name| (name.to_string(), test_spec()))
+                .collect::<BTreeMap<_, _>>();
+            fs::write(&path, serde_json::to_vec(&rois).unwrap()).unwrap();
+        };
+        write_rois(&["r1"]);
+        let mut presets = RoiPresets::new(Some(path.clone()));
+        assert_eq!(presets.get().len(), 1);
+
+        // the files are not checked again within the reload interval
+        write_rois(&["r1", "r2"]);
+        assert_eq!(presets.get().len(), 1);
+
+        presets.reload_interval = Duration::ZERO;
+        assert_eq!(presets.get().len(), 2);
+        // a broken file keeps the loaded presets
+        fs::write(&path, b"{").unwrap();
+        assert_eq!(presets.get().len(), 2);
+        fs::remove_file(path).unwrap();
+    }
+}
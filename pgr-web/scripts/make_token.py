@@ -0,0 +1,26 @@
+import base64
+import hashlib
+import hmac
+import json
+import sys
+import time
+
+## generate a HS256 signed token of pgr-server for a user, the secret is the `token_secret` of the auth config
+## usage: python3 make_token.py <token secret> <user name> <comma separated database names or *> [<valid seconds>]
+## e.g. python3 make_token.py my-secret alice hprc-y1,t2t 86400
+## the token is sent in the "Authorization: Bearer <token>" header or the "access_token" query parameter
+
+
+def b64(data):
+    return base64.urlsafe_b64encode(data).rstrip(b"=").decode("ascii")
+
+
+secret = sys.argv[1].encode("utf-8")
+claims = {"sub": sys.argv[2], "databases": sys.argv[3].split(",")}
+if len(sys.argv) > 4:
+    claims["exp"] = int(time.time()) + int(sys.argv[4])
+
+header = b64(json.dumps({"alg": "HS256", "typ": "JWT"}).encode("utf-8"))
+payload = b64(json.dumps(claims).encode("utf-8"))
+signature = hmac.new(secret, (header + "." + payload).encode("ascii"), hashlib.sha256).digest()
+print(header + "." + payload + "." + b64(signature))
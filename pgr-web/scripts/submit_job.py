@@ -0,0 +1,53 @@
+import json
+import sys
+import time
+import urllib.error
+import urllib.request
+
+## submit a principal bundle decomposition job of a ROI to pgr-server, wait for it and write the result to stdout
+## usage: python3 submit_job.py <server_url> <database name> <ROI json file> <ROI name>
+## e.g. python3 submit_job.py http://127.0.0.1:3000 default ROIs_examples.json AMY1A
+
+server_url = sys.argv[1].rstrip("/")
+db_name = sys.argv[2]
+roi_name = sys.argv[4]
+spec = json.loads(open(sys.argv[3]).read())[roi_name]
+
+
+def request(method, path, data=None):
+    if data is not None:
+        data = json.dumps(data).encode("utf-8")
+    req = urllib.request.Request(
+        server_url + path,
+        data=data,
+        method=method,
+        headers={"Content-Type": "application/json"},
+    )
+    try:
+        with urllib.request.urlopen(req) as resp:
+            return resp.status, json.loads(resp.read())
+    except urllib.error.HTTPError as e:
+        return e.code, json.loads(e.read())
+
+
+status, job = request("POST", f"/api/v1/db/{db_name}/jobs", spec)
+if status != 202:
+    sys.exit(f"job submission fails: {status} {job}")
+
+try:
+    while job["state"] in ("queued", "running"):
+        time.sleep(1)
+        _, job = request("GET", f"/api/v1/jobs/{job['id']}")
+        print(
+            f"job {job['id']}: {job['state']} {job['progress']:.2f} {job['stage']}",
+            file=sys.stderr,
+        )
+except KeyboardInterrupt:
+    _, job = request("DELETE", f"/api/v1/jobs/{job['id']}")
+    sys.exit(f"job {job['id']} is {job['state']}")
+
+if job["state"] != "done":
+    sys.exit(f"job {job['id']} is {job['state']}: {job['error']}")
+
+_, result = request("GET", f"/api/v1/jobs/{job['id']}/result")
+print(json.dumps(result))